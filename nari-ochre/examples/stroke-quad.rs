@@ -123,7 +123,7 @@ fn main() -> anyhow::Result<()> {
                     let frame = wsi.acquire().unwrap();
                     let pool = gpu.acquire_pool().unwrap();
 
-                    let euler = Euler {
+                    let euler = EulerSegment {
                         p: Point::new(0.0, 0.0),
                         scale: 300.0,
                         k: [0.0, 1.0, -6.0],
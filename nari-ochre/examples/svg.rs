@@ -1,6 +1,6 @@
-use nari_ochre::{Encoder, Rasterizer, Tile, TileRange, TILE_SIZE};
+use nari_ochre::{stroke::stroke, Coord, Encoder, Rasterizer, Tile, TileRange, TILE_SIZE};
 use std::ops::Range;
-use zeno::{apply, Cap, Command, Fill, Join, Mask, PathData, Point, Stroke, Style, Transform};
+use zeno::{Cap, Command, Fill, Join, Mask, PathData, Point, Stroke, Style};
 
 struct ImageEncoder {
     range: TileRange,
@@ -19,7 +19,7 @@ impl ImageEncoder {
 }
 
 impl Encoder for ImageEncoder {
-    fn solid(&mut self, y: i16, x: Range<i16>) {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
         let ty = (y - self.range.top) as usize;
         let nx = (self.range.right - self.range.left) as usize * TILE_SIZE;
 
@@ -37,7 +37,7 @@ impl Encoder for ImageEncoder {
         }
     }
 
-    fn mask(&mut self, y: i16, x: i16, mask: &Tile<u8>) {
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
         let nx = (self.range.right - self.range.left) as usize * TILE_SIZE;
 
         let tx = (x - self.range.left) as usize;
@@ -57,14 +57,13 @@ impl Encoder for ImageEncoder {
 
 pub struct Sink;
 impl Encoder for Sink {
-    fn solid(&mut self, _y: i16, _x: Range<i16>) {}
+    fn solid(&mut self, _y: Coord, _x: Range<Coord>) {}
 
-    fn mask(&mut self, _y: i16, _x: i16, _mask: &Tile<u8>) {}
+    fn mask(&mut self, _y: Coord, _x: Coord, _mask: &Tile<u8>) {}
 }
 
 struct PathCommand<'a> {
     style: Style<'a>,
-    transform: Option<Transform>,
 }
 
 fn encode_node<F>(node: &usvg::Node, encode: &mut F)
@@ -107,7 +106,6 @@ where
                 (encode)(
                     PathCommand {
                         style: Style::Fill(Fill::EvenOdd),
-                        transform: None,
                     },
                     &path,
                 );
@@ -126,7 +124,6 @@ where
                             offset: 0.0,
                             scale: true,
                         }),
-                        transform: None,
                     },
                     &path,
                 );
@@ -150,11 +147,8 @@ fn main() {
 
     let mut i = 0;
     encode_node(&svg.root(), &mut |cmd, path| {
-        let mut path_flat = Vec::<Command>::new();
-        apply(path, cmd.style, cmd.transform, &mut path_flat);
-
         // zeno reference
-        let (mask, place) = Mask::new(&path).render();
+        let (mask, place) = Mask::new(&path).style(cmd.style).render();
         image::save_buffer(
             &format!("{}.zeno_{}.png", file_path, i),
             &mask,
@@ -166,10 +160,16 @@ fn main() {
 
         // nari
         rasterizer.begin();
-        (&path_flat).copy_to(&mut rasterizer);
+        match cmd.style {
+            Style::Fill(_) => path.copy_to(&mut rasterizer),
+            Style::Stroke(ref style) => {
+                let tolerance = rasterizer.tolerance();
+                stroke(path, style, tolerance, &mut rasterizer);
+            }
+        }
         let range = rasterizer.range();
         let mut encoder = ImageEncoder::new(range);
-        rasterizer.end(&mut encoder);
+        rasterizer.end(&mut encoder).unwrap();
 
         image::save_buffer(
             &format!("{}.nari_{}.png", file_path, i),
@@ -0,0 +1,253 @@
+use crate::{Coord, Encoder, Tile, TILE_SIZE};
+use std::ops::Range;
+
+/// Rasterized clip path.
+///
+/// Uses the same representation as the rasterizer output: 8x8 mask tiles
+/// along the boundary and solid spans for the interior, both sorted by row
+/// and column.
+#[derive(Clone, Debug, Default)]
+pub struct Clip {
+    spans: Vec<(Coord, Range<Coord>)>,
+    masks: Vec<(Coord, Coord, Tile<u8>)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ClipCoverage<'a> {
+    /// Tile is fully clipped away.
+    None,
+    /// Tile is fully inside of the clip path.
+    Full,
+    /// Tile is partially covered by the clip path.
+    Mask(&'a Tile<u8>),
+}
+
+impl Clip {
+    /// Clip coverage of the tile at (`x`, `y`).
+    pub fn coverage(&self, y: Coord, x: Coord) -> ClipCoverage<'_> {
+        if let Ok(i) = self
+            .masks
+            .binary_search_by_key(&(y, x), |(ty, tx, _)| (*ty, *tx))
+        {
+            return ClipCoverage::Mask(&self.masks[i].2);
+        }
+
+        let i = self.span_start(y, x);
+        match self.spans.get(i) {
+            Some((ty, span)) if *ty == y && span.contains(&x) => ClipCoverage::Full,
+            _ => ClipCoverage::None,
+        }
+    }
+
+    /// Index of the first span in row `y` which ends after `x`.
+    fn span_start(&self, y: Coord, x: Coord) -> usize {
+        self.spans
+            .partition_point(|(ty, span)| (*ty, span.end) <= (y, x))
+    }
+
+    /// Index of the first mask in row `y` at or after `x`.
+    fn mask_start(&self, y: Coord, x: Coord) -> usize {
+        self.masks
+            .partition_point(|(ty, tx, _)| (*ty, *tx) < (y, x))
+    }
+}
+
+impl Encoder for Clip {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        self.spans.push((y, x));
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        self.masks.push((y, x, *mask));
+    }
+}
+
+/// Intersects the rasterizer output with a clip before forwarding it.
+pub(crate) struct ClipEncoder<'a, E> {
+    pub clip: &'a Clip,
+    pub encoder: &'a mut E,
+}
+
+impl<E: Encoder> Encoder for ClipEncoder<'_, E> {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        // solid spans of the clip: span intersection
+        for (ty, span) in &self.clip.spans[self.clip.span_start(y, x.start)..] {
+            if *ty != y || span.start >= x.end {
+                break;
+            }
+
+            let x0 = span.start.max(x.start);
+            let x1 = span.end.min(x.end);
+            if x0 < x1 {
+                self.encoder.solid(y, x0..x1);
+            }
+        }
+
+        // clip boundary tiles inside the span are taken as is
+        for (ty, tx, mask) in &self.clip.masks[self.clip.mask_start(y, x.start)..] {
+            if *ty != y || *tx >= x.end {
+                break;
+            }
+            self.encoder.mask(y, *tx, mask);
+        }
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        match self.clip.coverage(y, x) {
+            ClipCoverage::None => (),
+            ClipCoverage::Full => self.encoder.mask(y, x, mask),
+            ClipCoverage::Mask(clip) => {
+                let mut tile = [[0; TILE_SIZE]; TILE_SIZE];
+                for py in 0..TILE_SIZE {
+                    for px in 0..TILE_SIZE {
+                        tile[py][px] = mul_u8(mask[py][px], clip[py][px]);
+                    }
+                }
+                self.encoder.mask(y, x, &tile);
+            }
+        }
+    }
+}
+
+/// Multiply two coverage values, rounding to nearest.
+fn mul_u8(a: u8, b: u8) -> u8 {
+    let x = a as u32 * b as u32 + 128;
+    ((x + (x >> 8)) >> 8) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rasterizer;
+    use zeno::PathBuilder;
+
+    const SIZE: usize = 128;
+
+    /// Coverage per pixel, each pixel may only be written once.
+    struct Canvas(Vec<u8>);
+
+    impl Canvas {
+        fn new() -> Self {
+            Canvas(vec![0; SIZE * SIZE])
+        }
+
+        fn set(&mut self, x: usize, y: usize, coverage: u8) {
+            let pixel = &mut self.0[y * SIZE + x];
+            assert_eq!(*pixel, 0, "pixel ({}, {}) written twice", x, y);
+            *pixel = coverage;
+        }
+    }
+
+    impl Encoder for Canvas {
+        fn solid(&mut self, y: Coord, x: Range<Coord>) {
+            for tx in x {
+                self.mask(y, tx, &[[255; TILE_SIZE]; TILE_SIZE]);
+            }
+        }
+
+        fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+            for (py, row) in mask.iter().enumerate() {
+                for (px, coverage) in row.iter().enumerate() {
+                    if *coverage != 0 {
+                        let x = x as usize * TILE_SIZE + px;
+                        let y = y as usize * TILE_SIZE + py;
+                        self.set(x, y, *coverage);
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(rasterizer: &mut Rasterizer, path: impl Fn(&mut Rasterizer)) -> Canvas {
+        rasterizer.begin();
+        path(rasterizer);
+        let mut canvas = Canvas::new();
+        rasterizer.end(&mut canvas).unwrap();
+        canvas
+    }
+
+    fn push_clip(rasterizer: &mut Rasterizer, path: impl Fn(&mut Rasterizer)) {
+        rasterizer.begin();
+        path(rasterizer);
+        rasterizer.push_clip().unwrap();
+    }
+
+    /// Coverage of `canvas` multiplied by the coverage of `clip`.
+    fn clipped(canvas: &Canvas, clip: &Canvas) -> Vec<u8> {
+        canvas
+            .0
+            .iter()
+            .zip(&clip.0)
+            .map(|(a, b)| mul_u8(*a, *b))
+            .collect()
+    }
+
+    fn rounded_rect(rasterizer: &mut Rasterizer) {
+        rasterizer.add_round_rect([10.5, 12.25], 90.0, 70.0, 20.0, 20.0);
+    }
+
+    fn background(rasterizer: &mut Rasterizer) {
+        rasterizer.add_rect([0.0, 0.0], SIZE as f32, SIZE as f32);
+    }
+
+    #[test]
+    fn rounded_rect_over_solid() {
+        let mut rasterizer = Rasterizer::default();
+        let clip = fill(&mut rasterizer, rounded_rect);
+
+        push_clip(&mut rasterizer, rounded_rect);
+        let canvas = fill(&mut rasterizer, background);
+        assert!(canvas.0 == clip.0);
+    }
+
+    #[test]
+    fn nested() {
+        let circle = |rasterizer: &mut Rasterizer| {
+            rasterizer.add_circle([70.0, 60.0], 45.3);
+        };
+        let shape = |rasterizer: &mut Rasterizer| {
+            rasterizer.add_rect([5.25, 3.75], 110.0, 40.5);
+            rasterizer.add_circle([40.0, 80.0], 30.0);
+        };
+
+        let mut rasterizer = Rasterizer::default();
+        let outer = fill(&mut rasterizer, rounded_rect);
+        let inner = fill(&mut rasterizer, circle);
+        let unclipped = fill(&mut rasterizer, shape);
+
+        push_clip(&mut rasterizer, rounded_rect);
+        push_clip(&mut rasterizer, circle);
+        let canvas = fill(&mut rasterizer, shape);
+        let both = Canvas(clipped(&inner, &outer));
+        assert!(canvas.0 == clipped(&unclipped, &both));
+
+        rasterizer.pop_clip();
+        let canvas = fill(&mut rasterizer, shape);
+        assert!(canvas.0 == clipped(&unclipped, &outer));
+
+        rasterizer.pop_clip();
+        let canvas = fill(&mut rasterizer, shape);
+        assert!(canvas.0 == unclipped.0);
+    }
+
+    #[test]
+    fn partial_span_overlap() {
+        // clip spans starting before and ending inside of the fill spans,
+        // with clip boundary tiles in the middle of the fill spans
+        let clip = |rasterizer: &mut Rasterizer| {
+            rasterizer.add_rect([4.5, 4.5], 60.0, 100.0);
+            rasterizer.add_circle([90.0, 60.0], 20.25);
+        };
+        let shape = |rasterizer: &mut Rasterizer| {
+            rasterizer.add_rect([20.0, 16.0], 100.0, 80.0);
+        };
+
+        let mut rasterizer = Rasterizer::default();
+        let mask = fill(&mut rasterizer, clip);
+        let unclipped = fill(&mut rasterizer, shape);
+
+        push_clip(&mut rasterizer, clip);
+        let canvas = fill(&mut rasterizer, shape);
+        assert!(canvas.0 == clipped(&unclipped, &mask));
+    }
+}
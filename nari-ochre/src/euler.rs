@@ -1,7 +1,15 @@
-use zeno::{Point, Vector};
+use std::f64::consts::PI;
+use std::ops::Range;
+use zeno::{Bounds, Point, Vector};
 
-#[derive(Copy, Clone, Debug)]
-pub struct Euler {
+/// Euler spiral segment.
+///
+/// The curvature varies linearly along the segment. The segment is
+/// parameterized over `t` in `0..1` proportional to the arc length, the tangent
+/// angle at `t` is `k[0] + k[1] * t + k[2] * t² / 2` and `scale` is the
+/// arc length of the whole segment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EulerSegment {
     pub p: Point,
     pub scale: f64,
     pub k: [f64; 3],
@@ -111,33 +119,47 @@ fn spiro_n(k0: f64, k1: f64) -> (f64, f64) {
     }
 }
 
-pub fn euler_angle(euler: Euler, t: f64) -> f64 {
+pub fn euler_angle(euler: EulerSegment, t: f64) -> f64 {
     0.5 * euler.k[2] * t * t + euler.k[1] * t + euler.k[0]
 }
 
-pub fn euler_curvature_radius(euler: Euler, t: f64) -> f64 {
+pub fn euler_curvature_radius(euler: EulerSegment, t: f64) -> f64 {
     euler.scale / (euler.k[2] * t + euler.k[1])
 }
 
-pub fn euler_normal(euler: Euler, t: f64, offset: f64) -> Vector {
+pub fn euler_normal(euler: EulerSegment, t: f64, offset: f64) -> Vector {
     let theta = euler_angle(euler, t);
     let (ts, tc) = theta.sin_cos();
     Vector::new((-offset * ts) as f32, (offset * tc) as f32)
 }
 
-pub fn euler_inverse(euler: Euler) -> Euler {
-    Euler {
+pub fn euler_inverse(euler: EulerSegment) -> EulerSegment {
+    EulerSegment {
         p: euler.p,
         scale: euler.scale,
         k: [
-            euler.k[0] + euler.k[1] + euler.k[2] / 2.0 - std::f64::consts::PI,
+            euler.k[0] + euler.k[1] + euler.k[2] / 2.0 - PI,
             -(euler.k[1] + euler.k[2]),
             euler.k[2],
         ],
     }
 }
 
-pub fn euler_eval(euler: Euler, t0: f64, t1: f64) -> Point {
+/// Section `t0..t1` of the spiral, reparameterized to `0..1`.
+pub fn euler_segment(euler: EulerSegment, t0: f64, t1: f64) -> EulerSegment {
+    let dt = t1 - t0;
+    EulerSegment {
+        p: euler_eval(euler, 0.0, t0),
+        scale: euler.scale * dt,
+        k: [
+            euler_angle(euler, t0),
+            (euler.k[1] + euler.k[2] * t0) * dt,
+            euler.k[2] * dt * dt,
+        ],
+    }
+}
+
+pub fn euler_eval(euler: EulerSegment, t0: f64, t1: f64) -> Point {
     let s1s0 = t1 - t0;
     let s = (t0 + t1) * 0.5;
     let (u, v) = spiro_n(
@@ -151,18 +173,23 @@ pub fn euler_eval(euler: Euler, t0: f64, t1: f64) -> Point {
     euler.p + Point::new((euler.scale * x) as f32, (euler.scale * y) as f32)
 }
 
-fn euler_fit(p0: Point, len: f64, th: f64, th0: f64, th1: f64) -> Euler {
+/// Residual tangent angle error below which the spiral fit is considered
+/// converged.
+const FIT_RESIDUAL: f64 = 1e-9;
+
+/// Fit a spiral to the chord and endpoint tangents, also returns the residual
+/// error of the end tangent angle.
+fn euler_fit(p0: Point, len: f64, th: f64, th0: f64, th1: f64) -> (EulerSegment, f64) {
     let mut k2_old = 0.0;
     let mut e_old = th1 - th0;
     let k1 = th0 + th1;
-    let mut k2 = 6.0 * (1.0 - ((0.5 / std::f64::consts::PI) * k1).powi(3)) * e_old;
-    let mut x = 0.0;
-    let mut y = 0.0;
+    let mut k2 = 6.0 * (1.0 - ((0.5 / PI) * k1).powi(3)) * e_old;
+    let residual = |k2: f64, x: f64, y: f64| (th1 - th0) + 2.0 * y.atan2(x) - 0.25 * k2;
+
+    let (mut x, mut y) = spiro(k1, k2);
+    let mut e = residual(k2, x, y);
     for _ in 0..10 {
-        (x, y) = spiro(k1, k2);
-        let theta = y.atan2(x);
-        let e = (th1 - th0) + 2.0 * theta - 0.25 * k2;
-        if e.abs() < 1e-9 {
+        if e.abs() < FIT_RESIDUAL || e == e_old {
             break;
         }
 
@@ -170,6 +197,9 @@ fn euler_fit(p0: Point, len: f64, th: f64, th0: f64, th1: f64) -> Euler {
         k2_old = k2;
         e_old = e;
         k2 = new_k2;
+
+        (x, y) = spiro(k1, k2);
+        e = residual(k2, x, y);
     }
 
     let chord = (x * x + y * y).sqrt();
@@ -182,21 +212,687 @@ fn euler_fit(p0: Point, len: f64, th: f64, th0: f64, th1: f64) -> Euler {
     let u1 = k1 - k2 * 0.5;
     let u2 = k2;
 
-    Euler {
+    let segment = EulerSegment {
         p: p0,
         scale,
         k: [u0, u1, u2],
-    }
+    };
+    (segment, e)
+}
+
+pub fn euler_fit_cubic(p0: Point, p1: Point, p2: Point, p3: Point) -> EulerSegment {
+    fit_cubic(p0, p1, p2, p3).0
 }
 
-pub fn euler_fit_cubic(p0: Point, p1: Point, p2: Point, p3: Point) -> Euler {
-    let d10 = p1 - p0;
-    let d32 = p3 - p2;
+fn fit_cubic(p0: Point, p1: Point, p2: Point, p3: Point) -> (EulerSegment, f64) {
     let d30 = p3 - p0;
 
+    // control points coinciding with the endpoints (e.g. next to a cusp)
+    // leave the end tangent to the other control point
+    let eps = 1e-6 * (d30.length() + (p1 - p0).length() + (p3 - p2).length());
+    let d10 = if (p1 - p0).length() > eps {
+        p1 - p0
+    } else {
+        p2 - p0
+    };
+    let d32 = if (p3 - p2).length() > eps {
+        p3 - p2
+    } else {
+        p3 - p1
+    };
+
     let th0 = d30.cross(d10).atan2(d30.dot(d10));
     let th1 = d32.cross(d30).atan2(d32.dot(d30));
     let th = d30.y.atan2(d30.x);
 
     euler_fit(p0, d30.length() as f64, th as f64, -th0 as f64, -th1 as f64)
 }
+
+/// Result of fitting an euler spiral to a cubic bezier curve.
+#[derive(Copy, Clone, Debug)]
+pub struct CubicFit {
+    pub segment: EulerSegment,
+    /// The solver for the end tangent converged.
+    pub converged: bool,
+    /// Maximum distance between the cubic and the spiral, sampled along the
+    /// cubic.
+    pub error: f64,
+}
+
+/// Number of samples along a cubic for measuring the fit error.
+const FIT_SAMPLES: usize = 16;
+
+impl EulerSegment {
+    /// Fit a spiral to the endpoints and end tangents of a cubic bezier curve.
+    pub fn fit_cubic(p0: Point, p1: Point, p2: Point, p3: Point) -> CubicFit {
+        let (segment, residual) = fit_cubic(p0, p1, p2, p3);
+        CubicFit {
+            segment,
+            converged: residual.abs() < FIT_RESIDUAL,
+            error: segment.cubic_distance([p0, p1, p2, p3]),
+        }
+    }
+
+    pub fn eval(&self, t: f64) -> Point {
+        euler_eval(*self, 0.0, t)
+    }
+
+    pub fn start(&self) -> Point {
+        self.p
+    }
+
+    pub fn end(&self) -> Point {
+        self.eval(1.0)
+    }
+
+    /// Tangent angle at `t`.
+    pub fn angle(&self, t: f64) -> f64 {
+        euler_angle(*self, t)
+    }
+
+    /// Unit tangent at `t`.
+    pub fn tangent(&self, t: f64) -> Vector {
+        let (sin, cos) = self.angle(t).sin_cos();
+        Vector::new(cos as f32, sin as f32)
+    }
+
+    /// Signed curvature at `t`, positive when turning towards the normal.
+    pub fn curvature(&self, t: f64) -> f64 {
+        (self.k[1] + self.k[2] * t) / self.scale
+    }
+
+    pub fn arc_length(&self) -> f64 {
+        self.scale
+    }
+
+    /// Arc length of the section `0..t`.
+    pub fn arc_length_at(&self, t: f64) -> f64 {
+        self.scale * t
+    }
+
+    /// Parameter at arc length `s` from the start.
+    pub fn inv_arc_length(&self, s: f64) -> f64 {
+        if self.scale > 0.0 {
+            (s / self.scale).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Total turning angle, the integral of the absolute curvature.
+    pub fn turning(&self) -> f64 {
+        let (k1, k2) = (self.k[1], self.k[2]);
+        let integral = |t: f64| {
+            let k = k1 + k2 * t;
+            k * k.abs() / (2.0 * k2)
+        };
+        if k2 == 0.0 {
+            k1.abs()
+        } else {
+            integral(1.0) - integral(0.0)
+        }
+    }
+
+    /// Section of the segment, reparameterized to `0..1`.
+    pub fn subsegment(&self, range: Range<f64>) -> EulerSegment {
+        euler_segment(*self, range.start, range.end)
+    }
+
+    pub fn subdivide(&self, t: f64) -> (EulerSegment, EulerSegment) {
+        (self.subsegment(0.0..t), self.subsegment(t..1.0))
+    }
+
+    /// Same curve traversed from end to start.
+    pub fn reverse(&self) -> EulerSegment {
+        EulerSegment {
+            p: self.end(),
+            ..euler_inverse(*self)
+        }
+    }
+
+    /// Tight bounding box.
+    ///
+    /// Extrema are at the endpoints and where the tangent is axis aligned,
+    /// the tangent angle is quadratic in `t`.
+    pub fn bounding_box(&self) -> Bounds {
+        let [k0, k1, k2] = self.k;
+        let mut ts = vec![0.0, 1.0];
+
+        let (mut min, mut max) = (self.angle(0.0), self.angle(1.0));
+        if min > max {
+            std::mem::swap(&mut min, &mut max);
+        }
+        if k2 != 0.0 {
+            let vertex = -k1 / k2;
+            if vertex > 0.0 && vertex < 1.0 {
+                let angle = self.angle(vertex);
+                min = min.min(angle);
+                max = max.max(angle);
+            }
+        }
+
+        let quarter = 0.5 * PI;
+        let mut n = (min / quarter).ceil();
+        while n * quarter <= max {
+            solve_quadratic(0.5 * k2, k1, k0 - n * quarter, |t| {
+                if (0.0..=1.0).contains(&t) {
+                    ts.push(t);
+                }
+            });
+            n += 1.0;
+        }
+
+        Bounds::from_points(ts.into_iter().map(|t| self.eval(t)))
+    }
+
+    /// Parameter and distance of the point on the segment closest to `p`.
+    pub fn nearest(&self, p: Point) -> (f64, f64) {
+        // coarse samples resolving the turns, local minima are refined
+        let n = 8 + (self.turning() / (PI / 8.0)).ceil().min(1024.0) as usize;
+        let distance = |t: f64| (self.eval(t) - p).length() as f64;
+        let samples = (0..=n)
+            .map(|i| distance(i as f64 / n as f64))
+            .collect::<Vec<_>>();
+
+        let mut best = (0.0, samples[0]);
+        for i in 0..=n {
+            let prev = samples[i.saturating_sub(1)];
+            let next = samples[(i + 1).min(n)];
+            if samples[i] > prev || samples[i] > next {
+                continue;
+            }
+
+            let t0 = i.saturating_sub(1) as f64 / n as f64;
+            let t1 = (i + 1).min(n) as f64 / n as f64;
+            let t = self.refine_nearest(p, i as f64 / n as f64, t0..t1);
+            for (t, d) in [(i as f64 / n as f64, samples[i]), (t, distance(t))] {
+                if d < best.1 {
+                    best = (t, d);
+                }
+            }
+        }
+        best
+    }
+
+    /// Newton iterations for the local minimum of the distance to `p`.
+    fn refine_nearest(&self, p: Point, mut t: f64, range: Range<f64>) -> f64 {
+        for _ in 0..8 {
+            let d = self.eval(t) - p;
+            let (dx, dy) = (d.x as f64, d.y as f64);
+            let (sin, cos) = self.angle(t).sin_cos();
+
+            // derivative of the squared distance (halved) and its derivative
+            let g = dx * cos + dy * sin;
+            let dg = self.scale + (dy * cos - dx * sin) * (self.k[1] + self.k[2] * t);
+            if dg <= 0.0 || !dg.is_finite() {
+                break;
+            }
+            t = (t - g / dg).clamp(range.start, range.end);
+        }
+        t
+    }
+
+    /// Maximum distance of points along the cubic to the segment.
+    ///
+    /// Cubic samples are matched to the segment by arc length fraction and
+    /// then moved to the locally closest point.
+    fn cubic_distance(&self, cubic: [Point; 4]) -> f64 {
+        const SUBSAMPLES: usize = 4;
+        let n = FIT_SAMPLES * SUBSAMPLES;
+
+        let points = (0..=n)
+            .map(|i| cubic_eval(cubic, i as f32 / n as f32))
+            .collect::<Vec<_>>();
+        let mut lengths = vec![0.0];
+        for w in points.windows(2) {
+            lengths.push(lengths[lengths.len() - 1] + (w[1] - w[0]).length() as f64);
+        }
+        let total = lengths[n];
+
+        let mut error: f64 = 0.0;
+        for i in (0..=n).step_by(SUBSAMPLES) {
+            let guess = if total > 0.0 { lengths[i] / total } else { 0.0 };
+            let t = self.refine_nearest(points[i], guess, 0.0..1.0);
+            error = error.max((self.eval(t) - points[i]).length() as f64);
+        }
+        error
+    }
+
+    /// Single cubic bezier with control points along the end tangents.
+    ///
+    /// Handle lengths are chosen for circular arcs of the same turning angle.
+    pub fn to_cubic(&self) -> [Point; 4] {
+        let phi = self.angle(1.0) - self.angle(0.0);
+        let arm = if phi.abs() < 1e-6 {
+            self.scale / 3.0
+        } else {
+            self.scale * (4.0 / 3.0) * (0.25 * phi).tan() / phi
+        };
+
+        let p0 = self.start();
+        let p3 = self.end();
+        [
+            p0,
+            p0 + self.tangent(0.0) * arm as f32,
+            p3 - self.tangent(1.0) * arm as f32,
+            p3,
+        ]
+    }
+
+    /// Approximate the segment by cubic beziers within `tolerance`.
+    pub fn to_cubics(&self, tolerance: f64) -> Vec<[Point; 4]> {
+        let mut cubics = Vec::new();
+        self.push_cubics(tolerance, 0, &mut cubics);
+        cubics
+    }
+
+    fn push_cubics(&self, tolerance: f64, depth: usize, cubics: &mut Vec<[Point; 4]>) {
+        const MAX_DEPTH: usize = 16;
+
+        let cubic = self.to_cubic();
+        if depth < MAX_DEPTH {
+            let error = self.cubic_distance(cubic);
+            if self.turning() > 0.5 * PI || error.is_nan() || error > tolerance {
+                let (a, b) = self.subdivide(0.5);
+                a.push_cubics(tolerance, depth + 1, cubics);
+                b.push_cubics(tolerance, depth + 1, cubics);
+                return;
+            }
+        }
+        cubics.push(cubic);
+    }
+}
+
+fn cubic_eval(cubic: [Point; 4], t: f32) -> Point {
+    let mt = 1.0 - t;
+    cubic[0] * (mt * mt * mt)
+        + cubic[1] * (3.0 * mt * mt * t)
+        + cubic[2] * (3.0 * mt * t * t)
+        + cubic[3] * (t * t * t)
+}
+
+/// Real roots of `a * x² + b * x + c`.
+fn solve_quadratic(a: f64, b: f64, c: f64, mut root: impl FnMut(f64)) {
+    if a.abs() < 1e-12 {
+        if b != 0.0 {
+            root(-c / b);
+        }
+        return;
+    }
+
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return;
+    }
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    root(q / a);
+    if q != 0.0 {
+        root(c / q);
+    }
+}
+
+/// Convert a cubic bezier curve into a sequence of euler spiral segments.
+///
+/// The cubic is split at its inflection points and cusps first, the pieces
+/// are subdivided recursively until each spiral is within `tolerance` of the
+/// cubic. Degenerate pieces are dropped, consecutive segments connect within
+/// `tolerance`.
+pub fn cubic_to_euler(cubic: [Point; 4], tolerance: f64, mut segment: impl FnMut(EulerSegment)) {
+    let mut rest = cubic;
+    let mut t0 = 0.0;
+    for t in cubic_splits(cubic) {
+        let (piece, tail) = cubic_split(rest, ((t - t0) / (1.0 - t0)) as f32);
+        fit_pieces(piece, tolerance, 0, &mut segment);
+        rest = tail;
+        t0 = t;
+    }
+    fit_pieces(rest, tolerance, 0, &mut segment);
+}
+
+fn fit_pieces(
+    cubic: [Point; 4],
+    tolerance: f64,
+    depth: usize,
+    segment: &mut impl FnMut(EulerSegment),
+) {
+    const MAX_DEPTH: usize = 12;
+
+    let fit = EulerSegment::fit_cubic(cubic[0], cubic[1], cubic[2], cubic[3]);
+    let valid = fit.segment.scale.is_finite() && fit.error.is_finite();
+    if depth < MAX_DEPTH && (!valid || fit.error > tolerance) {
+        let (a, b) = cubic_split(cubic, 0.5);
+        fit_pieces(a, tolerance, depth + 1, segment);
+        fit_pieces(b, tolerance, depth + 1, segment);
+    } else if valid && fit.segment.scale > 0.0 {
+        segment(fit.segment);
+    }
+}
+
+/// Parameters of inflection points and cusps in `(0, 1)`, sorted.
+fn cubic_splits(cubic: [Point; 4]) -> Vec<f64> {
+    let p = cubic.map(|p| (p.x as f64, p.y as f64));
+    let cross = |a: (f64, f64), b: (f64, f64)| a.0 * b.1 - a.1 * b.0;
+    let dot = |a: (f64, f64), b: (f64, f64)| a.0 * b.0 + a.1 * b.1;
+
+    // derivative / 3 = a * t² + b * t + c
+    let a = (
+        p[3].0 - 3.0 * p[2].0 + 3.0 * p[1].0 - p[0].0,
+        p[3].1 - 3.0 * p[2].1 + 3.0 * p[1].1 - p[0].1,
+    );
+    let b = (
+        2.0 * (p[2].0 - 2.0 * p[1].0 + p[0].0),
+        2.0 * (p[2].1 - 2.0 * p[1].1 + p[0].1),
+    );
+    let c = (p[1].0 - p[0].0, p[1].1 - p[0].1);
+    let derivative = |t: f64| (a.0 * t * t + b.0 * t + c.0, a.1 * t * t + b.1 * t + c.1);
+
+    let mut splits = Vec::new();
+
+    // inflections: cross(derivative, second derivative) = 0
+    solve_quadratic(-cross(a, b), 2.0 * cross(c, a), cross(c, b), |t| {
+        splits.push(t)
+    });
+
+    // cusps: the derivative vanishes at a minimum of its length
+    let magnitude = dot(a, a).sqrt() + dot(b, b).sqrt() + dot(c, c).sqrt();
+    let stationary = |t: f64| {
+        2.0 * dot(a, a) * t * t * t
+            + 3.0 * dot(a, b) * t * t
+            + (dot(b, b) + 2.0 * dot(a, c)) * t
+            + dot(b, c)
+    };
+    const INTERVALS: usize = 32;
+    for i in 0..INTERVALS {
+        let (mut t0, mut t1) = (
+            i as f64 / INTERVALS as f64,
+            (i + 1) as f64 / INTERVALS as f64,
+        );
+        if stationary(t0).signum() == stationary(t1).signum() {
+            continue;
+        }
+        for _ in 0..48 {
+            let t = 0.5 * (t0 + t1);
+            if stationary(t).signum() == stationary(t0).signum() {
+                t0 = t;
+            } else {
+                t1 = t;
+            }
+        }
+        let d = derivative(t0);
+        if dot(d, d).sqrt() < 1e-3 * magnitude {
+            splits.push(t0);
+        }
+    }
+
+    splits.retain(|t| *t > 1e-6 && *t < 1.0 - 1e-6);
+    splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    splits.dedup_by(|a, b| (*a - *b).abs() < 1e-6);
+    splits
+}
+
+/// De Casteljau subdivision at `t`.
+fn cubic_split(c: [Point; 4], t: f32) -> ([Point; 4], [Point; 4]) {
+    let lerp = |a: Point, b: Point| a + (b - a) * t;
+    let (p01, p12, p23) = (lerp(c[0], c[1]), lerp(c[1], c[2]), lerp(c[2], c[3]));
+    let (p012, p123) = (lerp(p01, p12), lerp(p12, p23));
+    let p = lerp(p012, p123);
+    ([c[0], p01, p012, p], [p, p123, p23, c[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPIRALS: [EulerSegment; 5] = [
+        EulerSegment {
+            p: Point::new(0.0, 0.0),
+            scale: 100.0,
+            k: [0.0, 1.0, -6.0],
+        },
+        EulerSegment {
+            p: Point::new(10.0, -20.0),
+            scale: 250.0,
+            k: [0.3, 4.0, 10.0],
+        },
+        EulerSegment {
+            p: Point::new(5.0, 5.0),
+            scale: 80.0,
+            k: [-2.0, 0.0, 0.0],
+        },
+        EulerSegment {
+            p: Point::new(0.0, 0.0),
+            scale: 50.0,
+            k: [0.5, PI, 0.0],
+        },
+        EulerSegment {
+            p: Point::new(-30.0, 40.0),
+            scale: 120.0,
+            k: [1.0, -12.0, 30.0],
+        },
+    ];
+
+    /// Intervals of the numerical reference integration.
+    const STEPS: usize = 4096;
+
+    /// Points at `i / STEPS` by Simpson integration of the unit tangent.
+    fn reference(segment: EulerSegment) -> Vec<(f64, f64)> {
+        let tangent = |t: f64| {
+            let (sin, cos) = segment.angle(t).sin_cos();
+            (segment.scale * cos, segment.scale * sin)
+        };
+
+        let h = 1.0 / STEPS as f64;
+        let mut p = (segment.p.x as f64, segment.p.y as f64);
+        let mut points = vec![p];
+        for i in 0..STEPS {
+            let t = i as f64 * h;
+            let (a, b, c) = (tangent(t), tangent(t + 0.5 * h), tangent(t + h));
+            p.0 += h / 6.0 * (a.0 + 4.0 * b.0 + c.0);
+            p.1 += h / 6.0 * (a.1 + 4.0 * b.1 + c.1);
+            points.push(p);
+        }
+        points
+    }
+
+    fn distance(p: Point, q: (f64, f64)) -> f64 {
+        ((p.x as f64 - q.0).powi(2) + (p.y as f64 - q.1).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn eval() {
+        for segment in SPIRALS {
+            let reference = reference(segment);
+            for i in (0..=STEPS).step_by(STEPS / 16) {
+                let p = segment.eval(i as f64 / STEPS as f64);
+                assert!(distance(p, reference[i]) < 1e-3, "{:?} {}", segment, i);
+            }
+        }
+    }
+
+    #[test]
+    fn arc_length() {
+        for segment in SPIRALS {
+            let reference = reference(segment);
+            let length: f64 = reference
+                .windows(2)
+                .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+                .sum();
+            assert!((segment.arc_length() - length).abs() < 1e-6 * length);
+
+            for t in [0.0, 0.1, 0.5, 0.9, 1.0] {
+                let s = segment.arc_length_at(t);
+                assert!((segment.inv_arc_length(s) - t).abs() < 1e-12);
+            }
+            assert_eq!(segment.inv_arc_length(-1.0), 0.0);
+            assert_eq!(segment.inv_arc_length(2.0 * length), 1.0);
+        }
+    }
+
+    #[test]
+    fn subdivide() {
+        for segment in SPIRALS {
+            for t in [0.25, 0.7] {
+                let (a, b) = segment.subdivide(t);
+                assert!((a.scale + b.scale - segment.scale).abs() < 1e-9);
+                assert!((a.end() - segment.eval(t)).length() < 1e-3);
+                assert!((a.angle(1.0) - b.angle(0.0)).abs() < 1e-9);
+                for u in [0.0, 0.3, 1.0] {
+                    let p = b.eval(u);
+                    assert!((p - segment.eval(t + u * (1.0 - t))).length() < 1e-3);
+                    assert!((b.curvature(u) - segment.curvature(t + u * (1.0 - t))).abs() < 1e-9);
+                }
+            }
+
+            let reverse = segment.reverse();
+            assert!((reverse.end() - segment.start()).length() < 1e-3);
+            assert!((reverse.eval(0.25) - segment.eval(0.75)).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn bounding_box() {
+        for segment in SPIRALS {
+            let reference = reference(segment);
+            let bounds = segment.bounding_box();
+
+            let mut min = (f64::MAX, f64::MAX);
+            let mut max = (f64::MIN, f64::MIN);
+            for p in &reference {
+                min = (min.0.min(p.0), min.1.min(p.1));
+                max = (max.0.max(p.0), max.1.max(p.1));
+            }
+
+            let eps = 1e-3;
+            assert!((bounds.min.x as f64 - min.0).abs() < eps, "{:?}", segment);
+            assert!((bounds.min.y as f64 - min.1).abs() < eps, "{:?}", segment);
+            assert!((bounds.max.x as f64 - max.0).abs() < eps, "{:?}", segment);
+            assert!((bounds.max.y as f64 - max.1).abs() < eps, "{:?}", segment);
+        }
+    }
+
+    #[test]
+    fn nearest() {
+        for segment in SPIRALS {
+            let reference = reference(segment);
+            for y in -4..=4 {
+                for x in -4..=4 {
+                    let p = Point::new(x as f32 * 40.0, y as f32 * 40.0);
+                    let (t, d) = segment.nearest(p);
+                    let brute = reference
+                        .iter()
+                        .map(|q| distance(p, *q))
+                        .fold(f64::MAX, f64::min);
+                    assert!(d <= brute + 1e-3, "{:?} {:?}: {} {}", segment, p, d, brute);
+                    assert!(((segment.eval(t) - p).length() as f64 - d).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn to_cubics() {
+        for segment in SPIRALS {
+            for tolerance in [1.0, 0.1, 0.01] {
+                let cubics = segment.to_cubics(tolerance);
+                assert!((cubics[0][0] - segment.start()).length() < 1e-3);
+                assert!((cubics[cubics.len() - 1][3] - segment.end()).length() < 1e-3);
+                for w in cubics.windows(2) {
+                    assert!((w[0][3] - w[1][0]).length() < 1e-3);
+                }
+
+                for cubic in &cubics {
+                    for i in 0..=32 {
+                        let (_, d) = segment.nearest(cubic_eval(*cubic, i as f32 / 32.0));
+                        assert!(d <= tolerance + 1e-3, "{:?} {}: {}", segment, tolerance, d);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fit_cubic() {
+        // cubics generated from a spiral are fitted back closely
+        for segment in SPIRALS {
+            for cubic in segment.to_cubics(0.01) {
+                let fit = EulerSegment::fit_cubic(cubic[0], cubic[1], cubic[2], cubic[3]);
+                assert!(fit.converged, "{:?}", fit);
+                assert!(fit.error < 0.05, "{:?}", fit);
+            }
+        }
+
+        // a single spiral can't follow an s-shaped cubic with a loop
+        let fit = EulerSegment::fit_cubic(
+            Point::new(0.0, 0.0),
+            Point::new(300.0, 200.0),
+            Point::new(-200.0, 200.0),
+            Point::new(100.0, 0.0),
+        );
+        assert!(fit.error > 10.0, "{:?}", fit);
+    }
+
+    /// Distance from `p` to the closest of the segments.
+    fn distance_to(segments: &[EulerSegment], p: Point) -> f64 {
+        segments
+            .iter()
+            .map(|segment| segment.nearest(p).1)
+            .fold(f64::MAX, f64::min)
+    }
+
+    #[test]
+    fn cubic_to_euler() {
+        let cubics = [
+            // s-curve
+            [(0.0, 0.0), (100.0, 0.0), (0.0, 100.0), (100.0, 100.0)],
+            // cusp at t = 0.5
+            [(0.0, 0.0), (100.0, 100.0), (0.0, 100.0), (100.0, 0.0)],
+            // loop
+            [(0.0, 0.0), (150.0, 100.0), (-50.0, 100.0), (100.0, 0.0)],
+            // closed loop
+            [(0.0, 0.0), (200.0, 200.0), (-200.0, 200.0), (0.0, 0.0)],
+            // straight with coincident control points
+            [(0.0, 0.0), (0.0, 0.0), (100.0, 50.0), (100.0, 50.0)],
+            // nearly flat arc
+            [(0.0, 0.0), (30.0, 1.0), (70.0, 1.0), (100.0, 0.0)],
+        ];
+
+        for cubic in cubics {
+            let cubic = cubic.map(|(x, y)| Point::new(x, y));
+            for tolerance in [1.0, 0.1] {
+                let mut segments = Vec::new();
+                super::cubic_to_euler(cubic, tolerance, |segment| segments.push(segment));
+                assert!(!segments.is_empty() && segments.len() < 256, "{:?}", cubic);
+
+                let eps = tolerance + 1e-3;
+                assert!((segments[0].start() - cubic[0]).length() as f64 <= eps);
+                assert!((segments[segments.len() - 1].end() - cubic[3]).length() as f64 <= eps);
+                for w in segments.windows(2) {
+                    assert!((w[0].end() - w[1].start()).length() as f64 <= eps);
+                }
+
+                // cubic points are close to the spirals
+                for i in 0..=256 {
+                    let p = cubic_eval(cubic, i as f32 / 256.0);
+                    let d = distance_to(&segments, p);
+                    assert!(d <= eps, "{:?} {}: {}", cubic, tolerance, d);
+                }
+
+                // spiral points are close to the cubic
+                let polyline = (0..=1024)
+                    .map(|i| cubic_eval(cubic, i as f32 / 1024.0))
+                    .collect::<Vec<_>>();
+                for segment in &segments {
+                    for i in 0..=8 {
+                        let p = segment.eval(i as f64 / 8.0);
+                        let d = polyline
+                            .iter()
+                            .map(|q| (*q - p).length() as f64)
+                            .fold(f64::MAX, f64::min);
+                        // plus the vertex spacing of the polyline
+                        assert!(d <= eps + 0.5, "{:?} {}: {}", cubic, tolerance, d);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Curve flattening.
+//!
+//! Curves are subdivided adaptively so that each emitted line stays within
+//! `tolerance` pixels of the curve, spending more segments on the sections
+//! with high curvature. Only the interior subdivision points are emitted,
+//! the end point is left to the caller.
+
+use crate::{
+    euler::{cubic_to_euler, euler_eval, EulerSegment},
+    lerp,
+};
+use zeno::Point;
+
+/// Upper bound of lines per curve, limits the work for tiny tolerances.
+pub(crate) const MAX_SEGMENTS: usize = 1 << 16;
+
+/// Flatten a quadratic bezier curve.
+pub(crate) fn quad(
+    p0: Point,
+    p1: Point,
+    p2: Point,
+    tolerance: f32,
+    mut line_to: impl FnMut(Point),
+) {
+    quad_params(p0, p1, p2, tolerance, |t| {
+        let t = t as f32;
+        line_to(lerp(lerp(p0, p1, t), lerp(p1, p2, t), t));
+    });
+}
+
+/// Curve parameters of the interior subdivision points of a quadratic bezier
+/// curve.
+///
+/// Based on the parabola integral approximation of Raph Levien's
+/// "Flattening quadratic Béziers": the curve is mapped onto a segment of
+/// the unit parabola and the subdivision points are distributed evenly
+/// w.r.t. the (approximated) integral of `sqrt(curvature)`.
+fn quad_params(p0: Point, p1: Point, p2: Point, tolerance: f32, mut param: impl FnMut(f64)) {
+    let sqrt_tol = (tolerance as f64).sqrt();
+
+    let (x0, y0) = (p0.x as f64, p0.y as f64);
+    let (x1, y1) = (p1.x as f64, p1.y as f64);
+    let (x2, y2) = (p2.x as f64, p2.y as f64);
+
+    let (d01x, d01y) = (x1 - x0, y1 - y0);
+    let (d12x, d12y) = (x2 - x1, y2 - y1);
+    let (ddx, ddy) = (d01x - d12x, d01y - d12y);
+    let cross = (x2 - x0) * ddy - (y2 - y0) * ddx;
+
+    let u0 = (d01x * ddx + d01y * ddy) / cross;
+    let u2 = (d12x * ddx + d12y * ddy) / cross;
+    let scale = (cross / ((ddx * ddx + ddy * ddy).sqrt() * (u2 - u0))).abs();
+
+    if !scale.is_finite() || scale == 0.0 {
+        // (nearly) straight line
+        return;
+    }
+
+    let a0 = approx_parabola_integral(u0);
+    let a2 = approx_parabola_integral(u2);
+    let da = (a2 - a0).abs();
+    let sqrt_scale = scale.sqrt();
+    let val = if u0.signum() == u2.signum() {
+        da * sqrt_scale
+    } else {
+        // cusp-like section around the parabola vertex
+        let xmin = sqrt_tol / sqrt_scale;
+        sqrt_tol * da / approx_parabola_integral(xmin)
+    };
+
+    let n = (0.5 * val / sqrt_tol)
+        .ceil()
+        .clamp(1.0, MAX_SEGMENTS as f64) as usize;
+
+    let v0 = approx_parabola_inv_integral(a0);
+    let v2 = approx_parabola_inv_integral(a2);
+    let vscale = 1.0 / (v2 - v0);
+
+    for i in 1..n {
+        let a = a0 + (a2 - a0) * (i as f64 / n as f64);
+        param((approx_parabola_inv_integral(a) - v0) * vscale);
+    }
+}
+
+/// Flatten a cubic bezier curve.
+///
+/// The cubic is converted into euler spiral segments first, half of the
+/// tolerance is spent on the conversion and half on flattening the spirals.
+pub(crate) fn cubic(
+    p0: Point,
+    p1: Point,
+    p2: Point,
+    p3: Point,
+    tolerance: f32,
+    mut line_to: impl FnMut(Point),
+) {
+    let tolerance = 0.5 * tolerance;
+    let mut first = true;
+    cubic_to_euler([p0, p1, p2, p3], tolerance as f64, |segment| {
+        if !first {
+            line_to(segment.p);
+        }
+        first = false;
+        euler(segment, tolerance, &mut line_to);
+    });
+}
+
+/// Flatten an euler spiral segment.
+pub(crate) fn euler(euler: EulerSegment, tolerance: f32, mut line_to: impl FnMut(Point)) {
+    euler_params(euler, 0.0, tolerance, |t| {
+        line_to(euler_eval(euler, 0.0, t))
+    });
+}
+
+/// Curve parameters of the interior subdivision points of an euler spiral
+/// segment.
+///
+/// The curvature of the spiral is linear in the arc length, so the integral
+/// of `sqrt(curvature)` has a closed form, which is used to place the
+/// subdivision points (chord deviation of an arc is `k * len² / 8`).
+/// Around inflection points the curvature vanishes and the deviation is
+/// dominated by the constant curvature derivative `k'` instead
+/// (`k' * len³ / (72 * sqrt(3))`), which adds a uniform density term.
+///
+/// For curves at a distance `offset` from the spiral the turning angle per
+/// line is additionally limited, as the offset scales the chord deviation of
+/// each section.
+pub(crate) fn euler_params(
+    euler: EulerSegment,
+    offset: f64,
+    tolerance: f32,
+    mut param: impl FnMut(f64),
+) {
+    let k1 = euler.k[1];
+    let k2 = euler.k[2];
+    let tolerance = tolerance as f64;
+
+    // integral of sqrt(|k1 + k2 * t|) dt
+    let integral = |t: f64| -> f64 {
+        if k2 == 0.0 {
+            k1.abs().sqrt() * t
+        } else {
+            let k = k1 + k2 * t;
+            (2.0 / (3.0 * k2)) * k.signum() * k.abs().powf(1.5)
+        }
+    };
+
+    // integral of |k1 + k2 * t| dt, the turning angle
+    let turning = |t: f64| -> f64 {
+        if k2 == 0.0 {
+            k1.abs() * t
+        } else {
+            let k = k1 + k2 * t;
+            k * k.abs() / (2.0 * k2)
+        }
+    };
+
+    // segment densities over the curve parameter
+    let density_arc = (euler.scale / (8.0 * tolerance)).sqrt();
+    let density_inflection = (k2.abs() * euler.scale / (72.0 * 3f64.sqrt() * tolerance)).cbrt();
+    // an arc of radius `offset` deviates by `tolerance` at this angle
+    let density_offset = if offset.abs() > 0.5 * tolerance {
+        0.5 / (1.0 - tolerance / offset.abs()).acos()
+    } else {
+        0.0
+    };
+
+    let i0 = integral(0.0);
+    let a0 = turning(0.0);
+    let measure = |t: f64| {
+        density_arc * (integral(t) - i0)
+            + density_inflection * t
+            + density_offset * (turning(t) - a0)
+    };
+
+    let val = measure(1.0);
+    if !val.is_finite() {
+        return;
+    }
+    let n = val.ceil().clamp(1.0, MAX_SEGMENTS as f64) as usize;
+
+    // `measure` is monotonic, invert by bisection
+    let mut t0 = 0.0;
+    for i in 1..n {
+        let target = val * (i as f64 / n as f64);
+        let mut t1 = 1.0;
+        for _ in 0..48 {
+            let t = 0.5 * (t0 + t1);
+            if measure(t) < target {
+                t0 = t;
+            } else {
+                t1 = t;
+            }
+        }
+        param(t0);
+    }
+}
+
+fn approx_parabola_integral(x: f64) -> f64 {
+    const D: f64 = 0.67;
+    x / (1.0 - D + (D * D * D * D + 0.25 * x * x).sqrt().sqrt())
+}
+
+fn approx_parabola_inv_integral(x: f64) -> f64 {
+    const B: f64 = 0.39;
+    x * (1.0 - B + (B * B + 0.25 * x * x).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::euler::euler_fit_cubic;
+
+    const TOLERANCE: f32 = 0.1;
+    const ZOOM: [f32; 6] = [0.25, 1.0, 4.0, 16.0, 64.0, 256.0];
+
+    /// Maximum distance between curve and flattened polyline, and the number
+    /// of lines.
+    fn measure(
+        params: impl FnOnce(&mut dyn FnMut(f64)),
+        eval: impl Fn(f64) -> Point,
+    ) -> (f32, usize) {
+        let mut ts = vec![0.0];
+        params(&mut |t| ts.push(t));
+        ts.push(1.0);
+
+        let mut max_dev: f32 = 0.0;
+        for w in ts.windows(2) {
+            let p0 = eval(w[0]);
+            let p1 = eval(w[1]);
+            let d = p1 - p0;
+            let len = d.length();
+            for i in 1..32 {
+                let p = eval(w[0] + (w[1] - w[0]) * i as f64 / 32.0);
+                let dist = if len > 0.0 {
+                    (p - p0).cross(d).abs() / len
+                } else {
+                    (p - p0).length()
+                };
+                max_dev = max_dev.max(dist);
+            }
+        }
+
+        (max_dev, ts.len() - 1)
+    }
+
+    /// Segment counts should grow with the square root of the zoom factor.
+    fn check_counts(counts: &[usize]) {
+        for w in counts.windows(2) {
+            assert!(w[0] <= w[1], "{:?}", counts);
+            assert!(w[1] <= 3 * w[0], "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn quad_tolerance() {
+        let mut counts = Vec::new();
+        for zoom in ZOOM {
+            let p0 = Point::new(0.0, 0.0) * zoom;
+            let p1 = Point::new(50.0, 100.0) * zoom;
+            let p2 = Point::new(100.0, 0.0) * zoom;
+
+            let (max_dev, n) = measure(
+                |f| quad_params(p0, p1, p2, TOLERANCE, f),
+                |t| {
+                    let t = t as f32;
+                    lerp(lerp(p0, p1, t), lerp(p1, p2, t), t)
+                },
+            );
+            assert!(
+                max_dev <= 1.1 * TOLERANCE,
+                "zoom {}: {} lines, max deviation {}",
+                zoom,
+                n,
+                max_dev
+            );
+            counts.push(n);
+        }
+        check_counts(&counts);
+    }
+
+    #[test]
+    fn euler_tolerance() {
+        let mut counts = Vec::new();
+        for zoom in ZOOM {
+            let spiral = EulerSegment {
+                p: Point::new(0.0, 0.0),
+                scale: zoom as f64 * 100.0,
+                k: [0.0, 1.0, -6.0],
+            };
+
+            let (max_dev, n) = measure(
+                |f| euler_params(spiral, 0.0, TOLERANCE, f),
+                |t| euler_eval(spiral, 0.0, t),
+            );
+            assert!(
+                max_dev <= 1.1 * TOLERANCE,
+                "zoom {}: {} lines, max deviation {}",
+                zoom,
+                n,
+                max_dev
+            );
+            counts.push(n);
+        }
+        check_counts(&counts);
+    }
+
+    #[test]
+    fn cubic_tolerance() {
+        let cubics = [
+            // s-curve
+            [(0.0, 0.0), (100.0, 0.0), (0.0, 100.0), (100.0, 100.0)],
+            // cusp
+            [(0.0, 0.0), (100.0, 100.0), (0.0, 100.0), (100.0, 0.0)],
+            // loop
+            [(0.0, 0.0), (150.0, 100.0), (-50.0, 100.0), (100.0, 0.0)],
+        ];
+        for cubic in cubics {
+            let mut counts = Vec::new();
+            for zoom in ZOOM {
+                let [p0, p1, p2, p3] = cubic.map(|(x, y)| Point::new(x, y) * zoom);
+                let mut points = vec![p0];
+                super::cubic(p0, p1, p2, p3, TOLERANCE, |p| points.push(p));
+                points.push(p3);
+
+                // distance of curve points to the polyline
+                let mut max_dev: f32 = 0.0;
+                for i in 0..=512 {
+                    let t = i as f32 / 512.0;
+                    let mt = 1.0 - t;
+                    let p = p0 * (mt * mt * mt)
+                        + p1 * (3.0 * mt * mt * t)
+                        + p2 * (3.0 * mt * t * t)
+                        + p3 * (t * t * t);
+                    let dist = points
+                        .windows(2)
+                        .map(|w| {
+                            let d = w[1] - w[0];
+                            let s = ((p - w[0]).dot(d) / d.length_squared()).clamp(0.0, 1.0);
+                            (p - (w[0] + d * s)).length()
+                        })
+                        .fold(f32::MAX, f32::min);
+                    max_dev = max_dev.max(dist);
+                }
+                let n = points.len() - 1;
+                assert!(
+                    max_dev <= 1.1 * TOLERANCE,
+                    "{:?} zoom {}: {} lines, max deviation {}",
+                    cubic,
+                    zoom,
+                    n,
+                    max_dev
+                );
+                counts.push(n);
+            }
+            check_counts(&counts);
+        }
+    }
+
+    #[test]
+    fn straight() {
+        let mut n = 0;
+        quad(
+            Point::new(0.0, 0.0),
+            Point::new(5.0, 5.0),
+            Point::new(100.0, 100.0),
+            TOLERANCE,
+            |_| n += 1,
+        );
+        assert_eq!(n, 0);
+
+        let line = euler_fit_cubic(
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(20.0, 0.0),
+            Point::new(1000.0, 0.0),
+        );
+        euler(line, TOLERANCE, |_| n += 1);
+        assert_eq!(n, 0);
+    }
+}
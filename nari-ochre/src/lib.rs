@@ -1,77 +1,229 @@
+pub mod clip;
 pub mod euler;
+mod flatten;
+pub mod stroke;
 
+use clip::{Clip, ClipEncoder};
 use std::ops::Range;
 use zeno::{PathBuilder, Point};
 
 pub const TILE_SIZE: usize = 8;
-const TOLERANCE: f32 = 0.1;
+
+/// Pixel and tile coordinate type.
+///
+/// 16-bit by default, the `wide` feature switches to 32-bit coordinates for
+/// paths which extend beyond ±32k pixels.
+#[cfg(not(feature = "wide"))]
+pub type Coord = i16;
+#[cfg(feature = "wide")]
+pub type Coord = i32;
+
+/// Largest absolute pixel coordinate accepted by the rasterizer.
+///
+/// Leaves headroom of two tiles to the coordinate type limits.
+#[cfg(not(feature = "wide"))]
+pub const COORD_LIMIT: f32 = (Coord::MAX as usize - 2 * TILE_SIZE) as f32;
+/// Largest absolute pixel coordinate accepted by the rasterizer.
+///
+/// Limited by the `f32` precision of the path coordinates (1/8 px at the
+/// limit).
+#[cfg(feature = "wide")]
+pub const COORD_LIMIT: f32 = (1 << 20) as f32;
+
+/// Default maximum distance in pixels between a curve and its flattened lines.
+pub const DEFAULT_TOLERANCE: f32 = 0.1;
+/// Smallest flattening tolerance, lower values are clamped.
+pub const MIN_TOLERANCE: f32 = 1e-3;
+
+/// Clamp the tolerance to `MIN_TOLERANCE..=f32::MAX`, NaN to `MIN_TOLERANCE`.
+pub(crate) fn clamp_tolerance(tolerance: f32) -> f32 {
+    if tolerance >= MIN_TOLERANCE {
+        tolerance.min(f32::MAX)
+    } else {
+        MIN_TOLERANCE
+    }
+}
+
+/// Rule deciding which regions of a path are inside.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside where the winding number is non-zero.
+    #[default]
+    NonZero,
+    /// Inside where the winding number is odd.
+    EvenOdd,
+}
+
+impl FillRule {
+    /// Coverage of the accumulated signed area.
+    fn coverage(self, area: f32) -> f32 {
+        match self {
+            FillRule::NonZero => area.abs().min(1.0),
+            FillRule::EvenOdd => {
+                let area = area.abs();
+                (area - 2.0 * (area * 0.5).round()).abs()
+            }
+        }
+    }
+
+    /// Whether a point with the winding number is inside.
+    pub fn is_inside(self, winding: isize) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// Handling of path coordinates which can't be rasterized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// Clamp coordinates to `±COORD_LIMIT`, commands with NaN coordinates are
+    /// skipped.
+    Clamp,
+    /// Reject the whole path, `end` reports the first invalid coordinate.
+    Error,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Path contains NaN or infinite coordinates.
+    NonFinite,
+    /// Path exceeds the supported coordinate range of `±COORD_LIMIT`.
+    OutOfRange,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NonFinite => write!(f, "path contains non-finite coordinates"),
+            Error::OutOfRange => write!(f, "path exceeds coordinate limit of ±{}", COORD_LIMIT),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
 
 #[derive(Clone, Copy, Debug)]
 struct Increment {
-    x: i16,
-    y: i16,
+    x: Coord,
+    y: Coord,
     area: f32,
     height: f32,
 }
 
 #[derive(Clone, Copy)]
 struct TileIncrement {
-    tile_x: i16,
-    tile_y: i16,
+    tile_x: Coord,
+    tile_y: Coord,
     sign: i8,
 }
 
 #[derive(Clone, Copy)]
 struct Bin {
-    tile_x: i16,
-    tile_y: i16,
+    tile_x: Coord,
+    tile_y: Coord,
     start: usize,
     end: usize,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct TileRange {
-    pub top: i16,
-    pub left: i16,
-    pub bottom: i16,
-    pub right: i16,
+    pub top: Coord,
+    pub left: Coord,
+    pub bottom: Coord,
+    pub right: Coord,
 }
 
 pub type Tile<T> = [[T; TILE_SIZE]; TILE_SIZE];
 
 pub trait Encoder {
-    fn solid(&mut self, y: i16, x: Range<i16>);
-    fn mask(&mut self, y: i16, x: i16, mask: &Tile<u8>);
+    fn solid(&mut self, y: Coord, x: Range<Coord>);
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>);
 }
 
-#[derive(Default)]
 pub struct Rasterizer {
+    tolerance: f32,
+    fill_rule: FillRule,
+    validation: Validation,
+    error: Option<Error>,
+
     start: Point,
     cur: Point,
 
-    row_prev: i16,
+    row_prev: Coord,
 
     increments: Vec<Increment>,
     tile_increments: Vec<TileIncrement>,
     bins: Vec<Bin>,
+
+    clips: Vec<Clip>,
+}
+
+impl Default for Rasterizer {
+    fn default() -> Self {
+        Rasterizer {
+            tolerance: DEFAULT_TOLERANCE,
+            fill_rule: FillRule::NonZero,
+            validation: Validation::Clamp,
+            error: None,
+            start: Point::default(),
+            cur: Point::default(),
+            row_prev: 0,
+            increments: Vec::new(),
+            tile_increments: Vec::new(),
+            bins: Vec::new(),
+            clips: Vec::new(),
+        }
+    }
 }
 
 impl Rasterizer {
+    /// Flattening tolerance in pixels.
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Set the maximum distance in pixels between curves and their
+    /// flattened lines, clamped to `MIN_TOLERANCE`.
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        self.tolerance = clamp_tolerance(tolerance);
+    }
+
+    /// Fill rule of the rasterized paths, defaults to `FillRule::NonZero`.
+    pub fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+    }
+
+    /// Handling of invalid coordinates, defaults to `Validation::Clamp`.
+    pub fn validation(&self) -> Validation {
+        self.validation
+    }
+
+    pub fn set_validation(&mut self, validation: Validation) {
+        self.validation = validation;
+    }
+
     pub fn begin(&mut self) {
         self.increments.clear();
         self.tile_increments.clear();
+        self.error = None;
     }
 
     pub fn range(&self) -> TileRange {
         let mut range = TileRange {
-            top: i16::MAX,
-            bottom: i16::MIN,
-            left: i16::MAX,
-            right: i16::MIN,
+            top: Coord::MAX,
+            bottom: Coord::MIN,
+            left: Coord::MAX,
+            right: Coord::MIN,
         };
         for increment in &self.increments {
-            let tx = increment.x.wrapping_div_euclid(TILE_SIZE as i16);
-            let ty = increment.y.wrapping_div_euclid(TILE_SIZE as i16);
+            let tx = increment.x.div_euclid(TILE_SIZE as Coord);
+            let ty = increment.y.div_euclid(TILE_SIZE as Coord);
 
             range.left = range.left.min(tx);
             range.right = range.right.max(tx + 1);
@@ -82,8 +234,74 @@ impl Rasterizer {
         range
     }
 
-    pub fn end(&mut self, encoder: &mut impl Encoder) {
-        self.close();
+    /// Finish the current path and rasterize it into the encoder.
+    ///
+    /// The output is intersected with the active clip, if any.
+    /// With `Validation::Error` nothing is emitted for invalid paths.
+    pub fn end(&mut self, encoder: &mut impl Encoder) -> Result<(), Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let clips = std::mem::take(&mut self.clips);
+        match clips.last() {
+            Some(clip) => self.rasterize(&mut ClipEncoder { clip, encoder }),
+            None => self.rasterize(encoder),
+        }
+        self.clips = clips;
+
+        Ok(())
+    }
+
+    /// Finish the current path and push it onto the clip stack.
+    ///
+    /// The new clip is intersected with the active clip, all following paths
+    /// are clipped against it until the matching `pop_clip`.
+    /// Invalid paths push an empty clip.
+    pub fn push_clip(&mut self) -> Result<(), Error> {
+        let mut clip = Clip::default();
+        let result = self.end(&mut clip);
+        self.clips.push(clip);
+        result
+    }
+
+    /// Remove the most recently pushed clip.
+    pub fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    /// Currently active clip.
+    pub fn clip(&self) -> Option<&Clip> {
+        self.clips.last()
+    }
+
+    /// Apply the validation policy to a path coordinate.
+    ///
+    /// Returns `None` if the command should be skipped.
+    fn check(&mut self, p: Point) -> Option<Point> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let error = if !p.x.is_finite() || !p.y.is_finite() {
+            Error::NonFinite
+        } else if p.x.abs() > COORD_LIMIT || p.y.abs() > COORD_LIMIT {
+            Error::OutOfRange
+        } else {
+            return Some(p);
+        };
+
+        match self.validation {
+            Validation::Error => {
+                self.error = Some(error);
+                None
+            }
+            Validation::Clamp => clamp(p),
+        }
+    }
+
+    fn rasterize(&mut self, encoder: &mut impl Encoder) {
+        self.close_path();
 
         self.bins.clear();
         let mut bin: Bin = Bin {
@@ -93,13 +311,13 @@ impl Rasterizer {
             end: 0,
         };
         if let Some(first) = self.increments.first() {
-            bin.tile_x = (first.x as i16).wrapping_div_euclid(TILE_SIZE as i16);
-            bin.tile_y = (first.y as i16).wrapping_div_euclid(TILE_SIZE as i16);
+            bin.tile_x = first.x.div_euclid(TILE_SIZE as Coord);
+            bin.tile_y = first.y.div_euclid(TILE_SIZE as Coord);
         }
 
         for (i, increment) in self.increments.iter().enumerate() {
-            let tile_x = increment.x.wrapping_div_euclid(TILE_SIZE as i16);
-            let tile_y = increment.y.wrapping_div_euclid(TILE_SIZE as i16);
+            let tile_x = increment.x.div_euclid(TILE_SIZE as Coord);
+            let tile_y = increment.y.div_euclid(TILE_SIZE as Coord);
             if tile_x != bin.tile_x || tile_y != bin.tile_y {
                 self.bins.push(bin);
                 bin = Bin {
@@ -123,16 +341,17 @@ impl Rasterizer {
         let mut prev = [0.0; TILE_SIZE];
         let mut next = [0.0; TILE_SIZE];
 
+        let fill_rule = self.fill_rule;
         let mut tile_increments_i = 0;
         let mut winding = 0;
 
         for i in 0..self.bins.len() {
             let bin = self.bins[i];
             for increment in &self.increments[bin.start..bin.end] {
-                let x = (increment.x as usize).wrapping_rem_euclid(TILE_SIZE);
-                let y = (increment.y as usize).wrapping_rem_euclid(TILE_SIZE);
-                areas[(y * TILE_SIZE + x) as usize] += increment.area;
-                heights[(y * TILE_SIZE + x) as usize] += increment.height;
+                let x = increment.x.rem_euclid(TILE_SIZE as Coord) as usize;
+                let y = increment.y.rem_euclid(TILE_SIZE as Coord) as usize;
+                areas[y * TILE_SIZE + x] += increment.area;
+                heights[y * TILE_SIZE + x] += increment.height;
             }
 
             if i + 1 == self.bins.len()
@@ -143,8 +362,8 @@ impl Rasterizer {
                 for y in 0..TILE_SIZE {
                     let mut accum = prev[y];
                     for x in 0..TILE_SIZE {
-                        tile[y][x] =
-                            ((accum + areas[y * TILE_SIZE + x]).abs() * 256.0).min(255.0) as u8;
+                        let coverage = fill_rule.coverage(accum + areas[y * TILE_SIZE + x]);
+                        tile[y][x] = (coverage * 256.0).min(255.0) as u8;
                         accum += heights[y * TILE_SIZE + x];
                     }
                     next[y] = accum;
@@ -174,7 +393,7 @@ impl Rasterizer {
                         winding += tile_increment.sign as isize;
                         tile_increments_i += 1;
                     }
-                    if winding != 0 {
+                    if fill_rule.is_inside(winding) {
                         let width = self.bins[i + 1].tile_x - bin.tile_x - 1;
                         let x0 = bin.tile_x + 1;
                         let x1 = x0 + width;
@@ -190,64 +409,73 @@ fn lerp(p0: Point, p1: Point, t: f32) -> Point {
     p0 * (1.0 - t) + p1 * t
 }
 
-impl PathBuilder for Rasterizer {
-    fn current_point(&self) -> Point {
-        self.start
+/// Clamp a point into the supported coordinate range, NaN points are rejected.
+fn clamp(p: Point) -> Option<Point> {
+    if p.x.is_nan() || p.y.is_nan() {
+        return None;
     }
+    Some(Point::new(
+        p.x.clamp(-COORD_LIMIT, COORD_LIMIT),
+        p.y.clamp(-COORD_LIMIT, COORD_LIMIT),
+    ))
+}
 
-    fn move_to(&mut self, to: impl Into<Point>) -> &mut Self {
-        let to: Point = to.into();
-
-        self.row_prev = (to.y.floor() as i16).wrapping_div_euclid(TILE_SIZE as i16);
-
-        self.start = to;
-        self.cur = to;
-        self
+impl Rasterizer {
+    fn close_path(&mut self) {
+        if self.start != self.cur {
+            self.line(self.start);
+        }
     }
 
-    fn line_to(&mut self, to: impl Into<Point>) -> &mut Self {
-        let to: Point = to.into();
+    /// Rasterize a line from the current point.
+    ///
+    /// Points generated by curve flattening may leave the coordinate range
+    /// of the (validated) control points and are clamped here.
+    fn line(&mut self, to: Point) {
+        let to = match clamp(to) {
+            Some(to) => to,
+            None => return,
+        };
 
         if self.cur == to {
-            return self;
+            return;
         }
 
-        let x_dir = (to.x - self.cur.x).signum() as i16;
-        let y_dir = (to.y - self.cur.y).signum() as i16;
-        let dtdx = 1.0 / (to.x - self.cur.x);
-        let dtdy = 1.0 / (to.y - self.cur.y);
-        let mut x = self.cur.x.floor() as i16;
-        let mut y = self.cur.y.floor() as i16;
-        let mut row_t0: f32 = 0.0;
-        let mut col_t0: f32 = 0.0;
-        let mut row_t1 = if self.cur.y == to.y {
-            std::f32::INFINITY
-        } else {
-            let next_y = if to.y > self.cur.y {
-                (y + 1) as f32
-            } else {
-                y as f32
-            };
-            (dtdy * (next_y - self.cur.y)).min(1.0)
+        let cur = self.cur;
+        let x_dir = (to.x - cur.x).signum() as Coord;
+        let y_dir = (to.y - cur.y).signum() as Coord;
+        let dtdx = 1.0 / (to.x - cur.x);
+        let dtdy = 1.0 / (to.y - cur.y);
+
+        // Parameter of the next row/column boundary crossing. Computed from the
+        // start point instead of accumulating steps, which drifts for long lines.
+        let row_t = |y: Coord| -> f32 {
+            if cur.y == to.y {
+                return f32::INFINITY;
+            }
+            let next_y = if y_dir > 0 { y + 1 } else { y };
+            (dtdy * (next_y as f32 - cur.y)).min(1.0)
         };
-        let mut col_t1 = if self.cur.x == to.x {
-            std::f32::INFINITY
-        } else {
-            let next_x = if to.x > self.cur.x {
-                (x + 1) as f32
-            } else {
-                x as f32
-            };
-            (dtdx * (next_x - self.cur.x)).min(1.0)
+        let col_t = |x: Coord| -> f32 {
+            if cur.x == to.x {
+                return f32::INFINITY;
+            }
+            let next_x = if x_dir > 0 { x + 1 } else { x };
+            (dtdx * (next_x as f32 - cur.x)).min(1.0)
         };
-        let x_step = dtdx.abs();
-        let y_step = dtdy.abs();
+
+        let mut x = cur.x.floor() as Coord;
+        let mut y = cur.y.floor() as Coord;
+        let mut row_t0: f32 = 0.0;
+        let mut col_t0: f32 = 0.0;
+        let mut row_t1 = row_t(y);
+        let mut col_t1 = col_t(x);
 
         loop {
             let t0 = if row_t0 > col_t0 { row_t0 } else { col_t0 };
             let t1 = if row_t1 < col_t1 { row_t1 } else { col_t1 };
-            let p0 = lerp(self.cur, to, t0);
-            let p1 = lerp(self.cur, to, t1);
+            let p0 = lerp(cur, to, t0);
+            let p1 = lerp(cur, to, t1);
             let height = p1.y - p0.y;
             let right = (x + 1) as f32;
             let area = 0.5 * height * ((right - p0.x) + (right - p1.x));
@@ -256,29 +484,23 @@ impl PathBuilder for Rasterizer {
 
             if row_t1 < col_t1 {
                 row_t0 = row_t1;
-                row_t1 = row_t1 + y_step;
-                if row_t1 > 1.0 {
-                    row_t1 = 1.0;
-                }
                 y += y_dir;
+                row_t1 = row_t(y);
             } else {
                 col_t0 = col_t1;
-                col_t1 = col_t1 + x_step;
-                if col_t1 > 1.0 {
-                    col_t1 = 1.0;
-                }
                 x += x_dir;
+                col_t1 = col_t(x);
             }
 
             if row_t0 == 1.0 || col_t0 == 1.0 {
-                x = to.x.floor() as i16;
-                y = to.y.floor() as i16;
+                x = to.x.floor() as Coord;
+                y = to.y.floor() as Coord;
             }
 
-            let tile_y = y.wrapping_div_euclid(TILE_SIZE as i16);
+            let tile_y = y.div_euclid(TILE_SIZE as Coord);
             if tile_y != self.row_prev {
                 self.tile_increments.push(TileIncrement {
-                    tile_x: x.wrapping_div_euclid(TILE_SIZE as i16),
+                    tile_x: x.div_euclid(TILE_SIZE as Coord),
                     tile_y: self.row_prev.min(tile_y),
                     sign: (tile_y - self.row_prev) as i8,
                 });
@@ -291,27 +513,43 @@ impl PathBuilder for Rasterizer {
         }
 
         self.cur = to;
-        self
     }
+}
 
-    fn quad_to(&mut self, control1: impl Into<Point>, to: impl Into<Point>) -> &mut Self {
-        let p0 = self.cur;
-        let p1: Point = to.into();
-        let control1: Point = control1.into();
+impl PathBuilder for Rasterizer {
+    fn current_point(&self) -> Point {
+        self.cur
+    }
 
-        let dt = ((4.0 * TOLERANCE) / (p0 - control1 * 2.0 + p1).length()).sqrt();
+    fn move_to(&mut self, to: impl Into<Point>) -> &mut Self {
+        let to = match self.check(to.into()) {
+            Some(to) => to,
+            None => return self,
+        };
 
-        let mut t = 0.0;
-        while t < 1.0 {
-            t = (t + dt).min(1.0);
+        self.row_prev = (to.y.floor() as Coord).div_euclid(TILE_SIZE as Coord);
 
-            let p01 = lerp(p0, control1, t);
-            let p12 = lerp(control1, p1, t);
-            let p = lerp(p01, p12, t);
+        self.start = to;
+        self.cur = to;
+        self
+    }
 
-            self.line_to(p);
+    fn line_to(&mut self, to: impl Into<Point>) -> &mut Self {
+        if let Some(to) = self.check(to.into()) {
+            self.line(to);
         }
+        self
+    }
+
+    fn quad_to(&mut self, control1: impl Into<Point>, to: impl Into<Point>) -> &mut Self {
+        let p0 = self.cur;
+        let (control1, p1) = match (self.check(control1.into()), self.check(to.into())) {
+            (Some(control1), Some(p1)) => (control1, p1),
+            _ => return self,
+        };
 
+        flatten::quad(p0, control1, p1, self.tolerance, |p| self.line(p));
+        self.line(p1);
         self
     }
 
@@ -322,43 +560,399 @@ impl PathBuilder for Rasterizer {
         to: impl Into<Point>,
     ) -> &mut Self {
         let p0 = self.cur;
-        let p1: Point = to.into();
-        let control1: Point = control1.into();
-        let control2: Point = control2.into();
-
-        // let a = p0 * -1.0 + control1 * 3.0 - control2 * 3.0 + p1;
-        // let b = (p0 - control1 * 2.0 + control2) * 3.0;
-        // let conc = b.length().max((a + b).length());
-        // let dt = ((8.0f32.sqrt() * TOLERANCE) / conc).sqrt();
-
-        // let mut t = 0.0;
-        // while t < 1.0 {
-        //     t = (t + dt).min(1.0);
-        //     let p01 = lerp(p0, control1, t);
-        //     let p12 = lerp(control1, control2, t);
-        //     let p23 = lerp(control2, p1, t);
-        //     let p012 = lerp(p01, p12, t);
-        //     let p123 = lerp(p12, p23, t);
-        //     let p = lerp(p012, p123, t);
-
-        //     self.line_to(p);
-        // }
-
-        let euler = euler::euler_fit_cubic(p0, control1, control2, p1);
-        let dt = 1.0 / euler.scale;
-        let mut t = 0.0;
-        while t < 1.0 {
-            t = (t + dt).min(1.0);
-            self.line_to(euler::euler_eval(euler, 0.0, t));
-        }
+        let (control1, control2, p1) = match (
+            self.check(control1.into()),
+            self.check(control2.into()),
+            self.check(to.into()),
+        ) {
+            (Some(control1), Some(control2), Some(p1)) => (control1, control2, p1),
+            _ => return self,
+        };
 
+        flatten::cubic(p0, control1, control2, p1, self.tolerance, |p| self.line(p));
+        self.line(p1);
         self
     }
 
     fn close(&mut self) -> &mut Self {
-        if self.start != self.cur {
-            self.line_to(self.start);
-        }
+        self.close_path();
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xorshift generator, keeps the property tests deterministic.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, max: usize) -> usize {
+            (self.next() % max as u64) as usize
+        }
+
+        fn coord(&mut self, extent: f32) -> f32 {
+            (self.next() >> 40) as f32 / (1u64 << 24) as f32 * 2.0 * extent - extent
+        }
+    }
+
+    /// Checks the encoder output and sums up the covered area.
+    #[derive(Default)]
+    struct Checker {
+        area: f64,
+    }
+
+    impl Encoder for Checker {
+        fn solid(&mut self, y: Coord, x: Range<Coord>) {
+            assert!(x.start < x.end);
+            check_tile(y, x.start);
+            check_tile(y, x.end - 1);
+            self.area += (x.end - x.start) as f64 * (TILE_SIZE * TILE_SIZE) as f64;
+        }
+
+        fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+            check_tile(y, x);
+            for row in mask {
+                for coverage in row {
+                    self.area += *coverage as f64 / 255.0;
+                }
+            }
+        }
+    }
+
+    fn check_tile(y: Coord, x: Coord) {
+        let limit = (COORD_LIMIT as usize / TILE_SIZE + 1) as Coord;
+        assert!((-limit..=limit).contains(&x), "tile x {}", x);
+        assert!((-limit..=limit).contains(&y), "tile y {}", y);
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    enum Cmd {
+        Move(Point),
+        Line(Point),
+        Quad(Point, Point),
+        Curve(Point, Point, Point),
+        Close,
+    }
+
+    fn random_path(rng: &mut Rng, mut point: impl FnMut(&mut Rng) -> Point) -> Vec<Cmd> {
+        let mut path = vec![Cmd::Move(point(rng))];
+        for _ in 0..rng.range(8) {
+            path.push(match rng.range(5) {
+                0 => Cmd::Move(point(rng)),
+                1 => Cmd::Line(point(rng)),
+                2 => Cmd::Quad(point(rng), point(rng)),
+                3 => Cmd::Curve(point(rng), point(rng), point(rng)),
+                _ => Cmd::Close,
+            });
+        }
+        path
+    }
+
+    fn rasterize(rasterizer: &mut Rasterizer, path: &[Cmd]) -> (Result<(), Error>, Checker) {
+        rasterizer.begin();
+        for cmd in path {
+            match *cmd {
+                Cmd::Move(p) => rasterizer.move_to(p),
+                Cmd::Line(p) => rasterizer.line_to(p),
+                Cmd::Quad(c, p) => rasterizer.quad_to(c, p),
+                Cmd::Curve(c0, c1, p) => rasterizer.curve_to(c0, c1, p),
+                Cmd::Close => rasterizer.close(),
+            };
+        }
+        let mut checker = Checker::default();
+        let result = rasterizer.end(&mut checker);
+        (result, checker)
+    }
+
+    #[test]
+    fn random_paths() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut rasterizer = Rasterizer::default();
+        for _ in 0..500 {
+            let path = random_path(&mut rng, |rng| {
+                Point::new(rng.coord(300.0), rng.coord(300.0))
+            });
+            let (result, _) = rasterize(&mut rasterizer, &path);
+            assert_eq!(result, Ok(()), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn triangle_area() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut rasterizer = Rasterizer::default();
+        for _ in 0..500 {
+            let p: Vec<Point> = (0..3)
+                .map(|_| Point::new(rng.coord(100.0), rng.coord(100.0)))
+                .collect();
+            let path = [
+                Cmd::Move(p[0]),
+                Cmd::Line(p[1]),
+                Cmd::Line(p[2]),
+                Cmd::Close,
+            ];
+            let (result, checker) = rasterize(&mut rasterizer, &path);
+            assert_eq!(result, Ok(()));
+
+            let area = 0.5 * (p[1] - p[0]).cross(p[2] - p[0]).abs() as f64;
+            let perimeter =
+                ((p[1] - p[0]).length() + (p[2] - p[1]).length() + (p[0] - p[2]).length()) as f64;
+            // coverage is truncated to 8 bit per pixel along the boundary
+            let bound = 0.01 * perimeter + 1.0;
+            assert!(
+                (checker.area - area).abs() <= bound,
+                "{:?}: {} != {}",
+                p,
+                checker.area,
+                area
+            );
+        }
+    }
+
+    /// Polygon with `n` vertices sorted by angle around `center`, which is
+    /// simple for any radii.
+    fn radial(rng: &mut Rng, center: Point, n: usize, radius: (f32, f32)) -> Vec<Point> {
+        (0..n)
+            .map(|i| {
+                let angle = (i as f32 + rng.coord(0.5) + 0.5) / n as f32 * std::f32::consts::TAU;
+                let r = radius.0 + (rng.coord(0.5) + 0.5) * (radius.1 - radius.0);
+                center + Point::new(angle.cos(), angle.sin()) * r
+            })
+            .collect()
+    }
+
+    fn polygon_area(polygon: &[Point]) -> f32 {
+        let next = polygon.iter().cycle().skip(1);
+        0.5 * polygon
+            .iter()
+            .zip(next)
+            .map(|(p0, p1)| p0.cross(*p1))
+            .sum::<f32>()
+    }
+
+    #[test]
+    fn hole_area() {
+        let mut rng = Rng(0x5851_f42d_4c95_7f2d);
+        let mut rasterizer = Rasterizer::default();
+        for i in 0..500 {
+            // outer contours with at least 6 vertices keep the hole inside
+            let n = 6 + rng.range(7);
+            let mut outer = radial(&mut rng, Point::new(0.0, 0.0), n, (16.0, 30.0));
+            let n = 3 + rng.range(7);
+            let mut hole = radial(&mut rng, Point::new(0.0, 0.0), n, (2.0, 7.0));
+            if rng.range(2) == 0 {
+                outer.reverse();
+            }
+            if rng.range(2) == 0 {
+                hole.reverse();
+            }
+            let (outer_area, hole_area) = (polygon_area(&outer), polygon_area(&hole));
+
+            let mut path = Vec::new();
+            for contour in [&outer, &hole] {
+                path.push(Cmd::Move(contour[0]));
+                path.extend(contour[1..].iter().map(|p| Cmd::Line(*p)));
+                path.push(Cmd::Close);
+            }
+
+            let rule = if i % 2 == 0 {
+                FillRule::NonZero
+            } else {
+                FillRule::EvenOdd
+            };
+            rasterizer.set_fill_rule(rule);
+            let (result, checker) = rasterize(&mut rasterizer, &path);
+            assert_eq!(result, Ok(()));
+
+            let same_orientation = (outer_area > 0.0) == (hole_area > 0.0);
+            let area = if rule == FillRule::NonZero && same_orientation {
+                outer_area.abs()
+            } else {
+                outer_area.abs() - hole_area.abs()
+            } as f64;
+            let perimeter = [&outer, &hole]
+                .iter()
+                .flat_map(|contour| {
+                    let next = contour.iter().cycle().skip(1);
+                    contour
+                        .iter()
+                        .zip(next)
+                        .map(|(p0, p1)| (*p1 - *p0).length())
+                })
+                .sum::<f32>() as f64;
+            // coverage is truncated to 8 bit per pixel along the boundary
+            let bound = 0.01 * perimeter + 1.0;
+            assert!(
+                (checker.area - area).abs() <= bound,
+                "{:?}: {} != {}",
+                path,
+                checker.area,
+                area
+            );
+        }
+    }
+
+    const EXTREME: [f32; 12] = [
+        f32::NAN,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::MAX,
+        f32::MIN,
+        1.0e30,
+        -1.0e30,
+        2.0 * COORD_LIMIT,
+        COORD_LIMIT,
+        -COORD_LIMIT,
+        1.0e-30,
+        0.0,
+    ];
+
+    // lines across the full coordinate range are expensive in wide mode
+    const EXTREME_CASES: usize = if cfg!(feature = "wide") { 16 } else { 64 };
+
+    fn extreme_path(rng: &mut Rng) -> Vec<Cmd> {
+        let mut extremes = 2;
+        random_path(rng, |rng| {
+            let mut coord = |rng: &mut Rng| {
+                if extremes > 0 && rng.range(4) == 0 {
+                    extremes -= 1;
+                    EXTREME[rng.range(EXTREME.len())]
+                } else {
+                    rng.coord(64.0)
+                }
+            };
+            let x = coord(rng);
+            let y = coord(rng);
+            Point::new(x, y)
+        })
+    }
+
+    fn is_valid(path: &[Cmd]) -> bool {
+        let valid = |p: &Point| p.x.abs() <= COORD_LIMIT && p.y.abs() <= COORD_LIMIT;
+        path.iter().all(|cmd| match cmd {
+            Cmd::Move(p) | Cmd::Line(p) => valid(p),
+            Cmd::Quad(c, p) => valid(c) && valid(p),
+            Cmd::Curve(c0, c1, p) => valid(c0) && valid(c1) && valid(p),
+            Cmd::Close => true,
+        })
+    }
+
+    #[test]
+    fn extreme_clamp() {
+        let mut rng = Rng(0xdead_beef_cafe_f00d);
+        let mut rasterizer = Rasterizer::default();
+        for _ in 0..EXTREME_CASES {
+            let path = extreme_path(&mut rng);
+            let (result, _) = rasterize(&mut rasterizer, &path);
+            assert_eq!(result, Ok(()), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn extreme_error() {
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        let mut rasterizer = Rasterizer::default();
+        rasterizer.set_validation(Validation::Error);
+        for _ in 0..EXTREME_CASES {
+            let path = extreme_path(&mut rng);
+            let (result, checker) = rasterize(&mut rasterizer, &path);
+            assert_eq!(result.is_ok(), is_valid(&path), "{:?}", path);
+            if result.is_err() {
+                assert_eq!(checker.area, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_tolerance() {
+        let mut rasterizer = Rasterizer::default();
+        for tolerance in [0.0, -1.0, f32::NAN] {
+            rasterizer.set_tolerance(tolerance);
+            assert_eq!(rasterizer.tolerance(), MIN_TOLERANCE);
+        }
+        rasterizer.set_tolerance(f32::INFINITY);
+        assert!(rasterizer.tolerance().is_finite());
+
+        // segment counts are bounded, flattening terminates
+        rasterizer.set_tolerance(0.0);
+        let mut checker = Checker::default();
+        rasterizer.begin();
+        rasterizer.add_circle([0.0, 0.0], 1000.0);
+        rasterizer.move_to([3000.0, 0.0]);
+        rasterizer.quad_to([5000.0, 0.0], [5000.0, 2000.0]);
+        rasterizer.close();
+        rasterizer.end(&mut checker).unwrap();
+        // circle and 2/3 of the control triangle
+        let area = std::f64::consts::PI * 1000.0 * 1000.0 + 2000.0 * 2000.0 / 3.0;
+        assert!(
+            (checker.area - area).abs() < 1e-3 * area,
+            "{} != {}",
+            checker.area,
+            area
+        );
+
+        let style = zeno::Stroke::new(10.0);
+        let mut path = Vec::new();
+        path.add_circle([0.0, 0.0], 100.0);
+        let mut outline = Vec::<zeno::Command>::new();
+        stroke::stroke(&path[..], &style, 0.0, &mut outline);
+        assert!(!outline.is_empty());
+    }
+
+    #[test]
+    fn even_odd() {
+        let mut rasterizer = Rasterizer::default();
+        let mut area = |fill_rule| {
+            rasterizer.set_fill_rule(fill_rule);
+            rasterizer.begin();
+            rasterizer.add_rect([0.0, 0.0], 64.0, 64.0);
+            rasterizer.add_rect([16.5, 16.5], 31.0, 31.0);
+            let mut checker = Checker::default();
+            rasterizer.end(&mut checker).unwrap();
+            checker.area
+        };
+        assert!((area(FillRule::NonZero) - 4096.0).abs() < 1.0);
+        // inner square with winding 2 is a hole, including its solid spans
+        assert!((area(FillRule::EvenOdd) - (4096.0 - 961.0)).abs() < 1.0);
+    }
+
+    #[test]
+    fn degenerate() {
+        let p = Point::new(10.5, 10.5);
+        let q = Point::new(10.5, 20.5);
+        let paths = [
+            vec![Cmd::Move(p), Cmd::Line(p), Cmd::Close],
+            vec![
+                Cmd::Move(p),
+                Cmd::Quad(p, p),
+                Cmd::Curve(p, p, p),
+                Cmd::Close,
+            ],
+            vec![Cmd::Move(p), Cmd::Curve(q, q, p), Cmd::Close],
+            vec![Cmd::Move(p), Cmd::Curve(q, p, q), Cmd::Close],
+            vec![Cmd::Move(p), Cmd::Quad(q, p), Cmd::Close],
+            vec![
+                Cmd::Move(p),
+                Cmd::Line(Point::new(10.5 + 1.0e-6, 10.5)),
+                Cmd::Line(Point::new(10.5, 10.5 + 1.0e-6)),
+                Cmd::Close,
+            ],
+        ];
+
+        let mut rasterizer = Rasterizer::default();
+        for path in &paths {
+            let (result, checker) = rasterize(&mut rasterizer, path);
+            assert_eq!(result, Ok(()), "{:?}", path);
+            assert!(checker.area < 1.0, "{:?}: {}", path, checker.area);
+        }
+    }
+}
@@ -0,0 +1,660 @@
+//! Stroke outlines.
+//!
+//! Path segments are converted to euler spirals (within the flattening
+//! tolerance), which are offset analytically. Each segment emits a closed
+//! outline of its two offset curves, joins and caps are emitted as separate
+//! closed contours. All contours share the same orientation, so the output
+//! is meant to be filled with the nonzero rule, e.g. by passing the
+//! `Rasterizer` as sink.
+//!
+//! Where the stroke is wider than the radius of curvature the inner offset
+//! curve folds over. Instead of the folded offset curve the outline follows
+//! the evolute (the centers of curvature) around the fold, which keeps the
+//! winding number of the covered area positive.
+
+use crate::{
+    clamp_tolerance,
+    euler::{
+        cubic_to_euler, euler_angle, euler_curvature_radius, euler_eval, euler_inverse,
+        euler_normal, euler_segment, EulerSegment,
+    },
+    flatten,
+};
+use std::f64::consts::PI;
+use zeno::{Cap, Command, Join, PathBuilder, PathData, Point, Stroke};
+
+/// Stroke `path` with the given `style`, emitting the outline into `sink`.
+///
+/// Curves and arcs are flattened with `tolerance` pixels, clamped to
+/// `MIN_TOLERANCE`. The `scale` field of the style is ignored, the path is
+/// expected in device coordinates.
+pub fn stroke(path: impl PathData, style: &Stroke, tolerance: f32, sink: &mut impl PathBuilder) {
+    if style.width.is_nan() || style.width <= 0.0 {
+        return;
+    }
+
+    let tolerance = clamp_tolerance(tolerance);
+    let stroker = Stroker {
+        style,
+        offset: 0.5 * style.width as f64,
+        tolerance,
+    };
+
+    let dashes = dash_pattern(style.dashes);
+    for subpath in subpaths(path, tolerance) {
+        match &dashes {
+            Some(dashes) => {
+                for dash in stroker.dash(&subpath, dashes) {
+                    stroker.subpath(&dash, sink);
+                }
+            }
+            None => stroker.subpath(&subpath, sink),
+        }
+    }
+}
+
+/// Connected sequence of path segments.
+#[derive(Clone, Debug)]
+struct Subpath {
+    segments: Vec<EulerSegment>,
+    closed: bool,
+    /// Start point and direction, used for zero-length subpaths.
+    start: (Point, f64),
+    /// Subpath consists only of zero-length segments and is drawn as a dot.
+    dot: bool,
+}
+
+impl Subpath {
+    fn new(p: Point) -> Self {
+        Subpath {
+            segments: Vec::new(),
+            closed: false,
+            start: (p, 0.0),
+            dot: false,
+        }
+    }
+
+    fn line(&mut self, p0: Point, p1: Point) {
+        let d = p1 - p0;
+        let len = d.length() as f64;
+        if len > 0.0 && len.is_finite() {
+            self.segments.push(EulerSegment {
+                p: p0,
+                scale: len,
+                k: [(d.y as f64).atan2(d.x as f64), 0.0, 0.0],
+            });
+        } else {
+            self.dot = true;
+        }
+    }
+
+    fn cubic(&mut self, cubic: [Point; 4], tolerance: f32) {
+        if cubic[0] == cubic[1] && cubic[2] == cubic[3] {
+            self.line(cubic[0], cubic[3]);
+            return;
+        }
+
+        let n = self.segments.len();
+        cubic_to_euler(cubic, tolerance as f64, |segment| {
+            self.segments.push(segment)
+        });
+        if self.segments.len() == n {
+            self.dot = true;
+        }
+    }
+}
+
+/// Split path data into subpaths of euler spiral segments.
+fn subpaths(path: impl PathData, tolerance: f32) -> Vec<Subpath> {
+    let mut subpaths = Vec::new();
+    let mut subpath: Option<Subpath> = None;
+    let mut start = Point::ZERO;
+    let mut cur = Point::ZERO;
+
+    for cmd in path.commands() {
+        match cmd {
+            Command::MoveTo(p) => {
+                subpaths.extend(subpath.take());
+                subpath = Some(Subpath::new(p));
+                start = p;
+                cur = p;
+            }
+            Command::LineTo(p) => {
+                subpath
+                    .get_or_insert_with(|| Subpath::new(cur))
+                    .line(cur, p);
+                cur = p;
+            }
+            Command::QuadTo(p1, p2) => {
+                // degree elevation
+                let c1 = cur + (p1 - cur) * (2.0 / 3.0);
+                let c2 = p2 + (p1 - p2) * (2.0 / 3.0);
+                subpath
+                    .get_or_insert_with(|| Subpath::new(cur))
+                    .cubic([cur, c1, c2, p2], tolerance);
+                cur = p2;
+            }
+            Command::CurveTo(p1, p2, p3) => {
+                subpath
+                    .get_or_insert_with(|| Subpath::new(cur))
+                    .cubic([cur, p1, p2, p3], tolerance);
+                cur = p3;
+            }
+            Command::Close => {
+                let mut closed = subpath.take().unwrap_or_else(|| Subpath::new(cur));
+                if cur != start {
+                    closed.line(cur, start);
+                }
+                closed.closed = true;
+                closed.dot |= closed.segments.is_empty();
+                subpaths.push(closed);
+                cur = start;
+            }
+        }
+    }
+    subpaths.extend(subpath);
+
+    for subpath in &mut subpaths {
+        if let Some(first) = subpath.segments.first() {
+            subpath.start = (first.p, euler_angle(*first, 0.0));
+        }
+    }
+
+    subpaths
+}
+
+/// Normalized dash pattern, `None` if the path should be drawn solid.
+fn dash_pattern(dashes: &[f32]) -> Option<Vec<f64>> {
+    let valid = dashes.iter().all(|d| *d >= 0.0 && d.is_finite());
+    let total: f32 = dashes.iter().sum();
+    if dashes.is_empty() || !valid || total <= 0.0 {
+        return None;
+    }
+
+    // odd patterns are repeated to get alternating on/off pairs
+    let n = if dashes.len() % 2 == 1 { 2 } else { 1 };
+    Some(
+        dashes
+            .iter()
+            .cycle()
+            .take(n * dashes.len())
+            .map(|d| *d as f64)
+            .collect(),
+    )
+}
+
+struct Stroker<'a, 'b> {
+    style: &'a Stroke<'b>,
+    /// Half of the stroke width.
+    offset: f64,
+    tolerance: f32,
+}
+
+impl Stroker<'_, '_> {
+    /// Split a subpath into the open subpaths of the dash pattern.
+    fn dash(&self, subpath: &Subpath, dashes: &[f64]) -> Vec<Subpath> {
+        let total: f64 = dashes.iter().sum();
+
+        // locate the start of the pattern
+        let mut phase = (self.style.offset as f64).rem_euclid(total);
+        // zero-length entries at the start are kept as dots
+        let mut i = 0;
+        for _ in 0..dashes.len() {
+            if phase < dashes[i] || (phase == 0.0 && dashes[i] == 0.0) {
+                break;
+            }
+            phase -= dashes[i];
+            i = (i + 1) % dashes.len();
+        }
+        let mut remaining = (dashes[i] - phase).max(0.0);
+
+        let mut result = Vec::new();
+        let mut dash = Subpath::new(subpath.start.0);
+        dash.start = subpath.start;
+
+        for segment in &subpath.segments {
+            let len = segment.scale;
+            let mut s = 0.0;
+            loop {
+                let on = i % 2 == 0;
+                let step = remaining.min(len - s);
+                if on && step > 0.0 {
+                    let piece = euler_segment(*segment, s / len, (s + step) / len);
+                    if dash.segments.is_empty() {
+                        dash.start = (piece.p, euler_angle(piece, 0.0));
+                    }
+                    dash.segments.push(piece);
+                }
+                s += step;
+                remaining -= step;
+
+                if remaining > 0.0 {
+                    break;
+                }
+
+                // end of the current dash entry
+                if on {
+                    dash.dot = dash.segments.is_empty();
+                    if dash.dot {
+                        let t = s / len;
+                        dash.start = (euler_eval(*segment, 0.0, t), euler_angle(*segment, t));
+                    }
+                    result.push(std::mem::replace(&mut dash, Subpath::new(Point::ZERO)));
+                }
+                i = (i + 1) % dashes.len();
+                remaining = dashes[i];
+
+                if s >= len {
+                    break;
+                }
+            }
+        }
+
+        if !dash.segments.is_empty() {
+            result.push(dash);
+        }
+
+        result
+    }
+
+    fn subpath(&self, subpath: &Subpath, sink: &mut impl PathBuilder) {
+        let segments = &subpath.segments;
+        if segments.is_empty() {
+            if subpath.dot {
+                let (p, angle) = subpath.start;
+                self.cap(p, angle + PI, self.style.start_cap, sink);
+                self.cap(p, angle, self.style.end_cap, sink);
+            }
+            return;
+        }
+
+        for segment in segments {
+            self.segment(*segment, sink);
+        }
+        for pair in segments.windows(2) {
+            self.join(pair[0], pair[1], sink);
+        }
+
+        let first = segments[0];
+        let last = segments[segments.len() - 1];
+        if subpath.closed {
+            self.join(last, first, sink);
+        } else {
+            self.cap(
+                first.p,
+                euler_angle(first, 0.0) + PI,
+                self.style.start_cap,
+                sink,
+            );
+            self.cap(
+                euler_eval(last, 0.0, 1.0),
+                euler_angle(last, 1.0),
+                self.style.end_cap,
+                sink,
+            );
+        }
+    }
+
+    /// Outline of a single segment: positive side forward, negative side
+    /// backward.
+    fn segment(&self, euler: EulerSegment, sink: &mut impl PathBuilder) {
+        let mut inverse = euler_inverse(euler);
+        inverse.p = euler_eval(euler, 0.0, 1.0);
+
+        sink.move_to(euler.p);
+        self.side(euler, inverse, sink);
+        // the positive side of the inverse is the negative side of the segment
+        self.side(inverse, euler, sink);
+        sink.close();
+    }
+
+    /// Positive offset curve of `euler` with the evolute around folds.
+    ///
+    /// `inverse` is the same spiral traversed backwards.
+    fn side(&self, euler: EulerSegment, inverse: EulerSegment, sink: &mut impl PathBuilder) {
+        let offset = self.offset;
+        let winding = |t: f64| (euler.k[1] + euler.k[2] * t) * offset / euler.scale;
+
+        // curvature is linear, the fold starts or ends at most once
+        let c0 = (1.0 - winding(0.0)) / (euler.k[2] * offset / euler.scale);
+        let c0 = if c0.is_nan() { 1.0 } else { c0.clamp(0.0, 1.0) };
+
+        if c0 > 0.0 {
+            if winding(0.0) < 1.0 {
+                sink.line_to(self.offset_point(euler, 0.0, offset));
+                self.offset_to(euler_segment(euler, 0.0, c0), Some(offset), sink);
+            } else {
+                // evolute forward, offset backward, evolute forward
+                let evolute = euler_segment(euler, 0.0, c0);
+                sink.line_to(self.offset_point(euler, 0.0, euler_curvature_radius(euler, 0.0)));
+                self.offset_to(evolute, None, sink);
+                sink.line_to(self.offset_point(euler, c0, offset));
+                self.offset_to(euler_segment(inverse, 1.0 - c0, 1.0), Some(-offset), sink);
+                self.offset_to(evolute, None, sink);
+            }
+        }
+        if c0 < 1.0 {
+            if winding(1.0) < 1.0 {
+                sink.line_to(self.offset_point(euler, c0, offset));
+                self.offset_to(euler_segment(euler, c0, 1.0), Some(offset), sink);
+            } else {
+                let evolute = euler_segment(euler, c0, 1.0);
+                sink.line_to(self.offset_point(euler, c0, euler_curvature_radius(euler, c0)));
+                self.offset_to(evolute, None, sink);
+                sink.line_to(self.offset_point(euler, 1.0, offset));
+                self.offset_to(euler_segment(inverse, 0.0, 1.0 - c0), Some(-offset), sink);
+                self.offset_to(evolute, None, sink);
+            }
+        }
+    }
+
+    fn offset_point(&self, euler: EulerSegment, t: f64, offset: f64) -> Point {
+        euler_eval(euler, 0.0, t) + euler_normal(euler, t, offset)
+    }
+
+    /// Flattened offset curve of `euler`, the evolute if `offset` is `None`.
+    fn offset_to(&self, euler: EulerSegment, offset: Option<f64>, sink: &mut impl PathBuilder) {
+        let point = |t: f64| {
+            let offset = offset.unwrap_or_else(|| euler_curvature_radius(euler, t));
+            self.offset_point(euler, t, offset)
+        };
+        flatten::euler_params(euler, self.offset, self.tolerance, |t| {
+            sink.line_to(point(t));
+        });
+        sink.line_to(point(1.0));
+    }
+
+    /// Join on the outer side of the corner between two segments.
+    fn join(&self, prev: EulerSegment, next: EulerSegment, sink: &mut impl PathBuilder) {
+        let a0 = euler_angle(prev, 1.0);
+        let a1 = euler_angle(next, 0.0);
+        let turn = (a1 - a0 + PI).rem_euclid(2.0 * PI) - PI;
+        if turn.abs() < 1e-6 {
+            return;
+        }
+
+        let p = next.p;
+        let side = -turn.signum() * self.offset;
+        let n0 = euler_normal(prev, 1.0, side);
+        let n1 = euler_normal(next, 0.0, side);
+
+        let mut polygon = vec![p, p + n0];
+        match self.style.join {
+            Join::Bevel => (),
+            Join::Miter => {
+                // ratio of miter length and stroke width
+                let ratio = 1.0 / (0.5 * turn).cos();
+                if ratio <= self.style.miter_limit as f64 {
+                    polygon.push(p + (n0 + n1) * (1.0 / (1.0 + turn.cos())) as f32);
+                }
+            }
+            Join::Round => {
+                let angle = a0 + side.signum() * 0.5 * PI;
+                self.arc(p, angle, turn, &mut polygon);
+            }
+        }
+        polygon.push(p + n1);
+
+        self.polygon(&polygon, sink);
+    }
+
+    /// Cap at `p`, `angle` points away from the stroke.
+    fn cap(&self, p: Point, angle: f64, cap: Cap, sink: &mut impl PathBuilder) {
+        let (sin, cos) = angle.sin_cos();
+        let d = Point::new((self.offset * cos) as f32, (self.offset * sin) as f32);
+        let n = Point::new(-d.y, d.x);
+
+        let mut polygon = vec![p + n];
+        match cap {
+            Cap::Butt => return,
+            Cap::Square => {
+                polygon.push(p + n + d);
+                polygon.push(p - n + d);
+            }
+            Cap::Round => self.arc(p, angle + 0.5 * PI, -PI, &mut polygon),
+        }
+        polygon.push(p - n);
+
+        self.polygon(&polygon, sink);
+    }
+
+    /// Interior points of an arc around `center` with the stroke radius.
+    fn arc(&self, center: Point, angle: f64, sweep: f64, polygon: &mut Vec<Point>) {
+        let tolerance = self.tolerance as f64;
+        let step = if self.offset > 0.5 * tolerance {
+            2.0 * (1.0 - tolerance / self.offset).acos()
+        } else {
+            PI
+        };
+        let n = (sweep.abs() / step)
+            .ceil()
+            .clamp(1.0, flatten::MAX_SEGMENTS as f64) as usize;
+        for i in 1..n {
+            let (sin, cos) = (angle + sweep * i as f64 / n as f64).sin_cos();
+            polygon
+                .push(center + Point::new((self.offset * cos) as f32, (self.offset * sin) as f32));
+        }
+    }
+
+    /// Emit a closed polygon with the orientation of the segment outlines.
+    fn polygon(&self, polygon: &[Point], sink: &mut impl PathBuilder) {
+        let area: f32 = polygon
+            .iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .map(|(p0, p1)| p0.cross(*p1))
+            .sum();
+
+        let mut points: Box<dyn Iterator<Item = &Point>> = if area > 0.0 {
+            Box::new(polygon.iter().rev())
+        } else {
+            Box::new(polygon.iter())
+        };
+        if let Some(p) = points.next() {
+            sink.move_to(*p);
+        }
+        for p in points {
+            sink.line_to(*p);
+        }
+        sink.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coord, Encoder, Rasterizer, Tile, DEFAULT_TOLERANCE, TILE_SIZE};
+    use std::ops::Range;
+
+    /// Total coverage of the rasterized outline.
+    #[derive(Default)]
+    struct Area(f64);
+
+    impl Encoder for Area {
+        fn solid(&mut self, _y: Coord, x: Range<Coord>) {
+            self.0 += ((x.end - x.start) as usize * TILE_SIZE * TILE_SIZE) as f64;
+        }
+
+        fn mask(&mut self, _y: Coord, _x: Coord, mask: &Tile<u8>) {
+            for row in mask {
+                for v in row {
+                    self.0 += *v as f64 / 255.0;
+                }
+            }
+        }
+    }
+
+    fn area(path: &[Command], style: &Stroke) -> f64 {
+        let mut rasterizer = Rasterizer::default();
+        rasterizer.begin();
+        stroke(path, style, DEFAULT_TOLERANCE, &mut rasterizer);
+        let mut area = Area::default();
+        rasterizer.end(&mut area).unwrap();
+        area.0
+    }
+
+    fn style(width: f32, join: Join, cap: Cap) -> Stroke<'static> {
+        Stroke {
+            width,
+            join,
+            miter_limit: 4.0,
+            start_cap: cap,
+            end_cap: cap,
+            ..Stroke::default()
+        }
+    }
+
+    fn assert_area(area: f64, expected: f64) {
+        assert!(
+            (area - expected).abs() <= 0.01 * expected + 2.0,
+            "area {}, expected {}",
+            area,
+            expected
+        );
+    }
+
+    const P: fn(f32, f32) -> Point = Point::new;
+
+    #[test]
+    fn line_caps() {
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+        ];
+        assert_area(area(&path, &style(10.0, Join::Miter, Cap::Butt)), 1000.0);
+        assert_area(area(&path, &style(10.0, Join::Miter, Cap::Square)), 1100.0);
+        assert_area(
+            area(&path, &style(10.0, Join::Miter, Cap::Round)),
+            1000.0 + 25.0 * std::f64::consts::PI,
+        );
+    }
+
+    #[test]
+    fn joins() {
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+            Command::LineTo(P(120.0, 120.0)),
+        ];
+        // two 100x10 rectangles overlapping in a 5x5 square, outer corner 5x5
+        let base = 2000.0 - 25.0;
+        assert_area(
+            area(&path, &style(10.0, Join::Miter, Cap::Butt)),
+            base + 25.0,
+        );
+        assert_area(
+            area(&path, &style(10.0, Join::Bevel, Cap::Butt)),
+            base + 12.5,
+        );
+        assert_area(
+            area(&path, &style(10.0, Join::Round, Cap::Butt)),
+            base + 25.0 * std::f64::consts::PI / 4.0,
+        );
+
+        // sharp corner exceeding the miter limit falls back to bevel
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+            Command::LineTo(P(20.0, 30.0)),
+        ];
+        let mut miter = style(10.0, Join::Miter, Cap::Butt);
+        let bevel = area(&path, &style(10.0, Join::Bevel, Cap::Butt));
+        assert_area(area(&path, &miter), bevel);
+        miter.miter_limit = 100.0;
+        assert!(area(&path, &miter) > bevel + 50.0);
+    }
+
+    #[test]
+    fn closed_rect() {
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+            Command::LineTo(P(120.0, 120.0)),
+            Command::LineTo(P(20.0, 120.0)),
+            Command::Close,
+        ];
+        // 110x110 outer minus 90x90 inner
+        assert_area(
+            area(&path, &style(10.0, Join::Miter, Cap::Round)),
+            12100.0 - 8100.0,
+        );
+    }
+
+    #[test]
+    fn circle() {
+        // cubic approximation of a circle with radius 50
+        let r: f32 = 50.0;
+        let k = 0.5523 * r;
+        let (cx, cy) = (100.0, 100.0);
+        let path = [
+            Command::MoveTo(P(cx + r, cy)),
+            Command::CurveTo(P(cx + r, cy + k), P(cx + k, cy + r), P(cx, cy + r)),
+            Command::CurveTo(P(cx - k, cy + r), P(cx - r, cy + k), P(cx - r, cy)),
+            Command::CurveTo(P(cx - r, cy - k), P(cx - k, cy - r), P(cx, cy - r)),
+            Command::CurveTo(P(cx + k, cy - r), P(cx + r, cy - k), P(cx + r, cy)),
+            Command::Close,
+        ];
+        let r = r as f64;
+        assert_area(
+            area(&path, &style(10.0, Join::Miter, Cap::Butt)),
+            2.0 * std::f64::consts::PI * r * 10.0,
+        );
+
+        // wider than the diameter: the evolute fills the full disc
+        assert_area(
+            area(&path, &style(140.0, Join::Miter, Cap::Butt)),
+            std::f64::consts::PI * (r + 70.0).powi(2),
+        );
+    }
+
+    #[test]
+    fn dashes() {
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+        ];
+        let mut style = style(10.0, Join::Miter, Cap::Butt);
+        style.dashes = &[10.0, 10.0];
+        assert_area(area(&path, &style), 500.0);
+
+        // odd patterns repeat, offset shifts the pattern
+        style.dashes = &[10.0];
+        style.offset = 5.0;
+        assert_area(area(&path, &style), 500.0);
+
+        // zero-length dashes with round caps are dots
+        let path = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(110.0, 20.0)),
+        ];
+        style.dashes = &[0.0, 20.0];
+        style.offset = 0.0;
+        style.start_cap = Cap::Round;
+        style.end_cap = Cap::Round;
+        // arcs are approximated by inscribed polygons
+        let dots = 5.0 * 25.0 * std::f64::consts::PI;
+        let dotted = area(&path, &style);
+        assert!(dotted <= dots && dotted >= 0.95 * dots, "area {}", dotted);
+
+        // offsets skip zero-length dashes before the start
+        style.offset = 5.0;
+        let dots = 4.0 * 25.0 * std::f64::consts::PI;
+        let dotted = area(&path, &style);
+        assert!(dotted <= dots && dotted >= 0.95 * dots, "area {}", dotted);
+    }
+
+    #[test]
+    fn degenerate() {
+        let dot = [Command::MoveTo(P(20.0, 20.0)), Command::Close];
+        assert_area(area(&dot, &style(10.0, Join::Miter, Cap::Butt)), 0.0);
+        assert_area(area(&dot, &style(10.0, Join::Miter, Cap::Square)), 100.0);
+
+        let lone = [Command::MoveTo(P(20.0, 20.0))];
+        assert_area(area(&lone, &style(10.0, Join::Miter, Cap::Round)), 0.0);
+
+        let line = [
+            Command::MoveTo(P(20.0, 20.0)),
+            Command::LineTo(P(120.0, 20.0)),
+        ];
+        assert_area(area(&line, &style(0.0, Join::Miter, Cap::Round)), 0.0);
+    }
+}
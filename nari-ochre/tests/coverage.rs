@@ -0,0 +1,267 @@
+//! Coverage quality of the analytic rasterizer.
+//!
+//! Each path of the corpus is rasterized with nari-ochre and with a 16x16
+//! supersampled nonzero reference. The test reports the maximum and mean
+//! coverage error per path and fails if either exceeds the recorded bound.
+//!
+//! Run with `cargo test --test coverage -- --nocapture` to see the errors.
+
+use nari_ochre::{Coord, Encoder, Rasterizer, Tile, TILE_SIZE};
+use std::ops::Range;
+use zeno::{PathBuilder, Point};
+
+/// Canvas size in pixels, all corpus paths lie inside.
+const SIZE: usize = 128;
+/// Samples per pixel along each axis of the reference.
+const SAMPLES: usize = 16;
+
+type Polygon = Vec<Vec<(f32, f32)>>;
+
+struct Case {
+    name: &'static str,
+    polygon: Polygon,
+    /// Upper bound for the maximum pixel coverage error.
+    max: f32,
+    /// Upper bound for the mean pixel coverage error.
+    mean: f32,
+}
+
+/// Coverage image of the canvas.
+struct Image(Vec<f32>);
+
+impl Encoder for Image {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        for tx in x {
+            for py in 0..TILE_SIZE {
+                for px in 0..TILE_SIZE {
+                    self.set(tx, y, px, py, 1.0);
+                }
+            }
+        }
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        for (py, row) in mask.iter().enumerate() {
+            for (px, coverage) in row.iter().enumerate() {
+                self.set(x, y, px, py, *coverage as f32 / 255.0);
+            }
+        }
+    }
+}
+
+impl Image {
+    fn set(&mut self, tx: Coord, ty: Coord, px: usize, py: usize, coverage: f32) {
+        let x = tx as usize * TILE_SIZE + px;
+        let y = ty as usize * TILE_SIZE + py;
+        assert!(
+            tx >= 0 && ty >= 0 && x < SIZE && y < SIZE,
+            "tile out of canvas"
+        );
+        self.0[y * SIZE + x] = coverage;
+    }
+}
+
+fn rasterize(polygon: &Polygon) -> Vec<f32> {
+    let mut rasterizer = Rasterizer::default();
+    rasterizer.begin();
+    for contour in polygon {
+        let (x, y) = contour[0];
+        rasterizer.move_to(Point::new(x, y));
+        for &(x, y) in &contour[1..] {
+            rasterizer.line_to(Point::new(x, y));
+        }
+        rasterizer.close();
+    }
+
+    let mut image = Image(vec![0.0; SIZE * SIZE]);
+    rasterizer.end(&mut image).unwrap();
+    image.0
+}
+
+/// Edge crossings of a horizontal line at `y`, sorted by `x`, with direction.
+fn crossings(polygon: &Polygon, y: f64) -> Vec<(f64, i32)> {
+    let mut crossings = Vec::new();
+    for contour in polygon {
+        for i in 0..contour.len() {
+            let (x0, y0) = contour[i];
+            let (x1, y1) = contour[(i + 1) % contour.len()];
+            let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+            if (y0 <= y) == (y1 <= y) {
+                continue;
+            }
+            let t = (y - y0) / (y1 - y0);
+            crossings.push((x0 + t * (x1 - x0), if y1 > y0 { 1 } else { -1 }));
+        }
+    }
+    crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    crossings
+}
+
+fn reference(polygon: &Polygon) -> Vec<f32> {
+    let mut hits = vec![0u32; SIZE * SIZE];
+    for sy in 0..SIZE * SAMPLES {
+        let crossings = crossings(polygon, (sy as f64 + 0.5) / SAMPLES as f64);
+        let row = &mut hits[(sy / SAMPLES) * SIZE..][..SIZE];
+
+        let mut winding = 0;
+        let mut i = 0;
+        for sx in 0..SIZE * SAMPLES {
+            let x = (sx as f64 + 0.5) / SAMPLES as f64;
+            while i < crossings.len() && crossings[i].0 <= x {
+                winding += crossings[i].1;
+                i += 1;
+            }
+            if winding != 0 {
+                row[sx / SAMPLES] += 1;
+            }
+        }
+    }
+
+    hits.iter()
+        .map(|hits| *hits as f32 / (SAMPLES * SAMPLES) as f32)
+        .collect()
+}
+
+fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<(f32, f32)> {
+    vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+}
+
+fn star(cx: f32, cy: f32, r: f32, points: usize, step: usize) -> Vec<(f32, f32)> {
+    (0..points)
+        .map(|i| {
+            let a = (i * step) as f32 * std::f32::consts::TAU / points as f32;
+            (cx + r * a.sin(), cy - r * a.cos())
+        })
+        .collect()
+}
+
+fn corpus() -> Vec<Case> {
+    vec![
+        Case {
+            name: "sliver",
+            polygon: vec![vec![(4.0, 10.0), (120.0, 12.5), (4.0, 10.4)]],
+            max: 0.05,
+            mean: 0.0002,
+        },
+        Case {
+            name: "sliver vertical",
+            polygon: vec![vec![(10.2, 4.0), (13.0, 124.0), (10.5, 4.0)]],
+            max: 0.05,
+            mean: 0.0002,
+        },
+        Case {
+            name: "hairline",
+            polygon: vec![rect(3.0, 40.3, 125.0, 40.35)],
+            max: 0.03,
+            mean: 0.0002,
+        },
+        Case {
+            name: "near horizontal",
+            polygon: vec![vec![(2.0, 20.0), (126.0, 21.0), (126.0, 22.5), (2.0, 21.5)]],
+            max: 0.04,
+            mean: 0.0004,
+        },
+        Case {
+            name: "near horizontal steep",
+            polygon: vec![vec![
+                (2.0, 60.0),
+                (126.0, 60.01),
+                (126.0, 100.0),
+                (2.0, 100.0),
+            ]],
+            max: 0.02,
+            mean: 0.0001,
+        },
+        Case {
+            name: "bowtie",
+            polygon: vec![vec![
+                (10.0, 10.0),
+                (110.0, 110.0),
+                (110.0, 10.0),
+                (10.0, 110.0),
+            ]],
+            max: 0.05,
+            mean: 0.0006,
+        },
+        // Winding numbers above one are clamped per pixel, pixels containing
+        // both the inner and the outer region are overestimated.
+        Case {
+            name: "pentagram",
+            polygon: vec![star(64.0, 64.0, 60.0, 5, 2)],
+            max: 0.35,
+            mean: 0.0004,
+        },
+        // Clamped winding, same as above.
+        Case {
+            name: "overlapping contours",
+            polygon: vec![
+                rect(10.5, 10.5, 80.25, 80.25),
+                rect(40.75, 40.75, 120.0, 120.0),
+            ],
+            max: 0.08,
+            mean: 0.0001,
+        },
+        Case {
+            name: "tile edges",
+            polygon: vec![rect(8.0, 8.0, 64.0, 16.0), rect(80.0, 24.0, 88.0, 120.0)],
+            max: 0.01,
+            mean: 1e-05,
+        },
+        Case {
+            name: "tile corners",
+            polygon: vec![vec![(8.0, 8.0), (120.0, 120.0), (8.0, 120.0)]],
+            max: 0.05,
+            mean: 0.0004,
+        },
+        Case {
+            name: "tile boundary crossings",
+            polygon: vec![vec![
+                (7.9, 8.1),
+                (64.1, 7.9),
+                (120.1, 63.9),
+                (63.9, 120.1),
+                (8.1, 63.9),
+            ]],
+            max: 0.05,
+            mean: 0.0005,
+        },
+        Case {
+            name: "subpixel",
+            polygon: vec![
+                rect(20.2, 20.2, 20.7, 20.7),
+                rect(31.9, 31.9, 32.1, 32.1),
+                vec![(50.1, 50.1), (50.9, 50.3), (50.4, 50.8)],
+            ],
+            max: 0.02,
+            mean: 1e-05,
+        },
+    ]
+}
+
+#[test]
+fn coverage() {
+    let mut failures = Vec::new();
+    for case in corpus() {
+        let nari = rasterize(&case.polygon);
+        let reference = reference(&case.polygon);
+
+        let mut max: f32 = 0.0;
+        let mut sum = 0.0;
+        for (a, b) in nari.iter().zip(&reference) {
+            let error = (a - b).abs();
+            max = max.max(error);
+            sum += error as f64;
+        }
+        let mean = (sum / (SIZE * SIZE) as f64) as f32;
+
+        println!("{:<24} max {:.4} mean {:.6}", case.name, max, mean);
+        if max > case.max || mean > case.mean {
+            failures.push(format!(
+                "{}: max {} (bound {}), mean {} (bound {})",
+                case.name, max, case.max, mean, case.mean
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
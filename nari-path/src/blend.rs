@@ -0,0 +1,461 @@
+//! Compositing of premultiplied linear colors.
+//!
+//! Blend modes follow the W3C Compositing and Blending specification: the
+//! mix function is applied to the unpremultiplied colors, followed by the
+//! Porter-Duff operator on the premultiplied result.
+
+use crate::color::ColorSpace;
+use crate::image::{rgbaf32, Image};
+
+pub use nari_scene::{BlendMode, Compose, Mix};
+
+/// Source and destination factors `(Fa, Fb)` of the Porter-Duff operator for
+/// the source and destination alpha.
+fn factors(compose: Compose, sa: f32, da: f32) -> (f32, f32) {
+    match compose {
+        Compose::Clear => (0.0, 0.0),
+        Compose::Copy => (1.0, 0.0),
+        Compose::Dest => (0.0, 1.0),
+        Compose::SrcOver => (1.0, 1.0 - sa),
+        Compose::DestOver => (1.0 - da, 1.0),
+        Compose::SrcIn => (da, 0.0),
+        Compose::DestIn => (0.0, sa),
+        Compose::SrcOut => (1.0 - da, 0.0),
+        Compose::DestOut => (0.0, 1.0 - sa),
+        Compose::SrcAtop => (da, 1.0 - sa),
+        Compose::DestAtop => (1.0 - da, sa),
+        Compose::Xor => (1.0 - da, 1.0 - sa),
+        Compose::Plus => (1.0, 1.0),
+    }
+}
+
+fn is_separable(mode: Mix) -> bool {
+    !matches!(
+        mode,
+        Mix::Hue | Mix::Saturation | Mix::Color | Mix::Luminosity
+    )
+}
+
+/// Separable mix of the backdrop `cb` and source `cs` channel.
+fn separable(mode: Mix, cb: f32, cs: f32) -> f32 {
+    match mode {
+        Mix::Normal => cs,
+        Mix::Multiply => cb * cs,
+        Mix::Screen => cb + cs - cb * cs,
+        Mix::Overlay => separable(Mix::HardLight, cs, cb),
+        Mix::Darken => cb.min(cs),
+        Mix::Lighten => cb.max(cs),
+        Mix::ColorDodge => {
+            if cb == 0.0 {
+                0.0
+            } else if cs >= 1.0 {
+                1.0
+            } else {
+                (cb / (1.0 - cs)).min(1.0)
+            }
+        }
+        Mix::ColorBurn => {
+            if cb >= 1.0 {
+                1.0
+            } else if cs == 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - cb) / cs).min(1.0)
+            }
+        }
+        Mix::HardLight => {
+            if cs <= 0.5 {
+                cb * 2.0 * cs
+            } else {
+                separable(Mix::Screen, cb, 2.0 * cs - 1.0)
+            }
+        }
+        Mix::SoftLight => {
+            if cs <= 0.5 {
+                cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+            } else {
+                let d = if cb <= 0.25 {
+                    ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                } else {
+                    cb.sqrt()
+                };
+                cb + (2.0 * cs - 1.0) * (d - cb)
+            }
+        }
+        Mix::Difference => (cb - cs).abs(),
+        Mix::Exclusion => cb + cs - 2.0 * cb * cs,
+        Mix::Hue | Mix::Saturation | Mix::Color | Mix::Luminosity => unreachable!(),
+    }
+}
+
+/// Mix of the unpremultiplied backdrop `cb` and source `cs` colors.
+fn mix(mode: Mix, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+    if is_separable(mode) {
+        return [
+            separable(mode, cb[0], cs[0]),
+            separable(mode, cb[1], cs[1]),
+            separable(mode, cb[2], cs[2]),
+        ];
+    }
+    match mode {
+        Mix::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+        Mix::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+        Mix::Color => set_lum(cs, lum(cb)),
+        Mix::Luminosity => set_lum(cb, lum(cs)),
+        _ => unreachable!(),
+    }
+}
+
+fn lum([r, g, b]: [f32; 3]) -> f32 {
+    0.3 * r + 0.59 * g + 0.11 * b
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    c.map(|c| {
+        let mut c = c;
+        if n < 0.0 {
+            c = l + (c - l) * l / (l - n);
+        }
+        if x > 1.0 {
+            c = l + (c - l) * (1.0 - l) / (x - l);
+        }
+        c
+    })
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|c| c + d))
+}
+
+fn sat([r, g, b]: [f32; 3]) -> f32 {
+    r.max(g).max(b) - r.min(g).min(b)
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max <= min {
+        return [0.0; 3];
+    }
+    c.map(|c| (c - min) * s / (max - min))
+}
+
+fn unpremultiply(c: rgbaf32) -> [f32; 3] {
+    if c.a > 0.0 {
+        [c.r / c.a, c.g / c.a, c.b / c.a]
+    } else {
+        [0.0; 3]
+    }
+}
+
+/// Composite the source over the destination color.
+pub fn blend(src: rgbaf32, dst: rgbaf32, mode: BlendMode) -> rgbaf32 {
+    let src = if mode.mix == Mix::Normal || dst.a <= 0.0 {
+        src
+    } else {
+        // Cs' = (1 - ab) Cs + ab B(Cb, Cs), premultiplied by as
+        let cs = unpremultiply(src);
+        let mixed = mix(mode.mix, unpremultiply(dst), cs);
+        let c = |i: usize| src.a * ((1.0 - dst.a) * cs[i] + dst.a * mixed[i]);
+        rgbaf32::new(c(0), c(1), c(2), src.a)
+    };
+
+    let (fa, fb) = factors(mode.compose, src.a, dst.a);
+    let mut result = src * fa + dst * fb;
+    if mode.compose == Compose::Plus {
+        result.a = result.a.min(1.0);
+    }
+    result
+}
+
+/// Blend `src` with `coverage` onto the destination pixels, pixels outside
+/// of the coverage keep the destination color.
+pub fn blend_span(dst: &mut [rgbaf32], src: &[rgbaf32], coverage: Option<&[f32]>, mode: BlendMode) {
+    match coverage {
+        Some(coverage) => {
+            for ((dst, src), coverage) in dst.iter_mut().zip(src).zip(coverage) {
+                *dst = dst.lerp(blend(*src, *dst, mode), *coverage);
+            }
+        }
+        None => {
+            for (dst, src) in dst.iter_mut().zip(src) {
+                *dst = blend(*src, *dst, mode);
+            }
+        }
+    }
+}
+
+/// Blend like `blend_span` onto pixels storing values encoded in `space`.
+///
+/// Blending happens in linear light, `src` is linear in the primaries of
+/// `space`.
+pub fn blend_span_encoded(
+    space: ColorSpace,
+    dst: &mut [rgbaf32],
+    src: &[rgbaf32],
+    coverage: Option<&[f32]>,
+    mode: BlendMode,
+) {
+    if space.is_linear() {
+        return blend_span(dst, src, coverage, mode);
+    }
+    for (i, (dst, src)) in dst.iter_mut().zip(src).enumerate() {
+        let linear = dst.map_straight(|c| space.decode(c));
+        let mut result = blend(*src, linear, mode);
+        if let Some(coverage) = coverage {
+            result = linear.lerp(result, coverage[i]);
+        }
+        *dst = result.map_straight(|c| space.encode(c));
+    }
+}
+
+struct Group {
+    image: Image,
+    mode: BlendMode,
+    opacity: f32,
+}
+
+/// Target image with a stack of isolated layer groups.
+///
+/// Drawing goes into the top most group, which is composited onto the
+/// group below with its blend mode and opacity when popped.
+pub struct Layers {
+    base: Image,
+    groups: Vec<Group>,
+}
+
+impl Layers {
+    pub fn new(base: Image) -> Self {
+        Self {
+            base,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Image of the current group.
+    pub fn target(&mut self) -> &mut Image {
+        match self.groups.last_mut() {
+            Some(group) => &mut group.image,
+            None => &mut self.base,
+        }
+    }
+
+    /// Number of pushed groups.
+    pub fn depth(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Start a new transparent group in the color space of the base image.
+    pub fn push(&mut self, mode: BlendMode, opacity: f32) {
+        let image = Image::new(self.base.width, self.base.height, rgbaf32::TRANSPARENT)
+            .with_color_space(self.base.color_space);
+        self.groups.push(Group {
+            image,
+            mode,
+            opacity,
+        });
+    }
+
+    /// Composite the current group onto its parent.
+    pub fn pop(&mut self) {
+        let group = match self.groups.pop() {
+            Some(group) => group,
+            None => return,
+        };
+        let space = group.image.color_space;
+        let target = self.target();
+        for (dst, src) in target.memory.iter_mut().zip(group.image.memory.iter()) {
+            let src = src.map_straight(|c| space.decode(c)) * group.opacity;
+            blend_span_encoded(space, std::slice::from_mut(dst), &[src], None, group.mode);
+        }
+    }
+
+    /// Composite all open groups and return the base image.
+    pub fn finish(mut self) -> Image {
+        while !self.groups.is_empty() {
+            self.pop();
+        }
+        self.base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color(a: rgbaf32, b: rgbaf32) {
+        let d = (a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() + (a.a - b.a).abs();
+        assert!(d < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn porter_duff() {
+        let src = rgbaf32::from_straight(1.0, 0.0, 0.0, 0.6);
+        let dst = rgbaf32::from_straight(0.0, 0.0, 1.0, 0.5);
+        let mode = |compose| BlendMode::from(compose);
+
+        assert_color(blend(src, dst, mode(Compose::Clear)), rgbaf32::TRANSPARENT);
+        assert_color(blend(src, dst, mode(Compose::Copy)), src);
+        assert_color(blend(src, dst, mode(Compose::Dest)), dst);
+        assert_color(
+            blend(src, dst, mode(Compose::SrcOver)),
+            rgbaf32::new(0.6, 0.0, 0.2, 0.8),
+        );
+        assert_color(
+            blend(src, dst, mode(Compose::DestOver)),
+            rgbaf32::new(0.3, 0.0, 0.5, 0.8),
+        );
+        assert_color(blend(src, dst, mode(Compose::SrcIn)), src * 0.5);
+        assert_color(blend(src, dst, mode(Compose::DestIn)), dst * 0.6);
+        assert_color(blend(src, dst, mode(Compose::SrcOut)), src * 0.5);
+        assert_color(blend(src, dst, mode(Compose::DestOut)), dst * 0.4);
+        assert_color(
+            blend(src, dst, mode(Compose::SrcAtop)),
+            rgbaf32::new(0.3, 0.0, 0.2, 0.5),
+        );
+        assert_color(
+            blend(src, dst, mode(Compose::DestAtop)),
+            rgbaf32::new(0.3, 0.0, 0.3, 0.6),
+        );
+        assert_color(
+            blend(src, dst, mode(Compose::Xor)),
+            rgbaf32::new(0.3, 0.0, 0.2, 0.5),
+        );
+        assert_color(
+            blend(src, dst, mode(Compose::Plus)),
+            rgbaf32::new(0.6, 0.0, 0.5, 1.0),
+        );
+    }
+
+    #[test]
+    fn separable_mix() {
+        let (cb, cs) = (0.25, 0.75);
+        for (mix, expected) in [
+            (Mix::Normal, 0.75),
+            (Mix::Multiply, 0.1875),
+            (Mix::Screen, 0.8125),
+            (Mix::Overlay, 0.375),
+            (Mix::Darken, 0.25),
+            (Mix::Lighten, 0.75),
+            (Mix::ColorDodge, 1.0),
+            (Mix::ColorBurn, 0.0),
+            (Mix::HardLight, 0.625),
+            (Mix::SoftLight, 0.375),
+            (Mix::Difference, 0.5),
+            (Mix::Exclusion, 0.625),
+        ] {
+            let value = separable(mix, cb, cs);
+            assert!((value - expected).abs() < 1e-6, "{:?}: {}", mix, value);
+        }
+        assert_eq!(separable(Mix::ColorDodge, 0.25, 0.5), 0.5);
+        assert_eq!(separable(Mix::ColorBurn, 0.75, 0.5), 0.5);
+        assert!((separable(Mix::SoftLight, 0.5, 0.25) - 0.375).abs() < 1e-6);
+    }
+
+    #[test]
+    fn non_separable_mix() {
+        let red = [1.0, 0.0, 0.0];
+        let gray = [0.5, 0.5, 0.5];
+
+        // luminosity of the source with hue and saturation of the backdrop
+        let c = mix(Mix::Luminosity, red, gray);
+        assert!((lum(c) - 0.5).abs() < 1e-6);
+        assert!(c[0] > c[1] && c[1] == c[2]);
+
+        // gray source has no saturation
+        let c = mix(Mix::Saturation, red, gray);
+        assert!((c[0] - c[1]).abs() < 1e-6 && (c[1] - c[2]).abs() < 1e-6);
+        assert!((lum(c) - lum(red)).abs() < 1e-6);
+
+        // hue and saturation of the source, luminosity of the backdrop
+        let c = mix(Mix::Color, gray, [0.0, 0.0, 1.0]);
+        assert!((lum(c) - 0.5).abs() < 1e-6);
+        assert!(c[2] > c[0] && c[0] == c[1]);
+
+        // hue of the source, saturation and luminosity of the backdrop
+        let backdrop = [0.8, 0.4, 0.4];
+        let c = mix(Mix::Hue, backdrop, [0.0, 0.0, 1.0]);
+        assert!((lum(c) - lum(backdrop)).abs() < 1e-6);
+        assert!((sat(c) - sat(backdrop)).abs() < 1e-6);
+        assert!(c[2] > c[0] && c[0] == c[1]);
+
+        // colors stay in range
+        for c in [
+            mix(Mix::Color, [1.0; 3], red),
+            mix(Mix::Color, [0.0; 3], red),
+        ] {
+            assert!(c.iter().all(|c| (0.0..=1.0).contains(c)), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn mix_alpha() {
+        // fully covered backdrop uses the mixed color
+        let src = rgbaf32::from_straight(0.5, 0.5, 0.5, 1.0);
+        let dst = rgbaf32::from_straight(0.5, 1.0, 0.0, 1.0);
+        assert_color(
+            blend(src, dst, Mix::Multiply.into()),
+            rgbaf32::new(0.25, 0.5, 0.0, 1.0),
+        );
+
+        // transparent backdrop uses the source
+        assert_color(blend(src, rgbaf32::TRANSPARENT, Mix::Multiply.into()), src);
+
+        // ab = 0.5: Cs' = 0.5 Cs + 0.5 B, composited over the backdrop
+        let dst = rgbaf32::from_straight(0.5, 1.0, 0.0, 0.5);
+        let result = blend(src, dst, Mix::Multiply.into());
+        assert_color(result, rgbaf32::new(0.375, 0.5, 0.25, 1.0));
+    }
+
+    #[test]
+    fn layers() {
+        let base = Image::new(2, 1, rgbaf32::WHITE);
+        let mut layers = Layers::new(base);
+        layers.target()[(0, 0)] = rgbaf32::BLACK;
+
+        layers.push(BlendMode::SRC_OVER, 0.5);
+        assert_eq!(layers.depth(), 1);
+        let red = rgbaf32::new(1.0, 0.0, 0.0, 1.0);
+        let blue = rgbaf32::new(0.0, 0.0, 1.0, 1.0);
+        // overlapping draws inside the group don't show through each other
+        for color in [red, blue] {
+            blend_span(
+                layers.target().row_mut(0, 0),
+                &[color; 2],
+                None,
+                BlendMode::SRC_OVER,
+            );
+        }
+
+        layers.push(Mix::Multiply.into(), 1.0);
+        blend_span(
+            layers.target().row_mut(0, 0),
+            &[rgbaf32::new(0.5, 0.5, 0.5, 1.0); 2],
+            Some(&[0.0, 1.0]),
+            BlendMode::SRC_OVER,
+        );
+
+        let image = layers.finish();
+        assert_color(image[(0, 0)], rgbaf32::new(0.0, 0.0, 0.5, 1.0));
+        assert_color(image[(1, 0)], rgbaf32::new(0.5, 0.5, 0.75, 1.0));
+    }
+
+    #[test]
+    fn layers_encoded() {
+        let base = Image::new(1, 1, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+        let mut layers = Layers::new(base);
+        layers.push(BlendMode::SRC_OVER, 0.5);
+        assert_eq!(layers.target().color_space, ColorSpace::Srgb);
+        layers.target()[(0, 0)] = rgbaf32::BLACK;
+
+        // half opacity black over white is mid gray in linear light
+        let image = layers.finish();
+        let expected = crate::color::linear_to_srgb(0.5);
+        assert!((image[(0, 0)].r - expected).abs() < 1e-5);
+        assert!((expected - 0.735_357).abs() < 1e-5);
+    }
+}
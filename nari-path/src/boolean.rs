@@ -0,0 +1,603 @@
+//! Boolean operations on paths.
+//!
+//! Both paths are split into monotonic quadratic segments and flattened
+//! within the tolerance. The resulting edges are snapped to a grid, split at
+//! all mutual intersections and coincident edges are merged, keeping count
+//! of the windings of both operands. The winding numbers on either side of
+//! an edge decide whether the edge is part of the boundary of the result,
+//! which is then assembled into closed polygons.
+//!
+//! Operands are filled with the nonzero rule, the result is oriented such
+//! that its interior has a winding number of one. Curves are not preserved,
+//! the result consists of line segments only.
+
+use crate::implicit::{segments, Curve2};
+use kurbo::{BezPath, PathEl, Point, Vec2};
+use std::collections::HashMap;
+
+/// Maximum number of intersection passes, snapped intersections may
+/// introduce new intersections with nearby edges.
+const MAX_PASSES: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoolOp {
+    Union,
+    Intersection,
+    /// First path without the second path.
+    Difference,
+    Xor,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Tolerance is not positive and finite.
+    InvalidTolerance,
+    /// Snapped intersections still introduced new intersections after
+    /// `MAX_PASSES` passes.
+    Unresolved,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidTolerance => write!(f, "tolerance must be positive and finite"),
+            Error::Unresolved => write!(f, "path intersections did not converge"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl BoolOp {
+    fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            BoolOp::Union => a || b,
+            BoolOp::Intersection => a && b,
+            BoolOp::Difference => a && !b,
+            BoolOp::Xor => a != b,
+        }
+    }
+}
+
+/// Line segment with the number of edges of each operand running from `p0`
+/// to `p1`.
+#[derive(Copy, Clone, Debug)]
+struct Edge {
+    p0: Point,
+    p1: Point,
+    winding: [isize; 2],
+}
+
+impl Edge {
+    fn x_range(&self) -> (f64, f64) {
+        (self.p0.x.min(self.p1.x), self.p0.x.max(self.p1.x))
+    }
+
+    fn y_range(&self) -> (f64, f64) {
+        (self.p0.y.min(self.p1.y), self.p0.y.max(self.p1.y))
+    }
+
+    /// Parameter of `p` projected onto the edge.
+    fn project(&self, p: Point) -> f64 {
+        let d = self.p1 - self.p0;
+        (p - self.p0).dot(d) / d.hypot2()
+    }
+
+    /// Whether `p` lies within `grid` of the interior of the edge.
+    fn touches(&self, p: Point, grid: f64) -> bool {
+        if p == self.p0 || p == self.p1 {
+            return false;
+        }
+        let d = self.p1 - self.p0;
+        let t = self.project(p);
+        t > 0.0 && t < 1.0 && d.cross(p - self.p0).abs() <= grid * d.hypot()
+    }
+}
+
+struct Snap {
+    grid: f64,
+}
+
+impl Snap {
+    fn point(&self, p: Point) -> Point {
+        // adding zero avoids negative zeros in the edge keys
+        let snap = |x: f64| (x / self.grid).round() * self.grid + 0.0;
+        Point::new(snap(p.x), snap(p.y))
+    }
+}
+
+fn flatten(
+    path: impl IntoIterator<Item = PathEl>,
+    operand: usize,
+    tolerance: f64,
+    snap: &Snap,
+    edges: &mut Vec<Edge>,
+) {
+    let mut winding = [0; 2];
+    winding[operand] = 1;
+
+    for curve in segments(path, tolerance) {
+        // uniform subdivision of a quadratic deviates by |dd| / (8 n²)
+        let dd = curve.p0.to_vec2() - 2.0 * curve.p1.to_vec2() + curve.p2.to_vec2();
+        let n = (dd.hypot() / (8.0 * tolerance)).sqrt().ceil().max(1.0) as usize;
+
+        let mut prev = snap.point(curve.p0);
+        for i in 1..=n {
+            let p = if i == n {
+                curve.p2
+            } else {
+                curve.eval(i as f64 / n as f64)
+            };
+            let p = snap.point(p);
+            if p != prev {
+                edges.push(Edge {
+                    p0: prev,
+                    p1: p,
+                    winding,
+                });
+            }
+            prev = p;
+        }
+    }
+}
+
+/// Split the edges at their mutual intersections, returns `false` if there
+/// were no intersections.
+fn split(edges: &mut Vec<Edge>, snap: &Snap) -> bool {
+    let grid = snap.grid;
+    let mut splits = vec![Vec::new(); edges.len()];
+
+    let mut order = (0..edges.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| edges[a].x_range().0.total_cmp(&edges[b].x_range().0));
+
+    for (k, &i) in order.iter().enumerate() {
+        let a = edges[i];
+        let (_, ax1) = a.x_range();
+        let (ay0, ay1) = a.y_range();
+        for &j in &order[k + 1..] {
+            let b = edges[j];
+            let (bx0, _) = b.x_range();
+            if bx0 > ax1 + grid {
+                break;
+            }
+            let (by0, by1) = b.y_range();
+            if by0 > ay1 + grid || by1 < ay0 - grid {
+                continue;
+            }
+
+            // touching endpoints, including overlaps of collinear edges
+            for p in [b.p0, b.p1] {
+                if a.touches(p, grid) {
+                    splits[i].push(p);
+                }
+            }
+            for p in [a.p0, a.p1] {
+                if b.touches(p, grid) {
+                    splits[j].push(p);
+                }
+            }
+
+            // proper crossing
+            let r = a.p1 - a.p0;
+            let s = b.p1 - b.p0;
+            let d = r.cross(s);
+            if d == 0.0 {
+                continue;
+            }
+            let q = b.p0 - a.p0;
+            let t = q.cross(s) / d;
+            let u = q.cross(r) / d;
+            if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+                let p = snap.point(a.p0 + r * t);
+                splits[i].push(p);
+                splits[j].push(p);
+            }
+        }
+    }
+
+    let mut changed = false;
+    let mut result = Vec::with_capacity(edges.len());
+    for (edge, mut points) in edges.drain(..).zip(splits) {
+        points.retain(|p| *p != edge.p0 && *p != edge.p1);
+        if points.is_empty() {
+            result.push(edge);
+            continue;
+        }
+        changed = true;
+
+        points.sort_by(|a, b| edge.project(*a).total_cmp(&edge.project(*b)));
+        points.dedup();
+        points.push(edge.p1);
+
+        let mut prev = edge.p0;
+        for p in points {
+            result.push(Edge {
+                p0: prev,
+                p1: p,
+                winding: edge.winding,
+            });
+            prev = p;
+        }
+    }
+    *edges = result;
+
+    changed
+}
+
+/// Merge coincident edges into edges running from the lexicographically
+/// smaller point, dropping edges without winding.
+fn merge(edges: &[Edge]) -> Vec<Edge> {
+    let key = |p: Point| (p.x.to_bits(), p.y.to_bits());
+    let mut merged = HashMap::<_, usize>::new();
+    let mut result: Vec<Edge> = Vec::new();
+
+    for edge in edges {
+        let forward = (edge.p0.x, edge.p0.y) < (edge.p1.x, edge.p1.y);
+        let (p0, p1, sign) = if forward {
+            (edge.p0, edge.p1, 1)
+        } else {
+            (edge.p1, edge.p0, -1)
+        };
+        let index = *merged.entry((key(p0), key(p1))).or_insert_with(|| {
+            result.push(Edge {
+                p0,
+                p1,
+                winding: [0; 2],
+            });
+            result.len() - 1
+        });
+        for (w, dw) in result[index].winding.iter_mut().zip(edge.winding) {
+            *w += sign * dw;
+        }
+    }
+
+    result.retain(|edge| edge.winding != [0; 2]);
+    result
+}
+
+/// Edges of the boundary of the result, oriented such that the interior has
+/// a winding number of one. Quadratic in the number of edges.
+fn classify(edges: &[Edge], op: BoolOp) -> Vec<(Point, Point)> {
+    let mut boundary = Vec::new();
+
+    for (i, edge) in edges.iter().enumerate() {
+        // rays towards positive x don't cross horizontal edges, these are
+        // tested on the transposed geometry instead.
+        let transpose = edge.p0.y == edge.p1.y;
+        let map = |p: Point| if transpose { Point::new(p.y, p.x) } else { p };
+        let mid = map(edge.p0.midpoint(edge.p1));
+
+        // winding right of the edge
+        let mut right = [0; 2];
+        for (j, other) in edges.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let crossing = Curve2::line(map(other.p0), map(other.p1)).winding(mid);
+            for (w, dw) in right.iter_mut().zip(other.winding) {
+                *w += crossing * dw;
+            }
+        }
+        let direction = Curve2::line(map(edge.p0), map(edge.p1)).direction();
+        let mut left = right;
+        for (w, dw) in left.iter_mut().zip(edge.winding) {
+            *w += direction * dw;
+        }
+
+        let inside_left = op.apply(left[0] != 0, left[1] != 0);
+        let inside_right = op.apply(right[0] != 0, right[1] != 0);
+        if inside_left == inside_right {
+            continue;
+        }
+
+        // edges with increasing y add to the winding left of them,
+        // transposing flips the orientation.
+        let target = if inside_left == transpose { -1 } else { 1 };
+        if direction == target {
+            boundary.push((edge.p0, edge.p1));
+        } else {
+            boundary.push((edge.p1, edge.p0));
+        }
+    }
+
+    boundary
+}
+
+/// Link the boundary edges into closed polygons.
+fn assemble(boundary: &[(Point, Point)], grid: f64) -> BezPath {
+    let key = |p: Point| (p.x.to_bits(), p.y.to_bits());
+    let mut outgoing = HashMap::<_, Vec<usize>>::new();
+    for (i, (p0, _)) in boundary.iter().enumerate() {
+        outgoing.entry(key(*p0)).or_default().push(i);
+    }
+
+    let mut used = vec![false; boundary.len()];
+    let mut path = BezPath::new();
+    for start in 0..boundary.len() {
+        if used[start] {
+            continue;
+        }
+
+        let mut polygon = vec![boundary[start].0];
+        let mut current = start;
+        loop {
+            used[current] = true;
+            let (p0, p1) = boundary[current];
+            if p1 == polygon[0] {
+                break;
+            }
+            polygon.push(p1);
+
+            // keep the turn with the least rotation to the left, splitting
+            // touching polygons
+            let incoming = p1 - p0;
+            let turn = |i: &usize| {
+                let out = boundary[*i].1 - boundary[*i].0;
+                incoming.cross(out).atan2(incoming.dot(out))
+            };
+            let next = outgoing.get(&key(p1)).and_then(|candidates| {
+                candidates
+                    .iter()
+                    .filter(|i| !used[**i])
+                    .min_by(|a, b| turn(a).total_cmp(&turn(b)))
+                    .copied()
+            });
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        let polygon = simplify(polygon, grid);
+        if polygon.len() < 3 {
+            continue;
+        }
+        path.move_to(polygon[0]);
+        for p in &polygon[1..] {
+            path.line_to(*p);
+        }
+        path.close_path();
+    }
+
+    path
+}
+
+/// Remove vertices of the closed polygon which lie within `grid` of the
+/// line through their neighbors.
+fn simplify(mut polygon: Vec<Point>, grid: f64) -> Vec<Point> {
+    let collinear = |a: Point, b: Point, c: Point| {
+        let (d0, d1): (Vec2, Vec2) = (b - a, c - b);
+        let d = c - a;
+        d0.dot(d1) > 0.0 && d.cross(b - a).abs() <= grid * d.hypot()
+    };
+
+    let mut i = 0;
+    while polygon.len() >= 3 && i < polygon.len() {
+        let n = polygon.len();
+        let prev = polygon[(i + n - 1) % n];
+        let next = polygon[(i + 1) % n];
+        if collinear(prev, polygon[i], next) {
+            polygon.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+    polygon
+}
+
+/// Boolean combination of two paths as closed polygons.
+///
+/// Curves are flattened into lines with `tolerance`, which also bounds how
+/// far apart edges may be to be considered coincident.
+///
+/// Each resulting edge is classified by casting a ray against all other
+/// edges, so the cost grows quadratically with the number of flattened
+/// edges. This is meant for shapes of up to a few thousand edges, e.g.
+/// glyph outlines or UI shapes, not for complex maps or meshes.
+pub fn boolean(
+    a: impl IntoIterator<Item = PathEl>,
+    b: impl IntoIterator<Item = PathEl>,
+    op: BoolOp,
+    tolerance: f64,
+) -> Result<BezPath, Error> {
+    if !(tolerance > 0.0 && tolerance.is_finite()) {
+        return Err(Error::InvalidTolerance);
+    }
+    let snap = Snap {
+        grid: tolerance / 16.0,
+    };
+
+    let mut edges = Vec::new();
+    flatten(a, 0, tolerance, &snap, &mut edges);
+    flatten(b, 1, tolerance, &snap, &mut edges);
+
+    let mut passes = 0;
+    while split(&mut edges, &snap) {
+        passes += 1;
+        if passes > MAX_PASSES {
+            return Err(Error::Unresolved);
+        }
+    }
+
+    let edges = merge(&edges);
+    let boundary = classify(&edges, op);
+    Ok(assemble(&boundary, snap.grid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kurbo::{Affine, Circle, Rect, Shape};
+
+    const TOLERANCE: f64 = 0.01;
+
+    fn area(path: &BezPath) -> f64 {
+        path.area().abs()
+    }
+
+    fn op(a: &impl Shape, b: &impl Shape, op: BoolOp) -> BezPath {
+        boolean(
+            a.path_elements(0.001),
+            b.path_elements(0.001),
+            op,
+            TOLERANCE,
+        )
+        .unwrap()
+    }
+
+    fn vertices(path: &BezPath) -> usize {
+        path.elements()
+            .iter()
+            .filter(|el| matches!(el, PathEl::MoveTo(_) | PathEl::LineTo(_)))
+            .count()
+    }
+
+    /// Area identities of the operations, the union with an empty path
+    /// normalizes the operands.
+    fn identities(a: &impl Shape, b: &impl Shape) {
+        let empty = BezPath::new();
+        let area_a = area(&op(a, &empty, BoolOp::Union));
+        let area_b = area(&op(b, &empty, BoolOp::Union));
+
+        let union = area(&op(a, b, BoolOp::Union));
+        let intersection = area(&op(a, b, BoolOp::Intersection));
+        let difference = area(&op(a, b, BoolOp::Difference));
+        let xor = area(&op(a, b, BoolOp::Xor));
+
+        // snapping moves intersections by up to half a grid cell
+        let eps = TOLERANCE * (area_a + area_b).sqrt();
+        assert!((union + intersection - area_a - area_b).abs() < eps);
+        assert!((difference - (area_a - intersection)).abs() < eps);
+        assert!((xor - (union - intersection)).abs() < eps);
+        assert!(union >= area_a.max(area_b) - eps);
+        assert!(intersection <= area_a.min(area_b) + eps);
+    }
+
+    #[test]
+    fn rects() {
+        let a = Rect::new(0.0, 0.0, 2.0, 2.0);
+        let b = Rect::new(1.0, 1.0, 3.0, 3.0);
+        assert_eq!(area(&op(&a, &b, BoolOp::Union)), 7.0);
+        assert_eq!(area(&op(&a, &b, BoolOp::Intersection)), 1.0);
+        assert_eq!(area(&op(&a, &b, BoolOp::Difference)), 3.0);
+        assert_eq!(area(&op(&a, &b, BoolOp::Xor)), 6.0);
+        assert_eq!(vertices(&op(&a, &b, BoolOp::Union)), 8);
+        assert_eq!(vertices(&op(&a, &b, BoolOp::Intersection)), 4);
+        identities(&a, &b);
+
+        // disjoint
+        let c = Rect::new(5.0, 0.0, 6.0, 1.0);
+        assert_eq!(area(&op(&a, &c, BoolOp::Union)), 5.0);
+        assert!(op(&a, &c, BoolOp::Intersection).elements().is_empty());
+
+        // hole
+        let d = Rect::new(0.5, 0.5, 1.5, 1.5);
+        let ring = op(&a, &d, BoolOp::Difference);
+        assert_eq!(area(&ring), 3.0);
+        assert_eq!(vertices(&ring), 8);
+        assert!(ring.contains(Point::new(0.25, 1.0)));
+        assert!(!ring.contains(Point::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn coincident() {
+        // shared edge is removed and collinear vertices are merged
+        let a = Rect::new(0.0, 0.0, 1.0, 1.0);
+        let b = Rect::new(1.0, 0.0, 2.0, 1.0);
+        let union = op(&a, &b, BoolOp::Union);
+        assert_eq!(area(&union), 2.0);
+        assert_eq!(vertices(&union), 4);
+        assert!(op(&a, &b, BoolOp::Intersection).elements().is_empty());
+        identities(&a, &b);
+
+        // partially overlapping edges
+        let c = Rect::new(1.0, 0.5, 2.0, 2.0);
+        assert_eq!(area(&op(&a, &c, BoolOp::Union)), 2.5);
+        identities(&a, &c);
+
+        // identical operands
+        for shape in [a.to_path(0.1), Circle::new((3.0, 2.0), 1.5).to_path(0.1)] {
+            let area_a = area(&op(&shape, &BezPath::new(), BoolOp::Union));
+            assert!((area(&op(&shape, &shape, BoolOp::Intersection)) - area_a).abs() < 1e-9);
+            assert!((area(&op(&shape, &shape, BoolOp::Union)) - area_a).abs() < 1e-9);
+            assert!(op(&shape, &shape, BoolOp::Difference).elements().is_empty());
+            assert!(op(&shape, &shape, BoolOp::Xor).elements().is_empty());
+        }
+    }
+
+    #[test]
+    fn self_intersection() {
+        let empty = BezPath::new();
+
+        // square traversed twice
+        let mut twice = Rect::new(0.0, 0.0, 1.0, 1.0).to_path(0.1);
+        twice.extend(Rect::new(0.0, 0.0, 1.0, 1.0).to_path(0.1));
+        let square = op(&twice, &empty, BoolOp::Union);
+        assert_eq!(area(&square), 1.0);
+        assert_eq!(vertices(&square), 4);
+
+        // bow tie
+        let mut bowtie = BezPath::new();
+        bowtie.move_to((0.0, 0.0));
+        bowtie.line_to((2.0, 2.0));
+        bowtie.line_to((2.0, 0.0));
+        bowtie.line_to((0.0, 2.0));
+        bowtie.close_path();
+        let bowtie = op(&bowtie, &empty, BoolOp::Union);
+        assert_eq!(area(&bowtie), 2.0);
+        assert_eq!(vertices(&bowtie), 6);
+
+        // pentagram, the center is filled with the nonzero rule
+        let mut star = BezPath::new();
+        for i in 0..5 {
+            let angle = (i * 2) as f64 * std::f64::consts::TAU / 5.0;
+            let p = Point::new(10.0 * angle.sin(), -10.0 * angle.cos());
+            if i == 0 {
+                star.move_to(p);
+            } else {
+                star.line_to(p);
+            }
+        }
+        star.close_path();
+        let filled = op(&star, &empty, BoolOp::Union);
+        assert_eq!(vertices(&filled), 10);
+        assert!(filled.contains(Point::ZERO));
+        identities(&star, &Circle::new((0.0, 0.0), 4.0));
+    }
+
+    #[test]
+    fn curves() {
+        let a = Circle::new((0.0, 0.0), 10.0);
+        let b = Circle::new((8.0, 3.0), 6.0);
+        identities(&a, &b);
+
+        // flattened areas within the tolerance
+        let union = area(&op(&a, &BezPath::new(), BoolOp::Union));
+        let perimeter = std::f64::consts::TAU * 10.0;
+        assert!((union - a.area()).abs() < perimeter * TOLERANCE);
+
+        // ring with a hole of opposite orientation
+        let mut ring = Circle::new((0.0, 0.0), 10.0).to_path(0.001);
+        ring.extend(Affine::FLIP_X * Circle::new((0.0, 0.0), 4.0).to_path(0.001));
+        let intersection = op(&ring, &b, BoolOp::Intersection);
+        assert!(!intersection.contains(Point::new(2.0, 1.0)));
+        assert!(intersection.contains(Point::new(7.0, 3.0)));
+        identities(&ring, &b);
+        identities(&ring, &Rect::new(-20.0, -2.0, 20.0, 2.0));
+    }
+
+    #[test]
+    fn invalid_tolerance() {
+        let a = Circle::new((0.0, 0.0), 10.0);
+        for tolerance in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let result = boolean(
+                a.path_elements(0.1),
+                BezPath::new(),
+                BoolOp::Union,
+                tolerance,
+            );
+            assert_eq!(result, Err(Error::InvalidTolerance));
+        }
+    }
+}
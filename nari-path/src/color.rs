@@ -0,0 +1,262 @@
+//! Color spaces and conversions.
+//!
+//! Paints produce premultiplied linear sRGB colors. Images are tagged with
+//! the color space of their stored values and blending happens on the
+//! linear values in the primaries of the image, see `colorspace.h` for the
+//! GPU counterpart.
+
+use crate::image::rgbaf32;
+
+/// Color space of stored color values.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB primaries with sRGB transfer function.
+    Srgb,
+    /// sRGB primaries, linear light.
+    #[default]
+    LinearSrgb,
+    /// Display P3 primaries with sRGB transfer function.
+    DisplayP3,
+}
+
+#[rustfmt::skip]
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462_1, 0.177_538, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9],
+];
+
+#[rustfmt::skip]
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [ 1.224_940_1, -0.224_940_4, 0.0],
+    [-0.042_056_9,  1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+fn mul(m: &[[f32; 3]; 3], c: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * c[0] + row[1] * c[1] + row[2] * c[2])
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    let a = c.abs();
+    let linear = if a < 0.04045 {
+        a / 12.92
+    } else {
+        ((a + 0.055) / 1.055).powf(2.4)
+    };
+    linear.copysign(c)
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let a = c.abs();
+    let srgb = if a < 0.003_130_8 {
+        a * 12.92
+    } else {
+        1.055 * a.powf(1.0 / 2.4) - 0.055
+    };
+    srgb.copysign(c)
+}
+
+/// Luma, weighted sum of non-linear sRGB values.
+pub fn srgb_luma([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+impl ColorSpace {
+    pub fn is_linear(self) -> bool {
+        self == ColorSpace::LinearSrgb
+    }
+
+    /// Decode stored values to linear light in the primaries of the space.
+    pub fn decode(self, c: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::LinearSrgb => c,
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => c.map(srgb_to_linear),
+        }
+    }
+
+    /// Encode linear light in the primaries of the space to stored values.
+    pub fn encode(self, c: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::LinearSrgb => c,
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => c.map(linear_to_srgb),
+        }
+    }
+
+    /// Linear light in the primaries of the space from linear sRGB.
+    pub fn from_linear_srgb(self, c: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearSrgb => c,
+            ColorSpace::DisplayP3 => mul(&SRGB_TO_P3, c),
+        }
+    }
+
+    /// Linear sRGB from linear light in the primaries of the space.
+    pub fn to_linear_srgb(self, c: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearSrgb => c,
+            ColorSpace::DisplayP3 => mul(&P3_TO_SRGB, c),
+        }
+    }
+
+    /// Convert stored values of this space to stored values of `to`.
+    pub fn convert(self, to: ColorSpace, c: [f32; 3]) -> [f32; 3] {
+        if self == to {
+            return c;
+        }
+        to.encode(to.from_linear_srgb(self.to_linear_srgb(self.decode(c))))
+    }
+}
+
+impl rgbaf32 {
+    fn rgb(self) -> [f32; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    /// Unpremultiplied color channels.
+    pub fn straight(self) -> [f32; 3] {
+        if self.a > 0.0 {
+            self.rgb().map(|c| c / self.a)
+        } else {
+            [0.0; 3]
+        }
+    }
+
+    /// Map the unpremultiplied color channels.
+    pub fn map_straight(self, f: impl FnOnce([f32; 3]) -> [f32; 3]) -> Self {
+        if self.a <= 0.0 {
+            return rgbaf32::TRANSPARENT;
+        }
+        let [r, g, b] = f(self.straight());
+        rgbaf32::from_straight(r, g, b, self.a)
+    }
+
+    /// Convert the premultiplied color between color spaces.
+    pub fn convert(self, from: ColorSpace, to: ColorSpace) -> Self {
+        if from == to {
+            return self;
+        }
+        self.map_straight(|c| from.convert(to, c))
+    }
+}
+
+/// Coverage blending of text masks.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextBlend {
+    /// Coverage is applied in linear light.
+    #[default]
+    Linear,
+    /// Coverage is boosted by the luma of the text color to approximate
+    /// blending in a perceptual space, as in `canvas_mask.frag`.
+    Perceptual { contrast: f32, gamma: f32 },
+}
+
+impl TextBlend {
+    pub const PERCEPTUAL: Self = TextBlend::Perceptual {
+        contrast: 0.5,
+        gamma: 2.2,
+    };
+
+    /// Adjusted mask coverage for a text color with sRGB `luma`.
+    pub fn coverage(self, mask: f32, luma: f32) -> f32 {
+        let (contrast, gamma) = match self {
+            TextBlend::Linear => return mask,
+            TextBlend::Perceptual { contrast, gamma } => (contrast, gamma),
+        };
+
+        // based on skia's gamma hack as in the shader, which outputs the
+        // coverage for blending encoded values. Blending happens in linear
+        // light here, which only leaves the contrast boost against the
+        // inverted luma as background.
+        let lin_dst = (1.0 - luma).clamp(0.0, 1.0).powf(gamma);
+        mask + (1.0 - mask) * lin_dst * contrast * mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rgb(a: [f32; 3], b: [f32; 3], eps: f32) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < eps, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn transfer() {
+        for (srgb, linear) in [
+            (0.0, 0.0),
+            (0.04045, 0.003_130_8),
+            (0.5, 0.214_041_14),
+            (0.735_356_9, 0.5),
+            (1.0, 1.0),
+            (-0.5, -0.214_041_14),
+        ] {
+            assert!((srgb_to_linear(srgb) - linear).abs() < 1e-6);
+            assert!((linear_to_srgb(linear) - srgb).abs() < 1e-5);
+        }
+        for i in 0..=255 {
+            let c = i as f32 / 255.0;
+            assert_eq!(
+                (linear_to_srgb(srgb_to_linear(c)) * 255.0).round(),
+                i as f32
+            );
+        }
+    }
+
+    #[test]
+    fn display_p3() {
+        // sRGB red and green inside of P3
+        let red = ColorSpace::Srgb.convert(ColorSpace::DisplayP3, [1.0, 0.0, 0.0]);
+        assert_rgb(red, [0.917_488, 0.200_287, 0.138_561], 1e-4);
+        let green = ColorSpace::Srgb.convert(ColorSpace::DisplayP3, [0.0, 1.0, 0.0]);
+        assert_rgb(green, [0.458_402, 0.985_265, 0.298_295], 1e-4);
+
+        // P3 red is outside of sRGB
+        let red = ColorSpace::DisplayP3.to_linear_srgb([1.0, 0.0, 0.0]);
+        assert_rgb(red, [1.224_94, -0.042_057, -0.019_638], 1e-5);
+
+        // white point is shared
+        for space in [ColorSpace::Srgb, ColorSpace::LinearSrgb] {
+            let white = space.convert(ColorSpace::DisplayP3, [1.0; 3]);
+            assert_rgb(white, [1.0; 3], 1e-5);
+        }
+
+        let c = [0.2, 0.7, 0.4];
+        let p3 = ColorSpace::LinearSrgb.convert(ColorSpace::DisplayP3, c);
+        assert_rgb(
+            ColorSpace::DisplayP3.convert(ColorSpace::LinearSrgb, p3),
+            c,
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn premultiplied() {
+        let c = rgbaf32::from_straight(0.5, 1.0, 0.0, 0.5);
+        let linear = c.convert(ColorSpace::Srgb, ColorSpace::LinearSrgb);
+        assert!((linear.r - 0.214_041_14 * 0.5).abs() < 1e-6);
+        assert_eq!((linear.g, linear.b, linear.a), (0.5, 0.0, 0.5));
+        assert_eq!(
+            rgbaf32::TRANSPARENT.convert(ColorSpace::Srgb, ColorSpace::DisplayP3),
+            rgbaf32::TRANSPARENT
+        );
+    }
+
+    #[test]
+    fn text_blend() {
+        let text = TextBlend::PERCEPTUAL;
+        for luma in [0.0, 0.3, 1.0] {
+            assert_eq!(text.coverage(0.0, luma), 0.0);
+            assert!((text.coverage(1.0, luma) - 1.0).abs() < 1e-5);
+            assert_eq!(TextBlend::Linear.coverage(0.3, luma), 0.3);
+        }
+
+        // dark text gets bolder, light text stays linear
+        assert_eq!(text.coverage(0.5, 0.0), 0.625);
+        assert_eq!(text.coverage(0.5, 1.0), 0.5);
+        let gray = text.coverage(0.5, 0.5);
+        assert!((gray - (0.5 + 0.125 * 0.5f32.powf(2.2))).abs() < 1e-6);
+    }
+}
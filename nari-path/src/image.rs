@@ -0,0 +1,148 @@
+use crate::color::ColorSpace;
+use std::ops::{Add, Index, IndexMut, Mul};
+
+/// Premultiplied RGBA color with f32 channels.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub struct rgbaf32 {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl rgbaf32 {
+    pub const TRANSPARENT: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Self = Self::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Self = Self::new(1.0, 1.0, 1.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Premultiplied color from straight alpha channels.
+    pub fn from_straight(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::new(r * a, g * a, b * a, a)
+    }
+
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        self * (1.0 - t) + other * t
+    }
+}
+
+impl Add for rgbaf32 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(
+            self.r + rhs.r,
+            self.g + rhs.g,
+            self.b + rhs.b,
+            self.a + rhs.a,
+        )
+    }
+}
+
+impl Mul<f32> for rgbaf32 {
+    type Output = Self;
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a * rhs)
+    }
+}
+
+/// Framebuffer of premultiplied `rgbaf32` pixels.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub memory: Box<[rgbaf32]>,
+    pub width: u32,
+    pub height: u32,
+    pub row_pitch: usize,
+    /// Color space of the stored values.
+    pub color_space: ColorSpace,
+}
+
+impl Image {
+    /// Image filled with `clear`, panics if the pixel count overflows `usize`.
+    pub fn new(width: u32, height: u32, clear: rgbaf32) -> Self {
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .expect("image size overflows");
+        Self {
+            memory: vec![clear; len].into(),
+            width,
+            height,
+            row_pitch: width as usize,
+            color_space: ColorSpace::LinearSrgb,
+        }
+    }
+
+    /// Tag the stored values with `color_space`, without converting them.
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Copy of the image with the values converted to `color_space`.
+    pub fn convert(&self, color_space: ColorSpace) -> Image {
+        let mut image = self.clone();
+        for pixel in image.memory.iter_mut() {
+            *pixel = pixel.convert(self.color_space, color_space);
+        }
+        image.color_space = color_space;
+        image
+    }
+
+    /// Stored values as 8-bit straight alpha RGBA rows.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        let mut rgba = Vec::with_capacity(self.memory.len() * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = self[(x, y)];
+                let [r, g, b] = pixel.straight();
+                rgba.extend([quantize(r), quantize(g), quantize(b), quantize(pixel.a)]);
+            }
+        }
+        rgba
+    }
+
+    /// Pixels of row `y` starting at `x`.
+    pub fn row_mut(&mut self, x: u32, y: u32) -> &mut [rgbaf32] {
+        let start = x as usize + y as usize * self.row_pitch;
+        &mut self.memory[start..y as usize * self.row_pitch + self.width as usize]
+    }
+}
+
+pub type FragmentIdx = (u32, u32);
+
+impl Index<FragmentIdx> for Image {
+    type Output = rgbaf32;
+    fn index(&self, (x, y): FragmentIdx) -> &Self::Output {
+        let idx = x as usize + y as usize * self.row_pitch;
+        &self.memory[idx]
+    }
+}
+
+impl IndexMut<FragmentIdx> for Image {
+    fn index_mut(&mut self, (x, y): FragmentIdx) -> &mut Self::Output {
+        let idx = x as usize + y as usize * self.row_pitch;
+        &mut self.memory[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let mut image = Image::new(2, 1, rgbaf32::TRANSPARENT);
+        image[(0, 0)] = rgbaf32::from_straight(0.214_041_14, 1.0, 0.0, 0.5);
+        let srgb = image.convert(ColorSpace::Srgb);
+        assert_eq!(srgb.color_space, ColorSpace::Srgb);
+        assert_eq!(srgb.to_rgba8(), [128, 255, 0, 128, 0, 0, 0, 0]);
+        assert_eq!(image.to_rgba8(), [55, 255, 0, 128, 0, 0, 0, 0]);
+
+        let linear = srgb.convert(ColorSpace::LinearSrgb);
+        assert!((linear[(0, 0)].r - image[(0, 0)].r).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,577 @@
+//! Analytic coverage of quadratic curves.
+//!
+//! Paths are split into quadratic segments which are monotonic in x and y.
+//! A quadtree over the segments resolves winding contributions of segments
+//! right of a cell into a single winding number, leaving only a few segments
+//! per leaf cell. Winding and pixel coverage are then evaluated exactly from
+//! the curves without flattening.
+
+use kurbo::{CubicBez, PathEl, Point, Rect};
+
+/// Default number of segments per leaf cell of the tree.
+pub const MAX_CELL_SEGMENTS: usize = 4;
+
+/// Quadratic bezier segment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Curve2 {
+    pub p0: Point,
+    pub p1: Point,
+    pub p2: Point,
+}
+
+impl Curve2 {
+    pub fn new(p0: Point, p1: Point, p2: Point) -> Self {
+        Self { p0, p1, p2 }
+    }
+
+    /// Straight line as degenerate quadratic segment.
+    pub fn line(p0: Point, p1: Point) -> Self {
+        Self {
+            p0,
+            p1: p0.midpoint(p1),
+            p2: p1,
+        }
+    }
+
+    pub fn eval(&self, t: f64) -> Point {
+        let tt = 1.0 - t;
+        (self.p0.to_vec2() * (tt * tt)
+            + (self.p1.to_vec2() * (tt * 2.0) + self.p2.to_vec2() * t) * t)
+            .to_point()
+    }
+
+    /// Split the curve at its x and y extrema into monotonic segments.
+    pub fn monotonize(&self) -> Vec<Self> {
+        let a = self.p0.to_vec2() - 2.0 * self.p1.to_vec2() + self.p2.to_vec2();
+        let b = self.p1 - self.p0;
+
+        fn intersection(a: f64, b: f64) -> Option<f64> {
+            if a.signum() != b.signum() && b != 0.0 && b.abs() < a.abs() {
+                return Some(-b / a);
+            }
+            None
+        }
+
+        let mut roots = Vec::new();
+        if let Some(root) = intersection(a.x, b.x) {
+            roots.push(root);
+        }
+        if let Some(root) = intersection(a.y, b.y) {
+            roots.push(root);
+        }
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots.push(1.0);
+
+        let mut curves = Vec::new();
+        let mut prev = self.p0;
+        let mut t_prev = 0.0;
+        for t in roots {
+            if t <= t_prev {
+                continue;
+            }
+            let p0 = prev;
+            let p2 = if t == 1.0 { self.p2 } else { self.eval(t) };
+            let p1 = p0 + (self.p1 - self.p0).lerp(self.p2 - self.p1, t_prev) * (t - t_prev);
+            curves.push(Curve2 { p0, p1, p2 });
+            prev = p2;
+            t_prev = t;
+        }
+
+        curves
+    }
+
+    /// Winding direction of a monotonic segment, positive for increasing y.
+    pub fn direction(&self) -> isize {
+        if self.p2.y > self.p0.y {
+            1
+        } else {
+            -1
+        }
+    }
+
+    /// Parameter at which a monotonic segment crosses the horizontal line `y`.
+    fn t_at_y(&self, y: f64) -> f64 {
+        let a = self.p0.y - 2.0 * self.p1.y + self.p2.y;
+        let b = 2.0 * (self.p1.y - self.p0.y);
+        solve_monotone(a, b, self.p0.y - y)
+    }
+
+    /// Parameter at which a monotonic segment crosses the vertical line `x`.
+    fn t_at_x(&self, x: f64) -> f64 {
+        let a = self.p0.x - 2.0 * self.p1.x + self.p2.x;
+        let b = 2.0 * (self.p1.x - self.p0.x);
+        solve_monotone(a, b, self.p0.x - x)
+    }
+
+    /// Contribution of a monotonic segment to the winding number at `pt`.
+    ///
+    /// Counts crossings of the ray from `pt` towards positive x.
+    pub fn winding(&self, pt: Point) -> isize {
+        let (y0, y1) = (self.p0.y.min(self.p2.y), self.p0.y.max(self.p2.y));
+        if pt.y < y0 || pt.y >= y1 || pt.x >= self.p0.x.max(self.p2.x) {
+            return 0;
+        }
+        if pt.x < self.p0.x.min(self.p2.x) || self.eval(self.t_at_y(pt.y)).x > pt.x {
+            return self.direction();
+        }
+        0
+    }
+
+    /// Signed area of the unit pixel at (`x`, `y`) which lies left of a
+    /// monotonic segment.
+    ///
+    /// Equals the winding contribution of the segment averaged over the pixel.
+    pub fn area(&self, x: f64, y: f64) -> f64 {
+        let (ymin, ymax) = (self.p0.y.min(self.p2.y), self.p0.y.max(self.p2.y));
+        let ta = self.t_at_y(y.clamp(ymin, ymax));
+        let tb = self.t_at_y((y + 1.0).clamp(ymin, ymax));
+        let (ta, tb) = (ta.min(tb), ta.max(tb));
+        if ta >= tb {
+            return 0.0;
+        }
+
+        // split at the pixel's vertical boundaries
+        let mut ts = [ta, tb, tb, tb];
+        let (xmin, xmax) = (self.p0.x.min(self.p2.x), self.p0.x.max(self.p2.x));
+        for (i, bound) in [x, x + 1.0].into_iter().enumerate() {
+            if xmin < bound && bound < xmax {
+                ts[i + 1] = self.t_at_x(bound).clamp(ta, tb);
+            }
+        }
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let ax = self.p0.x - 2.0 * self.p1.x + self.p2.x;
+        let bx = 2.0 * (self.p1.x - self.p0.x);
+        let cx = self.p0.x - x;
+        let ay = self.p0.y - 2.0 * self.p1.y + self.p2.y;
+        let by = 2.0 * (self.p1.y - self.p0.y);
+
+        // antiderivative of (x(t) - x) * y'(t)
+        let integral = |t: f64| {
+            t * (cx * by
+                + t * ((bx * by + 2.0 * cx * ay) / 2.0
+                    + t * ((ax * by + 2.0 * bx * ay) / 3.0 + t * (ax * ay / 2.0))))
+        };
+
+        let mut area = 0.0;
+        for span in ts.windows(2) {
+            let (t0, t1) = (span[0], span[1]);
+            if t0 >= t1 {
+                continue;
+            }
+            let xm = self.eval(0.5 * (t0 + t1)).x;
+            if xm >= x + 1.0 {
+                area += self.eval(t1).y - self.eval(t0).y;
+            } else if xm > x {
+                area += integral(t1) - integral(t0);
+            }
+        }
+        area
+    }
+}
+
+/// Root of `a t² + b t + c` within [0, 1] for a quadratic monotonic on [0, 1].
+fn solve_monotone(a: f64, b: f64, c: f64) -> f64 {
+    let q = -0.5 * (b + b.signum() * (b * b - 4.0 * a * c).max(0.0).sqrt());
+    if q == 0.0 {
+        return 0.0;
+    }
+    let distance = |t: f64| (-t).max(t - 1.0);
+    let (r0, r1) = (q / a, c / q);
+    let root = if a == 0.0 || distance(r1) <= distance(r0) {
+        r1
+    } else {
+        r0
+    };
+    root.clamp(0.0, 1.0)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TreeElement {
+    /// Index of a segment intersecting the cell.
+    Segment(usize),
+    /// Winding number of segments spanning the cell on its right side.
+    Winding(isize),
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeCell {
+    pub rect: Rect,
+    pub elements: Vec<TreeElement>,
+}
+
+impl TreeCell {
+    fn num_segments(&self) -> usize {
+        self.elements
+            .iter()
+            .filter(|element| matches!(element, TreeElement::Segment(_)))
+            .count()
+    }
+}
+
+/// Quadtree over monotonic quadratic segments.
+///
+/// The root cell is a pixel aligned square with power of two size, such that
+/// all cells down to single pixels are aligned to the pixel grid. Cells
+/// without segments and winding are omitted.
+#[derive(Clone, Debug)]
+pub struct Tree {
+    pub rect: Rect,
+    pub segments: Vec<Curve2>,
+    pub cells: Vec<TreeCell>,
+}
+
+impl Tree {
+    /// Create a tree with a single root cell from monotonic segments.
+    pub fn new(segments: Vec<Curve2>) -> Self {
+        let segments: Vec<_> = segments
+            .into_iter()
+            .filter(|segment| segment.p0.y != segment.p2.y)
+            .collect();
+        if segments.is_empty() {
+            return Tree {
+                rect: Rect::ZERO,
+                segments,
+                cells: Vec::new(),
+            };
+        }
+
+        let mut bounds = Rect::from_points(segments[0].p0, segments[0].p0);
+        for segment in &segments {
+            bounds = bounds.union_pt(segment.p0).union_pt(segment.p2);
+        }
+        let origin = Point::new(bounds.x0.floor(), bounds.y0.floor());
+        let extent = (bounds.x1.ceil() - origin.x)
+            .max(bounds.y1.ceil() - origin.y)
+            .max(1.0);
+        let size = (extent as u64).next_power_of_two() as f64;
+        let rect = Rect::from_origin_size(origin, (size, size));
+
+        let elements = (0..segments.len()).map(TreeElement::Segment).collect();
+        Tree {
+            rect,
+            segments,
+            cells: vec![TreeCell { rect, elements }],
+        }
+    }
+
+    /// Build a subdivided tree from path elements.
+    ///
+    /// Cubic curves are approximated by quadratics within `accuracy`,
+    /// subpaths are implicitly closed.
+    pub fn from_path(path: impl IntoIterator<Item = PathEl>, accuracy: f64) -> Self {
+        let mut tree = Tree::new(segments(path, accuracy));
+        while tree
+            .cells
+            .iter()
+            .any(|cell| tree.is_splittable(cell, MAX_CELL_SEGMENTS))
+        {
+            tree = tree.split(MAX_CELL_SEGMENTS);
+        }
+        tree
+    }
+
+    fn is_splittable(&self, cell: &TreeCell, max_segments: usize) -> bool {
+        cell.rect.width() > 1.0 && cell.num_segments() > max_segments
+    }
+
+    /// Split all cells with more than `max_segments` segments into quadrants.
+    ///
+    /// Segments left of a child cell are dropped, segments spanning the
+    /// child cell vertically on its right side are folded into its winding.
+    pub fn split(&self, max_segments: usize) -> Self {
+        let mut cells = Vec::new();
+        for cell in &self.cells {
+            if !self.is_splittable(cell, max_segments) {
+                cells.push(cell.clone());
+                continue;
+            }
+
+            let center = cell.rect.center();
+            let quadrants = [
+                Rect::new(cell.rect.x0, cell.rect.y0, center.x, center.y),
+                Rect::new(center.x, cell.rect.y0, cell.rect.x1, center.y),
+                Rect::new(cell.rect.x0, center.y, center.x, cell.rect.y1),
+                Rect::new(center.x, center.y, cell.rect.x1, cell.rect.y1),
+            ];
+
+            for rect in quadrants {
+                let mut winding = 0;
+                let mut elements = Vec::new();
+                for element in &cell.elements {
+                    match *element {
+                        TreeElement::Segment(i) => {
+                            let segment = &self.segments[i];
+                            let Curve2 { p0, p2, .. } = *segment;
+                            let (y0, y1) = (p0.y.min(p2.y), p0.y.max(p2.y));
+
+                            if y1 <= rect.y0 || y0 >= rect.y1 || p0.x.max(p2.x) <= rect.x0 {
+                                continue;
+                            }
+                            if p0.x.min(p2.x) >= rect.x1 && y0 <= rect.y0 && y1 >= rect.y1 {
+                                winding += segment.direction();
+                            } else {
+                                elements.push(TreeElement::Segment(i));
+                            }
+                        }
+                        TreeElement::Winding(w) => winding += w,
+                    }
+                }
+
+                if winding != 0 {
+                    elements.push(TreeElement::Winding(winding));
+                }
+                if !elements.is_empty() {
+                    cells.push(TreeCell { rect, elements });
+                }
+            }
+        }
+
+        Self {
+            rect: self.rect,
+            segments: self.segments.clone(),
+            cells,
+        }
+    }
+
+    fn cell(&self, pt: Point) -> Option<&TreeCell> {
+        self.cells.iter().find(|cell| {
+            let rect = cell.rect;
+            pt.x >= rect.x0 && pt.x < rect.x1 && pt.y >= rect.y0 && pt.y < rect.y1
+        })
+    }
+
+    /// Winding number at `pt`.
+    pub fn winding(&self, pt: Point) -> isize {
+        let cell = match self.cell(pt) {
+            Some(cell) => cell,
+            None => return 0,
+        };
+
+        cell.elements
+            .iter()
+            .map(|element| match *element {
+                TreeElement::Segment(i) => self.segments[i].winding(pt),
+                TreeElement::Winding(w) => w,
+            })
+            .sum()
+    }
+
+    /// Exact coverage of the pixel at (`x`, `y`) for non-overlapping regions.
+    ///
+    /// Nonzero fill is approximated by clamping the accumulated area like
+    /// nari-ochre.
+    pub fn coverage(&self, x: i32, y: i32) -> f32 {
+        let pt = Point::new(x as f64, y as f64);
+        match self.cell(pt) {
+            Some(cell) => self.cell_coverage(cell, pt),
+            None => 0.0,
+        }
+    }
+
+    fn cell_coverage(&self, cell: &TreeCell, pt: Point) -> f32 {
+        let area: f64 = cell
+            .elements
+            .iter()
+            .map(|element| match *element {
+                TreeElement::Segment(i) => self.segments[i].area(pt.x, pt.y),
+                TreeElement::Winding(w) => w as f64,
+            })
+            .sum();
+        area.abs().min(1.0) as f32
+    }
+
+    /// Evaluate the coverage of all pixels with nonzero coverage.
+    pub fn rasterize(&self, mut pixel: impl FnMut(i32, i32, f32)) {
+        for cell in &self.cells {
+            let rect = cell.rect;
+            for y in rect.y0 as i32..rect.y1 as i32 {
+                for x in rect.x0 as i32..rect.x1 as i32 {
+                    let coverage = self.cell_coverage(cell, Point::new(x as f64, y as f64));
+                    if coverage > 0.0 {
+                        pixel(x, y, coverage);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Monotonic quadratic segments of a path, subpaths are implicitly closed.
+pub fn segments(path: impl IntoIterator<Item = PathEl>, accuracy: f64) -> Vec<Curve2> {
+    let mut curves = Vec::new();
+    let mut start = Point::ZERO;
+    let mut cur = Point::ZERO;
+    fn close(curves: &mut Vec<Curve2>, from: Point, to: Point) {
+        if from != to {
+            curves.push(Curve2::line(from, to));
+        }
+    }
+
+    for element in path {
+        match element {
+            PathEl::MoveTo(p) => {
+                close(&mut curves, cur, start);
+                start = p;
+                cur = p;
+            }
+            PathEl::LineTo(p) => {
+                curves.push(Curve2::line(cur, p));
+                cur = p;
+            }
+            PathEl::QuadTo(p1, p2) => {
+                curves.extend(Curve2::new(cur, p1, p2).monotonize());
+                cur = p2;
+            }
+            PathEl::CurveTo(p1, p2, p3) => {
+                for (_, _, quad) in CubicBez::new(cur, p1, p2, p3).to_quads(accuracy) {
+                    curves.extend(Curve2::new(quad.p0, quad.p1, quad.p2).monotonize());
+                }
+                cur = p3;
+            }
+            PathEl::ClosePath => {
+                close(&mut curves, cur, start);
+                cur = start;
+            }
+        }
+    }
+    close(&mut curves, cur, start);
+
+    curves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::{error, ochre};
+    use kurbo::{Affine, BezPath, Circle, ParamCurveArea, QuadBez, Shape};
+
+    const SIZE: usize = 128;
+
+    fn rasterize(tree: &Tree) -> Vec<f32> {
+        let mut image = vec![0.0; SIZE * SIZE];
+        tree.rasterize(|x, y, coverage| image[y as usize * SIZE + x as usize] = coverage);
+        image
+    }
+
+    fn ring() -> BezPath {
+        let mut path = Circle::new((60.3, 64.7), 50.2).to_path(0.001);
+        // mirrored inner circle with opposite orientation
+        let hole = Affine::new([-1.0, 0.0, 0.0, 1.0, 140.2, 0.0]);
+        path.extend(hole * Circle::new((70.1, 60.4), 20.6).to_path(0.001));
+        path
+    }
+
+    fn quads() -> BezPath {
+        let mut path = BezPath::new();
+        path.move_to((10.0, 20.5));
+        path.quad_to((64.0, -10.0), (118.3, 20.5));
+        path.quad_to((80.0, 64.0), (118.3, 110.2));
+        path.quad_to((64.0, 140.0), (10.0, 110.2));
+        path.quad_to((60.0, 64.0), (10.0, 20.5));
+        path.close_path();
+        path
+    }
+
+    fn pentagram() -> BezPath {
+        let mut path = BezPath::new();
+        for i in 0..5 {
+            let a = (i * 2) as f64 * std::f64::consts::TAU / 5.0;
+            let p = (64.0 + 60.0 * a.sin(), 64.0 - 60.0 * a.cos());
+            if i == 0 {
+                path.move_to(p);
+            } else {
+                path.line_to(p);
+            }
+        }
+        path.close_path();
+        path
+    }
+
+    #[test]
+    fn monotonize() {
+        let curve = Curve2::new(
+            Point::new(0.0, 0.0),
+            Point::new(100.0, 80.0),
+            Point::new(10.0, 20.0),
+        );
+        let segments = curve.monotonize();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].p0, curve.p0);
+        assert_eq!(segments[2].p2, curve.p2);
+        for (segment, next) in segments.iter().zip(&segments[1..]) {
+            assert_eq!(segment.p2, next.p0);
+        }
+        for segment in &segments {
+            for i in 0..=16 {
+                let t = i as f64 / 16.0;
+                let p = segment.eval(t);
+                let distance = (0..=4096)
+                    .map(|j| curve.eval(j as f64 / 4096.0).distance(p))
+                    .fold(f64::MAX, f64::min);
+                assert!(distance < 0.05);
+            }
+            let (x0, x1) = (
+                segment.p0.x.min(segment.p2.x),
+                segment.p0.x.max(segment.p2.x),
+            );
+            let (y0, y1) = (
+                segment.p0.y.min(segment.p2.y),
+                segment.p0.y.max(segment.p2.y),
+            );
+            assert!(segment.p1.x >= x0 - 1e-9 && segment.p1.x <= x1 + 1e-9);
+            assert!(segment.p1.y >= y0 - 1e-9 && segment.p1.y <= y1 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn winding() {
+        for path in [ring(), quads(), pentagram()] {
+            let tree = Tree::from_path(path.elements().iter().copied(), 0.001);
+            assert!(tree.cells.len() > 1);
+            for y in 0..64 {
+                for x in 0..64 {
+                    let pt = Point::new(x as f64 * 2.0 + 0.37, y as f64 * 2.0 + 0.71);
+                    let expected: isize = tree.segments.iter().map(|s| s.winding(pt)).sum();
+                    assert_eq!(tree.winding(pt), expected, "{:?}", pt);
+                }
+            }
+
+            let inside = path.winding(Point::new(64.0, 96.0));
+            assert_eq!(tree.winding(Point::new(64.0, 96.0)), inside as isize);
+        }
+    }
+
+    #[test]
+    fn area() {
+        // total coverage of non-overlapping regions equals the enclosed area
+        for path in [ring(), quads()] {
+            let tree = Tree::from_path(path.elements().iter().copied(), 0.001);
+            let expected: f64 = tree
+                .segments
+                .iter()
+                .map(|s| QuadBez::new(s.p0, s.p1, s.p2).signed_area())
+                .sum();
+            let mut area = 0.0;
+            tree.rasterize(|_, _, coverage| area += coverage as f64);
+            assert!(
+                (area - expected.abs()).abs() < 1e-3,
+                "{} {}",
+                area,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn ochre_reference() {
+        for (path, max) in [(ring(), 0.01), (quads(), 0.02), (pentagram(), 0.005)] {
+            let tree = Tree::from_path(path.elements().iter().copied(), 0.001);
+            let image = rasterize(&tree);
+            let reference = ochre(&path, SIZE, SIZE);
+
+            let (error, _) = error(&image, &reference);
+            assert!(error < max, "max error {}", error);
+        }
+    }
+}
@@ -1 +1,12 @@
+pub mod blend;
+pub mod boolean;
+pub mod color;
+pub mod image;
+pub mod implicit;
+pub mod msaa;
+pub mod paint;
+#[cfg(test)]
+mod reference;
+pub mod render;
 
+pub use kurbo;
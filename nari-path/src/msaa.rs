@@ -0,0 +1,607 @@
+//! Sparse multisample rasterizer.
+//!
+//! The framebuffer is partitioned into quads of 2x2 pixels with 8 samples per
+//! pixel. Each line emits sample masks for the quads it crosses, and row
+//! masks of the crossed sample rows for the quads right of it. Resolving the
+//! row masks per quad row yields fills for the quads in between, such that
+//! only the quads along the path outline carry explicit sample masks.
+
+use kurbo::PathEl;
+use nari_ochre::FillRule;
+
+pub const SAMPLES: u32 = 8;
+/// Horizontal sample location inside the pixel for each sample row of a quad.
+pub const SAMPLE_LOCATIONS: [i32; 16] = [0, 5, 3, 7, 1, 4, 6, 2, 0, 5, 3, 7, 1, 4, 6, 2];
+
+pub const QUAD_SIZE: u32 = 2;
+const QUAD_SIZE_F32: f32 = QUAD_SIZE as f32;
+/// Number of sample rows of a quad.
+const QUAD_ROWS: i32 = (QUAD_SIZE * SAMPLES) as i32;
+
+/// Number of quads in each dimension.
+pub const TILE_SIZE: u16 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub struct vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl vec2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CoverageQuad {
+    pub x: u16,
+    pub y: u16,
+
+    /// Winding of line according to x/y rays
+    pub winding: i32,
+    pub coverage: Coverage,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// Fill with winding number of topleft corner.
+    Fill(u32),
+
+    /// Sample mask for the quad patch.
+    /// 8 samples per pixel, bits 0..16 cover the sample rows of the left
+    /// pixel column and bits 16..32 the right one.
+    Mask(u32),
+}
+
+/// Tile of `TILE_SIZE`x`TILE_SIZE` quads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u16,
+    pub y: u16,
+
+    /// Index of the first quad of the tile.
+    pub quad_start: usize,
+    /// Occupied quads of the tile, bit `y * TILE_SIZE + x`.
+    pub quad_mask: u64,
+}
+
+/// Line crossing the sample rows in `mask` left of quad (`x`, `y`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Intersect {
+    pub x: u16,
+    pub y: u16,
+    pub winding: i32,
+    pub mask: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FrameParams {
+    /// Number of quads the framebuffer can fit in x direction.
+    pub width_quads: u16,
+    /// Number of quads the framebuffer can fit in y direction.
+    pub height_quads: u16,
+}
+
+impl FrameParams {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width_quads: width.div_ceil(QUAD_SIZE) as u16,
+            height_quads: height.div_ceil(QUAD_SIZE) as u16,
+        }
+    }
+}
+
+/// Sample masks and intersections of a line with the sample rows.
+///
+/// Parts of the line outside of the frame are clipped, lines left of the
+/// frame still contribute to the winding of the quads right of it.
+pub fn traverse_line(
+    frame: FrameParams,
+    p0: vec2,
+    p1: vec2,
+) -> (Vec<CoverageQuad>, Vec<Intersect>) {
+    let mut quads = Vec::default();
+    let mut intersects = Vec::default();
+
+    if p0.y == p1.y {
+        return (quads, intersects);
+    }
+
+    let (winding, top, bottom) = if p0.y < p1.y {
+        (1, p0, p1)
+    } else {
+        (-1, p1, p0)
+    };
+    let dx = (bottom.x - top.x) / (bottom.y - top.y);
+
+    // sample rows with centers inside [top.y, bottom.y)
+    let rows = frame.height_quads as i32 * QUAD_ROWS;
+    let y0 = ((top.y * SAMPLES as f32 - 0.5).ceil() as i32).clamp(0, rows);
+    let y1 = ((bottom.y * SAMPLES as f32 - 0.5).ceil() as i32).clamp(0, rows);
+
+    let mut quad: Option<CoverageQuad> = None;
+    let mut intersect: Option<Intersect> = None;
+
+    for y in y0..y1 {
+        let py = (y as f32 + 0.5) / SAMPLES as f32;
+        let x = top.x + (py - top.y) * dx;
+
+        let ty = (y / QUAD_ROWS) as u16;
+        let sy = y % QUAD_ROWS;
+        let tx = (x / QUAD_SIZE_F32).floor() as i32;
+        if tx >= frame.width_quads as i32 {
+            continue;
+        }
+
+        if tx >= 0 {
+            // samples right of the line inside the quad
+            let sx = x * SAMPLES as f32 - (tx * QUAD_ROWS) as f32;
+            let loc = SAMPLE_LOCATIONS[sy as usize] as f32 + 0.5;
+            let mut mask = 0u32;
+            if loc > sx {
+                mask |= 1 << sy;
+            }
+            if loc + SAMPLES as f32 > sx {
+                mask |= 1 << (sy + QUAD_ROWS);
+            }
+
+            let (x, y) = (tx as u16, ty);
+            match &mut quad {
+                Some(CoverageQuad {
+                    x: qx,
+                    y: qy,
+                    coverage: Coverage::Mask(quad_mask),
+                    ..
+                }) if *qx == x && *qy == y => *quad_mask |= mask,
+                _ => {
+                    quads.extend(quad.take().filter(has_samples));
+                    quad = Some(CoverageQuad {
+                        x,
+                        y,
+                        winding,
+                        coverage: Coverage::Mask(mask),
+                    });
+                }
+            }
+        }
+
+        let x = (tx + 1).max(0);
+        if x < frame.width_quads as i32 {
+            let x = x as u16;
+            match &mut intersect {
+                Some(i) if i.x == x && i.y == ty => i.mask |= 1 << sy,
+                _ => {
+                    intersects.extend(intersect.take());
+                    intersect = Some(Intersect {
+                        x,
+                        y: ty,
+                        winding,
+                        mask: 1 << sy,
+                    });
+                }
+            }
+        }
+    }
+
+    quads.extend(quad.filter(has_samples));
+    intersects.extend(intersect);
+
+    (quads, intersects)
+}
+
+fn has_samples(quad: &CoverageQuad) -> bool {
+    quad.coverage != Coverage::Mask(0)
+}
+
+/// Rasterizer accumulating the lines of one or more paths.
+pub struct Rasterizer {
+    frame: FrameParams,
+    width: u32,
+    height: u32,
+    fill_rule: FillRule,
+    quads: Vec<CoverageQuad>,
+    intersects: Vec<Intersect>,
+}
+
+impl Rasterizer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            frame: FrameParams::new(width, height),
+            width,
+            height,
+            fill_rule: FillRule::NonZero,
+            quads: Vec::new(),
+            intersects: Vec::new(),
+        }
+    }
+
+    /// Fill rule of the resolved coverage, defaults to `FillRule::NonZero`.
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+    }
+
+    pub fn line(&mut self, p0: vec2, p1: vec2) {
+        let (quads, intersects) = traverse_line(self.frame, p0, p1);
+        self.quads.extend(quads);
+        self.intersects.extend(intersects);
+    }
+
+    /// Flatten the path within `tolerance` and add its lines.
+    ///
+    /// Subpaths are implicitly closed.
+    pub fn fill(&mut self, path: impl IntoIterator<Item = PathEl>, tolerance: f64) {
+        let mut start = vec2::new(0.0, 0.0);
+        let mut cur = start;
+        kurbo::flatten(path, tolerance, |element| match element {
+            PathEl::MoveTo(p) => {
+                self.line(cur, start);
+                start = vec2::new(p.x as f32, p.y as f32);
+                cur = start;
+            }
+            PathEl::LineTo(p) => {
+                let p = vec2::new(p.x as f32, p.y as f32);
+                self.line(cur, p);
+                cur = p;
+            }
+            PathEl::ClosePath => {
+                self.line(cur, start);
+                cur = start;
+            }
+            _ => unreachable!(),
+        });
+        self.line(cur, start);
+    }
+
+    /// Resolve the intersections into fills and sort all quads into tiles.
+    pub fn finish(mut self) -> Raster {
+        self.intersects
+            .sort_by(|a, b| a.y.cmp(&b.y).then(a.x.cmp(&b.x)));
+        self.quads.sort_by(|a, b| a.y.cmp(&b.y).then(a.x.cmp(&b.x)));
+
+        let mut quads = Vec::with_capacity(self.quads.len());
+        let mut intersects = &self.intersects[..];
+        let mut masks = &self.quads[..];
+        while !intersects.is_empty() || !masks.is_empty() {
+            let y = match (intersects.first(), masks.first()) {
+                (Some(i), Some(q)) => i.y.min(q.y),
+                (Some(i), None) => i.y,
+                (None, Some(q)) => q.y,
+                (None, None) => unreachable!(),
+            };
+            let ni = intersects.iter().take_while(|i| i.y == y).count();
+            let nq = masks.iter().take_while(|q| q.y == y).count();
+            self.resolve_row(y, &intersects[..ni], &masks[..nq], &mut quads);
+            intersects = &intersects[ni..];
+            masks = &masks[nq..];
+        }
+
+        // group quads by tile, stable to keep quads of one position together
+        quads.sort_by_key(|q| (q.y / TILE_SIZE, q.x / TILE_SIZE, q.y, q.x));
+
+        let mut tiles: Vec<Tile> = Vec::new();
+        for (i, quad) in quads.iter().enumerate() {
+            let (x, y) = (quad.x / TILE_SIZE, quad.y / TILE_SIZE);
+            let bit = 1 << ((quad.y % TILE_SIZE) * TILE_SIZE + quad.x % TILE_SIZE);
+            match tiles.last_mut() {
+                Some(tile) if tile.x == x && tile.y == y => tile.quad_mask |= bit,
+                _ => tiles.push(Tile {
+                    x,
+                    y,
+                    quad_start: i,
+                    quad_mask: bit,
+                }),
+            }
+        }
+
+        Raster {
+            width: self.width,
+            height: self.height,
+            fill_rule: self.fill_rule,
+            quads,
+            tiles,
+        }
+    }
+
+    /// Emit the quads of a quad row, with fills for the winding of the
+    /// sample rows left of each quad.
+    fn resolve_row(
+        &self,
+        y: u16,
+        intersects: &[Intersect],
+        masks: &[CoverageQuad],
+        quads: &mut Vec<CoverageQuad>,
+    ) {
+        let mut winding = [0i32; QUAD_ROWS as usize];
+        let (mut i, mut q) = (0, 0);
+        let mut x = 0;
+        while x < self.frame.width_quads {
+            while i < intersects.len() && intersects[i].x == x {
+                for (row, w) in winding.iter_mut().enumerate() {
+                    if intersects[i].mask & (1 << row) != 0 {
+                        *w += intersects[i].winding;
+                    }
+                }
+                i += 1;
+            }
+
+            backdrop(x, y, &winding, quads);
+            while q < masks.len() && masks[q].x == x {
+                quads.push(masks[q]);
+                q += 1;
+            }
+
+            // skip empty quads
+            let next = match (intersects.get(i), masks.get(q)) {
+                (Some(i), Some(q)) => i.x.min(q.x),
+                (Some(i), None) => i.x,
+                (None, Some(q)) => q.x,
+                (None, None) => self.frame.width_quads,
+            };
+            if winding.iter().all(|w| *w == 0) {
+                x = next;
+            } else {
+                x += 1;
+            }
+        }
+    }
+}
+
+/// Quads for the accumulated winding of each sample row.
+fn backdrop(x: u16, y: u16, winding: &[i32; QUAD_ROWS as usize], quads: &mut Vec<CoverageQuad>) {
+    if winding.iter().all(|w| *w == winding[0]) {
+        if winding[0] != 0 {
+            quads.push(CoverageQuad {
+                x,
+                y,
+                winding: winding[0].signum(),
+                coverage: Coverage::Fill(winding[0].unsigned_abs()),
+            });
+        }
+        return;
+    }
+
+    let mut rows = 0u32;
+    for (row, w) in winding.iter().enumerate() {
+        if *w == 0 || rows & (1 << row) != 0 {
+            continue;
+        }
+        let mut mask = 0u32;
+        for (r, v) in winding.iter().enumerate().skip(row) {
+            if v == w {
+                mask |= 1 << r;
+            }
+        }
+        rows |= mask;
+        quads.push(CoverageQuad {
+            x,
+            y,
+            winding: *w,
+            coverage: Coverage::Mask(mask | (mask << QUAD_ROWS)),
+        });
+    }
+}
+
+/// Rasterized paths as sparse list of quads, grouped into tiles.
+#[derive(Clone, Debug)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pub fill_rule: FillRule,
+    /// Quads sorted by tile and position, with one or more quads for each
+    /// occupied position.
+    pub quads: Vec<CoverageQuad>,
+    pub tiles: Vec<Tile>,
+}
+
+impl Raster {
+    /// Quads of a tile.
+    pub fn tile_quads(&self, tile: usize) -> &[CoverageQuad] {
+        let end = self
+            .tiles
+            .get(tile + 1)
+            .map(|tile| tile.quad_start)
+            .unwrap_or(self.quads.len());
+        &self.quads[self.tiles[tile].quad_start..end]
+    }
+
+    /// Pixel coverage of the quads at the same position.
+    ///
+    /// Returns the coverage for the pixels in row major order.
+    pub fn resolve_quad(&self, quads: &[CoverageQuad]) -> [f32; 4] {
+        let mut winding = [0i32; 32];
+        for quad in quads {
+            match quad.coverage {
+                Coverage::Fill(num) => {
+                    for w in &mut winding {
+                        *w += num as i32 * quad.winding;
+                    }
+                }
+                Coverage::Mask(mask) => {
+                    for (s, w) in winding.iter_mut().enumerate() {
+                        if mask & (1 << s) != 0 {
+                            *w += quad.winding;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut coverage = [0.0; 4];
+        for iy in 0..QUAD_SIZE {
+            for ix in 0..QUAD_SIZE {
+                // Split sample mask into pixel.
+                let s = iy + ix * QUAD_SIZE;
+                let samples = winding[(s * SAMPLES) as usize..][..SAMPLES as usize]
+                    .iter()
+                    .filter(|w| self.fill_rule.is_inside(**w as isize))
+                    .count();
+                coverage[(iy * QUAD_SIZE + ix) as usize] = samples as f32 / SAMPLES as f32;
+            }
+        }
+        coverage
+    }
+
+    /// Coverage image with `width` x `height` pixels.
+    pub fn coverage(&self) -> Vec<f32> {
+        let mut image = vec![0.0; self.width as usize * self.height as usize];
+        for quads in self.quads.chunk_by(|a, b| a.x == b.x && a.y == b.y) {
+            let coverage = self.resolve_quad(quads);
+            for iy in 0..QUAD_SIZE {
+                for ix in 0..QUAD_SIZE {
+                    let x = quads[0].x as u32 * QUAD_SIZE + ix;
+                    let y = quads[0].y as u32 * QUAD_SIZE + iy;
+                    if x < self.width && y < self.height {
+                        image[y as usize * self.width as usize + x as usize] =
+                            coverage[(iy * QUAD_SIZE + ix) as usize];
+                    }
+                }
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::{error, ochre};
+    use kurbo::{BezPath, Circle, Rect, Shape};
+
+    const SIZE: u32 = 128;
+
+    fn rasterize(path: &BezPath, width: u32, height: u32) -> Raster {
+        let mut rasterizer = Rasterizer::new(width, height);
+        rasterizer.fill(path.elements().iter().copied(), 0.01);
+        rasterizer.finish()
+    }
+
+    fn polygon(points: &[(f64, f64)]) -> BezPath {
+        let mut path = BezPath::new();
+        path.move_to(points[0]);
+        for p in &points[1..] {
+            path.line_to(*p);
+        }
+        path.close_path();
+        path
+    }
+
+    fn diamond() -> BezPath {
+        polygon(&[(64.0, 14.0), (114.0, 64.0), (64.0, 114.0), (14.0, 64.0)])
+    }
+
+    fn pentagram() -> BezPath {
+        let points: Vec<_> = (0..5)
+            .map(|i| {
+                let a = (i * 2) as f64 * std::f64::consts::TAU / 5.0;
+                (64.0 + 60.0 * a.sin(), 64.0 - 60.0 * a.cos())
+            })
+            .collect();
+        polygon(&points)
+    }
+
+    #[test]
+    fn pixel_aligned() {
+        let path = Rect::new(10.0, 20.0, 37.0, 90.0).to_path(0.1);
+        let raster = rasterize(&path, SIZE, SIZE);
+        let reference = ochre(&path, SIZE as _, SIZE as _);
+        assert_eq!(error(&raster.coverage(), &reference), (0.0, 0.0));
+    }
+
+    #[test]
+    fn ochre_reference() {
+        let cases = [
+            ("diamond", diamond()),
+            ("circle", Circle::new((60.3, 64.7), 50.2).to_path(0.01)),
+            ("pentagram", pentagram()),
+            (
+                "sliver",
+                polygon(&[(4.0, 10.0), (120.0, 12.5), (4.0, 10.4)]),
+            ),
+            (
+                "near horizontal",
+                polygon(&[(2.0, 20.0), (126.0, 21.0), (126.0, 22.5), (2.0, 21.5)]),
+            ),
+        ];
+        for (name, path) in cases {
+            let raster = rasterize(&path, SIZE, SIZE);
+            let coverage = raster.coverage();
+            let reference = ochre(&path, SIZE as _, SIZE as _);
+
+            // 8 samples per pixel, edges pixels are off by a few samples
+            let (max, mean) = error(&coverage, &reference);
+            assert!(
+                max <= 0.4 && mean < 0.005,
+                "{}: max {}, mean {}",
+                name,
+                max,
+                mean
+            );
+
+            let area: f32 = coverage.iter().sum();
+            let expected: f32 = reference.iter().sum();
+            assert!(
+                (area - expected).abs() < 0.01 * expected,
+                "{}: area {} {}",
+                name,
+                area,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn even_odd() {
+        let pixel = |coverage: &[f32], x: usize, y: usize| coverage[y * SIZE as usize + x];
+        let nonzero = rasterize(&pentagram(), SIZE, SIZE).coverage();
+
+        let mut rasterizer = Rasterizer::new(SIZE, SIZE);
+        rasterizer.set_fill_rule(FillRule::EvenOdd);
+        rasterizer.fill(pentagram().elements().iter().copied(), 0.01);
+        let even_odd = rasterizer.finish().coverage();
+
+        // center of the pentagram has winding number 2
+        assert_eq!(pixel(&nonzero, 64, 64), 1.0);
+        assert_eq!(pixel(&even_odd, 64, 64), 0.0);
+        assert_eq!(pixel(&even_odd, 64, 12), 1.0);
+    }
+
+    #[test]
+    fn clipping() {
+        // partially outside of a frame with odd extent
+        let path = Circle::new((10.0, 100.0), 40.0).to_path(0.01);
+        let (width, height) = (95, 113);
+        let coverage = rasterize(&path, width, height).coverage();
+        let reference = ochre(&path, width as _, height as _);
+        let (max, mean) = error(&coverage, &reference);
+        assert!(max <= 0.4 && mean < 0.005, "max {}, mean {}", max, mean);
+    }
+
+    #[test]
+    fn tiles() {
+        let raster = rasterize(&pentagram(), SIZE, SIZE);
+        assert!(!raster.tiles.is_empty());
+        for (i, tile) in raster.tiles.iter().enumerate() {
+            let quads = raster.tile_quads(i);
+            let mut mask = 0u64;
+            for quad in quads {
+                assert_eq!((quad.x / TILE_SIZE, quad.y / TILE_SIZE), (tile.x, tile.y));
+                mask |= 1 << ((quad.y % TILE_SIZE) * TILE_SIZE + quad.x % TILE_SIZE);
+            }
+            assert_eq!(mask, tile.quad_mask);
+        }
+
+        // interior tiles are filled without sample masks
+        let raster = rasterize(&diamond(), SIZE, SIZE);
+        let center = raster
+            .tiles
+            .iter()
+            .position(|tile| (tile.x, tile.y) == (4, 4))
+            .unwrap();
+        assert!(raster
+            .tile_quads(center)
+            .iter()
+            .all(|quad| quad.coverage == Coverage::Fill(1)));
+    }
+}
@@ -0,0 +1,784 @@
+//! Paints for the CPU tile renderer.
+//!
+//! Paints are evaluated at pixel centers in device space. Solid spans of the
+//! rasterizer evaluate whole pixel rows at once, mask tiles evaluate the
+//! pixel rows of a tile and scale the colors by the mask coverage.
+
+use crate::blend::{blend_span_encoded, BlendMode};
+use crate::color::{linear_to_srgb, srgb_luma, ColorSpace, TextBlend};
+use crate::image::{rgbaf32, Image};
+use kurbo::{Affine, Point, Vec2};
+use nari_ochre::{Coord, Encoder, Tile, TILE_SIZE};
+use std::f64::consts::TAU;
+use std::ops::Range;
+use std::sync::Arc;
+
+pub use nari_scene::{Extend, GradientKind};
+
+/// Gradient stop with a premultiplied linear sRGB color.
+pub type ColorStop = nari_scene::ColorStop<rgbaf32>;
+
+/// Map the gradient parameter `t` into the `[0, 1]` range.
+pub fn extend(mode: Extend, t: f32) -> f32 {
+    match mode {
+        Extend::Pad => t.clamp(0.0, 1.0),
+        Extend::Repeat => t - t.floor(),
+        Extend::Reflect => {
+            let t = (t * 0.5 - (t * 0.5).floor()) * 2.0;
+            if t > 1.0 {
+                2.0 - t
+            } else {
+                t
+            }
+        }
+    }
+}
+
+/// Map the texel index `i` into `0..n`.
+fn extend_texel(mode: Extend, i: i64, n: u32) -> u32 {
+    let n = n as i64;
+    match mode {
+        Extend::Pad => i.clamp(0, n - 1) as u32,
+        Extend::Repeat => i.rem_euclid(n) as u32,
+        Extend::Reflect => {
+            let i = i.rem_euclid(2 * n);
+            if i >= n {
+                (2 * n - 1 - i) as u32
+            } else {
+                i as u32
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// Stops sorted by offset, interpolated in premultiplied space.
+    pub stops: Vec<ColorStop>,
+    pub extend: Extend,
+    /// Gradient to device space transform.
+    pub transform: Affine,
+}
+
+impl Gradient {
+    pub fn linear(start: impl Into<Point>, end: impl Into<Point>, stops: Vec<ColorStop>) -> Self {
+        Self::new(
+            GradientKind::Linear {
+                start: start.into(),
+                end: end.into(),
+            },
+            stops,
+        )
+    }
+
+    pub fn radial(center: impl Into<Point>, radius: f64, stops: Vec<ColorStop>) -> Self {
+        let center = center.into();
+        Self::new(
+            GradientKind::Radial {
+                center,
+                radius,
+                focal: center,
+            },
+            stops,
+        )
+    }
+
+    pub fn sweep(
+        center: impl Into<Point>,
+        start_angle: f64,
+        end_angle: f64,
+        stops: Vec<ColorStop>,
+    ) -> Self {
+        Self::new(
+            GradientKind::Sweep {
+                center: center.into(),
+                start_angle,
+                end_angle,
+            },
+            stops,
+        )
+    }
+
+    fn new(kind: GradientKind, stops: Vec<ColorStop>) -> Self {
+        Self {
+            kind,
+            stops,
+            extend: Extend::Pad,
+            transform: Affine::IDENTITY,
+        }
+    }
+
+    pub fn with_extend(mut self, extend: Extend) -> Self {
+        self.extend = extend;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Affine) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Gradient parameter at `p` in gradient space, `None` if undefined.
+    fn param(&self, p: Point) -> Option<f32> {
+        let t = match self.kind {
+            GradientKind::Linear { start, end } => {
+                let d = end - start;
+                let len2 = d.hypot2();
+                if len2 == 0.0 {
+                    return None;
+                }
+                (p - start).dot(d) / len2
+            }
+            GradientKind::Radial {
+                center,
+                radius,
+                focal,
+            } => {
+                // |q - t d| = t r
+                let d = center - focal;
+                let q = p - focal;
+                let a = d.hypot2() - radius * radius;
+                let b = q.dot(d);
+                let c = q.hypot2();
+                if a.abs() < 1e-9 * radius * radius {
+                    if b <= 0.0 {
+                        return None;
+                    }
+                    c / (2.0 * b)
+                } else {
+                    let disc = b * b - a * c;
+                    if disc < 0.0 {
+                        return None;
+                    }
+                    let (t0, t1) = ((b + disc.sqrt()) / a, (b - disc.sqrt()) / a);
+                    let t = t0.max(t1);
+                    if t < 0.0 {
+                        return None;
+                    }
+                    t
+                }
+            }
+            GradientKind::Sweep {
+                center,
+                start_angle,
+                end_angle,
+            } => {
+                if start_angle == end_angle {
+                    return None;
+                }
+                let v = p - center;
+                let angle = v.y.atan2(v.x).rem_euclid(TAU);
+                (angle - start_angle) / (end_angle - start_angle)
+            }
+        };
+        // e.g. degenerate radial gradients without radius
+        t.is_finite().then_some(t as f32)
+    }
+
+    /// Color of the stops at `t` after applying the extend mode, transparent
+    /// for NaN.
+    pub fn color(&self, t: f32) -> rgbaf32 {
+        let t = extend(self.extend, t);
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) if !t.is_nan() => (first, last),
+            _ => return rgbaf32::TRANSPARENT,
+        };
+        if t <= first.offset {
+            return first.color;
+        }
+        if t >= last.offset {
+            return last.color;
+        }
+
+        let i = self.stops.partition_point(|stop| stop.offset <= t);
+        let (s0, s1) = (&self.stops[i - 1], &self.stops[i]);
+        let dt = s1.offset - s0.offset;
+        if dt <= 0.0 {
+            return s1.color;
+        }
+        s0.color.lerp(s1.color, (t - s0.offset) / dt)
+    }
+
+    fn eval_span(&self, x: i32, y: i32, span: &mut [rgbaf32]) {
+        let Some((p, step)) = span_start(self.transform, x, y) else {
+            span.fill(rgbaf32::TRANSPARENT);
+            return;
+        };
+
+        if let GradientKind::Linear { start, end } = self.kind {
+            // linear in x, advance incrementally
+            let d = end - start;
+            let len2 = d.hypot2();
+            if len2 == 0.0 {
+                span.fill(rgbaf32::TRANSPARENT);
+                return;
+            }
+            let t0 = (p - start).dot(d) / len2;
+            let dt = step.dot(d) / len2;
+            for (i, pixel) in span.iter_mut().enumerate() {
+                *pixel = self.color((t0 + dt * i as f64) as f32);
+            }
+            return;
+        }
+
+        for (i, pixel) in span.iter_mut().enumerate() {
+            *pixel = match self.param(p + step * i as f64) {
+                Some(t) => self.color(t),
+                None => rgbaf32::TRANSPARENT,
+            };
+        }
+    }
+}
+
+/// Position of the pixel center (`x`, `y`) in paint space and the step
+/// between horizontal neighbors, `None` for singular transforms.
+fn span_start(transform: Affine, x: i32, y: i32) -> Option<(Point, Vec2)> {
+    let inverse = transform.inverse();
+    if !inverse.as_coeffs().iter().all(|c| c.is_finite()) {
+        return None;
+    }
+    let p = inverse * Point::new(x as f64 + 0.5, y as f64 + 0.5);
+    let step = inverse * Point::new(x as f64 + 1.5, y as f64 + 0.5) - p;
+    Some((p, step))
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// Image pattern.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub image: Arc<Image>,
+    /// Image to device space transform, texel `(i, j)` covers the unit
+    /// square at `(i, j)` in image space.
+    pub transform: Affine,
+    pub extend: Extend,
+    pub filter: Filter,
+}
+
+impl Pattern {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self {
+            image,
+            transform: Affine::IDENTITY,
+            extend: Extend::Pad,
+            filter: Filter::Bilinear,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Affine) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_extend(mut self, extend: Extend) -> Self {
+        self.extend = extend;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    fn texel(&self, i: i64, j: i64) -> rgbaf32 {
+        let image = &self.image;
+        let x = extend_texel(self.extend, i, image.width);
+        let y = extend_texel(self.extend, j, image.height);
+        image[(x, y)]
+    }
+
+    /// Sample the image at `p` in image space.
+    pub fn sample(&self, p: Point) -> rgbaf32 {
+        if self.image.width == 0 || self.image.height == 0 {
+            return rgbaf32::TRANSPARENT;
+        }
+        match self.filter {
+            Filter::Nearest => self.texel(p.x.floor() as i64, p.y.floor() as i64),
+            Filter::Bilinear => {
+                let (u, v) = (p.x - 0.5, p.y - 0.5);
+                let (i, j) = (u.floor(), v.floor());
+                let (fx, fy) = ((u - i) as f32, (v - j) as f32);
+                let (i, j) = (i as i64, j as i64);
+                let top = self.texel(i, j).lerp(self.texel(i + 1, j), fx);
+                let bottom = self.texel(i, j + 1).lerp(self.texel(i + 1, j + 1), fx);
+                top.lerp(bottom, fy)
+            }
+        }
+    }
+
+    fn eval_span(&self, x: i32, y: i32, span: &mut [rgbaf32]) {
+        let Some((p, step)) = span_start(self.transform, x, y) else {
+            span.fill(rgbaf32::TRANSPARENT);
+            return;
+        };
+        for (i, pixel) in span.iter_mut().enumerate() {
+            *pixel = self.sample(p + step * i as f64);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Paint {
+    Solid(rgbaf32),
+    Gradient(Gradient),
+    Pattern(Pattern),
+}
+
+impl Paint {
+    /// Color at the center of pixel (`x`, `y`).
+    pub fn eval(&self, x: i32, y: i32) -> rgbaf32 {
+        let mut color = [rgbaf32::TRANSPARENT];
+        self.eval_span(x, y, &mut color);
+        color[0]
+    }
+
+    /// Colors of the pixels in row `y` starting at column `x`.
+    pub fn eval_span(&self, x: i32, y: i32, span: &mut [rgbaf32]) {
+        match self {
+            Paint::Solid(color) => span.fill(*color),
+            Paint::Gradient(gradient) => gradient.eval_span(x, y, span),
+            Paint::Pattern(pattern) => pattern.eval_span(x, y, span),
+        }
+    }
+}
+
+/// Encoder painting rasterized paths onto an image.
+pub struct PaintEncoder<'a> {
+    image: &'a mut Image,
+    paint: &'a Paint,
+    mode: BlendMode,
+    text: TextBlend,
+    span: Vec<rgbaf32>,
+    coverage: Vec<f32>,
+}
+
+impl<'a> PaintEncoder<'a> {
+    pub fn new(image: &'a mut Image, paint: &'a Paint) -> Self {
+        Self {
+            image,
+            paint,
+            mode: BlendMode::SRC_OVER,
+            text: TextBlend::Linear,
+            span: Vec::new(),
+            coverage: Vec::new(),
+        }
+    }
+
+    /// Blend mode of the paint with the image, defaults to source-over.
+    pub fn with_blend(mut self, mode: impl Into<BlendMode>) -> Self {
+        self.mode = mode.into();
+        self
+    }
+
+    /// Coverage adjustment for text masks, defaults to linear.
+    pub fn with_text_blend(mut self, text: TextBlend) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Clip the pixel row `y` with columns `x` to the image.
+    fn clip(&self, y: i32, x: Range<i32>) -> Option<(u32, Range<u32>)> {
+        if y < 0 || y >= self.image.height as i32 {
+            return None;
+        }
+        let x0 = x.start.max(0);
+        let x1 = x.end.min(self.image.width as i32);
+        if x0 >= x1 {
+            return None;
+        }
+        Some((y as u32, x0 as u32..x1 as u32))
+    }
+
+    /// Paint the pixel row, scaled by the optional per pixel coverage of
+    /// the unclipped row.
+    fn row(&mut self, y: i32, x: Range<i32>, coverage: Option<&[u8; TILE_SIZE]>) {
+        let (py, px) = match self.clip(y, x.clone()) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+        let offset = (px.start as i32 - x.start) as usize;
+
+        self.span.clear();
+        self.span.resize(px.len(), rgbaf32::TRANSPARENT);
+        self.paint
+            .eval_span(px.start as i32, py as i32, &mut self.span);
+
+        let space = self.image.color_space;
+        if space == ColorSpace::DisplayP3 {
+            for color in &mut self.span {
+                *color = color.map_straight(|c| space.from_linear_srgb(c));
+            }
+        }
+
+        let coverage = coverage.map(|coverage| {
+            let coverage = &coverage[offset..offset + px.len()];
+            self.coverage.clear();
+            match self.text {
+                TextBlend::Linear => {
+                    self.coverage
+                        .extend(coverage.iter().map(|mask| *mask as f32 / 255.0));
+                }
+                TextBlend::Perceptual { .. } => {
+                    for (mask, src) in coverage.iter().zip(&self.span) {
+                        let luma = srgb_luma(src.straight().map(linear_to_srgb));
+                        let coverage = self.text.coverage(*mask as f32 / 255.0, luma);
+                        self.coverage.push(coverage);
+                    }
+                }
+            }
+            &self.coverage[..]
+        });
+        let row = &mut self.image.row_mut(px.start, py)[..px.len()];
+        blend_span_encoded(space, row, &self.span, coverage, self.mode);
+    }
+}
+
+impl Encoder for PaintEncoder<'_> {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        let tile = TILE_SIZE as i32;
+        let px = x.start as i32 * tile..x.end as i32 * tile;
+        for py in 0..tile {
+            self.row(y as i32 * tile + py, px.clone(), None);
+        }
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        let tile = TILE_SIZE as i32;
+        let px = x as i32 * tile..(x as i32 + 1) * tile;
+        for (py, coverage) in mask.iter().enumerate() {
+            self.row(y as i32 * tile + py as i32, px.clone(), Some(coverage));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kurbo::Shape;
+    use nari_ochre::Rasterizer;
+    use zeno::PathBuilder;
+
+    const RED: rgbaf32 = rgbaf32::new(1.0, 0.0, 0.0, 1.0);
+    const BLUE: rgbaf32 = rgbaf32::new(0.0, 0.0, 1.0, 1.0);
+
+    fn stops() -> Vec<ColorStop> {
+        vec![
+            ColorStop {
+                offset: 0.0,
+                color: RED,
+            },
+            ColorStop {
+                offset: 1.0,
+                color: BLUE,
+            },
+        ]
+    }
+
+    fn assert_color(a: rgbaf32, b: rgbaf32) {
+        let d = (a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() + (a.a - b.a).abs();
+        assert!(d < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn extend_modes() {
+        for (t, pad, repeat, reflect) in [
+            (0.25, 0.25, 0.25, 0.25),
+            (-0.25, 0.0, 0.75, 0.25),
+            (1.25, 1.0, 0.25, 0.75),
+            (2.5, 1.0, 0.5, 0.5),
+            (-1.75, 0.0, 0.25, 0.25),
+        ] {
+            assert!((extend(Extend::Pad, t) - pad).abs() < 1e-6);
+            assert!((extend(Extend::Repeat, t) - repeat).abs() < 1e-6);
+            assert!((extend(Extend::Reflect, t) - reflect).abs() < 1e-6);
+        }
+
+        assert_eq!(extend_texel(Extend::Pad, -3, 4), 0);
+        assert_eq!(extend_texel(Extend::Repeat, -1, 4), 3);
+        assert_eq!(extend_texel(Extend::Reflect, -1, 4), 0);
+        assert_eq!(extend_texel(Extend::Reflect, 5, 4), 2);
+    }
+
+    #[test]
+    fn stops_interpolation() {
+        let mut gradient = Gradient::linear((0.0, 0.0), (1.0, 0.0), stops());
+        gradient.stops.insert(
+            1,
+            ColorStop {
+                offset: 0.5,
+                color: rgbaf32::TRANSPARENT,
+            },
+        );
+        assert_color(gradient.color(-1.0), RED);
+        assert_color(gradient.color(0.25), RED * 0.5);
+        assert_color(gradient.color(0.5), rgbaf32::TRANSPARENT);
+        assert_color(gradient.color(0.75), BLUE * 0.5);
+        assert_color(gradient.color(2.0), BLUE);
+
+        // hard stop
+        gradient.stops[1].offset = 1.0;
+        assert_color(gradient.color(0.999), RED * 0.001);
+        assert_color(gradient.color(1.0), BLUE);
+    }
+
+    #[test]
+    fn linear() {
+        let gradient = Gradient::linear((0.0, 0.0), (100.0, 0.0), stops());
+        let paint = Paint::Gradient(gradient.clone());
+        assert_color(paint.eval(49, 7), RED.lerp(BLUE, 0.495));
+        assert_color(paint.eval(-10, 0), RED);
+
+        // rotated by the transform onto the diagonal
+        let paint = Paint::Gradient(
+            gradient
+                .with_transform(Affine::rotate(std::f64::consts::FRAC_PI_2))
+                .with_extend(Extend::Repeat),
+        );
+        assert_color(paint.eval(3, 24), RED.lerp(BLUE, 0.245));
+        assert_color(paint.eval(-50, 124), RED.lerp(BLUE, 0.245));
+    }
+
+    #[test]
+    fn radial() {
+        let paint = Paint::Gradient(Gradient::radial((50.0, 50.0), 40.0, stops()));
+        assert_color(paint.eval(49, 49), RED.lerp(BLUE, 0.5f32.hypot(0.5) / 40.0));
+        let t = (30.5f64.hypot(0.5) / 40.0) as f32;
+        assert_color(paint.eval(80, 49), RED.lerp(BLUE, t));
+        assert_color(paint.eval(95, 49), BLUE);
+
+        // focal point inside the circle
+        let gradient = Gradient::new(
+            GradientKind::Radial {
+                center: Point::new(0.0, 0.0),
+                radius: 10.0,
+                focal: Point::new(-4.5, 0.5),
+            },
+            stops(),
+        );
+        assert_color(
+            gradient.color(gradient.param(Point::new(-4.5, 0.5)).unwrap()),
+            RED,
+        );
+        for p in [(10.0, 0.0), (0.0, -10.0), (-6.0, 8.0)] {
+            assert!((gradient.param(Point::from(p)).unwrap() - 1.0).abs() < 1e-5);
+        }
+        // halfway between focal point and circle
+        let t = gradient.param(Point::new(2.75, 0.25)).unwrap();
+        assert!((t - 0.5).abs() < 1e-5);
+
+        // focal point outside the circle leaves the area outside the cone
+        let gradient = Gradient::new(
+            GradientKind::Radial {
+                center: Point::new(0.0, 0.0),
+                radius: 10.0,
+                focal: Point::new(-20.0, 0.0),
+            },
+            stops(),
+        );
+        assert!(gradient.param(Point::new(-20.0, 15.0)).is_none());
+        // the larger circle containing the point wins
+        assert!((gradient.param(Point::new(-10.0, 0.0)).unwrap() - 1.0).abs() < 1e-5);
+        assert!((gradient.param(Point::new(10.0, 0.0)).unwrap() - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn degenerate() {
+        // zero radius with the focal point at the center
+        let gradient = Gradient::radial((10.0, 10.0), 0.0, stops());
+        assert!(gradient.param(Point::new(10.0, 10.0)).is_none());
+        assert!(gradient.param(Point::new(15.0, 12.0)).is_none());
+        let paint = Paint::Gradient(gradient);
+        assert_color(paint.eval(10, 10), rgbaf32::TRANSPARENT);
+        assert_color(paint.eval(14, 3), rgbaf32::TRANSPARENT);
+
+        let gradient = Gradient::linear((0.0, 0.0), (1.0, 0.0), stops());
+        for extend in [Extend::Pad, Extend::Repeat, Extend::Reflect] {
+            let gradient = gradient.clone().with_extend(extend);
+            assert_color(gradient.color(f32::NAN), rgbaf32::TRANSPARENT);
+        }
+
+        // zero scale paint transforms have no inverse
+        let paints = [
+            Paint::Gradient(gradient.with_transform(Affine::scale(0.0))),
+            Paint::Gradient(
+                Gradient::radial((5.0, 5.0), 4.0, stops()).with_transform(Affine::scale(0.0)),
+            ),
+            Paint::Pattern(
+                Pattern::new(Arc::new(Image::new(2, 2, RED))).with_transform(Affine::scale(0.0)),
+            ),
+        ];
+        for paint in &paints {
+            let mut span = [RED; 9];
+            paint.eval_span(-3, 2, &mut span);
+            for color in span {
+                assert_color(color, rgbaf32::TRANSPARENT);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep() {
+        use std::f64::consts::PI;
+        let gradient = Gradient::sweep((10.0, 10.0), 0.0, PI, stops()).with_extend(Extend::Reflect);
+        let t = |x: f64, y: f64| gradient.param(Point::new(x, y)).unwrap();
+        assert!(t(20.0, 10.0).abs() < 1e-6);
+        assert!((t(10.0, 20.0) - 0.5).abs() < 1e-6);
+        assert!((t(0.0, 10.0) - 1.0).abs() < 1e-6);
+        assert!((t(10.0, 0.0) - 1.5).abs() < 1e-6);
+        assert_color(gradient.color(t(10.0, 0.0)), RED.lerp(BLUE, 0.5));
+    }
+
+    #[test]
+    fn pattern() {
+        let mut image = Image::new(2, 2, rgbaf32::TRANSPARENT);
+        image[(0, 0)] = RED;
+        image[(1, 0)] = BLUE;
+        image[(0, 1)] = rgbaf32::WHITE;
+        image[(1, 1)] = rgbaf32::BLACK;
+        let image = Arc::new(image);
+
+        // texel centers are exact
+        let pattern = Pattern::new(image.clone()).with_transform(Affine::scale(10.0));
+        let paint = Paint::Pattern(pattern.clone());
+        assert_color(paint.eval(4, 4), RED);
+        assert_color(paint.eval(15, 15), rgbaf32::BLACK);
+        assert_color(paint.eval(-20, 40), rgbaf32::WHITE);
+
+        // bilinear between the texel centers
+        let center = (RED + BLUE + rgbaf32::WHITE + rgbaf32::BLACK) * 0.25;
+        assert_color(pattern.sample(Point::new(1.0, 1.0)), center);
+        assert_color(pattern.sample(Point::new(0.75, 0.5)), RED.lerp(BLUE, 0.25));
+
+        let nearest = pattern.clone().with_filter(Filter::Nearest);
+        assert_color(nearest.sample(Point::new(0.99, 0.99)), RED);
+        assert_color(nearest.sample(Point::new(1.01, 0.99)), BLUE);
+
+        let repeat = pattern.with_extend(Extend::Repeat);
+        assert_color(repeat.sample(Point::new(2.5, 3.5)), rgbaf32::WHITE);
+        // wraps around between the last and first texel
+        assert_color(repeat.sample(Point::new(2.0, 0.5)), RED.lerp(BLUE, 0.5));
+    }
+
+    #[test]
+    fn spans() {
+        let image = Arc::new(Image::new(3, 5, rgbaf32::WHITE));
+        let transform = Affine::new([0.8, 0.3, -0.4, 1.1, 3.0, -2.0]);
+        let paints = [
+            Paint::Solid(RED),
+            Paint::Gradient(
+                Gradient::linear((2.0, 3.0), (17.0, -5.0), stops())
+                    .with_transform(transform)
+                    .with_extend(Extend::Reflect),
+            ),
+            Paint::Gradient(Gradient::radial((7.0, 9.0), 11.0, stops()).with_transform(transform)),
+            Paint::Gradient(Gradient::sweep((7.0, 9.0), 1.0, 4.0, stops())),
+            Paint::Pattern(Pattern::new(image).with_extend(Extend::Reflect)),
+        ];
+        for paint in &paints {
+            let mut span = [rgbaf32::TRANSPARENT; 37];
+            paint.eval_span(-5, 3, &mut span);
+            for (i, color) in span.iter().enumerate() {
+                assert_color(*color, paint.eval(i as i32 - 5, 3));
+            }
+        }
+    }
+
+    #[test]
+    fn encoder() {
+        let paint = Paint::Gradient(Gradient::radial((20.0, 16.0), 24.0, stops()));
+        let (width, height) = (40, 30);
+
+        let mut image = Image::new(width, height, rgbaf32::WHITE);
+        let mut rasterizer = Rasterizer::default();
+        rasterizer.set_tolerance(0.01);
+        rasterizer.begin();
+        rasterizer.add_circle([20.0, 16.0], 13.3);
+        // partially outside the image
+        rasterizer.add_rect([-4.5, 20.0], 12.0, 20.0);
+        rasterizer
+            .end(&mut PaintEncoder::new(&mut image, &paint))
+            .unwrap();
+
+        let mut path = kurbo::BezPath::new();
+        path.extend(kurbo::Circle::new((20.0, 16.0), 13.3).path_elements(0.01));
+        path.extend(kurbo::Rect::new(-4.5, 20.0, 7.5, 40.0).path_elements(0.01));
+        let coverage = crate::reference::ochre(&path, width as usize, height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let src = paint.eval(x as i32, y as i32) * coverage[(y * width + x) as usize];
+                let expected = src + rgbaf32::WHITE * (1.0 - src.a);
+                let actual = image[(x, y)];
+                let d = (actual.r - expected.r).abs() + (actual.b - expected.b).abs();
+                assert!(d < 0.02, "({}, {}): {:?} != {:?}", x, y, actual, expected);
+            }
+        }
+        assert_color(image[(20, 16)], RED.lerp(BLUE, 0.5f32.hypot(0.5) / 24.0));
+        assert_color(image[(39, 0)], rgbaf32::WHITE);
+    }
+
+    #[test]
+    fn encoder_blend() {
+        use crate::blend::Mix;
+
+        let paint = Paint::Solid(rgbaf32::new(0.5, 0.5, 0.5, 1.0));
+        let mut image = Image::new(16, 16, RED);
+        let mut rasterizer = Rasterizer::default();
+        rasterizer.begin();
+        rasterizer.add_rect([0.0, 0.0], 8.0, 4.5);
+        rasterizer
+            .end(&mut PaintEncoder::new(&mut image, &paint).with_blend(Mix::Multiply))
+            .unwrap();
+
+        assert_color(image[(3, 3)], rgbaf32::new(0.5, 0.0, 0.0, 1.0));
+        // half covered row, up to coverage quantization
+        assert!((image[(3, 4)].r - 0.75).abs() < 2.0 / 255.0);
+        assert_color(image[(3, 5)], RED);
+        assert_color(image[(9, 3)], RED);
+    }
+
+    #[test]
+    fn encoder_color_space() {
+        let paint = Paint::Solid(rgbaf32::BLACK);
+        let mut rasterizer = Rasterizer::default();
+        let mut draw = |image: &mut Image, paint: &Paint, text: TextBlend| {
+            rasterizer.begin();
+            rasterizer.add_rect([0.0, 0.0], 8.0, 0.5);
+            rasterizer.add_rect([0.0, 1.0], 8.0, 1.0);
+            rasterizer
+                .end(&mut PaintEncoder::new(image, paint).with_text_blend(text))
+                .unwrap();
+        };
+
+        // half coverage of black over white is blended in linear light
+        let mut image = Image::new(8, 8, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+        draw(&mut image, &paint, TextBlend::Linear);
+        let mask = 128.0 / 255.0;
+        let half = linear_to_srgb(1.0 - mask);
+        assert!((image[(0, 0)].r - half).abs() < 1e-5);
+        assert!((half - 0.735_357).abs() < 3e-3);
+        assert_color(image[(0, 1)], rgbaf32::BLACK);
+
+        // perceptual text blending makes dark text bolder
+        let mut image = Image::new(8, 8, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+        draw(&mut image, &paint, TextBlend::PERCEPTUAL);
+        let coverage = TextBlend::PERCEPTUAL.coverage(mask, 0.0);
+        assert!((image[(0, 0)].r - linear_to_srgb(1.0 - coverage)).abs() < 1e-5);
+        assert!(image[(0, 0)].r < half);
+
+        // linear sRGB paint colors are converted to the image primaries
+        let paint = Paint::Solid(RED);
+        let mut image = Image::new(8, 8, rgbaf32::WHITE).with_color_space(ColorSpace::DisplayP3);
+        draw(&mut image, &paint, TextBlend::Linear);
+        let p3 = image[(0, 1)];
+        assert!((p3.r - 0.917_488).abs() < 1e-4);
+        assert!((p3.g - 0.200_287).abs() < 1e-4);
+        assert!((p3.b - 0.138_561).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,81 @@
+//! Reference coverage from nari-ochre for tests.
+
+use kurbo::{BezPath, PathEl, Point};
+use nari_ochre::{Coord, Encoder, Rasterizer, Tile, TILE_SIZE};
+use std::ops::Range;
+use zeno::PathBuilder;
+
+struct Image {
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl Image {
+    fn set(&mut self, tx: Coord, ty: Coord, px: usize, py: usize, coverage: f32) {
+        let x = tx as isize * TILE_SIZE as isize + px as isize;
+        let y = ty as isize * TILE_SIZE as isize + py as isize;
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.coverage[y as usize * self.width + x as usize] = coverage;
+        }
+    }
+}
+
+impl Encoder for Image {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        for tx in x {
+            for py in 0..TILE_SIZE {
+                for px in 0..TILE_SIZE {
+                    self.set(tx, y, px, py, 1.0);
+                }
+            }
+        }
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        for (py, row) in mask.iter().enumerate() {
+            for (px, coverage) in row.iter().enumerate() {
+                self.set(x, y, px, py, *coverage as f32 / 255.0);
+            }
+        }
+    }
+}
+
+fn point(p: Point) -> zeno::Point {
+    zeno::Point::new(p.x as f32, p.y as f32)
+}
+
+/// Coverage image of the path rasterized by nari-ochre, with fine flattening.
+pub fn ochre(path: &BezPath, width: usize, height: usize) -> Vec<f32> {
+    let mut rasterizer = Rasterizer::default();
+    rasterizer.set_tolerance(0.01);
+    rasterizer.begin();
+    for element in path.elements() {
+        match *element {
+            PathEl::MoveTo(p) => rasterizer.move_to(point(p)),
+            PathEl::LineTo(p) => rasterizer.line_to(point(p)),
+            PathEl::QuadTo(p1, p2) => rasterizer.quad_to(point(p1), point(p2)),
+            PathEl::CurveTo(p1, p2, p3) => rasterizer.curve_to(point(p1), point(p2), point(p3)),
+            PathEl::ClosePath => rasterizer.close(),
+        };
+    }
+
+    let mut image = Image {
+        width,
+        height,
+        coverage: vec![0.0; width * height],
+    };
+    rasterizer.end(&mut image).unwrap();
+    image.coverage
+}
+
+/// Maximum and mean absolute difference of two coverage images.
+pub fn error(a: &[f32], b: &[f32]) -> (f32, f32) {
+    let mut max: f32 = 0.0;
+    let mut sum = 0.0;
+    for (a, b) in a.iter().zip(b) {
+        max = max.max((a - b).abs());
+        sum += (a - b).abs() as f64;
+    }
+    (max, (sum / a.len() as f64) as f32)
+}
@@ -0,0 +1,327 @@
+//! CPU rendering of `nari_scene` display lists.
+//!
+//! Paths are rasterized by nari-ochre and painted with the paints and blend
+//! modes of this crate.
+
+use crate::blend::Layers;
+use crate::color::{srgb_to_linear, ColorSpace};
+use crate::image::{rgbaf32, Image};
+use crate::paint::{ColorStop, Gradient, Paint, PaintEncoder, Pattern};
+use kurbo::{Affine, BezPath, PathEl, Point, Rect, Shape};
+use nari_ochre::{stroke::stroke, FillRule, Rasterizer};
+use nari_scene as scene;
+use std::collections::HashMap;
+use std::sync::Arc;
+use zeno::{Command, PathData};
+
+/// Flattening tolerance in device pixels.
+const TOLERANCE: f32 = 0.1;
+
+/// Premultiplied linear color from a straight sRGB color.
+fn color(color: scene::Color) -> rgbaf32 {
+    rgbaf32::from_straight(
+        srgb_to_linear(color.r),
+        srgb_to_linear(color.g),
+        srgb_to_linear(color.b),
+        color.a,
+    )
+}
+
+/// Paint of the brush with brush to device space `transform`.
+fn paint(brush: &scene::Brush, transform: Affine) -> Paint {
+    let gradient = match brush {
+        scene::Brush::Solid(c) => return Paint::Solid(color(*c)),
+        scene::Brush::Gradient(gradient) => gradient,
+    };
+
+    let stops = gradient
+        .stops
+        .iter()
+        .map(|stop| ColorStop {
+            offset: stop.offset,
+            color: color(stop.color),
+        })
+        .collect();
+    Paint::Gradient(Gradient {
+        kind: gradient.kind,
+        stops,
+        extend: gradient.extend,
+        transform,
+    })
+}
+
+/// Pattern image in linear sRGB.
+fn pattern_image(image: &scene::Image) -> Image {
+    let mut pattern = Image::new(image.width, image.height, rgbaf32::TRANSPARENT)
+        .with_color_space(ColorSpace::Srgb);
+    for (pixel, rgba) in pattern.memory.iter_mut().zip(image.data.chunks_exact(4)) {
+        let [r, g, b, a] = [0, 1, 2, 3].map(|i| rgba[i] as f32 / 255.0);
+        *pixel = rgbaf32::from_straight(r, g, b, a);
+    }
+    pattern.convert(ColorSpace::LinearSrgb)
+}
+
+fn commands(path: &BezPath) -> Vec<Command> {
+    let p = |p: Point| zeno::Point::new(p.x as f32, p.y as f32);
+    path.elements()
+        .iter()
+        .map(|el| match *el {
+            PathEl::MoveTo(p0) => Command::MoveTo(p(p0)),
+            PathEl::LineTo(p0) => Command::LineTo(p(p0)),
+            PathEl::QuadTo(p1, p2) => Command::QuadTo(p(p1), p(p2)),
+            PathEl::CurveTo(p1, p2, p3) => Command::CurveTo(p(p1), p(p2), p(p3)),
+            PathEl::ClosePath => Command::Close,
+        })
+        .collect()
+}
+
+fn transform(path: &[Command], transform: Affine) -> Vec<Command> {
+    let t = |p: zeno::Point| {
+        let p = transform * Point::new(p.x as f64, p.y as f64);
+        zeno::Point::new(p.x as f32, p.y as f32)
+    };
+    path.iter()
+        .map(|command| match *command {
+            Command::MoveTo(p0) => Command::MoveTo(t(p0)),
+            Command::LineTo(p0) => Command::LineTo(t(p0)),
+            Command::QuadTo(p1, p2) => Command::QuadTo(t(p1), t(p2)),
+            Command::CurveTo(p1, p2, p3) => Command::CurveTo(t(p1), t(p2), t(p3)),
+            Command::Close => Command::Close,
+        })
+        .collect()
+}
+
+/// Stroke outline of the path in device space.
+fn stroke_outline(style: &kurbo::Stroke, t: Affine, path: &BezPath) -> Vec<Command> {
+    let cap = |cap| match cap {
+        kurbo::Cap::Butt => zeno::Cap::Butt,
+        kurbo::Cap::Square => zeno::Cap::Square,
+        kurbo::Cap::Round => zeno::Cap::Round,
+    };
+    let join = match style.join {
+        kurbo::Join::Bevel => zeno::Join::Bevel,
+        kurbo::Join::Miter => zeno::Join::Miter,
+        kurbo::Join::Round => zeno::Join::Round,
+    };
+    let dashes = style
+        .dash_pattern
+        .iter()
+        .map(|dash| *dash as f32)
+        .collect::<Vec<_>>();
+    let style = zeno::Stroke {
+        width: style.width as f32,
+        join,
+        miter_limit: style.miter_limit as f32,
+        start_cap: cap(style.start_cap),
+        end_cap: cap(style.end_cap),
+        dashes: &dashes,
+        offset: style.dash_offset as f32,
+        scale: true,
+    };
+
+    let scale = t.determinant().abs().sqrt().max(1e-6) as f32;
+    let mut outline = Vec::new();
+    stroke(&commands(path)[..], &style, TOLERANCE / scale, &mut outline);
+    transform(&outline, t)
+}
+
+/// Open clip or layer of the display list.
+enum Group {
+    Clip,
+    Layer,
+}
+
+struct Renderer<'a> {
+    list: &'a scene::DisplayList,
+    rasterizer: Rasterizer,
+    layers: Layers,
+    images: HashMap<scene::ImageId, Arc<Image>>,
+}
+
+impl Renderer<'_> {
+    fn fill(
+        &mut self,
+        rule: FillRule,
+        outline: &[Command],
+        paint: &Paint,
+    ) -> Result<(), nari_ochre::Error> {
+        self.rasterizer.set_fill_rule(rule);
+        self.rasterizer.begin();
+        outline.copy_to(&mut self.rasterizer);
+        self.rasterizer
+            .end(&mut PaintEncoder::new(self.layers.target(), paint))
+    }
+
+    fn image(&mut self, id: scene::ImageId) -> Arc<Image> {
+        let list = self.list;
+        self.images
+            .entry(id)
+            .or_insert_with(|| Arc::new(pattern_image(list.image(id))))
+            .clone()
+    }
+}
+
+/// Render the display list onto `target`.
+///
+/// Commands are in device space of the target, unbalanced clips and layers
+/// are closed at the end.
+pub fn render(list: &scene::DisplayList, target: Image) -> Result<Image, nari_ochre::Error> {
+    let mut renderer = Renderer {
+        list,
+        rasterizer: Rasterizer::default(),
+        layers: Layers::new(target),
+        images: HashMap::new(),
+    };
+    renderer.rasterizer.set_tolerance(TOLERANCE);
+    let mut groups = Vec::new();
+
+    for command in list.commands() {
+        match command {
+            scene::Command::Fill {
+                rule,
+                transform: t,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                let rule = match rule {
+                    scene::FillRule::NonZero => FillRule::NonZero,
+                    scene::FillRule::EvenOdd => FillRule::EvenOdd,
+                };
+                let brush_transform = *t * brush_transform.unwrap_or_default();
+                let paint = paint(brush, brush_transform);
+                renderer.fill(rule, &transform(&commands(path), *t), &paint)?;
+            }
+            scene::Command::Stroke {
+                style,
+                transform: t,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                let brush_transform = *t * brush_transform.unwrap_or_default();
+                let paint = paint(brush, brush_transform);
+                renderer.fill(FillRule::NonZero, &stroke_outline(style, *t, path), &paint)?;
+            }
+            scene::Command::PushClip { transform: t, path } => {
+                let rasterizer = &mut renderer.rasterizer;
+                rasterizer.set_fill_rule(FillRule::NonZero);
+                rasterizer.begin();
+                (&transform(&commands(path), *t)[..]).copy_to(rasterizer);
+                rasterizer.push_clip()?;
+                groups.push(Group::Clip);
+            }
+            scene::Command::PushLayer { blend, opacity } => {
+                renderer.layers.push(*blend, *opacity);
+                groups.push(Group::Layer);
+            }
+            scene::Command::Pop => match groups.pop() {
+                Some(Group::Clip) => renderer.rasterizer.pop_clip(),
+                Some(Group::Layer) => renderer.layers.pop(),
+                None => {}
+            },
+            scene::Command::Glyphs {
+                transform: t,
+                brush,
+                glyphs,
+            } => {
+                let paint = paint(brush, *t);
+                for glyph in glyphs {
+                    let offset = Affine::translate((glyph.x as f64, glyph.y as f64));
+                    let outline = transform(&commands(list.glyph(glyph.id)), *t * offset);
+                    renderer.fill(FillRule::NonZero, &outline, &paint)?;
+                }
+            }
+            scene::Command::Image {
+                image,
+                transform: t,
+            } => {
+                let image = renderer.image(*image);
+                let rect = Rect::new(0.0, 0.0, image.width as f64, image.height as f64);
+                let paint = Paint::Pattern(Pattern::new(image).with_transform(*t));
+                let outline = transform(&commands(&rect.to_path(0.1)), *t);
+                renderer.fill(FillRule::NonZero, &outline, &paint)?;
+            }
+        }
+    }
+
+    Ok(renderer.layers.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scene::{Color, DisplayList, FillRule};
+
+    #[test]
+    fn display_list() {
+        let mut list = DisplayList::new(32, 32);
+        let red = Color::rgb(1.0, 0.0, 0.0);
+        let fill = |list: &mut DisplayList, color: Color, rect: Rect| {
+            list.fill(FillRule::NonZero, Affine::IDENTITY, color, None, &rect);
+        };
+        fill(&mut list, red, Rect::new(0.0, 0.0, 16.0, 16.0));
+
+        // clipped to the top right quadrant
+        list.push_clip(Affine::IDENTITY, &Rect::new(16.0, 0.0, 32.0, 16.0));
+        fill(
+            &mut list,
+            Color::rgb(0.0, 0.0, 1.0),
+            Rect::new(0.0, 0.0, 32.0, 32.0),
+        );
+        list.pop();
+
+        // half transparent layer
+        list.push_layer(scene::BlendMode::SRC_OVER, 0.5);
+        fill(&mut list, Color::BLACK, Rect::new(0.0, 16.0, 16.0, 32.0));
+        list.pop();
+
+        let glyph = list.add_glyph(0, || Rect::new(0.0, 0.0, 4.0, 4.0).to_path(0.1));
+        list.draw_glyphs(
+            Affine::translate((16.0, 16.0)),
+            Color::rgb(0.0, 1.0, 0.0),
+            [scene::Glyph {
+                id: glyph,
+                x: 4.0,
+                y: 4.0,
+            }],
+        );
+
+        let image = list.add_image(scene::Image::new(2, 2, [255, 0, 255, 255].repeat(4)));
+        list.draw_image(image, Affine::translate((28.0, 28.0)));
+
+        let target = Image::new(32, 32, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+        let rgba = render(&list, target).unwrap().to_rgba8();
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 32 + x) * 4;
+            [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]]
+        };
+
+        assert_eq!(pixel(4, 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(20, 4), [0, 0, 255, 255]);
+        assert_eq!(pixel(18, 18), [255, 255, 255, 255]);
+        // 50% linear gray
+        assert_eq!(pixel(4, 20), [188, 188, 188, 255]);
+        assert_eq!(pixel(21, 21), [0, 255, 0, 255]);
+        assert_eq!(pixel(25, 25), [255, 255, 255, 255]);
+        assert_eq!(pixel(29, 29), [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn even_odd() {
+        let mut path = Rect::new(0.0, 0.0, 16.0, 16.0).to_path(0.1);
+        path.extend(Rect::new(4.0, 4.0, 12.0, 12.0).path_elements(0.1));
+        let black = Color::BLACK;
+        let mut list = DisplayList::new(16, 32);
+        list.fill(FillRule::EvenOdd, Affine::IDENTITY, black, None, &path);
+        let below = Affine::translate((0.0, 16.0));
+        list.fill(FillRule::NonZero, below, black, None, &path);
+
+        let target = Image::new(16, 32, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+        let rgba = render(&list, target).unwrap().to_rgba8();
+        let pixel = |x: usize, y: usize| rgba[(y * 16 + x) * 4];
+        assert_eq!(pixel(2, 2), 0);
+        assert_eq!(pixel(8, 8), 255);
+        assert_eq!(pixel(8, 24), 0);
+    }
+}
@@ -0,0 +1,84 @@
+//! System clipboard with UTF-8 text and MIME typed payloads.
+
+use crate::{backend, headless};
+use std::rc::Rc;
+
+/// MIME type of plain text, mapped to the native text format of the platform.
+pub const TEXT: &str = "text/plain;charset=utf-8";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Clipboard is currently held by another application.
+    Unavailable,
+    /// Contents could not be stored, e.g. when out of memory.
+    Failed,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unavailable => write!(f, "clipboard is unavailable"),
+            Error::Failed => write!(f, "failed to store clipboard contents"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone)]
+enum ClipboardKind {
+    Native(backend::Surface),
+    Headless(Rc<headless::Clipboard>),
+}
+
+/// Handle to the clipboard of a platform.
+#[derive(Clone)]
+pub struct Clipboard {
+    kind: ClipboardKind,
+}
+
+impl Clipboard {
+    pub fn text(&self) -> Option<String> {
+        String::from_utf8(self.get(TEXT)?).ok()
+    }
+
+    pub fn set_text(&self, text: &str) -> Result<(), Error> {
+        self.set(&[(TEXT, text.as_bytes())])
+    }
+
+    /// Payload of the given MIME type.
+    pub fn get(&self, mime: &str) -> Option<Vec<u8>> {
+        match &self.kind {
+            ClipboardKind::Native(surface) => surface.clipboard(mime),
+            ClipboardKind::Headless(clipboard) => clipboard.get(mime),
+        }
+    }
+
+    /// Replace the clipboard contents with payloads of different MIME types,
+    /// e.g. structured data together with a `TEXT` fallback.
+    pub fn set(&self, contents: &[(&str, &[u8])]) -> Result<(), Error> {
+        match &self.kind {
+            ClipboardKind::Native(surface) => surface.set_clipboard(contents),
+            ClipboardKind::Headless(clipboard) => {
+                clipboard.set(contents);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<backend::Surface> for Clipboard {
+    fn from(surface: backend::Surface) -> Self {
+        Self {
+            kind: ClipboardKind::Native(surface),
+        }
+    }
+}
+
+impl From<Rc<headless::Clipboard>> for Clipboard {
+    fn from(clipboard: Rc<headless::Clipboard>) -> Self {
+        Self {
+            kind: ClipboardKind::Headless(clipboard),
+        }
+    }
+}
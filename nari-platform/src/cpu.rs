@@ -1,11 +1,4 @@
-use std::ptr::null_mut;
-
-use windows_sys::Win32::System::SystemInformation::{
-    GetLogicalProcessorInformation, RelationProcessorCore, RelationProcessorPackage,
-    SYSTEM_LOGICAL_PROCESSOR_INFORMATION,
-};
-
-///
+/// CPU vendor.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Vendor {
     Intel,
@@ -13,7 +6,7 @@ pub enum Vendor {
     Unknown,
 }
 
-///
+/// Properties of the host CPU.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DeviceProperties {
     /// Device Hardware Vendor
@@ -27,13 +20,15 @@ pub struct DeviceProperties {
 }
 
 impl DeviceProperties {
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unused_unsafe)] // `__cpuid` is safe on newer toolchains
     fn system_cpuid_vendor() -> Vendor {
         let brand = {
             let cpuid = unsafe { std::arch::x86_64::__cpuid(0) };
             let mut data = [0u8; 12];
-            data[0..4].copy_from_slice(unsafe { &std::mem::transmute::<_, [u8; 4]>(cpuid.ebx) });
-            data[4..8].copy_from_slice(unsafe { &std::mem::transmute::<_, [u8; 4]>(cpuid.edx) });
-            data[8..12].copy_from_slice(unsafe { &std::mem::transmute::<_, [u8; 4]>(cpuid.ecx) });
+            data[0..4].copy_from_slice(&cpuid.ebx.to_le_bytes());
+            data[4..8].copy_from_slice(&cpuid.edx.to_le_bytes());
+            data[8..12].copy_from_slice(&cpuid.ecx.to_le_bytes());
             data
         };
 
@@ -44,6 +39,8 @@ impl DeviceProperties {
         }
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unused_unsafe)]
     fn system_cpuid_vendor_device() -> (Vendor, String) {
         let vendor = Self::system_cpuid_vendor();
         let device = match vendor {
@@ -90,52 +87,116 @@ impl DeviceProperties {
         (vendor, device)
     }
 
+    #[cfg(not(target_arch = "x86_64"))]
+    fn system_cpuid_vendor_device() -> (Vendor, String) {
+        (Vendor::Unknown, String::new())
+    }
+
     pub fn query() -> Self {
         let (vendor, device) = Self::system_cpuid_vendor_device();
+        let (logical_cores, physical_cores) = system_cores();
+
+        Self {
+            vendor,
+            device,
+            logical_cores,
+            physical_cores,
+        }
+    }
+}
 
-        let processor_desc = {
-            let mut length = 0;
+/// Number of logical and physical cores.
+#[cfg(target_os = "windows")]
+fn system_cores() -> (usize, usize) {
+    use std::ptr::null_mut;
+    use windows_sys::Win32::System::SystemInformation::{
+        GetLogicalProcessorInformation, RelationProcessorCore, RelationProcessorPackage,
+        SYSTEM_LOGICAL_PROCESSOR_INFORMATION,
+    };
 
-            unsafe {
-                GetLogicalProcessorInformation(null_mut(), &mut length);
-            }
+    let processor_desc = {
+        let mut length = 0;
 
-            let info_size = std::mem::size_of::<SYSTEM_LOGICAL_PROCESSOR_INFORMATION>() as u32;
-            assert_eq!(length % info_size, 0);
-            let num_infos = length / info_size;
+        unsafe {
+            GetLogicalProcessorInformation(null_mut(), &mut length);
+        }
 
-            let mut infos = Vec::with_capacity(num_infos as _);
-            unsafe {
-                GetLogicalProcessorInformation(infos.as_mut_ptr(), &mut length);
-            }
-            unsafe {
-                infos.set_len(num_infos as _);
-            }
+        let info_size = std::mem::size_of::<SYSTEM_LOGICAL_PROCESSOR_INFORMATION>() as u32;
+        assert_eq!(length % info_size, 0);
+        let num_infos = length / info_size;
 
-            infos
-        };
+        let mut infos = Vec::with_capacity(num_infos as _);
+        unsafe {
+            GetLogicalProcessorInformation(infos.as_mut_ptr(), &mut length);
+        }
+        unsafe {
+            infos.set_len(num_infos as _);
+        }
+
+        infos
+    };
+
+    let mut logical_cores = 0;
+    let mut physical_cores = 0;
 
-        let mut logical_cores = 0;
-        let mut physical_cores = 0;
-
-        for desc in processor_desc {
-            #[allow(non_upper_case_globals)]
-            match desc.Relationship {
-                RelationProcessorCore => {
-                    physical_cores += 1;
-                }
-                RelationProcessorPackage => {
-                    logical_cores += desc.ProcessorMask.count_ones() as usize;
-                }
-                _ => (),
+    for desc in processor_desc {
+        #[allow(non_upper_case_globals)]
+        match desc.Relationship {
+            RelationProcessorCore => {
+                physical_cores += 1;
             }
+            RelationProcessorPackage => {
+                logical_cores += desc.ProcessorMask.count_ones() as usize;
+            }
+            _ => (),
         }
+    }
 
-        Self {
-            vendor,
-            device,
-            logical_cores,
-            physical_cores,
+    (logical_cores, physical_cores)
+}
+
+/// Number of logical and physical cores.
+#[cfg(target_os = "linux")]
+fn system_cores() -> (usize, usize) {
+    let logical_cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let physical_cores = std::fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| physical_cores(&cpuinfo))
+        .unwrap_or(logical_cores);
+
+    (logical_cores, physical_cores)
+}
+
+/// Count the distinct `(physical id, core id)` pairs of `/proc/cpuinfo`.
+#[cfg(target_os = "linux")]
+fn physical_cores(cpuinfo: &str) -> Option<usize> {
+    let mut cores = std::collections::HashSet::new();
+    for processor in cpuinfo.split("\n\n") {
+        let field = |name: &str| {
+            processor.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == name).then(|| value.trim())
+            })
+        };
+        if let Some(core) = field("core id") {
+            cores.insert((field("physical id"), core));
         }
     }
+
+    (!cores.is_empty()).then_some(cores.len())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpuinfo() {
+        let cpuinfo = "processor\t: 0\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 1\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 2\nphysical id\t: 0\ncore id\t\t: 1\n\n\
+                       processor\t: 3\nphysical id\t: 1\ncore id\t\t: 0\n";
+        assert_eq!(physical_cores(cpuinfo), Some(3));
+        assert_eq!(physical_cores("processor\t: 0\n"), None);
+    }
 }
@@ -0,0 +1,1117 @@
+//! Platform without a window, driven by a script of input events.
+//!
+//! Allows testing UI logic which depends on the event loop without a display
+//! server.
+
+use crate::{
+    Composition, ControlFlow, Cursor, Event, EventLoop, Extent, Key, KeyState, Modifiers,
+    MouseButtons, Rect, ScanCode, ScrollDelta, SurfaceArea, SurfaceId, TimerId,
+};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Surface {
+    pub(crate) id: SurfaceId,
+    pub(crate) extent: Extent,
+    pub(crate) dpi: f64,
+    pub(crate) maximized: bool,
+}
+
+type UserEvents = Arc<Mutex<Vec<(SurfaceId, Box<dyn Any + Send>)>>>;
+
+#[derive(Copy, Clone, Debug)]
+struct Timer {
+    id: TimerId,
+    surface: SurfaceId,
+    /// Time since the start of the platform.
+    deadline: Duration,
+    /// Interval of repeating timers.
+    interval: Option<Duration>,
+}
+
+/// Open surfaces of the platform.
+pub(crate) struct Surfaces {
+    surfaces: RefCell<Vec<Surface>>,
+    next_id: Cell<u64>,
+    /// Surfaces closed by the callback.
+    closing: RefCell<Vec<SurfaceId>>,
+    // properties of created surfaces
+    extent: Extent,
+    dpi: f64,
+
+    /// Simulated time, advanced by `Input::Wait`.
+    time: Cell<Duration>,
+    timers: RefCell<Vec<Timer>>,
+    next_timer: Cell<u64>,
+    /// Surfaces waiting for `Input::Frame`.
+    frames: RefCell<Vec<SurfaceId>>,
+    user_events: UserEvents,
+}
+
+impl Surfaces {
+    fn insert(&self, extent: Extent, dpi: f64, maximized: bool) -> Surface {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        let surface = Surface {
+            id: SurfaceId(id),
+            extent,
+            dpi,
+            maximized,
+        };
+        self.surfaces.borrow_mut().push(surface);
+        surface
+    }
+
+    pub(crate) fn create(&self) -> Surface {
+        self.insert(self.extent, self.dpi, false)
+    }
+
+    pub(crate) fn close(&self, id: SurfaceId) {
+        self.closing.borrow_mut().push(id);
+    }
+
+    pub(crate) fn request_animation_frame(&self, surface: SurfaceId) {
+        let mut frames = self.frames.borrow_mut();
+        if !frames.contains(&surface) {
+            frames.push(surface);
+        }
+    }
+
+    pub(crate) fn set_timer(
+        &self,
+        surface: SurfaceId,
+        interval: Duration,
+        repeat: bool,
+    ) -> TimerId {
+        let id = TimerId(self.next_timer.get());
+        self.next_timer.set(id.0 + 1);
+        self.timers.borrow_mut().push(Timer {
+            id,
+            surface,
+            deadline: self.time.get() + interval,
+            interval: repeat.then_some(interval),
+        });
+        id
+    }
+
+    pub(crate) fn cancel_timer(&self, timer: TimerId) {
+        self.timers.borrow_mut().retain(|t| t.id != timer);
+    }
+
+    pub(crate) fn create_proxy(&self, surface: SurfaceId) -> Proxy {
+        Proxy {
+            surface,
+            events: self.user_events.clone(),
+        }
+    }
+
+    fn get(&self, id: SurfaceId) -> Option<Surface> {
+        self.surfaces.borrow().iter().find(|s| s.id == id).copied()
+    }
+
+    fn update(&self, surface: Surface) {
+        for s in self.surfaces.borrow_mut().iter_mut() {
+            if s.id == surface.id {
+                *s = surface;
+            }
+        }
+    }
+
+    fn remove(&self, id: SurfaceId) {
+        self.surfaces.borrow_mut().retain(|s| s.id != id);
+        self.timers.borrow_mut().retain(|t| t.surface != id);
+        self.frames.borrow_mut().retain(|s| *s != id);
+    }
+
+    /// Next timer elapsing until `end`, repeating timers are rescheduled.
+    fn next_timer(&self, end: Duration) -> Option<Timer> {
+        let mut timers = self.timers.borrow_mut();
+        let (i, timer) = timers
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, t)| t.deadline <= end)
+            .min_by_key(|(_, t)| t.deadline)?;
+        match timer.interval {
+            Some(interval) => timers[i].deadline += interval,
+            None => {
+                timers.remove(i);
+            }
+        }
+        Some(timer)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.surfaces.borrow().is_empty()
+    }
+}
+
+/// Sends user events to a surface of the headless platform.
+#[derive(Clone)]
+pub(crate) struct Proxy {
+    surface: SurfaceId,
+    events: UserEvents,
+}
+
+impl Proxy {
+    pub(crate) fn send(&self, event: Box<dyn Any + Send>) {
+        self.events.lock().unwrap().push((self.surface, event));
+    }
+}
+
+/// Pointer state of a surface.
+#[derive(Copy, Clone, Debug)]
+struct Pointer {
+    cursor: Cursor,
+    position: Option<(i32, i32)>,
+    buttons: MouseButtons,
+    /// Acceptance of the last drag event.
+    drag_accept: bool,
+}
+
+impl Default for Pointer {
+    fn default() -> Self {
+        Pointer {
+            cursor: Cursor::Default,
+            position: None,
+            buttons: MouseButtons::empty(),
+            drag_accept: false,
+        }
+    }
+}
+
+/// In-memory clipboard, shared by all surfaces of the platform.
+#[derive(Debug, Default)]
+pub(crate) struct Clipboard {
+    contents: RefCell<Vec<(String, Vec<u8>)>>,
+}
+
+impl Clipboard {
+    pub(crate) fn get(&self, mime: &str) -> Option<Vec<u8>> {
+        let contents = self.contents.borrow();
+        let (_, data) = contents.iter().find(|(ty, _)| ty == mime)?;
+        Some(data.clone())
+    }
+
+    pub(crate) fn set(&self, contents: &[(&str, &[u8])]) {
+        *self.contents.borrow_mut() = contents
+            .iter()
+            .map(|(mime, data)| (mime.to_string(), data.to_vec()))
+            .collect();
+    }
+}
+
+/// Scripted input, translated into the matching `Event`.
+///
+/// Inputs are sent to the main surface of the platform unless wrapped in
+/// `Input::Surface`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// Send the input to another surface, ignored if the surface is closed.
+    Surface(SurfaceId, Box<Input>),
+    Paint,
+    /// Updates the surface extent before sending the event.
+    Resize(Extent),
+    /// Updates the surface DPI before sending the event and the extent to
+    /// the rect of the callback afterwards.
+    DpiChanged {
+        dpi: f64,
+        rect: Rect,
+    },
+    Focus(bool),
+    Occluded(bool),
+    /// Closes the surface unless the callback vetoes the request.
+    CloseRequest,
+    /// Removes the surface after sending the event.
+    Close,
+    /// Advance the time, sending `Event::Timer` for elapsed timers of all
+    /// surfaces.
+    Wait(Duration),
+    /// Display refresh, sending `Event::AnimationFrame` to all surfaces
+    /// which requested a frame.
+    Frame,
+    Key {
+        key: Key,
+        scancode: ScanCode,
+        state: KeyState,
+        repeat: bool,
+        modifiers: Modifiers,
+    },
+    Char(char),
+    Composition(Composition),
+    /// Updates the mouse position before sending the event.
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    /// Updates the pressed mouse buttons before sending the event.
+    MouseButton {
+        button: MouseButtons,
+        state: KeyState,
+        clicks: u32,
+        modifiers: Modifiers,
+    },
+    Scroll {
+        delta: ScrollDelta,
+        modifiers: Modifiers,
+    },
+    Hittest {
+        x: i32,
+        y: i32,
+    },
+    DragEnter {
+        paths: Vec<PathBuf>,
+        x: i32,
+        y: i32,
+    },
+    DragOver {
+        paths: Vec<PathBuf>,
+        x: i32,
+        y: i32,
+    },
+    DragLeave,
+    /// Sent as `Event::DragLeave` unless the last drag event was accepted.
+    Drop {
+        paths: Vec<PathBuf>,
+        x: i32,
+        y: i32,
+    },
+}
+
+/// Result of the event callback for a single input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    pub control_flow: ControlFlow,
+    /// Cursor after the event, only changed by `Input::MouseMove`.
+    pub cursor: Cursor,
+    /// Area chosen for `Input::Hittest`.
+    pub area: Option<SurfaceArea>,
+    /// Acceptance of `Input::DragEnter` and `Input::DragOver`.
+    pub accept: Option<bool>,
+}
+
+/// Target surface and input, unwrapping `Input::Surface`.
+fn target(surface: SurfaceId, input: Input) -> (SurfaceId, Input) {
+    match input {
+        Input::Surface(surface, input) => target(surface, *input),
+        input => (surface, input),
+    }
+}
+
+pub struct Platform {
+    /// Main surface, receiving inputs by default.
+    pub surface: crate::Surface,
+    surfaces: Rc<Surfaces>,
+    clipboard: Rc<Clipboard>,
+}
+
+impl Platform {
+    pub fn new(extent: Extent, dpi: f64) -> Self {
+        Self::with_maximized(extent, dpi, false)
+    }
+
+    pub fn with_maximized(extent: Extent, dpi: f64, maximized: bool) -> Self {
+        let surfaces = Rc::new(Surfaces {
+            surfaces: RefCell::new(Vec::new()),
+            next_id: Cell::new(1),
+            closing: RefCell::new(Vec::new()),
+            extent,
+            dpi,
+            time: Cell::new(Duration::ZERO),
+            timers: RefCell::new(Vec::new()),
+            next_timer: Cell::new(1),
+            frames: RefCell::new(Vec::new()),
+            user_events: UserEvents::default(),
+        });
+        let surface = surfaces.insert(extent, dpi, maximized);
+        Platform {
+            surface: surface.into(),
+            surfaces,
+            clipboard: Rc::default(),
+        }
+    }
+
+    pub fn surfaces(&self) -> crate::Surfaces {
+        self.surfaces.clone().into()
+    }
+
+    pub fn clipboard(&self) -> crate::clipboard::Clipboard {
+        self.clipboard.clone().into()
+    }
+
+    /// Proxy sending user events to the main surface.
+    pub fn create_proxy(&self) -> crate::EventLoopProxy {
+        self.surfaces.create_proxy(self.surface.id()).into()
+    }
+
+    /// Send the script to the callback, returning one output per processed
+    /// input.
+    ///
+    /// Like the native event loop, processing stops once the callback
+    /// returns `ControlFlow::Exit` or all surfaces are closed. Surfaces
+    /// closed by the callback receive `Event::Close` and events of proxies
+    /// are sent after each input.
+    pub fn run<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        self,
+        script: impl IntoIterator<Item = Input>,
+        mut callback: F,
+    ) -> Vec<Output> {
+        let main = self.surface.id();
+        let mut pointers = HashMap::<SurfaceId, Pointer>::new();
+
+        let mut outputs = Vec::new();
+        for input in script {
+            let (id, input) = target(main, input);
+            let output = match input {
+                Input::Wait(duration) => Output {
+                    control_flow: self.wait(duration, &mut pointers, &mut callback),
+                    cursor: pointers.get(&id).map_or(Cursor::Default, |p| p.cursor),
+                    area: None,
+                    accept: None,
+                },
+                Input::Frame => Output {
+                    control_flow: self.frame(&mut pointers, &mut callback),
+                    cursor: pointers.get(&id).map_or(Cursor::Default, |p| p.cursor),
+                    area: None,
+                    accept: None,
+                },
+                input => {
+                    let Some(surface) = self.surfaces.get(id) else {
+                        continue;
+                    };
+                    let pointer = pointers.entry(id).or_default();
+                    self.send_input(surface, input, pointer, &mut callback)
+                }
+            };
+
+            let mut control_flow = output.control_flow;
+            outputs.push(output);
+            if control_flow == ControlFlow::Continue {
+                control_flow = self.send_pending(&mut pointers, &mut callback);
+            }
+
+            if control_flow == ControlFlow::Exit || self.surfaces.is_empty() {
+                break;
+            }
+        }
+
+        outputs
+    }
+
+    fn send_input<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        &self,
+        mut surface: Surface,
+        input: Input,
+        pointer: &mut Pointer,
+        callback: &mut F,
+    ) -> Output {
+        // like the native platforms, rejected drops leave the surface
+        let input = match input {
+            Input::Drop { .. } if !pointer.drag_accept => Input::DragLeave,
+            input => input,
+        };
+        let is_hittest = matches!(input, Input::Hittest { .. });
+        let is_drag = matches!(input, Input::DragEnter { .. } | Input::DragOver { .. });
+        let is_drag_end = matches!(input, Input::DragLeave | Input::Drop { .. });
+        let is_close_request = matches!(input, Input::CloseRequest);
+        let is_close = matches!(input, Input::Close);
+        let mut area = SurfaceArea::Client;
+        let mut accept = false;
+        let mut close = true;
+        let mut rect = None;
+        let paths;
+
+        match input {
+            Input::Resize(extent) => {
+                surface.extent = extent;
+                self.surfaces.update(surface);
+            }
+            Input::DpiChanged { dpi, .. } => {
+                surface.dpi = dpi;
+                self.surfaces.update(surface);
+            }
+            Input::MouseMove { x, y } => pointer.position = Some((x, y)),
+            Input::MouseButton { button, state, .. } => match state {
+                KeyState::Down => pointer.buttons |= button,
+                KeyState::Up => pointer.buttons -= button,
+            },
+            _ => (),
+        }
+
+        let event_loop = self.event_loop(surface, pointer);
+        let event = match input {
+            Input::Surface(..) | Input::Wait(_) | Input::Frame => unreachable!(),
+            Input::Paint => Event::Paint,
+            Input::Resize(extent) => Event::Resize(extent),
+            Input::DpiChanged { dpi, rect: r } => Event::DpiChanged {
+                dpi,
+                rect: rect.insert(r),
+            },
+            Input::Focus(focused) => Event::Focus(focused),
+            Input::Occluded(occluded) => Event::Occluded(occluded),
+            Input::CloseRequest => Event::CloseRequest { close: &mut close },
+            Input::Close => Event::Close,
+            Input::Key {
+                key,
+                scancode,
+                state,
+                repeat,
+                modifiers,
+            } => Event::Key {
+                key,
+                scancode,
+                state,
+                repeat,
+                modifiers,
+            },
+            Input::Char(c) => Event::Char(c),
+            Input::Composition(composition) => Event::Composition(composition),
+            Input::MouseMove { .. } => Event::MouseMove {
+                cursor: &mut pointer.cursor,
+            },
+            Input::MouseButton {
+                button,
+                state,
+                clicks,
+                modifiers,
+            } => Event::MouseButton {
+                button,
+                state,
+                clicks,
+                modifiers,
+            },
+            Input::Scroll { delta, modifiers } => Event::Scroll { delta, modifiers },
+            Input::Hittest { x, y } => Event::Hittest {
+                x,
+                y,
+                area: &mut area,
+            },
+            Input::DragEnter { paths: p, x, y } => {
+                paths = p;
+                Event::DragEnter {
+                    paths: &paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            }
+            Input::DragOver { paths: p, x, y } => {
+                paths = p;
+                Event::DragOver {
+                    paths: &paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            }
+            Input::DragLeave => Event::DragLeave,
+            Input::Drop { paths: p, x, y } => {
+                paths = p;
+                Event::Drop {
+                    paths: &paths,
+                    x,
+                    y,
+                }
+            }
+        };
+
+        let control_flow = callback(event_loop, event);
+        if is_drag {
+            pointer.drag_accept = accept;
+        } else if is_drag_end {
+            pointer.drag_accept = false;
+        }
+        if let Some(rect) = rect {
+            surface.extent = Extent {
+                width: rect.width as f64,
+                height: rect.height as f64,
+            };
+            self.surfaces.update(surface);
+        }
+        if is_close_request && close {
+            self.surfaces.close(surface.id);
+        }
+        if is_close {
+            self.surfaces.remove(surface.id);
+        }
+
+        Output {
+            control_flow,
+            cursor: pointer.cursor,
+            area: is_hittest.then_some(area),
+            accept: is_drag.then_some(accept),
+        }
+    }
+
+    /// Send an event to the surface, unless it's closed.
+    fn send<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        &self,
+        surface: SurfaceId,
+        event: Event,
+        pointers: &mut HashMap<SurfaceId, Pointer>,
+        callback: &mut F,
+    ) -> ControlFlow {
+        match self.surfaces.get(surface) {
+            Some(s) => callback(
+                self.event_loop(s, pointers.entry(surface).or_default()),
+                event,
+            ),
+            None => ControlFlow::Continue,
+        }
+    }
+
+    fn wait<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        &self,
+        duration: Duration,
+        pointers: &mut HashMap<SurfaceId, Pointer>,
+        callback: &mut F,
+    ) -> ControlFlow {
+        let end = self.surfaces.time.get() + duration;
+        while let Some(timer) = self.surfaces.next_timer(end) {
+            self.surfaces.time.set(timer.deadline);
+            let event = Event::Timer(timer.id);
+            if self.send(timer.surface, event, pointers, callback) == ControlFlow::Exit {
+                return ControlFlow::Exit;
+            }
+        }
+        self.surfaces.time.set(end);
+        ControlFlow::Continue
+    }
+
+    fn frame<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        &self,
+        pointers: &mut HashMap<SurfaceId, Pointer>,
+        callback: &mut F,
+    ) -> ControlFlow {
+        let frames = mem::take(&mut *self.surfaces.frames.borrow_mut());
+        for surface in frames {
+            if self.send(surface, Event::AnimationFrame, pointers, callback) == ControlFlow::Exit {
+                return ControlFlow::Exit;
+            }
+        }
+        ControlFlow::Continue
+    }
+
+    /// Close surfaces and send user events requested during the last input.
+    fn send_pending<F: FnMut(EventLoop, Event) -> ControlFlow>(
+        &self,
+        pointers: &mut HashMap<SurfaceId, Pointer>,
+        callback: &mut F,
+    ) -> ControlFlow {
+        let closing = mem::take(&mut *self.surfaces.closing.borrow_mut());
+        for surface in closing {
+            let control_flow = self.send(surface, Event::Close, pointers, callback);
+            self.surfaces.remove(surface);
+            if control_flow == ControlFlow::Exit {
+                return ControlFlow::Exit;
+            }
+        }
+
+        let events = mem::take(&mut *self.surfaces.user_events.lock().unwrap());
+        for (surface, event) in events {
+            if self.send(surface, Event::User(event), pointers, callback) == ControlFlow::Exit {
+                return ControlFlow::Exit;
+            }
+        }
+        ControlFlow::Continue
+    }
+
+    fn event_loop(&self, surface: Surface, pointer: &Pointer) -> EventLoop {
+        EventLoop {
+            surface: surface.into(),
+            surfaces: self.surfaces.clone().into(),
+            clipboard: self.clipboard.clone().into(),
+            mouse_position: pointer.position,
+            mouse_buttons: pointer.buttons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clipboard, KeyCode};
+
+    const EXTENT: Extent = Extent {
+        width: 200.0,
+        height: 100.0,
+    };
+
+    #[test]
+    fn surface() {
+        let platform = Platform::new(EXTENT, 1.5);
+        let surface = platform.surface;
+        assert_eq!(surface.extent(), EXTENT);
+        assert_eq!(surface.dpi(), 1.5);
+        assert!(!surface.is_maximized());
+        assert!(raw_window_handle::HasWindowHandle::window_handle(&surface).is_err());
+
+        let outputs = platform.run(
+            [Input::Resize(Extent {
+                width: 300.0,
+                height: 150.0,
+            })],
+            |event_loop, event| {
+                assert!(
+                    matches!(event, Event::Resize(extent) if extent == event_loop.surface.extent())
+                );
+                ControlFlow::Continue
+            },
+        );
+        assert_eq!(outputs.len(), 1);
+    }
+
+    #[test]
+    fn script() {
+        let platform = Platform::with_maximized(EXTENT, 1.0, true);
+
+        let mut presses = 0;
+        let outputs = platform.run(
+            [
+                Input::Hittest { x: 195, y: 5 },
+                Input::Hittest { x: 5, y: 50 },
+                Input::MouseMove { x: 20, y: 50 },
+                Input::MouseButton {
+                    button: MouseButtons::LEFT,
+                    state: KeyState::Down,
+                    clicks: 1,
+                    modifiers: Modifiers::empty(),
+                },
+                Input::MouseButton {
+                    button: MouseButtons::LEFT,
+                    state: KeyState::Up,
+                    clicks: 1,
+                    modifiers: Modifiers::empty(),
+                },
+                Input::Scroll {
+                    delta: ScrollDelta::Pixels { x: 0.0, y: -12.5 },
+                    modifiers: Modifiers::empty(),
+                },
+                Input::Key {
+                    key: Key::Code(KeyCode::Escape),
+                    scancode: ScanCode(0x01),
+                    state: KeyState::Down,
+                    repeat: false,
+                    modifiers: Modifiers::empty(),
+                },
+                Input::Char('a'),
+            ],
+            |event_loop, event| match event {
+                Event::Hittest { x, y, area } => {
+                    let extent = event_loop.surface.extent();
+                    if x as f64 > extent.width - 10.0 && y < 10 {
+                        *area = SurfaceArea::Close;
+                    } else if x < 10 && !event_loop.surface.is_maximized() {
+                        *area = SurfaceArea::Left;
+                    }
+                    ControlFlow::Continue
+                }
+                Event::MouseMove { cursor } => {
+                    assert_eq!(event_loop.mouse_position, Some((20, 50)));
+                    *cursor = Cursor::Hand;
+                    ControlFlow::Continue
+                }
+                Event::MouseButton { state, .. } => {
+                    if state == KeyState::Down {
+                        assert_eq!(event_loop.mouse_buttons, MouseButtons::LEFT);
+                        presses += 1;
+                    } else {
+                        assert!(event_loop.mouse_buttons.is_empty());
+                    }
+                    ControlFlow::Continue
+                }
+                Event::Scroll { delta, .. } => {
+                    assert_eq!(delta, ScrollDelta::Pixels { x: 0.0, y: -12.5 });
+                    ControlFlow::Continue
+                }
+                Event::Key {
+                    key: Key::Code(KeyCode::Escape),
+                    ..
+                } => ControlFlow::Exit,
+                _ => ControlFlow::Continue,
+            },
+        );
+
+        assert_eq!(presses, 1);
+        assert_eq!(outputs.len(), 7);
+        assert_eq!(outputs[0].area, Some(SurfaceArea::Close));
+        assert_eq!(outputs[1].area, Some(SurfaceArea::Client));
+        assert_eq!(outputs[1].cursor, Cursor::Default);
+        assert_eq!(outputs[2].area, None);
+        assert_eq!(outputs[2].cursor, Cursor::Hand);
+        assert_eq!(outputs[4].cursor, Cursor::Hand);
+        assert_eq!(outputs[5].control_flow, ControlFlow::Continue);
+        assert_eq!(outputs[6].control_flow, ControlFlow::Exit);
+    }
+
+    #[test]
+    fn surfaces() {
+        let platform = Platform::new(EXTENT, 1.0);
+        let main = platform.surface.id();
+        let inspector = platform.surfaces().create();
+        assert_ne!(inspector.id(), main);
+        assert_eq!(inspector.extent(), EXTENT);
+
+        let to_inspector = |input| Input::Surface(inspector.id(), Box::new(input));
+        let mut events = Vec::new();
+        let outputs = platform.run(
+            [
+                Input::MouseMove { x: 10, y: 10 },
+                to_inspector(Input::Paint),
+                Input::Close,
+                // ignored, the main surface is closed
+                Input::Paint,
+                to_inspector(Input::Char('q')),
+                to_inspector(Input::Paint),
+            ],
+            |event_loop, event| {
+                let name = match event {
+                    Event::MouseMove { .. } => "move",
+                    Event::Paint => {
+                        // pointer state is tracked per surface
+                        assert_eq!(event_loop.mouse_position, None);
+                        "paint"
+                    }
+                    Event::Char(_) => {
+                        event_loop.surfaces.close(&event_loop.surface);
+                        "char"
+                    }
+                    Event::Close => "close",
+                    _ => "other",
+                };
+                events.push((event_loop.surface.id(), name));
+                ControlFlow::Continue
+            },
+        );
+
+        assert_eq!(
+            events,
+            [
+                (main, "move"),
+                (inspector.id(), "paint"),
+                (main, "close"),
+                (inspector.id(), "char"),
+                (inspector.id(), "close"),
+            ]
+        );
+        // stops once all surfaces are closed
+        assert_eq!(outputs.len(), 4);
+    }
+
+    #[test]
+    fn window_state() {
+        let platform = Platform::new(EXTENT, 1.0);
+
+        let mut unsaved = true;
+        let mut events = Vec::new();
+        platform.run(
+            [
+                Input::Focus(true),
+                Input::Occluded(true),
+                Input::DpiChanged {
+                    dpi: 2.0,
+                    rect: Rect {
+                        x: 10,
+                        y: 20,
+                        width: 400,
+                        height: 200,
+                    },
+                },
+                Input::Paint,
+                Input::CloseRequest,
+                Input::Char('s'),
+                Input::CloseRequest,
+                // ignored, the surface is closed
+                Input::Paint,
+            ],
+            |event_loop, event| {
+                let name = match event {
+                    Event::Focus(true) => "focus",
+                    Event::Occluded(true) => "occluded",
+                    Event::DpiChanged { dpi, rect } => {
+                        assert_eq!(dpi, 2.0);
+                        assert_eq!(event_loop.surface.dpi(), 2.0);
+                        rect.width = 300;
+                        "dpi"
+                    }
+                    Event::Paint => {
+                        // rect of the callback is applied
+                        assert_eq!(
+                            event_loop.surface.extent(),
+                            Extent {
+                                width: 300.0,
+                                height: 200.0
+                            }
+                        );
+                        "paint"
+                    }
+                    Event::CloseRequest { close } => {
+                        *close = !unsaved;
+                        "close request"
+                    }
+                    Event::Char(_) => {
+                        unsaved = false;
+                        "save"
+                    }
+                    Event::Close => "close",
+                    _ => "other",
+                };
+                events.push(name);
+                ControlFlow::Continue
+            },
+        );
+
+        assert_eq!(
+            events,
+            [
+                "focus",
+                "occluded",
+                "dpi",
+                "paint",
+                "close request",
+                "save",
+                "close request",
+                "close",
+            ]
+        );
+    }
+
+    #[test]
+    fn timers() {
+        let platform = Platform::new(EXTENT, 1.0);
+
+        let proxy = platform.create_proxy();
+        std::thread::spawn(move || proxy.send("loaded"))
+            .join()
+            .unwrap();
+
+        let mut repeating = None;
+        let mut once = None;
+        let mut events = Vec::new();
+        platform.run(
+            [
+                Input::Paint,
+                Input::Wait(Duration::from_millis(30)),
+                Input::Frame,
+                // no frame requested
+                Input::Frame,
+                Input::Wait(Duration::from_millis(50)),
+            ],
+            |event_loop, event| {
+                let name = match event {
+                    Event::Paint => {
+                        repeating = Some(event_loop.set_timer(Duration::from_millis(10), true));
+                        once = Some(event_loop.set_timer(Duration::from_millis(25), false));
+                        event_loop.request_animation_frame();
+                        event_loop.request_animation_frame();
+                        "paint"
+                    }
+                    Event::User(event) => {
+                        assert_eq!(event.downcast_ref::<&str>(), Some(&"loaded"));
+                        "user"
+                    }
+                    Event::Timer(timer) if Some(timer) == repeating => "repeating",
+                    Event::Timer(timer) if Some(timer) == once => "once",
+                    Event::AnimationFrame => {
+                        event_loop.cancel_timer(repeating.unwrap());
+                        "frame"
+                    }
+                    _ => "other",
+                };
+                events.push(name);
+                ControlFlow::Continue
+            },
+        );
+
+        assert_eq!(
+            events,
+            [
+                "paint",
+                "user",
+                "repeating",
+                "repeating",
+                "once",
+                "repeating",
+                "frame",
+            ]
+        );
+    }
+
+    #[test]
+    fn composition() {
+        let platform = Platform::new(EXTENT, 1.0);
+
+        let mut text = String::new();
+        let mut preedit = String::new();
+        platform.run(
+            [
+                Input::Char('a'),
+                Input::Composition(Composition::Start),
+                Input::Composition(Composition::Update {
+                    text: "かんじ".into(),
+                    cursor: Some(9..9),
+                }),
+                Input::Composition(Composition::Update {
+                    text: "漢字".into(),
+                    cursor: Some(0..6),
+                }),
+                Input::Composition(Composition::End),
+                Input::Composition(Composition::Commit("漢字".into())),
+            ],
+            |_, event| {
+                match event {
+                    Event::Char(c) => text.push(c),
+                    Event::Composition(Composition::Update { text, .. }) => preedit = text,
+                    Event::Composition(Composition::End) => preedit.clear(),
+                    Event::Composition(Composition::Commit(commit)) => text += &commit,
+                    _ => (),
+                }
+                ControlFlow::Continue
+            },
+        );
+
+        assert_eq!(text, "a漢字");
+        assert!(preedit.is_empty());
+    }
+
+    #[test]
+    fn drag_drop() {
+        let platform = Platform::new(EXTENT, 1.0);
+        let svg = || vec![PathBuf::from("/tmp/icon.svg")];
+
+        let mut dropped = Vec::new();
+        let outputs = platform.run(
+            [
+                Input::DragEnter {
+                    paths: svg(),
+                    x: 5,
+                    y: 5,
+                },
+                Input::DragOver {
+                    paths: svg(),
+                    x: 50,
+                    y: 50,
+                },
+                Input::Drop {
+                    paths: svg(),
+                    x: 50,
+                    y: 50,
+                },
+                Input::DragLeave,
+            ],
+            |_, event| {
+                match event {
+                    // only accepted outside of the caption
+                    Event::DragEnter { y, accept, .. } | Event::DragOver { y, accept, .. } => {
+                        *accept = y > 10
+                    }
+                    Event::Drop { paths, x, y } => dropped.push((paths.to_vec(), x, y)),
+                    _ => (),
+                }
+                ControlFlow::Continue
+            },
+        );
+
+        assert_eq!(outputs[0].accept, Some(false));
+        assert_eq!(outputs[1].accept, Some(true));
+        assert_eq!(outputs[2].accept, None);
+        assert_eq!(outputs[3].accept, None);
+        assert_eq!(dropped, [(svg(), 50, 50)]);
+    }
+
+    #[test]
+    fn rejected_drop() {
+        let platform = Platform::new(EXTENT, 1.0);
+        let svg = || vec![PathBuf::from("/tmp/icon.svg")];
+        let drag = |y| Input::DragOver {
+            paths: svg(),
+            x: 50,
+            y,
+        };
+        let drop = |y| Input::Drop {
+            paths: svg(),
+            x: 50,
+            y,
+        };
+
+        let mut events = Vec::new();
+        platform.run(
+            [drag(5), drop(5), drag(50), drop(50), drop(50)],
+            |_, event| {
+                match event {
+                    Event::DragOver { y, accept, .. } => *accept = y > 10,
+                    Event::Drop { y, .. } => events.push(format!("drop {}", y)),
+                    Event::DragLeave => events.push("leave".to_string()),
+                    _ => (),
+                }
+                ControlFlow::Continue
+            },
+        );
+
+        // drops after a rejection or without a new drag are rejected
+        assert_eq!(events, ["leave", "drop 50", "leave"]);
+    }
+
+    #[test]
+    fn clipboard() {
+        const NOTEBOOK: &str = "application/x-nari-notebook";
+
+        let platform = Platform::new(EXTENT, 1.0);
+        let clipboard = platform.clipboard();
+        assert_eq!(clipboard.text(), None);
+
+        let copy = Input::Key {
+            key: Key::Char('C'),
+            scancode: ScanCode(0x2E),
+            state: KeyState::Down,
+            repeat: false,
+            modifiers: Modifiers::CONTROL,
+        };
+        let paste = Input::Key {
+            key: Key::Char('V'),
+            scancode: ScanCode(0x2F),
+            state: KeyState::Down,
+            repeat: false,
+            modifiers: Modifiers::CONTROL,
+        };
+
+        let mut pasted = Vec::new();
+        platform.run([copy, paste], |event_loop, event| {
+            match event {
+                Event::Key {
+                    key: Key::Char('C'),
+                    ..
+                } => event_loop
+                    .clipboard
+                    .set(&[(NOTEBOOK, &[1, 2, 3]), (clipboard::TEXT, b"cell")])
+                    .unwrap(),
+                Event::Key {
+                    key: Key::Char('V'),
+                    ..
+                } => {
+                    pasted.push(event_loop.clipboard.get(NOTEBOOK));
+                    pasted.push(event_loop.clipboard.text().map(String::into_bytes));
+                }
+                _ => (),
+            }
+            ControlFlow::Continue
+        });
+
+        assert_eq!(pasted, [Some(vec![1, 2, 3]), Some(b"cell".to_vec())]);
+        // shared with the clipboard of the platform
+        assert_eq!(clipboard.text().as_deref(), Some("cell"));
+
+        clipboard.set_text("text").unwrap();
+        assert_eq!(clipboard.get(NOTEBOOK), None);
+    }
+}
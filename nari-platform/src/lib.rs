@@ -1,371 +1,23 @@
-use core::num::NonZeroIsize;
 use raw_window_handle::{
-    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
-    RawWindowHandle, Win32WindowHandle, WindowHandle, WindowsDisplayHandle,
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WindowHandle,
 };
+use std::{any::Any, ops::Range, path::PathBuf, rc::Rc, time::Duration};
 
+pub mod clipboard;
 pub mod cpu;
+pub mod headless;
 
-use std::{
-    cell::{Cell, RefCell},
-    ffi::OsStr,
-    iter::once,
-    mem::{self, MaybeUninit},
-    os::windows::ffi::OsStrExt,
-    ptr,
-    rc::Rc,
-};
-use windows_sys::{
-    core::PCWSTR,
-    Win32::{
-        Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM},
-        Graphics::{
-            Dwm::{DwmExtendFrameIntoClientArea, DwmFlush},
-            Gdi::{
-                GetMonitorInfoW, MonitorFromRect, RedrawWindow, ScreenToClient, ValidateRect,
-                MONITORINFOEXW, MONITOR_DEFAULTTONULL, RDW_INVALIDATE,
-            },
-        },
-        System::SystemServices::{IMAGE_DOS_HEADER, MK_LBUTTON, MK_RBUTTON},
-        UI::{
-            Controls::{HOVER_DEFAULT, MARGINS, WM_MOUSELEAVE},
-            HiDpi::{
-                GetDpiForWindow, SetProcessDpiAwarenessContext,
-                DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
-            },
-            Input::KeyboardAndMouse::{
-                GetKeyState, MapVirtualKeyW, ReleaseCapture, SetCapture, TrackMouseEvent,
-                MAPVK_VK_TO_CHAR, TME_LEAVE, TME_NONCLIENT, TRACKMOUSEEVENT, VK_CONTROL, VK_DOWN,
-                VK_LEFT, VK_MENU, VK_RIGHT, VK_SHIFT, VK_UP,
-            },
-            WindowsAndMessaging::{
-                CreateWindowExW, DefWindowProcW, DispatchMessageW, GetClientRect, GetMessageW,
-                GetWindowLongPtrW, GetWindowPlacement, LoadCursorW, PostMessageW, RegisterClassExW,
-                SetCursor, SetWindowLongPtrW, ShowWindow, TranslateMessage, CREATESTRUCTW,
-                CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, GWL_USERDATA, HTBOTTOM, HTBOTTOMLEFT,
-                HTBOTTOMRIGHT, HTCAPTION, HTCLIENT, HTCLOSE, HTLEFT, HTMAXBUTTON, HTMINBUTTON,
-                HTRIGHT, HTTOP, HTTOPLEFT, HTTOPRIGHT, IDC_ARROW, IDC_HAND, IDC_IBEAM,
-                IDC_SIZENESW, IDC_SIZENS, IDC_SIZENWSE, IDC_SIZEWE, NCCALCSIZE_PARAMS, SC_CLOSE,
-                SC_MAXIMIZE, SC_MINIMIZE, SC_RESTORE, SW_MAXIMIZE, SW_SHOW, WINDOWPLACEMENT,
-                WM_CHAR, WM_DESTROY, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
-                WM_MOUSEMOVE, WM_NCCALCSIZE, WM_NCCREATE, WM_NCHITTEST, WM_NCLBUTTONDOWN,
-                WM_NCLBUTTONUP, WM_NCMOUSELEAVE, WM_NCMOUSEMOVE, WM_PAINT, WM_RBUTTONDOWN,
-                WM_RBUTTONUP, WM_SETCURSOR, WM_SIZE, WM_SYSCOMMAND, WNDCLASSEXW, WS_CAPTION,
-                WS_EX_ACCEPTFILES, WS_EX_APPWINDOW, WS_EX_WINDOWEDGE, WS_MAXIMIZEBOX,
-                WS_MINIMIZEBOX, WS_SIZEBOX, WS_SYSMENU,
-            },
-        },
-    },
-};
-
-fn encode_wide(string: impl AsRef<OsStr>) -> Vec<u16> {
-    string.as_ref().encode_wide().chain(once(0)).collect()
-}
-
-fn get_instance_handle() -> HINSTANCE {
-    extern "C" {
-        static __ImageBase: IMAGE_DOS_HEADER;
-    }
-    unsafe { &__ImageBase as *const _ as _ }
-}
-
-#[inline(always)]
-const fn loword(x: u32) -> u16 {
-    (x & 0xFFFF) as u16
-}
-
-#[inline(always)]
-const fn hiword(x: u32) -> u16 {
-    ((x >> 16) & 0xFFFF) as u16
-}
-
-unsafe extern "system" fn window_proc(
-    window: HWND,
-    msg: u32,
-    wparam: WPARAM,
-    lparam: LPARAM,
-) -> LRESULT {
-    let surface = Surface { hwnd: window };
-
-    // match msg {
-    //     WM_NCCREATE => println!("WM_NCCREATE"),
-    //     WM_NCCALCSIZE => println!("WM_NCCALCSIZE"),
-    //     WM_CREATE => println!("WM_CREATE"),
-    //     WM_MOUSEMOVE => println!("WM_MOUSEMOVE"),
-    //     WM_NCMOUSEMOVE => println!("WM_NCMOUSEMOVE"),
-    //     WM_MOUSELEAVE => println!("WM_MOUSELEAVE"),
-    //     WM_NCMOUSELEAVE => println!("WM_NCMOUSELEAVE"),
-    //     WM_PAINT => println!("WM_PAINT"),
-    //     _ => (), // println!("{}", msg),
-    // }
-
-    let user_data = {
-        let ptr = GetWindowLongPtrW(window, GWL_USERDATA) as *mut UserData;
-        if ptr.is_null() {
-            match msg {
-                WM_NCCREATE => {
-                    let createstruct = &mut *(lparam as *mut CREATESTRUCTW);
-                    SetWindowLongPtrW(window, GWL_USERDATA, createstruct.lpCreateParams as _);
-
-                    let user_data = createstruct.lpCreateParams as *mut UserData;
-                    (*user_data).surface.set(surface);
-
-                    // add window shadow effect
-                    let margins = MARGINS {
-                        cxLeftWidth: 1,
-                        cxRightWidth: 1,
-                        cyTopHeight: 0,
-                        cyBottomHeight: 1,
-                    };
-                    DwmExtendFrameIntoClientArea(window, &margins);
-                }
-                _ => (),
-            };
-
-            return DefWindowProcW(window, msg, wparam, lparam);
-        }
-
-        &mut *ptr
-    };
-
-    match msg {
-        WM_NCCALCSIZE => {
-            if wparam == false.into() {
-                return DefWindowProcW(window, msg, wparam, lparam);
-            }
-
-            let params = &mut *(lparam as *mut NCCALCSIZE_PARAMS);
-
-            if surface.is_maximized() {
-                // limit to current monitor, otherwise window gets too large
-                let monitor = MonitorFromRect(&params.rgrc[0], MONITOR_DEFAULTTONULL);
-                let mut monitor_info: MONITORINFOEXW = mem::zeroed();
-                monitor_info.monitorInfo.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
-                GetMonitorInfoW(monitor, &mut monitor_info as *mut _ as *mut _);
-                params.rgrc[0] = monitor_info.monitorInfo.rcWork;
-            }
-
-            // Sync with DWM here giving us maximum amount of time to redraw the screen.
-            DwmFlush();
-            0
-        }
-
-        WM_NCHITTEST => {
-            let x = loword(lparam as u32) as i32;
-            let y = hiword(lparam as u32) as i32;
-
-            let mut point = POINT { x, y };
-            ScreenToClient(window, &mut point);
-
-            let mut area = SurfaceArea::Client;
-            user_data.send(Event::Hittest {
-                x: point.x,
-                y: point.y,
-                area: &mut area,
-            });
-
-            let wm_area = match area {
-                SurfaceArea::Client => HTCLIENT,
-                SurfaceArea::Top => HTTOP,
-                SurfaceArea::Bottom => HTBOTTOM,
-                SurfaceArea::Left => HTLEFT,
-                SurfaceArea::Right => HTRIGHT,
-                SurfaceArea::BottomLeft => HTBOTTOMLEFT,
-                SurfaceArea::BottomRight => HTBOTTOMRIGHT,
-                SurfaceArea::TopLeft => HTTOPLEFT,
-                SurfaceArea::TopRight => HTTOPRIGHT,
-                SurfaceArea::Caption => HTCAPTION,
-                SurfaceArea::Minimize => HTMINBUTTON,
-                SurfaceArea::Maximize => HTMAXBUTTON,
-                SurfaceArea::Close => HTCLOSE,
-            };
-
-            wm_area as LRESULT
-        }
-
-        WM_NCLBUTTONDOWN => {
-            user_data.keydown_area.set(wparam);
-            match wparam as u32 {
-                // Prevent windows from drawing ugly legacy buttons on button down..
-                // But we manually have to send the SYSCOMMANDs now
-                HTMINBUTTON | HTMAXBUTTON | HTCLOSE => 0,
-                _ => DefWindowProcW(window, msg, wparam, lparam),
-            }
-        }
-
-        WM_NCLBUTTONUP => {
-            let prev_hit = user_data.keydown_area.get();
-            if prev_hit == wparam {
-                match wparam as u32 {
-                    HTMINBUTTON => {
-                        PostMessageW(window, WM_SYSCOMMAND, SC_MINIMIZE as _, lparam);
-                    }
-                    HTMAXBUTTON => {
-                        let action = if surface.is_maximized() {
-                            SC_RESTORE
-                        } else {
-                            SC_MAXIMIZE
-                        };
-                        PostMessageW(window, WM_SYSCOMMAND, action as _, lparam);
-                    }
-                    HTCLOSE => {
-                        PostMessageW(window, WM_SYSCOMMAND, SC_CLOSE as _, lparam);
-                    }
-                    _ => {}
-                }
-            }
-
-            DefWindowProcW(window, msg, wparam, lparam)
-        }
-
-        WM_CHAR => {
-            if let Some(high_surrogate) = user_data.u16_surrogate.take() {
-                let is_low_surrogate = (0xDC00..=0xDFFF).contains(&wparam);
-                if is_low_surrogate {
-                    if let Some(Ok(c)) = char::decode_utf16([high_surrogate, wparam as u16]).next()
-                    {
-                        if !c.is_control() {
-                            user_data.send(Event::Char(c));
-                        }
-                    }
-                }
-            }
-
-            let is_high_surrogate = (0xDC00..=0xDFFF).contains(&wparam);
-            if is_high_surrogate {
-                user_data.u16_surrogate.set(Some(wparam as u16));
-            } else if let Some(c) = char::from_u32(wparam as u32) {
-                if !c.is_control() {
-                    user_data.send(Event::Char(c));
-                }
-            }
-
-            0
-        }
-
-        WM_KEYDOWN => {
-            user_data.on_key(wparam, KeyState::Down);
-            0
-        }
-
-        WM_KEYUP => {
-            user_data.on_key(wparam, KeyState::Up);
-            0
-        }
-
-        WM_LBUTTONDOWN => {
-            user_data.on_button(wparam, MouseButtons::LEFT, KeyState::Down);
-            0
-        }
-        WM_LBUTTONUP => {
-            user_data.on_button(wparam, MouseButtons::LEFT, KeyState::Up);
-            0
-        }
-        WM_RBUTTONDOWN => {
-            user_data.on_button(wparam, MouseButtons::RIGHT, KeyState::Down);
-            0
-        }
-        WM_RBUTTONUP => {
-            user_data.on_button(wparam, MouseButtons::RIGHT, KeyState::Up);
-            0
-        }
-
-        WM_SETCURSOR => {
-            unsafe {
-                SetCursor(LoadCursorW(0, user_data.cursor.get().idc()));
-            }
-            1
-        }
+#[cfg(target_os = "linux")]
+mod x11;
+#[cfg(target_os = "linux")]
+use x11 as backend;
 
-        WM_MOUSEMOVE => {
-            let x = loword(lparam as u32) as i16 as i32;
-            let y = hiword(lparam as u32) as i16 as i32;
+#[cfg(target_os = "windows")]
+mod win32;
+#[cfg(target_os = "windows")]
+use win32 as backend;
 
-            // Track to get `WM_MOUSELEAVE` events
-            TrackMouseEvent(&mut TRACKMOUSEEVENT {
-                cbSize: mem::size_of::<TRACKMOUSEEVENT>() as u32,
-                dwFlags: TME_LEAVE,
-                hwndTrack: window,
-                dwHoverTime: HOVER_DEFAULT,
-            });
-
-            user_data.mouse_position.set(Some((x, y)));
-
-            let mut cursor = user_data.cursor.get();
-            user_data.send(Event::MouseMove {
-                cursor: &mut cursor,
-            });
-            user_data.cursor.set(cursor);
-
-            0
-        }
-
-        WM_NCMOUSEMOVE => {
-            match wparam as u32 {
-                HTCAPTION | HTMINBUTTON | HTMAXBUTTON | HTCLOSE | HTBOTTOM | HTLEFT | HTRIGHT
-                | HTTOP | HTBOTTOMLEFT | HTBOTTOMRIGHT | HTTOPLEFT | HTTOPRIGHT => {
-                    // Track to get `WM_NCMOUSELEAVE` events
-                    TrackMouseEvent(&mut TRACKMOUSEEVENT {
-                        cbSize: mem::size_of::<TRACKMOUSEEVENT>() as u32,
-                        dwFlags: TME_LEAVE | TME_NONCLIENT,
-                        hwndTrack: window,
-                        dwHoverTime: HOVER_DEFAULT,
-                    });
-
-                    let x = loword(lparam as u32) as i16 as i32;
-                    let y = hiword(lparam as u32) as i16 as i32;
-
-                    let mut pt = POINT { x, y };
-                    ScreenToClient(window, &mut pt);
-                    user_data.mouse_position.set(Some((pt.x, pt.y)));
-                }
-                _ => {
-                    // handled by system, considered out of user area
-                    user_data.mouse_position.set(None);
-                }
-            }
-
-            let mut cursor = user_data.cursor.get();
-            user_data.send(Event::MouseMove {
-                cursor: &mut cursor,
-            });
-            user_data.cursor.set(cursor);
-
-            surface.redraw();
-
-            DefWindowProcW(window, msg, wparam, lparam)
-        }
-
-        WM_MOUSELEAVE | WM_NCMOUSELEAVE => {
-            user_data.mouse_position.set(None);
-            surface.redraw();
-
-            0
-        }
-
-        WM_PAINT => {
-            user_data.send(Event::Paint);
-            ValidateRect(window, ptr::null());
-
-            DefWindowProcW(window, msg, wparam, lparam)
-        }
-        WM_SIZE => {
-            let width = loword(lparam as u32) as f64;
-            let height = hiword(lparam as u32) as f64;
-            user_data.send(Event::Resize(Extent { width, height }));
-
-            0
-        }
-        WM_DESTROY => {
-            user_data.control_flow.set(ControlFlow::Exit);
-
-            0
-        }
-        _ => DefWindowProcW(window, msg, wparam, lparam),
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ControlFlow {
     Continue,
     Exit,
@@ -395,27 +47,16 @@ bitflags::bitflags! {
         const ALT     = 0b001;
         const CONTROL = 0b010;
         const SHIFT   = 0b100;
+        const SUPER   = 0b1000;
     }
 
+    #[derive(Default)]
     pub struct MouseButtons: u32 {
-        const LEFT    = 0b01;
-        const RIGHT   = 0b10;
-    }
-}
-
-impl Modifiers {
-    unsafe fn query() -> Self {
-        let mut modifiers = Modifiers::empty();
-        if GetKeyState(VK_MENU as i32) & 0x80 != 0 {
-            modifiers |= Modifiers::ALT;
-        }
-        if GetKeyState(VK_CONTROL as i32) & 0x80 != 0 {
-            modifiers |= Modifiers::CONTROL;
-        }
-        if GetKeyState(VK_SHIFT as i32) & 0x80 != 0 {
-            modifiers |= Modifiers::SHIFT;
-        }
-        modifiers
+        const LEFT    = 0b00001;
+        const RIGHT   = 0b00010;
+        const MIDDLE  = 0b00100;
+        const BACK    = 0b01000;
+        const FORWARD = 0b10000;
     }
 }
 
@@ -425,12 +66,111 @@ pub enum KeyState {
     Up,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Logical keys without character representation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum KeyCode {
     Left,
     Right,
     Up,
     Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+
+    Insert,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Escape,
+
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    PrintScreen,
+    Pause,
+    ContextMenu,
+
+    // Modifiers
+    ShiftLeft,
+    ShiftRight,
+    ControlLeft,
+    ControlRight,
+    AltLeft,
+    AltRight,
+    SuperLeft,
+    SuperRight,
+}
+
+impl KeyCode {
+    const FUNCTION: [KeyCode; 24] = [
+        KeyCode::F1,
+        KeyCode::F2,
+        KeyCode::F3,
+        KeyCode::F4,
+        KeyCode::F5,
+        KeyCode::F6,
+        KeyCode::F7,
+        KeyCode::F8,
+        KeyCode::F9,
+        KeyCode::F10,
+        KeyCode::F11,
+        KeyCode::F12,
+        KeyCode::F13,
+        KeyCode::F14,
+        KeyCode::F15,
+        KeyCode::F16,
+        KeyCode::F17,
+        KeyCode::F18,
+        KeyCode::F19,
+        KeyCode::F20,
+        KeyCode::F21,
+        KeyCode::F22,
+        KeyCode::F23,
+        KeyCode::F24,
+    ];
+
+    /// Function key `F<n>`, starting at 1.
+    pub fn function(n: u32) -> Option<KeyCode> {
+        Self::FUNCTION.get(n.checked_sub(1)? as usize).copied()
+    }
+
+    /// Modifier flag of modifier keys.
+    pub fn modifier(self) -> Option<Modifiers> {
+        match self {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => Some(Modifiers::SHIFT),
+            KeyCode::ControlLeft | KeyCode::ControlRight => Some(Modifiers::CONTROL),
+            KeyCode::AltLeft | KeyCode::AltRight => Some(Modifiers::ALT),
+            KeyCode::SuperLeft | KeyCode::SuperRight => Some(Modifiers::SUPER),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -439,20 +179,59 @@ pub enum Key {
     Code(KeyCode),
 }
 
+/// Physical key as PC set 1 scan code, independent of the keyboard layout.
+///
+/// Extended keys are prefixed with `0xE0`, e.g. `0xE04B` for the left arrow
+/// key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScanCode(pub u32);
+
 #[derive(Debug)]
 pub enum Event<'a> {
     Paint,
     Resize(Extent),
+    /// Surface moved to a display with a different DPI. `rect` is the
+    /// suggested new surface rect in screen coordinates, applied after the
+    /// event.
+    DpiChanged {
+        dpi: f64,
+        rect: &'a mut Rect,
+    },
+    /// Keyboard focus gained or lost.
+    Focus(bool),
+    /// Surface is minimized or fully covered, painting can be paused until
+    /// it's visible again.
+    Occluded(bool),
+    /// Close requested by the user, e.g. with the close button. The surface
+    /// is closed after the event unless `close` is set to false.
+    CloseRequest {
+        close: &'a mut bool,
+    },
+    /// Surface is destroyed after the event, other surfaces stay open.
+    /// The event loop exits once all surfaces are closed, remaining surfaces
+    /// are closed on `ControlFlow::Exit`.
+    Close,
 
     // Should be only used for hotkey like semantics.
     Key {
         key: Key,
+        scancode: ScanCode,
         state: KeyState,
+        /// Key down generated by holding the key.
+        repeat: bool,
         modifiers: Modifiers,
     },
     MouseButton {
         button: MouseButtons,
         state: KeyState,
+        /// Number of consecutive clicks within the system double click
+        /// interval, e.g. 2 for a double click. Releases repeat the count of
+        /// their press.
+        clicks: u32,
+        modifiers: Modifiers,
+    },
+    Scroll {
+        delta: ScrollDelta,
         modifiers: Modifiers,
     },
     MouseMove {
@@ -461,6 +240,8 @@ pub enum Event<'a> {
 
     /// Character input for text processing.
     Char(char),
+    /// Text input of an input method editor.
+    Composition(Composition),
 
     /// Window Area hittest.
     Hittest {
@@ -468,101 +249,83 @@ pub enum Event<'a> {
         y: i32,
         area: &'a mut SurfaceArea,
     },
-}
 
-struct UserData {
-    surface: Cell<Surface>,
-    cursor: Cell<Cursor>,
-    control_flow: Cell<ControlFlow>,
-    event_callback: RefCell<Box<dyn FnMut(EventLoop, Event) -> ControlFlow>>,
-    mouse_position: Cell<Option<(i32, i32)>>,
-    mouse_buttons: Cell<MouseButtons>,
-    keydown_area: Cell<WPARAM>,       // WM_NCLBUTTON
-    u16_surrogate: Cell<Option<u16>>, // WM_CHAR
-}
-
-impl UserData {
-    fn send(&self, event: Event) {
-        let event_loop = EventLoop {
-            surface: self.surface.get(),
-            mouse_position: self.mouse_position.get(),
-            mouse_buttons: self.mouse_buttons.get(),
-        };
-        let mut callback = self.event_callback.borrow_mut();
-        let control_flow = callback(event_loop, event);
-        self.control_flow.set(control_flow);
-    }
-
-    fn on_key(&self, wparam: WPARAM, state: KeyState) {
-        let c = unsafe { MapVirtualKeyW(wparam as u32, MAPVK_VK_TO_CHAR) };
-
-        let modifiers = unsafe { Modifiers::query() };
-
-        if c == 0 {
-            let code = match wparam as u16 {
-                VK_LEFT => KeyCode::Left,
-                VK_RIGHT => KeyCode::Right,
-                VK_UP => KeyCode::Up,
-                VK_DOWN => KeyCode::Down,
-
-                // todo: support other keys as well
-                _ => return,
-            };
+    /// Files dragged into the surface, `accept` reports whether they can be
+    /// dropped at the position.
+    DragEnter {
+        paths: &'a [PathBuf],
+        x: i32,
+        y: i32,
+        accept: &'a mut bool,
+    },
+    DragOver {
+        paths: &'a [PathBuf],
+        x: i32,
+        y: i32,
+        accept: &'a mut bool,
+    },
+    DragLeave,
+    /// Files dropped at the position, only sent if the last `DragEnter` or
+    /// `DragOver` accepted them.
+    Drop {
+        paths: &'a [PathBuf],
+        x: i32,
+        y: i32,
+    },
 
-            self.send(Event::Key {
-                key: Key::Code(code),
-                state,
-                modifiers,
-            });
-        } else {
-            const DEAD_KEY_FLAG: u32 = 1 << 31;
-            if let Some(c) = char::from_u32(c & !DEAD_KEY_FLAG) {
-                self.send(Event::Key {
-                    key: Key::Char(c),
-                    state,
-                    modifiers,
-                });
-            }
-        }
-    }
+    /// Display is ready for the next frame, see
+    /// `EventLoop::request_animation_frame`.
+    AnimationFrame,
+    Timer(TimerId),
+    /// Event sent with an `EventLoopProxy`.
+    User(Box<dyn Any + Send>),
+}
 
-    fn on_button(&self, wparam: WPARAM, button: MouseButtons, state: KeyState) {
-        let buttons = {
-            let mut buttons = MouseButtons::empty();
-            let wparam = wparam as u32;
-            if wparam & MK_LBUTTON != 0 {
-                buttons |= MouseButtons::LEFT;
-            }
-            if wparam & MK_RBUTTON != 0 {
-                buttons |= MouseButtons::RIGHT;
-            }
-            buttons
-        };
-        self.mouse_buttons.set(buttons);
-
-        // Mouse capture logic, required that we receive mouse events
-        // once we leave the window.
-        match (state, buttons.bits().count_ones()) {
-            (KeyState::Down, 1) => unsafe {
-                SetCapture(self.surface.get().hwnd);
-            },
-            (KeyState::Up, 0) => unsafe {
-                ReleaseCapture();
-            },
-            _ => (),
-        }
+/// Scroll distance, positive values scroll up and to the right.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScrollDelta {
+    /// Mouse wheel steps, already multiplied with the number of lines
+    /// scrolled per step. Fractional for touchpads and high resolution
+    /// wheels reporting partial steps.
+    Lines { x: f64, y: f64 },
+    /// High precision scrolling of touchpads in physical pixels.
+    ///
+    /// Not reported by the native backends yet, Windows reports touchpads
+    /// in fractional lines and X11 in whole wheel steps.
+    Pixels { x: f64, y: f64 },
+}
 
-        let modifiers = unsafe { Modifiers::query() };
+/// Input method composition, e.g. for CJK scripts.
+///
+/// Preedit text is displayed by the application at the caret until the
+/// composition ends. Committed text may arrive before or after the end.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Composition {
+    Start,
+    /// Preedit text changed, replacing the previous preedit text.
+    Update {
+        text: String,
+        /// Byte range in `text` of the clause being converted, or an empty
+        /// range at the caret position.
+        cursor: Option<Range<usize>>,
+    },
+    /// Preedit text is removed.
+    End,
+    /// Text to insert at the caret.
+    Commit(String),
+}
 
-        self.send(Event::MouseButton {
-            button,
-            state,
-            modifiers,
-        });
-    }
+/// Rectangle in physical pixels, relative to the client area unless noted
+/// otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Cursor {
     Default,
     Hand,
@@ -578,219 +341,405 @@ pub enum Cursor {
     ResizeBottomRight,
 }
 
-impl Cursor {
-    fn idc(self) -> PCWSTR {
-        match self {
-            Self::Default => IDC_ARROW,
-            Self::Hand => IDC_HAND,
-            Self::Text => IDC_IBEAM,
-            Self::ResizeLeft | Self::ResizeRight => IDC_SIZEWE,
-            Self::ResizeTop | Self::ResizeBottom => IDC_SIZENS,
-            Self::ResizeBottomLeft | Self::ResizeTopRight => IDC_SIZENESW,
-            Self::ResizeBottomRight | Self::ResizeTopLeft => IDC_SIZENWSE,
+type EventCallback = Box<dyn FnMut(EventLoop, Event) -> ControlFlow>;
+
+/// Counts consecutive clicks of the same button.
+#[derive(Copy, Clone, Debug, Default)]
+struct ClickCounter {
+    button: MouseButtons,
+    /// Time of the last press in milliseconds.
+    time: u32,
+    position: (i32, i32),
+    count: u32,
+}
+
+impl ClickCounter {
+    /// Register a button press, returns the number of consecutive clicks.
+    ///
+    /// Presses count as consecutive when they happen within `interval`
+    /// milliseconds and `distance` pixels of the previous press.
+    fn press(
+        &mut self,
+        button: MouseButtons,
+        time: u32,
+        position: (i32, i32),
+        interval: u32,
+        distance: (i32, i32),
+    ) -> u32 {
+        let consecutive = self.count > 0
+            && self.button == button
+            && time.wrapping_sub(self.time) <= interval
+            && (position.0 - self.position.0).abs() <= distance.0
+            && (position.1 - self.position.1).abs() <= distance.1;
+
+        *self = ClickCounter {
+            button,
+            time,
+            position,
+            count: if consecutive { self.count + 1 } else { 1 },
+        };
+        self.count
+    }
+
+    /// Number of clicks for the release of `button`.
+    fn release(&self, button: MouseButtons) -> u32 {
+        if self.button == button {
+            self.count.max(1)
+        } else {
+            1
         }
     }
 }
 
 pub struct EventLoop {
+    /// Surface receiving the event.
     pub surface: Surface,
+    pub surfaces: Surfaces,
+    pub clipboard: clipboard::Clipboard,
     // pub cursor: Option<Cursor>,
     pub mouse_position: Option<(i32, i32)>,
     pub mouse_buttons: MouseButtons,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl EventLoop {
+    /// Request a single `Event::AnimationFrame` for the surface with the next
+    /// display refresh.
+    pub fn request_animation_frame(&self) {
+        self.surfaces.request_animation_frame(self.surface.id())
+    }
+
+    /// Start a timer sending `Event::Timer` to the surface after `interval`,
+    /// and every `interval` afterwards if `repeat` is set.
+    ///
+    /// Timers are cancelled once they stop or the surface is closed.
+    pub fn set_timer(&self, interval: Duration, repeat: bool) -> TimerId {
+        let interval = interval.max(MIN_TIMER_INTERVAL);
+        self.surfaces.set_timer(self.surface.id(), interval, repeat)
+    }
+
+    pub fn cancel_timer(&self, timer: TimerId) {
+        self.surfaces.cancel_timer(timer)
+    }
+
+    /// Proxy sending user events to the surface.
+    pub fn create_proxy(&self) -> EventLoopProxy {
+        self.surfaces.create_proxy(self.surface.id())
+    }
+}
+
+/// Shortest interval of timers, repeating timers without interval would
+/// block the event loop.
+const MIN_TIMER_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Identifies a running timer of the event loop.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+#[derive(Clone)]
+enum EventLoopProxyKind {
+    Native(backend::Proxy),
+    Headless(headless::Proxy),
+}
+
+/// Sends events into the event loop from other threads.
+#[derive(Clone)]
+pub struct EventLoopProxy {
+    kind: EventLoopProxyKind,
+}
+
+impl EventLoopProxy {
+    /// Send `Event::User` to the surface of the proxy, dropped if the surface
+    /// is closed.
+    pub fn send(&self, event: impl Any + Send) {
+        let event = Box::new(event);
+        match &self.kind {
+            EventLoopProxyKind::Native(proxy) => proxy.send(event),
+            EventLoopProxyKind::Headless(proxy) => proxy.send(event),
+        }
+    }
+}
+
+impl From<backend::Proxy> for EventLoopProxy {
+    fn from(proxy: backend::Proxy) -> Self {
+        Self {
+            kind: EventLoopProxyKind::Native(proxy),
+        }
+    }
+}
+
+impl From<headless::Proxy> for EventLoopProxy {
+    fn from(proxy: headless::Proxy) -> Self {
+        Self {
+            kind: EventLoopProxyKind::Headless(proxy),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Extent {
     pub width: f64,
     pub height: f64,
 }
 
+/// Identifies a surface of the platform, unique while the surface is open.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SurfaceId(u64);
+
+#[derive(Copy, Clone)]
+enum SurfaceKind {
+    Native(backend::Surface),
+    Headless(headless::Surface),
+}
+
+/// Handle to the window of a platform.
 #[derive(Copy, Clone)]
 pub struct Surface {
-    hwnd: HWND,
+    kind: SurfaceKind,
 }
 
 impl Surface {
+    pub fn id(&self) -> SurfaceId {
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.id(),
+            SurfaceKind::Headless(surface) => surface.id,
+        }
+    }
+
     pub fn show(&self) {
-        unsafe {
-            ShowWindow(self.hwnd, SW_SHOW);
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.show(),
+            SurfaceKind::Headless(_) => (),
         }
     }
 
-    pub fn hwnd(self) -> HWND {
-        self.hwnd
+    #[cfg(target_os = "windows")]
+    pub fn hwnd(self) -> windows_sys::Win32::Foundation::HWND {
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.hwnd(),
+            SurfaceKind::Headless(_) => 0,
+        }
     }
 
-    pub fn extent(&self) -> Extent {
-        let mut rect = MaybeUninit::uninit();
-        unsafe {
-            GetClientRect(self.hwnd, rect.as_mut_ptr());
+    #[cfg(target_os = "linux")]
+    pub fn window(self) -> x11_dl::xlib::Window {
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.window(),
+            SurfaceKind::Headless(_) => 0,
         }
-        let rect = unsafe { rect.assume_init() };
-        Extent {
-            width: (rect.right - rect.left) as f64,
-            height: (rect.bottom - rect.top) as f64,
+    }
+
+    pub fn extent(&self) -> Extent {
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.extent(),
+            SurfaceKind::Headless(surface) => surface.extent,
         }
     }
 
     pub fn is_maximized(&self) -> bool {
-        unsafe {
-            let mut placement: WINDOWPLACEMENT = mem::zeroed();
-            placement.length = mem::size_of::<WINDOWPLACEMENT>() as u32;
-            GetWindowPlacement(self.hwnd, &mut placement);
-            placement.showCmd == SW_MAXIMIZE
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.is_maximized(),
+            SurfaceKind::Headless(surface) => surface.maximized,
         }
     }
 
     pub fn dpi(&self) -> f64 {
-        unsafe { GetDpiForWindow(self.hwnd) as f64 / 96.0 }
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.dpi(),
+            SurfaceKind::Headless(surface) => surface.dpi,
+        }
     }
 
     pub fn redraw(&self) {
-        unsafe {
-            RedrawWindow(self.hwnd, ptr::null(), 0, RDW_INVALIDATE);
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.redraw(),
+            SurfaceKind::Headless(_) => (),
+        }
+    }
+
+    /// Set the area of the text caret, candidate windows of input methods
+    /// are placed next to it.
+    pub fn set_ime_cursor_area(&self, area: Rect) {
+        match self.kind {
+            SurfaceKind::Native(surface) => surface.set_ime_cursor_area(area),
+            SurfaceKind::Headless(_) => (),
         }
     }
 }
+
+impl From<backend::Surface> for Surface {
+    fn from(surface: backend::Surface) -> Self {
+        Self {
+            kind: SurfaceKind::Native(surface),
+        }
+    }
+}
+
+impl From<headless::Surface> for Surface {
+    fn from(surface: headless::Surface) -> Self {
+        Self {
+            kind: SurfaceKind::Headless(surface),
+        }
+    }
+}
+
+impl HasDisplayHandle for Surface {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        match &self.kind {
+            SurfaceKind::Native(surface) => surface.display_handle(),
+            SurfaceKind::Headless(_) => Err(HandleError::NotSupported),
+        }
+    }
+}
+
+impl HasWindowHandle for Surface {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        match &self.kind {
+            SurfaceKind::Native(surface) => surface.window_handle(),
+            SurfaceKind::Headless(_) => Err(HandleError::NotSupported),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum SurfacesKind {
+    Native(backend::Surfaces),
+    Headless(Rc<headless::Surfaces>),
+}
+
+/// Handle to the surfaces of a platform, allows opening further windows.
+#[derive(Clone)]
+pub struct Surfaces {
+    kind: SurfacesKind,
+}
+
+impl Surfaces {
+    /// Create a hidden surface, its events are sent to the callback of the
+    /// platform.
+    pub fn create(&self) -> Surface {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.create().into(),
+            SurfacesKind::Headless(surfaces) => surfaces.create().into(),
+        }
+    }
+
+    /// Close the surface after the current event, sending `Event::Close`.
+    pub fn close(&self, surface: &Surface) {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.close(surface.id()),
+            SurfacesKind::Headless(surfaces) => surfaces.close(surface.id()),
+        }
+    }
+
+    fn request_animation_frame(&self, surface: SurfaceId) {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.request_animation_frame(surface),
+            SurfacesKind::Headless(surfaces) => surfaces.request_animation_frame(surface),
+        }
+    }
+
+    fn set_timer(&self, surface: SurfaceId, interval: Duration, repeat: bool) -> TimerId {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.set_timer(surface, interval, repeat),
+            SurfacesKind::Headless(surfaces) => surfaces.set_timer(surface, interval, repeat),
+        }
+    }
+
+    fn cancel_timer(&self, timer: TimerId) {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.cancel_timer(timer),
+            SurfacesKind::Headless(surfaces) => surfaces.cancel_timer(timer),
+        }
+    }
+
+    fn create_proxy(&self, surface: SurfaceId) -> EventLoopProxy {
+        match &self.kind {
+            SurfacesKind::Native(surfaces) => surfaces.create_proxy(surface).into(),
+            SurfacesKind::Headless(surfaces) => surfaces.create_proxy(surface).into(),
+        }
+    }
+}
+
+impl From<backend::Surfaces> for Surfaces {
+    fn from(surfaces: backend::Surfaces) -> Self {
+        Self {
+            kind: SurfacesKind::Native(surfaces),
+        }
+    }
+}
+
+impl From<Rc<headless::Surfaces>> for Surfaces {
+    fn from(surfaces: Rc<headless::Surfaces>) -> Self {
+        Self {
+            kind: SurfacesKind::Headless(surfaces),
+        }
+    }
+}
+
 pub struct Platform {
     pub cpu: cpu::DeviceProperties,
+    /// Main surface, created with the platform.
     pub surface: Surface,
-    user_data: Rc<UserData>,
+    platform: backend::Platform,
 }
 
 impl Platform {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let cpu = cpu::DeviceProperties::query();
+        let platform = backend::Platform::new();
 
-        let user_data = Rc::new(UserData {
-            surface: Cell::new(Surface { hwnd: 0 }), // set during WM_NCCREATE
-            cursor: Cell::new(Cursor::Default),
-            control_flow: Cell::new(ControlFlow::Continue),
-            event_callback: RefCell::new(Box::new(|_, _| ControlFlow::Continue)),
-            mouse_position: Cell::new(None),
-            mouse_buttons: Cell::new(MouseButtons::empty()),
-            keydown_area: Cell::new(HTCLIENT as WPARAM),
-            u16_surrogate: Cell::new(None),
-        });
-
-        unsafe {
-            SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
-        }
-
-        unsafe {
-            let hinstance = get_instance_handle();
-            let class_name = encode_wide("nari::win32::class");
-            let class = WNDCLASSEXW {
-                cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
-                style: CS_HREDRAW | CS_VREDRAW,
-                lpfnWndProc: Some(window_proc),
-                cbClsExtra: 0,
-                cbWndExtra: 0,
-                hInstance: hinstance,
-                hIcon: 0,
-                hCursor: LoadCursorW(0, IDC_ARROW),
-                hbrBackground: 0,
-                lpszMenuName: ptr::null(),
-                lpszClassName: class_name.as_ptr(),
-                hIconSm: 0,
-            };
-            RegisterClassExW(&class);
-
-            let title = encode_wide("nari");
-
-            // style required to support aero behavior
-            let style = WS_SYSMENU | WS_SIZEBOX | WS_CAPTION | WS_MINIMIZEBOX | WS_MAXIMIZEBOX;
-            let style_ex = WS_EX_APPWINDOW | WS_EX_WINDOWEDGE | WS_EX_ACCEPTFILES;
-
-            let hwnd = {
-                let user_data = user_data.clone();
-                CreateWindowExW(
-                    style_ex,
-                    class_name.as_ptr(),
-                    title.as_ptr(),
-                    style,
-                    CW_USEDEFAULT,
-                    CW_USEDEFAULT,
-                    CW_USEDEFAULT,
-                    CW_USEDEFAULT,
-                    0,
-                    0,
-                    hinstance,
-                    Rc::as_ptr(&user_data) as _,
-                )
-            };
-
-            let surface = Surface { hwnd };
-
-            Platform {
-                cpu,
-                surface,
-                user_data,
-            }
+        Platform {
+            cpu,
+            surface: platform.surface.into(),
+            platform,
         }
     }
 
-    pub fn run<F: FnMut(EventLoop, Event) -> ControlFlow + 'static>(self, callback: F) {
-        use windows_sys::Win32::UI::WindowsAndMessaging::{
-            SetWindowPos, SWP_FRAMECHANGED, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOREPOSITION,
-            SWP_NOSIZE, SWP_NOZORDER,
-        };
+    pub fn surfaces(&self) -> Surfaces {
+        self.platform.surfaces().into()
+    }
 
-        let _ = self.user_data.event_callback.replace(Box::new(callback));
-
-        // force recalc to trigger WM_NCCALCSIZE otherwise the frame will be still seen
-        unsafe {
-            SetWindowPos(
-                self.surface.hwnd,
-                0,
-                0,
-                0,
-                0,
-                0,
-                SWP_FRAMECHANGED
-                    | SWP_NOSIZE
-                    | SWP_NOZORDER
-                    | SWP_NOREPOSITION
-                    | SWP_NOMOVE
-                    | SWP_NOACTIVATE,
-            );
-        }
+    /// Proxy sending user events to the main surface.
+    pub fn create_proxy(&self) -> EventLoopProxy {
+        self.platform
+            .surfaces()
+            .create_proxy(self.surface.id())
+            .into()
+    }
 
-        self.surface.show();
-
-        'main: loop {
-            unsafe {
-                let mut msg = std::mem::MaybeUninit::uninit();
-                let ret = GetMessageW(msg.as_mut_ptr(), 0, 0, 0);
-                let msg = msg.assume_init();
-
-                if ret != false.into() {
-                    TranslateMessage(&msg);
-                    DispatchMessageW(&msg);
-                } else {
-                    break 'main;
-                }
-
-                if let ControlFlow::Exit = self.user_data.control_flow.get() {
-                    break 'main;
-                }
-            }
-        }
+    pub fn clipboard(&self) -> clipboard::Clipboard {
+        self.platform.surface.into()
     }
-}
 
-impl HasDisplayHandle for Surface {
-    fn display_handle(&self) -> Result<DisplayHandle, HandleError> {
-        Ok(unsafe {
-            DisplayHandle::borrow_raw(RawDisplayHandle::Windows(WindowsDisplayHandle::new()))
-        })
+    pub fn run<F: FnMut(EventLoop, Event) -> ControlFlow + 'static>(self, callback: F) {
+        self.platform.run(callback)
     }
 }
 
-impl HasWindowHandle for Surface {
-    fn window_handle(&self) -> Result<WindowHandle, HandleError> {
-        let mut handle = Win32WindowHandle::new(NonZeroIsize::new(self.hwnd as _).unwrap());
-        handle.hinstance = NonZeroIsize::new(get_instance_handle() as _);
-        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks() {
+        let mut counter = ClickCounter::default();
+        let mut press = |button, time, position| counter.press(button, time, position, 500, (2, 2));
+
+        assert_eq!(press(MouseButtons::LEFT, 1000, (10, 10)), 1);
+        assert_eq!(press(MouseButtons::LEFT, 1200, (11, 9)), 2);
+        assert_eq!(press(MouseButtons::LEFT, 1600, (11, 9)), 3);
+        // too slow
+        assert_eq!(press(MouseButtons::LEFT, 2200, (11, 9)), 1);
+        // moved too far
+        assert_eq!(press(MouseButtons::LEFT, 2300, (20, 9)), 1);
+        // other button
+        assert_eq!(press(MouseButtons::RIGHT, 2400, (20, 9)), 1);
+        assert_eq!(press(MouseButtons::LEFT, 2500, (20, 9)), 1);
+        // timer wrap around
+        assert_eq!(press(MouseButtons::LEFT, u32::MAX - 100, (20, 9)), 1);
+        assert_eq!(press(MouseButtons::LEFT, 100, (20, 9)), 2);
+
+        assert_eq!(counter.release(MouseButtons::LEFT), 2);
+        assert_eq!(counter.release(MouseButtons::RIGHT), 1);
     }
 }
@@ -0,0 +1,1711 @@
+use crate::{
+    clipboard, ClickCounter, Composition, ControlFlow, Cursor, Event, EventCallback, EventLoop,
+    Extent, Key, KeyCode, KeyState, Modifiers, MouseButtons, Rect, ScanCode, ScrollDelta,
+    SurfaceArea, SurfaceId, TimerId,
+};
+use core::num::NonZeroIsize;
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, Win32WindowHandle, WindowHandle, WindowsDisplayHandle,
+};
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    ffi::{c_void, OsStr, OsString},
+    iter::once,
+    mem::{self, MaybeUninit},
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    path::PathBuf,
+    ptr,
+    rc::Rc,
+    slice,
+    sync::{Arc, Mutex, Once},
+    time::Duration,
+};
+use windows_sys::{
+    core::{GUID, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{
+            E_NOINTERFACE, HINSTANCE, HWND, LPARAM, LRESULT, POINT, POINTL, RECT, S_OK, WPARAM,
+        },
+        Globalization::HIMC,
+        Graphics::{
+            Dwm::{DwmExtendFrameIntoClientArea, DwmFlush},
+            Gdi::{
+                GetMonitorInfoW, MonitorFromRect, RedrawWindow, ScreenToClient, ValidateRect,
+                MONITORINFOEXW, MONITOR_DEFAULTTONULL, RDW_INVALIDATE,
+            },
+        },
+        System::DataExchange::{
+            CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable,
+            OpenClipboard, RegisterClipboardFormatW, SetClipboardData,
+        },
+        System::Memory::{
+            GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+        },
+        System::Ole::{OleInitialize, RegisterDragDrop, RevokeDragDrop},
+        System::SystemServices::{
+            IMAGE_DOS_HEADER, MK_LBUTTON, MK_MBUTTON, MK_RBUTTON, MK_XBUTTON1, MK_XBUTTON2,
+        },
+        UI::{
+            Controls::{HOVER_DEFAULT, MARGINS, WM_MOUSELEAVE},
+            HiDpi::{
+                GetDpiForWindow, SetProcessDpiAwarenessContext,
+                DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+            },
+            Input::Ime::{
+                ImmGetCompositionStringW, ImmGetContext, ImmReleaseContext, ImmSetCandidateWindow,
+                ImmSetCompositionWindow, CANDIDATEFORM, CFS_EXCLUDE, CFS_POINT, COMPOSITIONFORM,
+                GCS_COMPATTR, GCS_COMPSTR, GCS_CURSORPOS, GCS_RESULTSTR, IME_COMPOSITION_STRING,
+                ISC_SHOWUICOMPOSITIONWINDOW,
+            },
+            Input::KeyboardAndMouse::{
+                GetDoubleClickTime, GetKeyState, MapVirtualKeyW, ReleaseCapture, SetCapture,
+                TrackMouseEvent, MAPVK_VK_TO_CHAR, TME_LEAVE, TME_NONCLIENT, TRACKMOUSEEVENT,
+                VIRTUAL_KEY, VK_APPS, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_DELETE, VK_DOWN, VK_END,
+                VK_ESCAPE, VK_F1, VK_F24, VK_HOME, VK_INSERT, VK_LEFT, VK_LWIN, VK_MENU, VK_NEXT,
+                VK_NUMLOCK, VK_PAUSE, VK_PRIOR, VK_RETURN, VK_RIGHT, VK_RWIN, VK_SCROLL, VK_SHIFT,
+                VK_SNAPSHOT, VK_TAB, VK_UP,
+            },
+            Shell::DragQueryFileW,
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetClientRect,
+                GetMessageTime, GetMessageW, GetSystemMetrics, GetWindowLongPtrW,
+                GetWindowPlacement, KillTimer, LoadCursorW, PeekMessageW, PostMessageW,
+                RegisterClassExW, SetCursor, SetTimer, SetWindowLongPtrW, SetWindowPos, ShowWindow,
+                SystemParametersInfoW, TranslateMessage, CREATESTRUCTW, CS_HREDRAW, CS_VREDRAW,
+                CW_USEDEFAULT, GWL_USERDATA, HTBOTTOM, HTBOTTOMLEFT, HTBOTTOMRIGHT, HTCAPTION,
+                HTCLIENT, HTCLOSE, HTLEFT, HTMAXBUTTON, HTMINBUTTON, HTRIGHT, HTTOP, HTTOPLEFT,
+                HTTOPRIGHT, IDC_ARROW, IDC_HAND, IDC_IBEAM, IDC_SIZENESW, IDC_SIZENS, IDC_SIZENWSE,
+                IDC_SIZEWE, NCCALCSIZE_PARAMS, PM_REMOVE, SC_CLOSE, SC_MAXIMIZE, SC_MINIMIZE,
+                SC_RESTORE, SIZE_MINIMIZED, SM_CXDOUBLECLK, SM_CYDOUBLECLK,
+                SPI_GETWHEELSCROLLCHARS, SPI_GETWHEELSCROLLLINES, SWP_FRAMECHANGED, SWP_NOACTIVATE,
+                SWP_NOMOVE, SWP_NOREPOSITION, SWP_NOSIZE, SWP_NOZORDER, SW_MAXIMIZE, SW_SHOW,
+                WHEEL_DELTA, WINDOWPLACEMENT, WM_CHAR, WM_CLOSE, WM_DESTROY, WM_DPICHANGED,
+                WM_IME_COMPOSITION, WM_IME_ENDCOMPOSITION, WM_IME_SETCONTEXT,
+                WM_IME_STARTCOMPOSITION, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_LBUTTONDOWN,
+                WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE,
+                WM_MOUSEWHEEL, WM_NCCALCSIZE, WM_NCCREATE, WM_NCHITTEST, WM_NCLBUTTONDOWN,
+                WM_NCLBUTTONUP, WM_NCMOUSELEAVE, WM_NCMOUSEMOVE, WM_PAINT, WM_QUIT, WM_RBUTTONDOWN,
+                WM_RBUTTONUP, WM_SETCURSOR, WM_SETFOCUS, WM_SIZE, WM_SYSCOMMAND, WM_SYSKEYDOWN,
+                WM_SYSKEYUP, WM_TIMER, WM_USER, WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSEXW,
+                WS_CAPTION, WS_EX_APPWINDOW, WS_EX_WINDOWEDGE, WS_MAXIMIZEBOX, WS_MINIMIZEBOX,
+                WS_SIZEBOX, WS_SYSMENU,
+            },
+        },
+    },
+};
+
+fn encode_wide(string: impl AsRef<OsStr>) -> Vec<u16> {
+    string.as_ref().encode_wide().chain(once(0)).collect()
+}
+
+fn get_instance_handle() -> HINSTANCE {
+    extern "C" {
+        static __ImageBase: IMAGE_DOS_HEADER;
+    }
+    unsafe { &__ImageBase as *const _ as _ }
+}
+
+const CLASS_NAME: &str = "nari::win32::class";
+
+/// Register the window class shared by all surfaces of the process.
+fn register_class() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        let class_name = encode_wide(CLASS_NAME);
+        let class = WNDCLASSEXW {
+            cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: get_instance_handle(),
+            hIcon: 0,
+            hCursor: LoadCursorW(0, IDC_ARROW),
+            hbrBackground: 0,
+            lpszMenuName: ptr::null(),
+            lpszClassName: class_name.as_ptr(),
+            hIconSm: 0,
+        };
+        RegisterClassExW(&class);
+    });
+}
+
+#[inline(always)]
+const fn loword(x: u32) -> u16 {
+    (x & 0xFFFF) as u16
+}
+
+#[inline(always)]
+const fn hiword(x: u32) -> u16 {
+    ((x >> 16) & 0xFFFF) as u16
+}
+
+/// Posted by proxies after queueing user events.
+const WM_NARI_USER_EVENT: u32 = WM_USER;
+
+// `GET_XBUTTON_WPARAM` values
+const XBUTTON1: u16 = 0x0001;
+
+// Standard clipboard format of UTF-16 text.
+const CF_UNICODETEXT: u32 = 13;
+
+/// Clipboard format of a MIME type, custom types are registered by name.
+fn clipboard_format(mime: &str) -> u32 {
+    match mime {
+        clipboard::TEXT => CF_UNICODETEXT,
+        _ => unsafe { RegisterClipboardFormatW(encode_wide(mime).as_ptr()) },
+    }
+}
+
+// Custom payloads are prefixed with their length, `GlobalSize` may include
+// padding.
+const CLIPBOARD_PREFIX: usize = mem::size_of::<u64>();
+
+// OLE drag and drop, `windows-sys` only provides opaque interface pointers.
+const CF_HDROP: u16 = 15;
+const DVASPECT_CONTENT: u32 = 1;
+const TYMED_HGLOBAL: u32 = 1;
+const DROPEFFECT_NONE: u32 = 0;
+const DROPEFFECT_COPY: u32 = 1;
+
+const IID_IUNKNOWN: GUID = GUID::from_u128(0x00000000_0000_0000_c000_000000000046);
+const IID_IDROPTARGET: GUID = GUID::from_u128(0x00000122_0000_0000_c000_000000000046);
+
+#[repr(C)]
+struct FormatEtc {
+    format: u16,
+    target_device: *mut c_void,
+    aspect: u32,
+    index: i32,
+    tymed: u32,
+}
+
+#[repr(C)]
+struct StgMedium {
+    tymed: u32,
+    handle: isize,
+    release: *mut c_void,
+}
+
+#[link(name = "ole32")]
+extern "system" {
+    fn ReleaseStgMedium(medium: *mut StgMedium);
+}
+
+/// Leading methods of the `IDataObject` vtable.
+#[repr(C)]
+struct DataObjectVtbl {
+    query_interface: usize,
+    add_ref: usize,
+    release: usize,
+    get_data:
+        unsafe extern "system" fn(*mut DataObject, *const FormatEtc, *mut StgMedium) -> HRESULT,
+}
+
+#[repr(C)]
+struct DataObject {
+    vtbl: *const DataObjectVtbl,
+}
+
+fn guid_eq(a: &GUID, b: &GUID) -> bool {
+    a.data1 == b.data1 && a.data2 == b.data2 && a.data3 == b.data3 && a.data4 == b.data4
+}
+
+/// File paths of a dragged data object.
+unsafe fn drop_paths(data: *mut DataObject) -> Vec<PathBuf> {
+    let format = FormatEtc {
+        format: CF_HDROP,
+        target_device: ptr::null_mut(),
+        aspect: DVASPECT_CONTENT,
+        index: -1,
+        tymed: TYMED_HGLOBAL,
+    };
+    let mut medium: StgMedium = mem::zeroed();
+    if ((*(*data).vtbl).get_data)(data, &format, &mut medium) < 0 {
+        return Vec::new();
+    }
+
+    let hdrop = medium.handle;
+    let count = DragQueryFileW(hdrop, u32::MAX, ptr::null_mut(), 0);
+    let paths = (0..count)
+        .map(|i| {
+            let len = DragQueryFileW(hdrop, i, ptr::null_mut(), 0) as usize;
+            let mut path = vec![0u16; len + 1];
+            DragQueryFileW(hdrop, i, path.as_mut_ptr(), path.len() as u32);
+            PathBuf::from(OsString::from_wide(&path[..len]))
+        })
+        .collect();
+
+    ReleaseStgMedium(&mut medium);
+    paths
+}
+
+/// `IDropTarget` implementation, sending drag events to the window.
+#[repr(C)]
+struct DropTarget {
+    vtbl: *const DropTargetVtbl,
+    refs: Cell<u32>,
+    user_data: *const UserData,
+    paths: RefCell<Vec<PathBuf>>, // DragEnter
+    accept: Cell<bool>,
+}
+
+#[repr(C)]
+struct DropTargetVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut DropTarget, *const GUID, *mut *mut c_void) -> HRESULT,
+    add_ref: unsafe extern "system" fn(*mut DropTarget) -> u32,
+    release: unsafe extern "system" fn(*mut DropTarget) -> u32,
+    drag_enter: unsafe extern "system" fn(
+        *mut DropTarget,
+        *mut DataObject,
+        u32,
+        POINTL,
+        *mut u32,
+    ) -> HRESULT,
+    drag_over: unsafe extern "system" fn(*mut DropTarget, u32, POINTL, *mut u32) -> HRESULT,
+    drag_leave: unsafe extern "system" fn(*mut DropTarget) -> HRESULT,
+    drop: unsafe extern "system" fn(
+        *mut DropTarget,
+        *mut DataObject,
+        u32,
+        POINTL,
+        *mut u32,
+    ) -> HRESULT,
+}
+
+static DROP_TARGET_VTBL: DropTargetVtbl = DropTargetVtbl {
+    query_interface: DropTarget::query_interface,
+    add_ref: DropTarget::add_ref,
+    release: DropTarget::release,
+    drag_enter: DropTarget::drag_enter,
+    drag_over: DropTarget::drag_over,
+    drag_leave: DropTarget::drag_leave,
+    drop: DropTarget::drop,
+};
+
+impl DropTarget {
+    fn create(user_data: *const UserData) -> *mut DropTarget {
+        Box::into_raw(Box::new(DropTarget {
+            vtbl: &DROP_TARGET_VTBL,
+            refs: Cell::new(1),
+            user_data,
+            paths: RefCell::new(Vec::new()),
+            accept: Cell::new(false),
+        }))
+    }
+
+    unsafe fn send_drag(&self, point: POINTL, effect: *mut u32, enter: bool) {
+        let user_data = &*self.user_data;
+        let mut point = POINT {
+            x: point.x,
+            y: point.y,
+        };
+        ScreenToClient(user_data.surface.get().hwnd, &mut point);
+        let (x, y) = (point.x, point.y);
+
+        let paths = self.paths.borrow();
+        let mut accept = false;
+        if !paths.is_empty() {
+            user_data.mouse_position.set(Some((x, y)));
+            let event = if enter {
+                Event::DragEnter {
+                    paths: &paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            } else {
+                Event::DragOver {
+                    paths: &paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            };
+            user_data.send(event);
+        }
+
+        self.accept.set(accept);
+        *effect = if accept {
+            DROPEFFECT_COPY
+        } else {
+            DROPEFFECT_NONE
+        };
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut DropTarget,
+        iid: *const GUID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        if guid_eq(&*iid, &IID_IUNKNOWN) || guid_eq(&*iid, &IID_IDROPTARGET) {
+            Self::add_ref(this);
+            *object = this as *mut c_void;
+            S_OK
+        } else {
+            *object = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut DropTarget) -> u32 {
+        let refs = (*this).refs.get() + 1;
+        (*this).refs.set(refs);
+        refs
+    }
+
+    unsafe extern "system" fn release(this: *mut DropTarget) -> u32 {
+        let refs = (*this).refs.get() - 1;
+        (*this).refs.set(refs);
+        if refs == 0 {
+            drop(Box::from_raw(this));
+        }
+        refs
+    }
+
+    unsafe extern "system" fn drag_enter(
+        this: *mut DropTarget,
+        data: *mut DataObject,
+        _key_state: u32,
+        point: POINTL,
+        effect: *mut u32,
+    ) -> HRESULT {
+        *(*this).paths.borrow_mut() = drop_paths(data);
+        (*this).send_drag(point, effect, true);
+        S_OK
+    }
+
+    unsafe extern "system" fn drag_over(
+        this: *mut DropTarget,
+        _key_state: u32,
+        point: POINTL,
+        effect: *mut u32,
+    ) -> HRESULT {
+        (*this).send_drag(point, effect, false);
+        S_OK
+    }
+
+    unsafe extern "system" fn drag_leave(this: *mut DropTarget) -> HRESULT {
+        let paths = (*this).paths.take();
+        if !paths.is_empty() {
+            let user_data = &*(*this).user_data;
+            user_data.mouse_position.set(None);
+            user_data.send(Event::DragLeave);
+        }
+        S_OK
+    }
+
+    unsafe extern "system" fn drop(
+        this: *mut DropTarget,
+        _data: *mut DataObject,
+        _key_state: u32,
+        point: POINTL,
+        effect: *mut u32,
+    ) -> HRESULT {
+        let user_data = &*(*this).user_data;
+        let paths = (*this).paths.take();
+
+        *effect = DROPEFFECT_NONE;
+        if (*this).accept.get() {
+            let mut point = POINT {
+                x: point.x,
+                y: point.y,
+            };
+            ScreenToClient(user_data.surface.get().hwnd, &mut point);
+            let delivered = user_data.send(Event::Drop {
+                paths: &paths,
+                x: point.x,
+                y: point.y,
+            });
+            if delivered {
+                *effect = DROPEFFECT_COPY;
+            }
+        }
+        S_OK
+    }
+}
+
+// `GCS_COMPATTR` attributes of the clause being converted.
+const ATTR_TARGET_CONVERTED: u8 = 0x01;
+const ATTR_TARGET_NOTCONVERTED: u8 = 0x03;
+
+/// Data of the composition string, `index` is one of the `GCS_*` flags.
+unsafe fn composition_data(himc: HIMC, index: IME_COMPOSITION_STRING) -> Vec<u8> {
+    let len = ImmGetCompositionStringW(himc, index, ptr::null_mut(), 0);
+    if len <= 0 {
+        return Vec::new();
+    }
+    let mut data = vec![0u8; len as usize];
+    ImmGetCompositionStringW(himc, index, data.as_mut_ptr() as _, len as u32);
+    data
+}
+
+unsafe fn composition_string(himc: HIMC, index: IME_COMPOSITION_STRING) -> Vec<u16> {
+    composition_data(himc, index)
+        .chunks_exact(2)
+        .map(|unit| u16::from_ne_bytes([unit[0], unit[1]]))
+        .collect()
+}
+
+/// Preedit update of the current composition string.
+unsafe fn preedit(himc: HIMC) -> Composition {
+    let text = composition_string(himc, GCS_COMPSTR);
+    // one attribute per UTF-16 unit
+    let attributes = composition_data(himc, GCS_COMPATTR);
+    let caret = ImmGetCompositionStringW(himc, GCS_CURSORPOS, ptr::null_mut(), 0).max(0) as usize;
+
+    let offset = |index: usize| String::from_utf16_lossy(&text[..index.min(text.len())]).len();
+    let target = |attribute: &u8| {
+        *attribute == ATTR_TARGET_CONVERTED || *attribute == ATTR_TARGET_NOTCONVERTED
+    };
+    let cursor = match (
+        attributes.iter().position(target),
+        attributes.iter().rposition(target),
+    ) {
+        (Some(first), Some(last)) => offset(first)..offset(last + 1),
+        _ => offset(caret)..offset(caret),
+    };
+
+    Composition::Update {
+        text: String::from_utf16_lossy(&text),
+        cursor: Some(cursor),
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    let surface = Surface { hwnd: window };
+
+    // match msg {
+    //     WM_NCCREATE => println!("WM_NCCREATE"),
+    //     WM_NCCALCSIZE => println!("WM_NCCALCSIZE"),
+    //     WM_CREATE => println!("WM_CREATE"),
+    //     WM_MOUSEMOVE => println!("WM_MOUSEMOVE"),
+    //     WM_NCMOUSEMOVE => println!("WM_NCMOUSEMOVE"),
+    //     WM_MOUSELEAVE => println!("WM_MOUSELEAVE"),
+    //     WM_NCMOUSELEAVE => println!("WM_NCMOUSELEAVE"),
+    //     WM_PAINT => println!("WM_PAINT"),
+    //     _ => (), // println!("{}", msg),
+    // }
+
+    let user_data = {
+        let ptr = GetWindowLongPtrW(window, GWL_USERDATA) as *mut UserData;
+        if ptr.is_null() {
+            match msg {
+                WM_NCCREATE => {
+                    let createstruct = &mut *(lparam as *mut CREATESTRUCTW);
+                    SetWindowLongPtrW(window, GWL_USERDATA, createstruct.lpCreateParams as _);
+
+                    let user_data = createstruct.lpCreateParams as *mut UserData;
+                    (*user_data).surface.set(surface);
+
+                    // add window shadow effect
+                    let margins = MARGINS {
+                        cxLeftWidth: 1,
+                        cxRightWidth: 1,
+                        cyTopHeight: 0,
+                        cyBottomHeight: 1,
+                    };
+                    DwmExtendFrameIntoClientArea(window, &margins);
+                }
+                _ => (),
+            };
+
+            return DefWindowProcW(window, msg, wparam, lparam);
+        }
+
+        &mut *ptr
+    };
+
+    match msg {
+        WM_NCCALCSIZE => {
+            if wparam == false.into() {
+                return DefWindowProcW(window, msg, wparam, lparam);
+            }
+
+            let params = &mut *(lparam as *mut NCCALCSIZE_PARAMS);
+
+            if surface.is_maximized() {
+                // limit to current monitor, otherwise window gets too large
+                let monitor = MonitorFromRect(&params.rgrc[0], MONITOR_DEFAULTTONULL);
+                let mut monitor_info: MONITORINFOEXW = mem::zeroed();
+                monitor_info.monitorInfo.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
+                GetMonitorInfoW(monitor, &mut monitor_info as *mut _ as *mut _);
+                params.rgrc[0] = monitor_info.monitorInfo.rcWork;
+            }
+
+            // Sync with DWM here giving us maximum amount of time to redraw the screen.
+            DwmFlush();
+            0
+        }
+
+        WM_NCHITTEST => {
+            let x = loword(lparam as u32) as i32;
+            let y = hiword(lparam as u32) as i32;
+
+            let mut point = POINT { x, y };
+            ScreenToClient(window, &mut point);
+
+            let mut area = SurfaceArea::Client;
+            user_data.send(Event::Hittest {
+                x: point.x,
+                y: point.y,
+                area: &mut area,
+            });
+
+            let wm_area = match area {
+                SurfaceArea::Client => HTCLIENT,
+                SurfaceArea::Top => HTTOP,
+                SurfaceArea::Bottom => HTBOTTOM,
+                SurfaceArea::Left => HTLEFT,
+                SurfaceArea::Right => HTRIGHT,
+                SurfaceArea::BottomLeft => HTBOTTOMLEFT,
+                SurfaceArea::BottomRight => HTBOTTOMRIGHT,
+                SurfaceArea::TopLeft => HTTOPLEFT,
+                SurfaceArea::TopRight => HTTOPRIGHT,
+                SurfaceArea::Caption => HTCAPTION,
+                SurfaceArea::Minimize => HTMINBUTTON,
+                SurfaceArea::Maximize => HTMAXBUTTON,
+                SurfaceArea::Close => HTCLOSE,
+            };
+
+            wm_area as LRESULT
+        }
+
+        WM_NCLBUTTONDOWN => {
+            user_data.keydown_area.set(wparam);
+            match wparam as u32 {
+                // Prevent windows from drawing ugly legacy buttons on button down..
+                // But we manually have to send the SYSCOMMANDs now
+                HTMINBUTTON | HTMAXBUTTON | HTCLOSE => 0,
+                _ => DefWindowProcW(window, msg, wparam, lparam),
+            }
+        }
+
+        WM_NCLBUTTONUP => {
+            let prev_hit = user_data.keydown_area.get();
+            if prev_hit == wparam {
+                match wparam as u32 {
+                    HTMINBUTTON => {
+                        PostMessageW(window, WM_SYSCOMMAND, SC_MINIMIZE as _, lparam);
+                    }
+                    HTMAXBUTTON => {
+                        let action = if surface.is_maximized() {
+                            SC_RESTORE
+                        } else {
+                            SC_MAXIMIZE
+                        };
+                        PostMessageW(window, WM_SYSCOMMAND, action as _, lparam);
+                    }
+                    HTCLOSE => {
+                        PostMessageW(window, WM_SYSCOMMAND, SC_CLOSE as _, lparam);
+                    }
+                    _ => {}
+                }
+            }
+
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+
+        WM_CHAR => {
+            // Characters outside of the BMP arrive as two messages.
+            let unit = wparam as u16;
+            let units = match user_data.u16_surrogate.take() {
+                Some(high_surrogate) => vec![high_surrogate, unit],
+                None if (0xD800..=0xDBFF).contains(&unit) => {
+                    user_data.u16_surrogate.set(Some(unit));
+                    return 0;
+                }
+                None => vec![unit],
+            };
+
+            for c in char::decode_utf16(units).filter_map(Result::ok) {
+                if !c.is_control() {
+                    user_data.send(Event::Char(c));
+                }
+            }
+
+            0
+        }
+
+        WM_IME_SETCONTEXT => {
+            // Preedit text is drawn by the application.
+            let lparam = lparam & !(ISC_SHOWUICOMPOSITIONWINDOW as LPARAM);
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+
+        WM_IME_STARTCOMPOSITION => {
+            user_data.send(Event::Composition(Composition::Start));
+            0
+        }
+
+        WM_IME_COMPOSITION => {
+            let himc = ImmGetContext(window);
+            if himc == 0 {
+                return DefWindowProcW(window, msg, wparam, lparam);
+            }
+
+            let flags = lparam as u32;
+            // Committed text is handled here instead of `WM_IME_CHAR`.
+            if flags & GCS_RESULTSTR != 0 {
+                let text = composition_string(himc, GCS_RESULTSTR);
+                user_data.send(Event::Composition(Composition::Commit(
+                    String::from_utf16_lossy(&text),
+                )));
+            }
+            if flags & GCS_COMPSTR != 0 {
+                user_data.send(Event::Composition(preedit(himc)));
+            }
+
+            ImmReleaseContext(window, himc);
+            0
+        }
+
+        WM_IME_ENDCOMPOSITION => {
+            user_data.send(Event::Composition(Composition::End));
+            0
+        }
+
+        WM_KEYDOWN => {
+            user_data.on_key(wparam, lparam, KeyState::Down);
+            0
+        }
+
+        WM_KEYUP => {
+            user_data.on_key(wparam, lparam, KeyState::Up);
+            0
+        }
+
+        // Keys pressed with Alt and F10, the system still handles shortcuts like Alt+F4.
+        WM_SYSKEYDOWN => {
+            user_data.on_key(wparam, lparam, KeyState::Down);
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+
+        WM_SYSKEYUP => {
+            user_data.on_key(wparam, lparam, KeyState::Up);
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+
+        WM_LBUTTONDOWN => {
+            user_data.on_button(wparam, lparam, MouseButtons::LEFT, KeyState::Down);
+            0
+        }
+        WM_LBUTTONUP => {
+            user_data.on_button(wparam, lparam, MouseButtons::LEFT, KeyState::Up);
+            0
+        }
+        WM_RBUTTONDOWN => {
+            user_data.on_button(wparam, lparam, MouseButtons::RIGHT, KeyState::Down);
+            0
+        }
+        WM_RBUTTONUP => {
+            user_data.on_button(wparam, lparam, MouseButtons::RIGHT, KeyState::Up);
+            0
+        }
+        WM_MBUTTONDOWN => {
+            user_data.on_button(wparam, lparam, MouseButtons::MIDDLE, KeyState::Down);
+            0
+        }
+        WM_MBUTTONUP => {
+            user_data.on_button(wparam, lparam, MouseButtons::MIDDLE, KeyState::Up);
+            0
+        }
+        WM_XBUTTONDOWN | WM_XBUTTONUP => {
+            let button = match hiword(wparam as u32) {
+                XBUTTON1 => MouseButtons::BACK,
+                _ => MouseButtons::FORWARD,
+            };
+            let state = if msg == WM_XBUTTONDOWN {
+                KeyState::Down
+            } else {
+                KeyState::Up
+            };
+            user_data.on_button(wparam, lparam, button, state);
+            1
+        }
+
+        WM_MOUSEWHEEL => {
+            user_data.on_wheel(hiword(wparam as u32) as i16, false);
+            0
+        }
+        WM_MOUSEHWHEEL => {
+            user_data.on_wheel(hiword(wparam as u32) as i16, true);
+            0
+        }
+
+        WM_SETCURSOR => {
+            unsafe {
+                SetCursor(LoadCursorW(0, user_data.cursor.get().idc()));
+            }
+            1
+        }
+
+        WM_MOUSEMOVE => {
+            let x = loword(lparam as u32) as i16 as i32;
+            let y = hiword(lparam as u32) as i16 as i32;
+
+            // Track to get `WM_MOUSELEAVE` events
+            TrackMouseEvent(&mut TRACKMOUSEEVENT {
+                cbSize: mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                dwFlags: TME_LEAVE,
+                hwndTrack: window,
+                dwHoverTime: HOVER_DEFAULT,
+            });
+
+            user_data.mouse_position.set(Some((x, y)));
+
+            let mut cursor = user_data.cursor.get();
+            user_data.send(Event::MouseMove {
+                cursor: &mut cursor,
+            });
+            user_data.cursor.set(cursor);
+
+            0
+        }
+
+        WM_NCMOUSEMOVE => {
+            match wparam as u32 {
+                HTCAPTION | HTMINBUTTON | HTMAXBUTTON | HTCLOSE | HTBOTTOM | HTLEFT | HTRIGHT
+                | HTTOP | HTBOTTOMLEFT | HTBOTTOMRIGHT | HTTOPLEFT | HTTOPRIGHT => {
+                    // Track to get `WM_NCMOUSELEAVE` events
+                    TrackMouseEvent(&mut TRACKMOUSEEVENT {
+                        cbSize: mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                        dwFlags: TME_LEAVE | TME_NONCLIENT,
+                        hwndTrack: window,
+                        dwHoverTime: HOVER_DEFAULT,
+                    });
+
+                    let x = loword(lparam as u32) as i16 as i32;
+                    let y = hiword(lparam as u32) as i16 as i32;
+
+                    let mut pt = POINT { x, y };
+                    ScreenToClient(window, &mut pt);
+                    user_data.mouse_position.set(Some((pt.x, pt.y)));
+                }
+                _ => {
+                    // handled by system, considered out of user area
+                    user_data.mouse_position.set(None);
+                }
+            }
+
+            let mut cursor = user_data.cursor.get();
+            user_data.send(Event::MouseMove {
+                cursor: &mut cursor,
+            });
+            user_data.cursor.set(cursor);
+
+            surface.redraw();
+
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+
+        WM_MOUSELEAVE | WM_NCMOUSELEAVE => {
+            user_data.mouse_position.set(None);
+            surface.redraw();
+
+            0
+        }
+
+        WM_PAINT => {
+            user_data.send(Event::Paint);
+            ValidateRect(window, ptr::null());
+
+            DefWindowProcW(window, msg, wparam, lparam)
+        }
+        WM_SIZE => {
+            let minimized = wparam == SIZE_MINIMIZED as WPARAM;
+            if user_data.occluded.replace(minimized) != minimized {
+                user_data.send(Event::Occluded(minimized));
+            }
+
+            // Minimized windows report an empty client area.
+            if !minimized {
+                let width = loword(lparam as u32) as f64;
+                let height = hiword(lparam as u32) as f64;
+                user_data.send(Event::Resize(Extent { width, height }));
+            }
+
+            0
+        }
+        WM_DPICHANGED => {
+            let dpi = hiword(wparam as u32) as f64 / 96.0;
+            let suggested = &*(lparam as *const RECT);
+            let mut rect = Rect {
+                x: suggested.left,
+                y: suggested.top,
+                width: suggested.right - suggested.left,
+                height: suggested.bottom - suggested.top,
+            };
+            user_data.send(Event::DpiChanged {
+                dpi,
+                rect: &mut rect,
+            });
+            SetWindowPos(
+                window,
+                0,
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+
+            0
+        }
+        WM_SETFOCUS => {
+            user_data.send(Event::Focus(true));
+            0
+        }
+        WM_KILLFOCUS => {
+            user_data.send(Event::Focus(false));
+            0
+        }
+        WM_CLOSE => {
+            let mut close = true;
+            if !user_data.send(Event::CloseRequest { close: &mut close }) {
+                // Deliver it once the callback returned.
+                let mut requests = user_data.shared.close_requests.borrow_mut();
+                if !requests.contains(&window) {
+                    requests.push(window);
+                }
+            } else if close {
+                user_data.shared.closing.borrow_mut().push(window);
+            }
+
+            0
+        }
+        WM_TIMER => {
+            let shared = &user_data.shared;
+            let timer = TimerId(wparam as u64);
+            let repeat = shared.timers.borrow().get(&timer).map(|t| t.repeat);
+            if let Some(repeat) = repeat {
+                if !repeat {
+                    KillTimer(window, wparam);
+                    shared.timers.borrow_mut().remove(&timer);
+                }
+                user_data.send(Event::Timer(timer));
+            }
+
+            0
+        }
+        WM_NARI_USER_EVENT => {
+            user_data.shared.send_user_events();
+            0
+        }
+        WM_DESTROY => {
+            RevokeDragDrop(window);
+            user_data.send(Event::Close);
+
+            // Releases the user data, remaining messages go to `DefWindowProcW`.
+            SetWindowLongPtrW(window, GWL_USERDATA, 0);
+            let shared = user_data.shared.clone();
+            shared.windows.borrow_mut().remove(&window);
+            // Timers are destroyed together with the window.
+            shared.timers.borrow_mut().retain(|_, t| t.window != window);
+            shared.frames.borrow_mut().retain(|w| *w != window);
+
+            0
+        }
+        _ => DefWindowProcW(window, msg, wparam, lparam),
+    }
+}
+
+impl Modifiers {
+    unsafe fn query() -> Self {
+        let mut modifiers = Modifiers::empty();
+        if GetKeyState(VK_MENU as i32) & 0x80 != 0 {
+            modifiers |= Modifiers::ALT;
+        }
+        if GetKeyState(VK_CONTROL as i32) & 0x80 != 0 {
+            modifiers |= Modifiers::CONTROL;
+        }
+        if GetKeyState(VK_SHIFT as i32) & 0x80 != 0 {
+            modifiers |= Modifiers::SHIFT;
+        }
+        if (GetKeyState(VK_LWIN as i32) | GetKeyState(VK_RWIN as i32)) & 0x80 != 0 {
+            modifiers |= Modifiers::SUPER;
+        }
+        modifiers
+    }
+}
+
+type UserEvents = Arc<Mutex<Vec<(HWND, Box<dyn Any + Send>)>>>;
+
+#[derive(Copy, Clone, Debug)]
+struct Timer {
+    window: HWND,
+    repeat: bool,
+}
+
+/// Event without references into the message handler, `None` for events
+/// returning values.
+fn owned_event(event: Event) -> Option<Event<'static>> {
+    let event = match event {
+        Event::Paint => Event::Paint,
+        Event::Resize(extent) => Event::Resize(extent),
+        Event::Focus(focused) => Event::Focus(focused),
+        Event::Occluded(occluded) => Event::Occluded(occluded),
+        Event::Close => Event::Close,
+        Event::Key {
+            key,
+            scancode,
+            state,
+            repeat,
+            modifiers,
+        } => Event::Key {
+            key,
+            scancode,
+            state,
+            repeat,
+            modifiers,
+        },
+        Event::MouseButton {
+            button,
+            state,
+            clicks,
+            modifiers,
+        } => Event::MouseButton {
+            button,
+            state,
+            clicks,
+            modifiers,
+        },
+        Event::Scroll { delta, modifiers } => Event::Scroll { delta, modifiers },
+        Event::Char(c) => Event::Char(c),
+        Event::Composition(composition) => Event::Composition(composition),
+        Event::DragLeave => Event::DragLeave,
+        Event::AnimationFrame => Event::AnimationFrame,
+        Event::Timer(timer) => Event::Timer(timer),
+        Event::User(event) => Event::User(event),
+        Event::DpiChanged { .. }
+        | Event::CloseRequest { .. }
+        | Event::MouseMove { .. }
+        | Event::Hittest { .. }
+        | Event::DragEnter { .. }
+        | Event::DragOver { .. }
+        | Event::Drop { .. } => return None,
+    };
+    Some(event)
+}
+
+/// State shared by all surfaces of the platform.
+struct Shared {
+    control_flow: Cell<ControlFlow>,
+    event_callback: RefCell<EventCallback>,
+    windows: RefCell<HashMap<HWND, Rc<UserData>>>,
+    /// Windows to destroy after the current message.
+    closing: RefCell<Vec<HWND>>,
+    /// Events of messages sent while the callback runs, e.g. by creating or
+    /// showing a surface.
+    pending: RefCell<VecDeque<(HWND, Event<'static>)>>,
+    /// Windows whose close request arrived while the callback runs.
+    close_requests: RefCell<Vec<HWND>>,
+    /// Running timers, identified by the `SetTimer` id.
+    timers: RefCell<HashMap<TimerId, Timer>>,
+    next_timer: Cell<u64>,
+    /// Windows waiting for the next animation frame.
+    frames: RefCell<Vec<HWND>>,
+    user_events: UserEvents,
+}
+
+impl Shared {
+    fn user_data(&self, hwnd: HWND) -> Option<Rc<UserData>> {
+        self.windows.borrow().get(&hwnd).cloned()
+    }
+
+    /// Send the events queued while the callback was running.
+    fn send_pending(&self) {
+        loop {
+            let Some((hwnd, event)) = self.pending.borrow_mut().pop_front() else {
+                break;
+            };
+            if let Some(user_data) = self.user_data(hwnd) {
+                user_data.send(event);
+            }
+        }
+        loop {
+            let Some(hwnd) = self.close_requests.borrow_mut().pop() else {
+                break;
+            };
+            if let Some(user_data) = self.user_data(hwnd) {
+                let mut close = true;
+                if user_data.send(Event::CloseRequest { close: &mut close }) && close {
+                    self.closing.borrow_mut().push(hwnd);
+                }
+            }
+        }
+    }
+
+    fn close_windows(&self) {
+        let closing = mem::take(&mut *self.closing.borrow_mut());
+        for hwnd in closing {
+            if self.windows.borrow().contains_key(&hwnd) {
+                unsafe {
+                    DestroyWindow(hwnd);
+                }
+            }
+        }
+    }
+
+    /// Send user events queued by proxies.
+    fn send_user_events(&self) {
+        let user_events = mem::take(&mut *self.user_events.lock().unwrap());
+        for (hwnd, event) in user_events {
+            if let Some(user_data) = self.user_data(hwnd) {
+                user_data.send(Event::User(event));
+            }
+        }
+    }
+
+    /// Wait for the next composition of the desktop window manager and send
+    /// the requested animation frames.
+    fn send_frames(&self) {
+        unsafe {
+            DwmFlush();
+        }
+        let frames = mem::take(&mut *self.frames.borrow_mut());
+        for hwnd in frames {
+            if let Some(user_data) = self.user_data(hwnd) {
+                user_data.send(Event::AnimationFrame);
+            }
+        }
+    }
+}
+
+struct UserData {
+    shared: Rc<Shared>,
+    surface: Cell<Surface>,
+    cursor: Cell<Cursor>,
+    mouse_position: Cell<Option<(i32, i32)>>,
+    mouse_buttons: Cell<MouseButtons>,
+    clicks: Cell<ClickCounter>,       // WM_*BUTTONDOWN
+    keydown_area: Cell<WPARAM>,       // WM_NCLBUTTON
+    u16_surrogate: Cell<Option<u16>>, // WM_CHAR
+    occluded: Cell<bool>,             // WM_SIZE
+}
+
+impl UserData {
+    /// Send the event to the callback, returns `false` if the callback is
+    /// running.
+    ///
+    /// Events of messages sent from within the callback are queued until it
+    /// returns. Events returning values to the platform can't be delayed and
+    /// keep their defaults.
+    fn send(&self, event: Event) -> bool {
+        let Ok(mut callback) = self.shared.event_callback.try_borrow_mut() else {
+            if let Some(event) = owned_event(event) {
+                let hwnd = self.surface.get().hwnd;
+                self.shared.pending.borrow_mut().push_back((hwnd, event));
+            }
+            return false;
+        };
+        let event_loop = EventLoop {
+            surface: self.surface.get().into(),
+            surfaces: Surfaces {
+                shared: self.shared.clone(),
+            }
+            .into(),
+            clipboard: self.surface.get().into(),
+            mouse_position: self.mouse_position.get(),
+            mouse_buttons: self.mouse_buttons.get(),
+        };
+        let control_flow = callback(event_loop, event);
+        self.shared.control_flow.set(control_flow);
+        drop(callback);
+
+        self.shared.send_pending();
+        true
+    }
+
+    fn on_key(&self, wparam: WPARAM, lparam: LPARAM, state: KeyState) {
+        let lparam = lparam as u32;
+        let scancode = {
+            let code = (lparam >> 16) & 0xFF;
+            let extended = lparam & (1 << 24) != 0;
+            ScanCode(if extended { 0xE000 | code } else { code })
+        };
+        // previous key state, already down for auto repeated keys
+        let repeat = state == KeyState::Down && lparam & (1 << 30) != 0;
+
+        let modifiers = unsafe { Modifiers::query() };
+
+        let key = match key_code(wparam as VIRTUAL_KEY, scancode) {
+            Some(code) => Key::Code(code),
+            None => {
+                let c = unsafe { MapVirtualKeyW(wparam as u32, MAPVK_VK_TO_CHAR) };
+
+                const DEAD_KEY_FLAG: u32 = 1 << 31;
+                match char::from_u32(c & !DEAD_KEY_FLAG) {
+                    Some(c) if c != '\0' => Key::Char(c),
+                    _ => return,
+                }
+            }
+        };
+
+        self.send(Event::Key {
+            key,
+            scancode,
+            state,
+            repeat,
+            modifiers,
+        });
+    }
+
+    fn on_button(&self, wparam: WPARAM, lparam: LPARAM, button: MouseButtons, state: KeyState) {
+        let buttons = {
+            let mut buttons = MouseButtons::empty();
+            let wparam = wparam as u32;
+            if wparam & MK_LBUTTON != 0 {
+                buttons |= MouseButtons::LEFT;
+            }
+            if wparam & MK_RBUTTON != 0 {
+                buttons |= MouseButtons::RIGHT;
+            }
+            if wparam & MK_MBUTTON != 0 {
+                buttons |= MouseButtons::MIDDLE;
+            }
+            if wparam & MK_XBUTTON1 != 0 {
+                buttons |= MouseButtons::BACK;
+            }
+            if wparam & MK_XBUTTON2 != 0 {
+                buttons |= MouseButtons::FORWARD;
+            }
+            buttons
+        };
+        self.mouse_buttons.set(buttons);
+
+        // Mouse capture logic, required that we receive mouse events
+        // once we leave the window.
+        match (state, buttons.bits().count_ones()) {
+            (KeyState::Down, 1) => unsafe {
+                SetCapture(self.surface.get().hwnd);
+            },
+            (KeyState::Up, 0) => unsafe {
+                ReleaseCapture();
+            },
+            _ => (),
+        }
+
+        let mut counter = self.clicks.get();
+        let clicks = match state {
+            KeyState::Down => unsafe {
+                let x = loword(lparam as u32) as i16 as i32;
+                let y = hiword(lparam as u32) as i16 as i32;
+                // size of the rectangle centered around the first click
+                let distance = (
+                    GetSystemMetrics(SM_CXDOUBLECLK) / 2,
+                    GetSystemMetrics(SM_CYDOUBLECLK) / 2,
+                );
+                counter.press(
+                    button,
+                    GetMessageTime() as u32,
+                    (x, y),
+                    GetDoubleClickTime(),
+                    distance,
+                )
+            },
+            KeyState::Up => counter.release(button),
+        };
+        self.clicks.set(counter);
+
+        let modifiers = unsafe { Modifiers::query() };
+
+        self.send(Event::MouseButton {
+            button,
+            state,
+            clicks,
+            modifiers,
+        });
+    }
+
+    fn on_wheel(&self, delta: i16, horizontal: bool) {
+        // user setting for lines (vertical) or characters (horizontal) per wheel step
+        let mut amount: u32 = 3;
+        unsafe {
+            let action = if horizontal {
+                SPI_GETWHEELSCROLLCHARS
+            } else {
+                SPI_GETWHEELSCROLLLINES
+            };
+            SystemParametersInfoW(action, 0, &mut amount as *mut u32 as _, 0);
+        }
+
+        // Precision touchpads and high resolution wheels report fractions of
+        // a wheel step, scaled like full steps.
+        let lines = delta as f64 / WHEEL_DELTA as f64 * amount as f64;
+        let delta = if horizontal {
+            ScrollDelta::Lines { x: lines, y: 0.0 }
+        } else {
+            ScrollDelta::Lines { x: 0.0, y: lines }
+        };
+
+        let modifiers = unsafe { Modifiers::query() };
+        self.send(Event::Scroll { delta, modifiers });
+    }
+}
+
+impl Cursor {
+    fn idc(self) -> PCWSTR {
+        match self {
+            Self::Default => IDC_ARROW,
+            Self::Hand => IDC_HAND,
+            Self::Text => IDC_IBEAM,
+            Self::ResizeLeft | Self::ResizeRight => IDC_SIZEWE,
+            Self::ResizeTop | Self::ResizeBottom => IDC_SIZENS,
+            Self::ResizeBottomLeft | Self::ResizeTopRight => IDC_SIZENESW,
+            Self::ResizeBottomRight | Self::ResizeTopLeft => IDC_SIZENWSE,
+        }
+    }
+}
+
+fn key_code(vk: VIRTUAL_KEY, scancode: ScanCode) -> Option<KeyCode> {
+    // Modifiers without side are disambiguated by the scan code.
+    let right = scancode.0 & 0xE000 != 0;
+
+    let code = match vk {
+        VK_LEFT => KeyCode::Left,
+        VK_RIGHT => KeyCode::Right,
+        VK_UP => KeyCode::Up,
+        VK_DOWN => KeyCode::Down,
+        VK_HOME => KeyCode::Home,
+        VK_END => KeyCode::End,
+        VK_PRIOR => KeyCode::PageUp,
+        VK_NEXT => KeyCode::PageDown,
+        VK_INSERT => KeyCode::Insert,
+        VK_DELETE => KeyCode::Delete,
+        VK_BACK => KeyCode::Backspace,
+        VK_RETURN => KeyCode::Enter,
+        VK_TAB => KeyCode::Tab,
+        VK_ESCAPE => KeyCode::Escape,
+        VK_F1..=VK_F24 => return KeyCode::function((vk - VK_F1) as u32 + 1),
+        VK_CAPITAL => KeyCode::CapsLock,
+        VK_NUMLOCK => KeyCode::NumLock,
+        VK_SCROLL => KeyCode::ScrollLock,
+        VK_SNAPSHOT => KeyCode::PrintScreen,
+        VK_PAUSE => KeyCode::Pause,
+        VK_APPS => KeyCode::ContextMenu,
+        VK_SHIFT if scancode.0 == 0x36 => KeyCode::ShiftRight,
+        VK_SHIFT => KeyCode::ShiftLeft,
+        VK_CONTROL if right => KeyCode::ControlRight,
+        VK_CONTROL => KeyCode::ControlLeft,
+        VK_MENU if right => KeyCode::AltRight,
+        VK_MENU => KeyCode::AltLeft,
+        VK_LWIN => KeyCode::SuperLeft,
+        VK_RWIN => KeyCode::SuperRight,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+#[derive(Copy, Clone)]
+pub struct Surface {
+    hwnd: HWND,
+}
+
+impl Surface {
+    pub fn show(&self) {
+        unsafe {
+            ShowWindow(self.hwnd, SW_SHOW);
+        }
+    }
+
+    pub fn hwnd(self) -> HWND {
+        self.hwnd
+    }
+
+    pub fn id(&self) -> SurfaceId {
+        SurfaceId(self.hwnd as u64)
+    }
+
+    pub fn extent(&self) -> Extent {
+        let mut rect = MaybeUninit::uninit();
+        unsafe {
+            GetClientRect(self.hwnd, rect.as_mut_ptr());
+        }
+        let rect = unsafe { rect.assume_init() };
+        Extent {
+            width: (rect.right - rect.left) as f64,
+            height: (rect.bottom - rect.top) as f64,
+        }
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        unsafe {
+            let mut placement: WINDOWPLACEMENT = mem::zeroed();
+            placement.length = mem::size_of::<WINDOWPLACEMENT>() as u32;
+            GetWindowPlacement(self.hwnd, &mut placement);
+            placement.showCmd == SW_MAXIMIZE
+        }
+    }
+
+    pub fn dpi(&self) -> f64 {
+        unsafe { GetDpiForWindow(self.hwnd) as f64 / 96.0 }
+    }
+
+    pub fn redraw(&self) {
+        unsafe {
+            RedrawWindow(self.hwnd, ptr::null(), 0, RDW_INVALIDATE);
+        }
+    }
+
+    pub fn clipboard(&self, mime: &str) -> Option<Vec<u8>> {
+        let format = clipboard_format(mime);
+
+        let data = unsafe {
+            if IsClipboardFormatAvailable(format) == 0 || OpenClipboard(self.hwnd) == 0 {
+                return None;
+            }
+
+            let handle = GetClipboardData(format);
+            let ptr = GlobalLock(handle) as *const u8;
+            let data = (!ptr.is_null()).then(|| {
+                let data = slice::from_raw_parts(ptr, GlobalSize(handle)).to_vec();
+                GlobalUnlock(handle);
+                data
+            });
+            CloseClipboard();
+            data?
+        };
+
+        if format == CF_UNICODETEXT {
+            let text = data
+                .chunks_exact(2)
+                .map(|unit| u16::from_ne_bytes([unit[0], unit[1]]))
+                .take_while(|unit| *unit != 0)
+                .collect::<Vec<_>>();
+            return Some(String::from_utf16_lossy(&text).into_bytes());
+        }
+
+        let (prefix, payload) = data.split_at_checked(CLIPBOARD_PREFIX)?;
+        let len = u64::from_ne_bytes(prefix.try_into().unwrap());
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= payload.len())?;
+        Some(payload[..len].to_vec())
+    }
+
+    pub fn set_clipboard(&self, contents: &[(&str, &[u8])]) -> Result<(), clipboard::Error> {
+        unsafe {
+            if OpenClipboard(self.hwnd) == 0 {
+                return Err(clipboard::Error::Unavailable);
+            }
+            EmptyClipboard();
+
+            for (mime, data) in contents {
+                let format = clipboard_format(mime);
+                let data = if format == CF_UNICODETEXT {
+                    let text = String::from_utf8_lossy(data);
+                    encode_wide(&*text)
+                        .into_iter()
+                        .flat_map(u16::to_ne_bytes)
+                        .collect()
+                } else {
+                    let len = (data.len() as u64).to_ne_bytes();
+                    [&len[..], data].concat()
+                };
+
+                let handle = GlobalAlloc(GMEM_MOVEABLE, data.len());
+                let ptr = GlobalLock(handle) as *mut u8;
+                if ptr.is_null() {
+                    if handle != 0 {
+                        GlobalFree(handle);
+                    }
+                    CloseClipboard();
+                    return Err(clipboard::Error::Failed);
+                }
+                ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+                GlobalUnlock(handle);
+
+                // The system owns the memory afterwards.
+                if SetClipboardData(format, handle) == 0 {
+                    GlobalFree(handle);
+                    CloseClipboard();
+                    return Err(clipboard::Error::Failed);
+                }
+            }
+
+            CloseClipboard();
+        }
+
+        Ok(())
+    }
+
+    pub fn set_ime_cursor_area(&self, area: Rect) {
+        let position = POINT {
+            x: area.x,
+            y: area.y,
+        };
+        let rect = RECT {
+            left: area.x,
+            top: area.y,
+            right: area.x + area.width,
+            bottom: area.y + area.height,
+        };
+
+        unsafe {
+            let himc = ImmGetContext(self.hwnd);
+            if himc == 0 {
+                return;
+            }
+
+            ImmSetCompositionWindow(
+                himc,
+                &COMPOSITIONFORM {
+                    dwStyle: CFS_POINT,
+                    ptCurrentPos: position,
+                    rcArea: rect,
+                },
+            );
+            // Candidates are placed next to the caret without overlapping it.
+            ImmSetCandidateWindow(
+                himc,
+                &CANDIDATEFORM {
+                    dwIndex: 0,
+                    dwStyle: CFS_EXCLUDE,
+                    ptCurrentPos: position,
+                    rcArea: rect,
+                },
+            );
+            ImmReleaseContext(self.hwnd, himc);
+        }
+    }
+}
+
+/// Creates and closes the windows of the platform.
+#[derive(Clone)]
+pub struct Surfaces {
+    shared: Rc<Shared>,
+}
+
+impl Surfaces {
+    pub fn create(&self) -> Surface {
+        register_class();
+
+        let user_data = Rc::new(UserData {
+            shared: self.shared.clone(),
+            surface: Cell::new(Surface { hwnd: 0 }), // set during WM_NCCREATE
+            cursor: Cell::new(Cursor::Default),
+            mouse_position: Cell::new(None),
+            mouse_buttons: Cell::new(MouseButtons::empty()),
+            clicks: Cell::new(ClickCounter::default()),
+            keydown_area: Cell::new(HTCLIENT as WPARAM),
+            u16_surrogate: Cell::new(None),
+            occluded: Cell::new(false),
+        });
+
+        unsafe {
+            let class_name = encode_wide(CLASS_NAME);
+            let title = encode_wide("nari");
+
+            // style required to support aero behavior
+            let style = WS_SYSMENU | WS_SIZEBOX | WS_CAPTION | WS_MINIMIZEBOX | WS_MAXIMIZEBOX;
+            let style_ex = WS_EX_APPWINDOW | WS_EX_WINDOWEDGE;
+
+            let hwnd = CreateWindowExW(
+                style_ex,
+                class_name.as_ptr(),
+                title.as_ptr(),
+                style,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                0,
+                0,
+                get_instance_handle(),
+                Rc::as_ptr(&user_data) as _,
+            );
+
+            // Registration holds its own reference, released by `RevokeDragDrop`.
+            let drop_target = DropTarget::create(Rc::as_ptr(&user_data));
+            RegisterDragDrop(hwnd, drop_target as _);
+            DropTarget::release(drop_target);
+
+            self.shared.windows.borrow_mut().insert(hwnd, user_data);
+
+            // force recalc to trigger WM_NCCALCSIZE otherwise the frame will be still seen
+            SetWindowPos(
+                hwnd,
+                0,
+                0,
+                0,
+                0,
+                0,
+                SWP_FRAMECHANGED
+                    | SWP_NOSIZE
+                    | SWP_NOZORDER
+                    | SWP_NOREPOSITION
+                    | SWP_NOMOVE
+                    | SWP_NOACTIVATE,
+            );
+
+            Surface { hwnd }
+        }
+    }
+
+    pub fn close(&self, surface: SurfaceId) {
+        self.shared.closing.borrow_mut().push(surface.0 as HWND);
+    }
+
+    pub fn request_animation_frame(&self, surface: SurfaceId) {
+        let mut frames = self.shared.frames.borrow_mut();
+        let hwnd = surface.0 as HWND;
+        if !frames.contains(&hwnd) {
+            frames.push(hwnd);
+        }
+    }
+
+    pub fn set_timer(&self, surface: SurfaceId, interval: Duration, repeat: bool) -> TimerId {
+        let id = TimerId(self.shared.next_timer.get());
+        self.shared.next_timer.set(id.0 + 1);
+
+        let hwnd = surface.0 as HWND;
+        // Longer intervals are clamped to `USER_TIMER_MAXIMUM`.
+        let elapse = interval.as_millis().min(u32::MAX as u128) as u32;
+        unsafe {
+            SetTimer(hwnd, id.0 as usize, elapse, None);
+        }
+        self.shared.timers.borrow_mut().insert(
+            id,
+            Timer {
+                window: hwnd,
+                repeat,
+            },
+        );
+        id
+    }
+
+    pub fn cancel_timer(&self, timer: TimerId) {
+        if let Some(Timer { window, .. }) = self.shared.timers.borrow_mut().remove(&timer) {
+            unsafe {
+                KillTimer(window, timer.0 as usize);
+            }
+        }
+    }
+
+    pub fn create_proxy(&self, surface: SurfaceId) -> Proxy {
+        Proxy {
+            hwnd: surface.0 as HWND,
+            events: self.shared.user_events.clone(),
+        }
+    }
+}
+
+/// Sends user events to a window from other threads.
+#[derive(Clone)]
+pub struct Proxy {
+    hwnd: HWND,
+    events: UserEvents,
+}
+
+impl Proxy {
+    pub fn send(&self, event: Box<dyn Any + Send>) {
+        self.events.lock().unwrap().push((self.hwnd, event));
+        unsafe {
+            PostMessageW(self.hwnd, WM_NARI_USER_EVENT, 0, 0);
+        }
+    }
+}
+
+pub struct Platform {
+    pub surface: Surface,
+    surfaces: Surfaces,
+}
+
+impl Platform {
+    pub fn new() -> Self {
+        unsafe {
+            SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+            // required for drag and drop
+            OleInitialize(ptr::null_mut());
+        }
+
+        let shared = Rc::new(Shared {
+            control_flow: Cell::new(ControlFlow::Continue),
+            event_callback: RefCell::new(Box::new(|_, _| ControlFlow::Continue)),
+            windows: RefCell::new(HashMap::new()),
+            closing: RefCell::new(Vec::new()),
+            timers: RefCell::new(HashMap::new()),
+            next_timer: Cell::new(1),
+            frames: RefCell::new(Vec::new()),
+            user_events: UserEvents::default(),
+            pending: RefCell::new(VecDeque::new()),
+            close_requests: RefCell::new(Vec::new()),
+        });
+        let surfaces = Surfaces { shared };
+        let surface = surfaces.create();
+
+        Platform { surface, surfaces }
+    }
+
+    pub fn surfaces(&self) -> Surfaces {
+        self.surfaces.clone()
+    }
+
+    pub fn run<F: FnMut(EventLoop, Event) -> ControlFlow + 'static>(self, callback: F) {
+        let shared = &self.surfaces.shared;
+        let _ = shared.event_callback.replace(Box::new(callback));
+
+        self.surface.show();
+
+        loop {
+            shared.close_windows();
+            if shared.control_flow.get() == ControlFlow::Exit || shared.windows.borrow().is_empty()
+            {
+                break;
+            }
+
+            unsafe {
+                let mut msg = MaybeUninit::uninit();
+                if shared.frames.borrow().is_empty() {
+                    if GetMessageW(msg.as_mut_ptr(), 0, 0, 0) == false.into() {
+                        break;
+                    }
+                } else if PeekMessageW(msg.as_mut_ptr(), 0, 0, 0, PM_REMOVE) == false.into() {
+                    // Animation frames are sent once all messages are handled.
+                    shared.send_frames();
+                    continue;
+                }
+                let msg = msg.assume_init();
+
+                if msg.message == WM_QUIT {
+                    break;
+                }
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+
+        // Remaining windows reference the shared state.
+        let remaining = shared.windows.borrow().keys().copied().collect::<Vec<_>>();
+        shared.closing.borrow_mut().extend(remaining);
+        shared.close_windows();
+    }
+}
+
+impl HasDisplayHandle for Surface {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Ok(unsafe {
+            DisplayHandle::borrow_raw(RawDisplayHandle::Windows(WindowsDisplayHandle::new()))
+        })
+    }
+}
+
+impl HasWindowHandle for Surface {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let mut handle = Win32WindowHandle::new(NonZeroIsize::new(self.hwnd as _).unwrap());
+        handle.hinstance = NonZeroIsize::new(get_instance_handle() as _);
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+    }
+}
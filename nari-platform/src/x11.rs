@@ -0,0 +1,2372 @@
+use crate::{
+    clipboard, ClickCounter, Composition, ControlFlow, Cursor, Event, EventCallback, EventLoop,
+    Extent, Key, KeyCode, KeyState, Modifiers, MouseButtons, Rect, ScanCode, ScrollDelta,
+    SurfaceArea, SurfaceId, TimerId,
+};
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, WindowHandle, XlibDisplayHandle, XlibWindowHandle,
+};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    ffi::{c_char, c_int, c_long, c_short, c_uint, c_ulong, c_ushort, c_void, CStr, CString},
+    io::{Read, Write},
+    iter::once,
+    mem::{self, MaybeUninit},
+    os::unix::{ffi::OsStringExt, io::AsRawFd, net::UnixStream},
+    path::PathBuf,
+    ptr::{self, NonNull},
+    rc::Rc,
+    slice,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use x11_dl::{keysym, xlib, xrandr};
+
+/// Initial window size, X11 has no equivalent of `CW_USEDEFAULT`.
+const DEFAULT_EXTENT: (c_uint, c_uint) = (1280, 720);
+
+// Glyphs of the X cursor font (`X11/cursorfont.h`).
+const XC_BOTTOM_LEFT_CORNER: c_uint = 12;
+const XC_BOTTOM_RIGHT_CORNER: c_uint = 14;
+const XC_BOTTOM_SIDE: c_uint = 16;
+const XC_HAND2: c_uint = 60;
+const XC_LEFT_PTR: c_uint = 68;
+const XC_LEFT_SIDE: c_uint = 70;
+const XC_RIGHT_SIDE: c_uint = 96;
+const XC_TOP_LEFT_CORNER: c_uint = 134;
+const XC_TOP_RIGHT_CORNER: c_uint = 136;
+const XC_TOP_SIDE: c_uint = 138;
+const XC_XTERM: c_uint = 152;
+
+// `_NET_WM_MOVERESIZE` directions of the EWMH spec.
+const MOVERESIZE_SIZE_TOPLEFT: c_long = 0;
+const MOVERESIZE_SIZE_TOP: c_long = 1;
+const MOVERESIZE_SIZE_TOPRIGHT: c_long = 2;
+const MOVERESIZE_SIZE_RIGHT: c_long = 3;
+const MOVERESIZE_SIZE_BOTTOMRIGHT: c_long = 4;
+const MOVERESIZE_SIZE_BOTTOM: c_long = 5;
+const MOVERESIZE_SIZE_BOTTOMLEFT: c_long = 6;
+const MOVERESIZE_SIZE_LEFT: c_long = 7;
+const MOVERESIZE_MOVE: c_long = 8;
+
+const NET_WM_STATE_TOGGLE: c_long = 2;
+
+// Input styles, preedit text is either drawn by us or not shown in the window.
+const IM_STYLE_CALLBACKS: XIMStyle = (xlib::XIMPreeditCallbacks | xlib::XIMStatusNothing) as _;
+const IM_STYLE_NOTHING: XIMStyle = (xlib::XIMPreeditNothing | xlib::XIMStatusNothing) as _;
+
+/// Highlighting of the preedit clause being converted.
+const XIM_REVERSE: xlib::XIMFeedback = 1;
+
+const LC_CTYPE: c_int = 0;
+const POLLIN: c_short = 0x1;
+
+extern "C" {
+    fn setlocale(category: c_int, locale: *const c_char) -> *mut c_char;
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+type XIMStyle = c_ulong;
+
+/// Result of `XNQueryInputStyle`, missing in `x11_dl`.
+#[repr(C)]
+struct XIMStyles {
+    count_styles: c_ushort,
+    supported_styles: *mut XIMStyle,
+}
+
+/// Maximum time to wait for a selection owner to convert its contents.
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Supported version of the XDND protocol.
+const XDND_VERSION: c_long = 5;
+
+/// Lines per wheel step, matching the Windows default.
+const WHEEL_LINES: f64 = 3.0;
+
+/// Double click interval in milliseconds without `*multiClickTime` resource.
+const DEFAULT_CLICK_INTERVAL: u32 = 400;
+/// Maximum pointer movement between consecutive clicks.
+const CLICK_DISTANCE: (i32, i32) = (4, 4);
+
+/// Animation frame interval if the refresh rate can't be queried.
+const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+/// Move or resize direction of the window manager for non-client areas
+/// which are dragged.
+fn moveresize_direction(area: SurfaceArea) -> Option<c_long> {
+    match area {
+        SurfaceArea::TopLeft => Some(MOVERESIZE_SIZE_TOPLEFT),
+        SurfaceArea::Top => Some(MOVERESIZE_SIZE_TOP),
+        SurfaceArea::TopRight => Some(MOVERESIZE_SIZE_TOPRIGHT),
+        SurfaceArea::Right => Some(MOVERESIZE_SIZE_RIGHT),
+        SurfaceArea::BottomRight => Some(MOVERESIZE_SIZE_BOTTOMRIGHT),
+        SurfaceArea::Bottom => Some(MOVERESIZE_SIZE_BOTTOM),
+        SurfaceArea::BottomLeft => Some(MOVERESIZE_SIZE_BOTTOMLEFT),
+        SurfaceArea::Left => Some(MOVERESIZE_SIZE_LEFT),
+        SurfaceArea::Caption => Some(MOVERESIZE_MOVE),
+        SurfaceArea::Client
+        | SurfaceArea::Minimize
+        | SurfaceArea::Maximize
+        | SurfaceArea::Close => None,
+    }
+}
+
+/// Value of a resource of the resource manager string.
+fn resource<'a>(resources: &'a str, name: &str) -> Option<&'a str> {
+    resources.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == name).then(|| value.trim())
+    })
+}
+
+fn key_code(keysym: c_uint) -> Option<KeyCode> {
+    let code = match keysym {
+        keysym::XK_Left | keysym::XK_KP_Left => KeyCode::Left,
+        keysym::XK_Right | keysym::XK_KP_Right => KeyCode::Right,
+        keysym::XK_Up | keysym::XK_KP_Up => KeyCode::Up,
+        keysym::XK_Down | keysym::XK_KP_Down => KeyCode::Down,
+        keysym::XK_Home | keysym::XK_KP_Home => KeyCode::Home,
+        keysym::XK_End | keysym::XK_KP_End => KeyCode::End,
+        keysym::XK_Prior | keysym::XK_KP_Prior => KeyCode::PageUp,
+        keysym::XK_Next | keysym::XK_KP_Next => KeyCode::PageDown,
+        keysym::XK_Insert | keysym::XK_KP_Insert => KeyCode::Insert,
+        keysym::XK_Delete | keysym::XK_KP_Delete => KeyCode::Delete,
+        keysym::XK_BackSpace => KeyCode::Backspace,
+        keysym::XK_Return | keysym::XK_KP_Enter => KeyCode::Enter,
+        keysym::XK_Tab | keysym::XK_ISO_Left_Tab => KeyCode::Tab,
+        keysym::XK_Escape => KeyCode::Escape,
+        keysym::XK_F1..=keysym::XK_F24 => return KeyCode::function(keysym - keysym::XK_F1 + 1),
+        keysym::XK_Caps_Lock => KeyCode::CapsLock,
+        keysym::XK_Num_Lock => KeyCode::NumLock,
+        keysym::XK_Scroll_Lock => KeyCode::ScrollLock,
+        keysym::XK_Print => KeyCode::PrintScreen,
+        keysym::XK_Pause => KeyCode::Pause,
+        keysym::XK_Menu => KeyCode::ContextMenu,
+        keysym::XK_Shift_L => KeyCode::ShiftLeft,
+        keysym::XK_Shift_R => KeyCode::ShiftRight,
+        keysym::XK_Control_L => KeyCode::ControlLeft,
+        keysym::XK_Control_R => KeyCode::ControlRight,
+        keysym::XK_Alt_L => KeyCode::AltLeft,
+        // AltGr, reported as right Alt on Windows as well.
+        keysym::XK_Alt_R | keysym::XK_ISO_Level3_Shift => KeyCode::AltRight,
+        keysym::XK_Super_L => KeyCode::SuperLeft,
+        keysym::XK_Super_R => KeyCode::SuperRight,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// Character of a printable keysym.
+fn keysym_char(keysym: c_uint) -> Option<char> {
+    match keysym {
+        // Keypad keysyms are offset from their ASCII characters.
+        keysym::XK_KP_Space | keysym::XK_KP_Equal | 0xFFAA..=0xFFB9 => {
+            char::from_u32(keysym - 0xFF80)
+        }
+        0x20..=0x7E | 0xA0..=0xFF => char::from_u32(keysym),
+        // Unicode keysyms.
+        0x0100_0000..=0x0110_FFFF => char::from_u32(keysym - 0x0100_0000),
+        _ => None,
+    }
+}
+
+/// Scan code of an X keycode, which are evdev codes offset by 8.
+fn scancode(keycode: c_uint) -> ScanCode {
+    let code = keycode.saturating_sub(8);
+    ScanCode(match code {
+        // evdev matches set 1 for the non-extended keys
+        0..=88 => code,
+        96 => 0xE01C,  // KEY_KPENTER
+        97 => 0xE01D,  // KEY_RIGHTCTRL
+        98 => 0xE035,  // KEY_KPSLASH
+        99 => 0xE037,  // KEY_SYSRQ
+        100 => 0xE038, // KEY_RIGHTALT
+        102 => 0xE047, // KEY_HOME
+        103 => 0xE048, // KEY_UP
+        104 => 0xE049, // KEY_PAGEUP
+        105 => 0xE04B, // KEY_LEFT
+        106 => 0xE04D, // KEY_RIGHT
+        107 => 0xE04F, // KEY_END
+        108 => 0xE050, // KEY_DOWN
+        109 => 0xE051, // KEY_PAGEDOWN
+        110 => 0xE052, // KEY_INSERT
+        111 => 0xE053, // KEY_DELETE
+        119 => 0x45,   // KEY_PAUSE
+        125 => 0xE05B, // KEY_LEFTMETA
+        126 => 0xE05C, // KEY_RIGHTMETA
+        127 => 0xE05D, // KEY_COMPOSE
+        _ => 0,
+    })
+}
+
+fn mouse_button(button: c_uint) -> Option<MouseButtons> {
+    match button {
+        xlib::Button1 => Some(MouseButtons::LEFT),
+        xlib::Button2 => Some(MouseButtons::MIDDLE),
+        xlib::Button3 => Some(MouseButtons::RIGHT),
+        8 => Some(MouseButtons::BACK),
+        9 => Some(MouseButtons::FORWARD),
+        _ => None,
+    }
+}
+
+/// Wheel steps are reported as presses of the buttons 4 to 7.
+///
+/// Only whole steps are reported, touchpads scroll in steps as well. Smooth
+/// scrolling would require the scroll valuators of XInput 2.1, which are not
+/// used yet.
+fn wheel_delta(button: c_uint) -> Option<ScrollDelta> {
+    let (x, y) = match button {
+        xlib::Button4 => (0.0, 1.0),
+        xlib::Button5 => (0.0, -1.0),
+        6 => (-1.0, 0.0),
+        7 => (1.0, 0.0),
+        _ => return None,
+    };
+    Some(ScrollDelta::Lines {
+        x: x * WHEEL_LINES,
+        y: y * WHEEL_LINES,
+    })
+}
+
+/// Local file paths of a `text/uri-list`.
+fn uri_list_paths(list: &[u8]) -> Vec<PathBuf> {
+    String::from_utf8_lossy(list)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|uri| {
+            // `file://host/path`, the host is usually empty
+            let path = uri.strip_prefix("file://")?;
+            let path = &path[path.find('/')?..];
+            Some(PathBuf::from(OsString::from_vec(percent_decode(path))))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Preedit text of input methods with the callbacks style.
+#[derive(Debug, Default)]
+struct Preedit {
+    chars: Vec<char>,
+    feedback: Vec<xlib::XIMFeedback>,
+    /// Character index of the caret.
+    caret: usize,
+}
+
+impl Preedit {
+    /// Replace `length` characters at `first`, without `chars` only the
+    /// highlighting of the characters changes.
+    fn draw(
+        &mut self,
+        first: usize,
+        length: usize,
+        chars: Option<&[char]>,
+        feedback: &[xlib::XIMFeedback],
+    ) {
+        let first = first.min(self.chars.len());
+        let end = (first + length).min(self.chars.len());
+        match chars {
+            Some(chars) => {
+                let feedback = (0..chars.len()).map(|i| feedback.get(i).copied().unwrap_or(0));
+                self.chars.splice(first..end, chars.iter().copied());
+                self.feedback.splice(first..end, feedback);
+            }
+            None => {
+                for (dst, src) in self.feedback[first..end].iter_mut().zip(feedback) {
+                    *dst = *src;
+                }
+            }
+        }
+    }
+
+    fn composition(&self) -> Composition {
+        let offset = |index: usize| -> usize {
+            self.chars[..index.min(self.chars.len())]
+                .iter()
+                .map(|c| c.len_utf8())
+                .sum()
+        };
+
+        let selected = |feedback: &xlib::XIMFeedback| feedback & XIM_REVERSE != 0;
+        let first = self.feedback.iter().position(selected);
+        let last = self.feedback.iter().rposition(selected);
+        let cursor = match (first, last) {
+            (Some(first), Some(last)) => offset(first)..offset(last + 1),
+            _ => offset(self.caret)..offset(self.caret),
+        };
+
+        Composition::Update {
+            text: self.chars.iter().collect(),
+            cursor: Some(cursor),
+        }
+    }
+}
+
+/// Characters of preedit text, `None` if only the highlighting changed.
+unsafe fn preedit_chars(text: &xlib::XIMText) -> Option<Vec<char>> {
+    let string = text.string.multi_byte;
+    if string.is_null() {
+        return None;
+    }
+
+    let chars = if text.encoding_is_wchar != 0 {
+        // `wchar_t` holds UTF-32 code points
+        slice::from_raw_parts(string as *const u32, text.length as usize)
+            .iter()
+            .filter_map(|&c| char::from_u32(c))
+            .collect()
+    } else {
+        CStr::from_ptr(string).to_string_lossy().chars().collect()
+    };
+    Some(chars)
+}
+
+unsafe extern "C" fn preedit_start(
+    _: xlib::XIM,
+    client_data: xlib::XPointer,
+    _: xlib::XPointer,
+) -> c_int {
+    let user_data = &*(client_data as *const UserData);
+    *user_data.preedit.borrow_mut() = Preedit::default();
+    user_data.compositions.borrow_mut().push(Composition::Start);
+    -1 // no length limit
+}
+
+unsafe extern "C" fn preedit_done(_: xlib::XIM, client_data: xlib::XPointer, _: xlib::XPointer) {
+    let user_data = &*(client_data as *const UserData);
+    *user_data.preedit.borrow_mut() = Preedit::default();
+    user_data.compositions.borrow_mut().push(Composition::End);
+}
+
+unsafe extern "C" fn preedit_draw(
+    _: xlib::XIM,
+    client_data: xlib::XPointer,
+    call_data: xlib::XPointer,
+) {
+    let user_data = &*(client_data as *const UserData);
+    let draw = &*(call_data as *const xlib::XIMPreeditDrawCallbackStruct);
+
+    let mut preedit = user_data.preedit.borrow_mut();
+    let first = draw.chg_first.max(0) as usize;
+    let length = draw.chg_length.max(0) as usize;
+    match draw.text.as_ref() {
+        Some(text) => {
+            let feedback = if text.feedback.is_null() {
+                &[][..]
+            } else {
+                slice::from_raw_parts(text.feedback, text.length as usize)
+            };
+            preedit.draw(first, length, preedit_chars(text).as_deref(), feedback);
+        }
+        // deletion
+        None => preedit.draw(first, length, Some(&[]), &[]),
+    }
+    preedit.caret = draw.caret.max(0) as usize;
+
+    let composition = preedit.composition();
+    user_data.compositions.borrow_mut().push(composition);
+}
+
+unsafe extern "C" fn preedit_caret(
+    _: xlib::XIM,
+    client_data: xlib::XPointer,
+    call_data: xlib::XPointer,
+) {
+    let user_data = &*(client_data as *const UserData);
+    let caret = &mut *(call_data as *mut xlib::XIMPreeditCaretCallbackStruct);
+
+    let mut preedit = user_data.preedit.borrow_mut();
+    let len = preedit.chars.len();
+    preedit.caret = match caret.direction {
+        xlib::XIMCaretDirection::XIMAbsolutePosition => caret.position.max(0) as usize,
+        xlib::XIMCaretDirection::XIMForwardChar => preedit.caret + 1,
+        xlib::XIMCaretDirection::XIMBackwardChar => preedit.caret.saturating_sub(1),
+        xlib::XIMCaretDirection::XIMLineStart => 0,
+        xlib::XIMCaretDirection::XIMLineEnd => len,
+        _ => preedit.caret,
+    }
+    .min(len);
+    // The new position is reported back to the input method.
+    caret.position = preedit.caret as c_int;
+
+    let composition = preedit.composition();
+    user_data.compositions.borrow_mut().push(composition);
+}
+
+/// Text input of a key press from the input context, including text
+/// committed by the input method.
+unsafe fn lookup_text(
+    xlib: &xlib::Xlib,
+    ic: xlib::XIC,
+    event: &mut xlib::XKeyEvent,
+) -> Option<String> {
+    let mut buffer = vec![0u8; 32];
+    let mut status = 0;
+    loop {
+        let len = (xlib.Xutf8LookupString)(
+            ic,
+            event,
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len() as c_int,
+            ptr::null_mut(),
+            &mut status,
+        );
+        match status {
+            xlib::XBufferOverflow => buffer.resize(len as usize, 0),
+            xlib::XLookupChars | xlib::XLookupBoth => {
+                buffer.truncate(len as usize);
+                return String::from_utf8(buffer).ok();
+            }
+            _ => return None,
+        }
+    }
+}
+
+impl Modifiers {
+    fn from_state(state: c_uint) -> Self {
+        let mut modifiers = Modifiers::empty();
+        if state & xlib::Mod1Mask != 0 {
+            modifiers |= Modifiers::ALT;
+        }
+        if state & xlib::ControlMask != 0 {
+            modifiers |= Modifiers::CONTROL;
+        }
+        if state & xlib::ShiftMask != 0 {
+            modifiers |= Modifiers::SHIFT;
+        }
+        if state & xlib::Mod4Mask != 0 {
+            modifiers |= Modifiers::SUPER;
+        }
+        modifiers
+    }
+}
+
+impl Cursor {
+    const COUNT: usize = 11;
+
+    fn glyph(self) -> c_uint {
+        match self {
+            Self::Default => XC_LEFT_PTR,
+            Self::Hand => XC_HAND2,
+            Self::Text => XC_XTERM,
+            Self::ResizeLeft => XC_LEFT_SIDE,
+            Self::ResizeRight => XC_RIGHT_SIDE,
+            Self::ResizeTop => XC_TOP_SIDE,
+            Self::ResizeBottom => XC_BOTTOM_SIDE,
+            Self::ResizeTopLeft => XC_TOP_LEFT_CORNER,
+            Self::ResizeTopRight => XC_TOP_RIGHT_CORNER,
+            Self::ResizeBottomLeft => XC_BOTTOM_LEFT_CORNER,
+            Self::ResizeBottomRight => XC_BOTTOM_RIGHT_CORNER,
+        }
+    }
+}
+
+struct Atoms {
+    wm_protocols: xlib::Atom,
+    wm_delete_window: xlib::Atom,
+    motif_wm_hints: xlib::Atom,
+    net_wm_name: xlib::Atom,
+    net_wm_state: xlib::Atom,
+    net_wm_state_maximized_vert: xlib::Atom,
+    net_wm_state_maximized_horz: xlib::Atom,
+    net_wm_moveresize: xlib::Atom,
+    utf8_string: xlib::Atom,
+    clipboard: xlib::Atom,
+    targets: xlib::Atom,
+    incr: xlib::Atom,
+    /// Property receiving converted selections.
+    nari_selection: xlib::Atom,
+    xdnd_aware: xlib::Atom,
+    xdnd_enter: xlib::Atom,
+    xdnd_position: xlib::Atom,
+    xdnd_status: xlib::Atom,
+    xdnd_leave: xlib::Atom,
+    xdnd_drop: xlib::Atom,
+    xdnd_finished: xlib::Atom,
+    xdnd_selection: xlib::Atom,
+    xdnd_action_copy: xlib::Atom,
+    text_uri_list: xlib::Atom,
+}
+
+/// Connection to the X server.
+///
+/// Lives until the end of the process, surfaces are `Copy` handles into it.
+struct Connection {
+    xlib: xlib::Xlib,
+    display: *mut xlib::Display,
+    screen: c_int,
+    root: xlib::Window,
+    /// Hidden window owning and requesting selections, which stays valid
+    /// while surfaces are closed.
+    selection_window: xlib::Window,
+    atoms: Atoms,
+    click_interval: u32,
+    /// Refresh interval of the screen, paces animation frames.
+    frame_interval: Duration,
+    cursors: [Cell<xlib::Cursor>; Cursor::COUNT], // created on first use
+    im: xlib::XIM,                                // null without input method
+    im_style: XIMStyle,
+    input_contexts: RefCell<HashMap<xlib::Window, xlib::XIC>>,
+    /// Contents of the clipboard selection per target while we own it.
+    clipboard: RefCell<Vec<(xlib::Atom, Vec<u8>)>>,
+}
+
+impl Connection {
+    fn open() -> &'static Self {
+        let xlib = xlib::Xlib::open().expect("failed to load libX11");
+
+        unsafe {
+            let display = (xlib.XOpenDisplay)(ptr::null());
+            assert!(!display.is_null(), "failed to open X display");
+
+            // Report key repeats without synthetic key releases, matching `WM_KEYDOWN`.
+            (xlib.XkbSetDetectableAutoRepeat)(display, xlib::True, ptr::null_mut());
+
+            let screen = (xlib.XDefaultScreen)(display);
+            let root = (xlib.XRootWindow)(display, screen);
+            let selection_window = (xlib.XCreateSimpleWindow)(display, root, 0, 0, 1, 1, 0, 0, 0);
+            // Changes of the resource database, e.g. `Xft.dpi`.
+            (xlib.XSelectInput)(display, root, xlib::PropertyChangeMask);
+
+            let atom = |name: &CStr| (xlib.XInternAtom)(display, name.as_ptr(), xlib::False);
+            let atoms = Atoms {
+                wm_protocols: atom(c"WM_PROTOCOLS"),
+                wm_delete_window: atom(c"WM_DELETE_WINDOW"),
+                motif_wm_hints: atom(c"_MOTIF_WM_HINTS"),
+                net_wm_name: atom(c"_NET_WM_NAME"),
+                net_wm_state: atom(c"_NET_WM_STATE"),
+                net_wm_state_maximized_vert: atom(c"_NET_WM_STATE_MAXIMIZED_VERT"),
+                net_wm_state_maximized_horz: atom(c"_NET_WM_STATE_MAXIMIZED_HORZ"),
+                net_wm_moveresize: atom(c"_NET_WM_MOVERESIZE"),
+                utf8_string: atom(c"UTF8_STRING"),
+                clipboard: atom(c"CLIPBOARD"),
+                targets: atom(c"TARGETS"),
+                incr: atom(c"INCR"),
+                nari_selection: atom(c"NARI_SELECTION"),
+                xdnd_aware: atom(c"XdndAware"),
+                xdnd_enter: atom(c"XdndEnter"),
+                xdnd_position: atom(c"XdndPosition"),
+                xdnd_status: atom(c"XdndStatus"),
+                xdnd_leave: atom(c"XdndLeave"),
+                xdnd_drop: atom(c"XdndDrop"),
+                xdnd_finished: atom(c"XdndFinished"),
+                xdnd_selection: atom(c"XdndSelection"),
+                xdnd_action_copy: atom(c"XdndActionCopy"),
+                text_uri_list: atom(c"text/uri-list"),
+            };
+
+            let (im, im_style) = Self::open_im(&xlib, display);
+
+            let mut connection = Connection {
+                xlib,
+                display,
+                screen,
+                root,
+                selection_window,
+                atoms,
+                click_interval: DEFAULT_CLICK_INTERVAL,
+                frame_interval: Self::frame_interval(display, root),
+                cursors: Default::default(),
+                im,
+                im_style,
+                input_contexts: RefCell::new(HashMap::new()),
+                clipboard: RefCell::new(Vec::new()),
+            };
+            if let Some(interval) = connection
+                .resources()
+                .and_then(|resources| resource(&resources, "*multiClickTime")?.parse().ok())
+            {
+                connection.click_interval = interval;
+            }
+
+            Box::leak(Box::new(connection))
+        }
+    }
+
+    /// Refresh interval of the current screen configuration.
+    unsafe fn frame_interval(display: *mut xlib::Display, root: xlib::Window) -> Duration {
+        let Ok(xrandr) = xrandr::Xrandr::open() else {
+            return DEFAULT_FRAME_INTERVAL;
+        };
+        let config = (xrandr.XRRGetScreenInfo)(display, root);
+        if config.is_null() {
+            return DEFAULT_FRAME_INTERVAL;
+        }
+        let rate = (xrandr.XRRConfigCurrentRate)(config);
+        (xrandr.XRRFreeScreenConfigInfo)(config);
+
+        match rate {
+            rate if rate > 0 => Duration::from_secs(1) / rate as u32,
+            _ => DEFAULT_FRAME_INTERVAL,
+        }
+    }
+
+    /// Open the input method selected by `XMODIFIERS`.
+    unsafe fn open_im(xlib: &xlib::Xlib, display: *mut xlib::Display) -> (xlib::XIM, XIMStyle) {
+        // Preedit and committed text are encoded in the locale of the process,
+        // keep the locale if the application already set one.
+        let locale = setlocale(LC_CTYPE, ptr::null());
+        if locale.is_null() || CStr::from_ptr(locale) == c"C" {
+            setlocale(LC_CTYPE, c"".as_ptr());
+        }
+        if (xlib.XSupportsLocale)() == xlib::False {
+            return (ptr::null_mut(), 0);
+        }
+        (xlib.XSetLocaleModifiers)(c"".as_ptr());
+
+        let im = (xlib.XOpenIM)(display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+        if im.is_null() {
+            return (im, 0);
+        }
+
+        let mut styles: *mut XIMStyles = ptr::null_mut();
+        (xlib.XGetIMValues)(
+            im,
+            xlib::XNQueryInputStyle_0.as_ptr(),
+            &mut styles,
+            ptr::null_mut::<c_void>(),
+        );
+        let mut style = IM_STYLE_NOTHING;
+        if !styles.is_null() {
+            let supported =
+                slice::from_raw_parts((*styles).supported_styles, (*styles).count_styles as usize);
+            if supported.contains(&IM_STYLE_CALLBACKS) {
+                style = IM_STYLE_CALLBACKS;
+            }
+            (xlib.XFree)(styles as *mut c_void);
+        }
+
+        (im, style)
+    }
+
+    /// Create the input context of a window, preedit callbacks receive
+    /// `user_data`.
+    unsafe fn create_ic(&self, window: xlib::Window, user_data: *const UserData) {
+        if self.im.is_null() {
+            return;
+        }
+
+        let xlib = &self.xlib;
+        let client_data = user_data as xlib::XPointer;
+        let callback = |callback: xlib::XIMProc| xlib::XIMCallback {
+            client_data,
+            callback,
+        };
+
+        let mut ic = ptr::null_mut();
+        if self.im_style == IM_STYLE_CALLBACKS {
+            // The start callback returns the maximum preedit length.
+            let start = mem::transmute::<
+                unsafe extern "C" fn(xlib::XIM, xlib::XPointer, xlib::XPointer) -> c_int,
+                unsafe extern "C" fn(xlib::XIM, xlib::XPointer, xlib::XPointer),
+            >(preedit_start);
+            let start = callback(Some(start));
+            let done = callback(Some(preedit_done));
+            let draw = callback(Some(preedit_draw));
+            let caret = callback(Some(preedit_caret));
+            let mut spot = xlib::XPoint { x: 0, y: 0 };
+
+            let attributes = (xlib.XVaCreateNestedList)(
+                0,
+                xlib::XNPreeditStartCallback_0.as_ptr(),
+                &start,
+                xlib::XNPreeditDoneCallback_0.as_ptr(),
+                &done,
+                xlib::XNPreeditDrawCallback_0.as_ptr(),
+                &draw,
+                xlib::XNPreeditCaretCallback_0.as_ptr(),
+                &caret,
+                xlib::XNSpotLocation_0.as_ptr(),
+                &mut spot,
+                ptr::null_mut::<c_void>(),
+            );
+            ic = (xlib.XCreateIC)(
+                self.im,
+                xlib::XNInputStyle_0.as_ptr(),
+                IM_STYLE_CALLBACKS,
+                xlib::XNClientWindow_0.as_ptr(),
+                window,
+                xlib::XNFocusWindow_0.as_ptr(),
+                window,
+                xlib::XNPreeditAttributes_0.as_ptr(),
+                attributes,
+                ptr::null_mut::<c_void>(),
+            );
+            (xlib.XFree)(attributes);
+        }
+        if ic.is_null() {
+            ic = (xlib.XCreateIC)(
+                self.im,
+                xlib::XNInputStyle_0.as_ptr(),
+                IM_STYLE_NOTHING,
+                xlib::XNClientWindow_0.as_ptr(),
+                window,
+                xlib::XNFocusWindow_0.as_ptr(),
+                window,
+                ptr::null_mut::<c_void>(),
+            );
+        }
+        if !ic.is_null() {
+            self.input_contexts.borrow_mut().insert(window, ic);
+        }
+    }
+
+    fn input_context(&self, window: xlib::Window) -> Option<xlib::XIC> {
+        self.input_contexts.borrow().get(&window).copied()
+    }
+
+    fn destroy_ic(&self, window: xlib::Window) {
+        if let Some(ic) = self.input_contexts.borrow_mut().remove(&window) {
+            unsafe { (self.xlib.XDestroyIC)(ic) };
+        }
+    }
+
+    fn atom(&self, name: &str) -> xlib::Atom {
+        let name = CString::new(name).unwrap_or_default();
+        unsafe { (self.xlib.XInternAtom)(self.display, name.as_ptr(), xlib::False) }
+    }
+
+    /// Selection target of a clipboard MIME type.
+    fn clipboard_target(&self, mime: &str) -> xlib::Atom {
+        match mime {
+            clipboard::TEXT => self.atoms.utf8_string,
+            _ => self.atom(mime),
+        }
+    }
+
+    /// Request the contents of a selection from its owner.
+    fn convert_selection(
+        &self,
+        selection: xlib::Atom,
+        target: xlib::Atom,
+        time: xlib::Time,
+    ) -> Option<Vec<u8>> {
+        let xlib = &self.xlib;
+        let atoms = &self.atoms;
+
+        unsafe {
+            (xlib.XConvertSelection)(
+                self.display,
+                selection,
+                target,
+                atoms.nari_selection,
+                self.selection_window,
+                time,
+            );
+            (xlib.XFlush)(self.display);
+
+            // Wait for the owner, other events stay queued for the event loop.
+            let deadline = Instant::now() + SELECTION_TIMEOUT;
+            let mut event = MaybeUninit::uninit();
+            while (xlib.XCheckTypedWindowEvent)(
+                self.display,
+                self.selection_window,
+                xlib::SelectionNotify,
+                event.as_mut_ptr(),
+            ) == xlib::False
+            {
+                if Instant::now() > deadline {
+                    return None;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            let event = event.assume_init();
+            if event.selection.property == 0 {
+                return None;
+            }
+
+            let (ty, data) = self.take_property(atoms.nari_selection)?;
+            // Incremental transfers of large contents are not supported.
+            (ty != atoms.incr).then_some(data)
+        }
+    }
+
+    /// Read and delete a property with 8 bit format, returning its type and data.
+    fn take_property(&self, property: xlib::Atom) -> Option<(xlib::Atom, Vec<u8>)> {
+        let mut ty = 0;
+        let mut format = 0;
+        let mut num_items = 0;
+        let mut bytes_after = 0;
+        let mut data = ptr::null_mut();
+
+        unsafe {
+            // length in 32 bit units
+            let status = (self.xlib.XGetWindowProperty)(
+                self.display,
+                self.selection_window,
+                property,
+                0,
+                c_long::MAX / 4,
+                xlib::True,
+                xlib::AnyPropertyType as xlib::Atom,
+                &mut ty,
+                &mut format,
+                &mut num_items,
+                &mut bytes_after,
+                &mut data,
+            );
+            if data.is_null() {
+                return None;
+            }
+
+            let bytes = (status == xlib::Success as c_int && format == 8)
+                .then(|| slice::from_raw_parts(data, num_items as usize).to_vec());
+            (self.xlib.XFree)(data as *mut c_void);
+            Some((ty, bytes?))
+        }
+    }
+
+    /// Answer a request of another client for the clipboard contents.
+    unsafe fn on_selection_request(&self, request: &xlib::XSelectionRequestEvent) {
+        let xlib = &self.xlib;
+        let contents = self.clipboard.borrow();
+
+        // Obsolete clients don't specify a property.
+        let mut property = match request.property {
+            0 => request.target,
+            property => property,
+        };
+        if request.selection != self.atoms.clipboard {
+            property = 0;
+        } else if request.target == self.atoms.targets {
+            let targets = once(self.atoms.targets)
+                .chain(contents.iter().map(|(target, _)| *target))
+                .collect::<Vec<_>>();
+            (xlib.XChangeProperty)(
+                self.display,
+                request.requestor,
+                property,
+                xlib::XA_ATOM,
+                32,
+                xlib::PropModeReplace,
+                targets.as_ptr() as *const _,
+                targets.len() as c_int,
+            );
+        } else if let Some((_, data)) = contents
+            .iter()
+            .find(|(target, _)| *target == request.target)
+        {
+            (xlib.XChangeProperty)(
+                self.display,
+                request.requestor,
+                property,
+                request.target,
+                8,
+                xlib::PropModeReplace,
+                data.as_ptr(),
+                data.len() as c_int,
+            );
+        } else {
+            property = 0;
+        }
+
+        let mut event: xlib::XEvent = mem::zeroed();
+        event.selection = xlib::XSelectionEvent {
+            type_: xlib::SelectionNotify,
+            serial: 0,
+            send_event: xlib::True,
+            display: self.display,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property,
+            time: request.time,
+        };
+        (xlib.XSendEvent)(self.display, request.requestor, xlib::False, 0, &mut event);
+        (xlib.XFlush)(self.display);
+    }
+
+    /// Resource database of the root window, read on each call as
+    /// `XResourceManagerString` keeps the database at connection time.
+    fn resources(&self) -> Option<String> {
+        let mut ty = 0;
+        let mut format = 0;
+        let mut num_items = 0;
+        let mut bytes_after = 0;
+        let mut data = ptr::null_mut();
+
+        unsafe {
+            let status = (self.xlib.XGetWindowProperty)(
+                self.display,
+                self.root,
+                xlib::XA_RESOURCE_MANAGER,
+                0,
+                c_long::MAX / 4,
+                xlib::False,
+                xlib::XA_STRING,
+                &mut ty,
+                &mut format,
+                &mut num_items,
+                &mut bytes_after,
+                &mut data,
+            );
+            if data.is_null() {
+                return None;
+            }
+
+            let resources = (status == xlib::Success as c_int && format == 8).then(|| {
+                let bytes = slice::from_raw_parts(data, num_items as usize);
+                String::from_utf8_lossy(bytes).into_owned()
+            });
+            (self.xlib.XFree)(data as *mut c_void);
+            resources
+        }
+    }
+
+    fn cursor(&self, cursor: Cursor) -> xlib::Cursor {
+        let cached = &self.cursors[cursor as usize];
+        if cached.get() == 0 {
+            cached.set(unsafe { (self.xlib.XCreateFontCursor)(self.display, cursor.glyph()) });
+        }
+        cached.get()
+    }
+}
+
+/// Files dragged over the window with the XDND protocol.
+struct Drag {
+    source: xlib::Window,
+    /// Requested with the first position.
+    paths: Option<Vec<PathBuf>>,
+    accept: bool,
+}
+
+type UserEvents = Arc<Mutex<Vec<(xlib::Window, Box<dyn Any + Send>)>>>;
+
+#[derive(Copy, Clone, Debug)]
+struct Timer {
+    id: TimerId,
+    window: xlib::Window,
+    deadline: Instant,
+    /// Interval of repeating timers.
+    interval: Option<Duration>,
+}
+
+/// State shared by all surfaces of the platform.
+struct Shared {
+    connection: &'static Connection,
+    control_flow: Cell<ControlFlow>,
+    event_callback: RefCell<EventCallback>,
+    windows: RefCell<HashMap<xlib::Window, Rc<UserData>>>,
+    /// Windows to destroy after the current event.
+    closing: RefCell<Vec<xlib::Window>>,
+    timers: RefCell<Vec<Timer>>,
+    next_timer: Cell<u64>,
+    /// Windows waiting for the next animation frame.
+    frames: RefCell<Vec<xlib::Window>>,
+    last_frame: Cell<Instant>,
+    user_events: UserEvents,
+    /// Socket pair waking up the event loop after proxies queued user events.
+    wake: (UnixStream, Arc<UnixStream>),
+}
+
+impl Shared {
+    fn user_data(&self, window: xlib::Window) -> Option<Rc<UserData>> {
+        self.windows.borrow().get(&window).cloned()
+    }
+
+    unsafe fn handle(&self, event: &mut xlib::XEvent) {
+        let connection = self.connection;
+
+        match event.get_type() {
+            xlib::SelectionRequest => connection.on_selection_request(&event.selection_request),
+            xlib::SelectionClear
+                if event.selection_clear.selection == connection.atoms.clipboard =>
+            {
+                connection.clipboard.borrow_mut().clear();
+            }
+            xlib::PropertyNotify
+                if event.property.window == connection.root
+                    && event.property.atom == xlib::XA_RESOURCE_MANAGER =>
+            {
+                let windows = self.windows.borrow().values().cloned().collect::<Vec<_>>();
+                for user_data in windows {
+                    user_data.update_dpi();
+                }
+            }
+            _ => {
+                if let Some(user_data) = self.user_data(event.any.window) {
+                    user_data.handle(event);
+                }
+            }
+        }
+    }
+
+    /// Destroy the windows closed during the last event.
+    fn close_windows(&self) {
+        let closing = mem::take(&mut *self.closing.borrow_mut());
+        for window in closing {
+            let Some(user_data) = self.windows.borrow_mut().remove(&window) else {
+                continue;
+            };
+            user_data.send(Event::Close);
+
+            self.timers.borrow_mut().retain(|t| t.window != window);
+            self.frames.borrow_mut().retain(|w| *w != window);
+
+            let connection = self.connection;
+            connection.destroy_ic(window);
+            unsafe {
+                (connection.xlib.XDestroyWindow)(connection.display, window);
+                (connection.xlib.XFlush)(connection.display);
+            }
+        }
+    }
+
+    fn next_frame(&self) -> Option<Instant> {
+        if self.frames.borrow().is_empty() {
+            return None;
+        }
+        Some(self.last_frame.get() + self.connection.frame_interval)
+    }
+
+    /// Block until events of the X server or proxies arrive, or the next
+    /// timer or animation frame is due.
+    fn wait(&self) {
+        let now = Instant::now();
+        let deadline = self
+            .timers
+            .borrow()
+            .iter()
+            .map(|t| t.deadline)
+            .chain(self.next_frame())
+            .min();
+        let timeout = match deadline {
+            // rounded up, waking up early would spin until the deadline
+            Some(deadline) => deadline
+                .saturating_duration_since(now)
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(c_int::MAX as u128) as c_int,
+            None => -1,
+        };
+
+        let connection = self.connection;
+        let (wake, _) = &self.wake;
+        let mut fds = [
+            PollFd {
+                fd: unsafe { (connection.xlib.XConnectionNumber)(connection.display) },
+                events: POLLIN,
+                revents: 0,
+            },
+            PollFd {
+                fd: wake.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        unsafe {
+            poll(fds.as_mut_ptr(), fds.len() as c_ulong, timeout);
+        }
+
+        let mut buffer = [0; 64];
+        while matches!((&*wake).read(&mut buffer), Ok(n) if n > 0) {}
+    }
+
+    /// Next timer elapsed at `now`, repeating timers are rescheduled.
+    fn next_timer(&self, now: Instant) -> Option<Timer> {
+        let mut timers = self.timers.borrow_mut();
+        let (i, timer) = timers
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, t)| t.deadline <= now)
+            .min_by_key(|(_, t)| t.deadline)?;
+        match timer.interval {
+            // skip missed intervals instead of firing them in a burst
+            Some(interval) => timers[i].deadline = (timer.deadline + interval).max(now),
+            None => {
+                timers.remove(i);
+            }
+        }
+        Some(timer)
+    }
+
+    /// Send queued user events, elapsed timers and due animation frames.
+    fn dispatch(&self) {
+        let user_events = mem::take(&mut *self.user_events.lock().unwrap());
+        for (window, event) in user_events {
+            if let Some(user_data) = self.user_data(window) {
+                user_data.send(Event::User(event));
+            }
+        }
+
+        let now = Instant::now();
+        while let Some(timer) = self.next_timer(now) {
+            if let Some(user_data) = self.user_data(timer.window) {
+                user_data.send(Event::Timer(timer.id));
+            }
+        }
+
+        if self.next_frame().is_some_and(|frame| frame <= now) {
+            self.last_frame.set(now);
+            let frames = mem::take(&mut *self.frames.borrow_mut());
+            for window in frames {
+                if let Some(user_data) = self.user_data(window) {
+                    user_data.send(Event::AnimationFrame);
+                }
+            }
+        }
+    }
+}
+
+struct UserData {
+    shared: Rc<Shared>,
+    surface: Surface,
+    cursor: Cell<Cursor>,
+    mouse_position: Cell<Option<(i32, i32)>>,
+    mouse_buttons: Cell<MouseButtons>,
+    clicks: Cell<ClickCounter>,              // ButtonPress
+    keydown_area: Cell<SurfaceArea>,         // ButtonPress
+    size: Cell<(i32, i32)>,                  // ConfigureNotify
+    dpi: Cell<f64>,                          // PropertyNotify
+    focused: Cell<bool>,                     // FocusIn
+    occluded: Cell<bool>,                    // VisibilityNotify
+    pressed_keys: RefCell<HashSet<u32>>,     // KeyPress
+    preedit: RefCell<Preedit>,               // XIM callbacks
+    compositions: RefCell<Vec<Composition>>, // XIM callbacks
+    drag: RefCell<Option<Drag>>,             // XdndEnter
+}
+
+impl UserData {
+    fn send(&self, event: Event) {
+        let event_loop = EventLoop {
+            surface: self.surface.into(),
+            surfaces: Surfaces {
+                shared: self.shared.clone(),
+            }
+            .into(),
+            clipboard: self.surface.into(),
+            mouse_position: self.mouse_position.get(),
+            mouse_buttons: self.mouse_buttons.get(),
+        };
+        let mut callback = self.shared.event_callback.borrow_mut();
+        let control_flow = callback(event_loop, event);
+        self.shared.control_flow.set(control_flow);
+    }
+
+    fn close(&self) {
+        self.shared.closing.borrow_mut().push(self.surface.window);
+    }
+
+    /// Close the window unless the callback vetoes the request.
+    fn request_close(&self) {
+        let mut close = true;
+        self.send(Event::CloseRequest { close: &mut close });
+        if close {
+            self.close();
+        }
+    }
+
+    /// Send compositions of the preedit callbacks, which are called from
+    /// within Xlib and can't call the event callback directly.
+    fn send_compositions(&self) {
+        let compositions = mem::take(&mut *self.compositions.borrow_mut());
+        for composition in compositions {
+            self.send(Event::Composition(composition));
+        }
+    }
+
+    fn hittest(&self, x: i32, y: i32) -> SurfaceArea {
+        let mut area = SurfaceArea::Client;
+        self.send(Event::Hittest {
+            x,
+            y,
+            area: &mut area,
+        });
+        area
+    }
+
+    fn set_focused(&self, focused: bool) {
+        if self.focused.replace(focused) != focused {
+            self.send(Event::Focus(focused));
+        }
+    }
+
+    fn set_occluded(&self, occluded: bool) {
+        if self.occluded.replace(occluded) != occluded {
+            self.send(Event::Occluded(occluded));
+        }
+    }
+
+    /// Send `Event::DpiChanged` after the `Xft.dpi` resource changed,
+    /// suggesting a rect which keeps the logical size of the window.
+    fn update_dpi(&self) {
+        let dpi = self.surface.dpi();
+        let old_dpi = self.dpi.replace(dpi);
+        if dpi == old_dpi {
+            return;
+        }
+
+        let connection = self.surface.connection;
+        let (mut x, mut y) = (0, 0);
+        unsafe {
+            let mut child = 0;
+            (connection.xlib.XTranslateCoordinates)(
+                connection.display,
+                self.surface.window,
+                connection.root,
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            );
+        }
+        let (width, height) = self.size.get();
+        let scale = dpi / old_dpi;
+        let mut rect = Rect {
+            x,
+            y,
+            width: (width as f64 * scale).round() as i32,
+            height: (height as f64 * scale).round() as i32,
+        };
+        self.send(Event::DpiChanged {
+            dpi,
+            rect: &mut rect,
+        });
+
+        unsafe {
+            (connection.xlib.XMoveResizeWindow)(
+                connection.display,
+                self.surface.window,
+                rect.x,
+                rect.y,
+                rect.width.max(1) as c_uint,
+                rect.height.max(1) as c_uint,
+            );
+            (connection.xlib.XFlush)(connection.display);
+        }
+    }
+
+    fn resize(&self, width: i32, height: i32) {
+        if self.size.replace((width, height)) != (width, height) {
+            self.send(Event::Resize(Extent {
+                width: width as f64,
+                height: height as f64,
+            }));
+        }
+    }
+
+    unsafe fn handle(&self, event: &mut xlib::XEvent) {
+        let connection = self.surface.connection;
+
+        match event.get_type() {
+            // Only paint once for a series of exposed rectangles.
+            xlib::Expose if event.expose.count == 0 => self.send(Event::Paint),
+
+            xlib::ConfigureNotify => {
+                self.resize(event.configure.width, event.configure.height);
+            }
+
+            xlib::ClientMessage => self.on_client_message(&event.client_message),
+
+            // Focus of the pointer window while no window is focused.
+            xlib::FocusIn | xlib::FocusOut if event.focus_change.detail == xlib::NotifyPointer => {}
+            xlib::FocusIn => {
+                if let Some(ic) = connection.input_context(self.surface.window) {
+                    (connection.xlib.XSetICFocus)(ic);
+                }
+                self.set_focused(true);
+            }
+            xlib::FocusOut => {
+                // Key releases are delivered to the newly focused window.
+                self.pressed_keys.borrow_mut().clear();
+                if let Some(ic) = connection.input_context(self.surface.window) {
+                    (connection.xlib.XUnsetICFocus)(ic);
+                }
+                self.set_focused(false);
+            }
+
+            // Minimized windows are unmapped by the window manager.
+            xlib::MapNotify => self.set_occluded(false),
+            xlib::UnmapNotify => self.set_occluded(true),
+            xlib::VisibilityNotify => {
+                self.set_occluded(event.visibility.state == xlib::VisibilityFullyObscured);
+            }
+
+            xlib::KeyPress => self.on_key(&mut event.key, KeyState::Down),
+            xlib::KeyRelease => self.on_key(&mut event.key, KeyState::Up),
+
+            xlib::ButtonPress => self.on_button_press(&event.button),
+            xlib::ButtonRelease => self.on_button_release(&event.button),
+
+            xlib::MotionNotify => {
+                let motion = &event.motion;
+                self.mouse_position.set(Some((motion.x, motion.y)));
+
+                let mut cursor = self.cursor.get();
+                self.send(Event::MouseMove {
+                    cursor: &mut cursor,
+                });
+                if self.cursor.replace(cursor) != cursor {
+                    (connection.xlib.XDefineCursor)(
+                        connection.display,
+                        self.surface.window,
+                        connection.cursor(cursor),
+                    );
+                }
+            }
+
+            // Pointer is still tracked by the implicit grab while buttons are held.
+            xlib::LeaveNotify
+                if event.crossing.mode == xlib::NotifyNormal
+                    && self.mouse_buttons.get().is_empty() =>
+            {
+                self.mouse_position.set(None);
+                self.surface.redraw();
+            }
+
+            _ => (),
+        }
+    }
+
+    unsafe fn on_key(&self, event: &mut xlib::XKeyEvent, state: KeyState) {
+        let connection = self.surface.connection;
+        let xlib = &connection.xlib;
+        let ic = connection.input_context(self.surface.window);
+
+        // Text committed by the input method arrives as key press without keycode.
+        if event.keycode == 0 {
+            if let Some(text) = ic.and_then(|ic| lookup_text(xlib, ic, event)) {
+                if state == KeyState::Down {
+                    self.send(Event::Composition(Composition::Commit(text)));
+                }
+            }
+            return;
+        }
+
+        let scancode = scancode(event.keycode);
+
+        // Auto repeat only generates further key presses.
+        let repeat = {
+            let mut pressed_keys = self.pressed_keys.borrow_mut();
+            match state {
+                KeyState::Down => !pressed_keys.insert(event.keycode),
+                KeyState::Up => {
+                    pressed_keys.remove(&event.keycode);
+                    false
+                }
+            }
+        };
+
+        // The state of the event doesn't include the pressed key itself yet.
+        let mut modifiers = Modifiers::from_state(event.state);
+
+        let mut buffer = [0 as c_char; 16];
+        let mut text_keysym = 0;
+        (xlib.XLookupString)(
+            event,
+            buffer.as_mut_ptr(),
+            buffer.len() as c_int,
+            &mut text_keysym,
+            ptr::null_mut(),
+        );
+        let text_keysym = text_keysym as c_uint;
+
+        // Unshifted key like `MAPVK_VK_TO_CHAR`, which reports letters uppercase.
+        // Keypad keys depend on the Num Lock state instead.
+        let keysym = match text_keysym {
+            0xFF80..=0xFFBD => text_keysym,
+            _ => (xlib.XLookupKeysym)(event, 0) as c_uint,
+        };
+        let key = match (key_code(keysym), keysym_char(keysym)) {
+            (Some(code), _) => {
+                if let Some(modifier) = code.modifier() {
+                    modifiers.set(modifier, state == KeyState::Down);
+                }
+                Key::Code(code)
+            }
+            (None, Some(c)) => Key::Char(c.to_ascii_uppercase()),
+            (None, None) => return,
+        };
+        self.send(Event::Key {
+            key,
+            scancode,
+            state,
+            repeat,
+            modifiers,
+        });
+
+        if state == KeyState::Down {
+            let text = match ic {
+                Some(ic) => lookup_text(xlib, ic, event),
+                None => keysym_char(text_keysym).map(String::from),
+            };
+            for c in text.iter().flat_map(|text| text.chars()) {
+                if !c.is_control() {
+                    self.send(Event::Char(c));
+                }
+            }
+        }
+    }
+
+    fn on_client_message(&self, message: &xlib::XClientMessageEvent) {
+        let atoms = &self.surface.connection.atoms;
+        let data = message.data;
+        let ty = message.message_type;
+
+        if ty == atoms.wm_protocols {
+            if data.get_long(0) as xlib::Atom == atoms.wm_delete_window {
+                self.request_close();
+            }
+        } else if ty == atoms.xdnd_enter {
+            *self.drag.borrow_mut() = Some(Drag {
+                source: data.get_long(0) as xlib::Window,
+                paths: None,
+                accept: false,
+            });
+        } else if ty == atoms.xdnd_position {
+            self.on_xdnd_position(message);
+        } else if ty == atoms.xdnd_leave {
+            self.mouse_position.set(None);
+            let drag = self.drag.take();
+            if drag.is_some_and(|drag| drag.paths.is_some_and(|paths| !paths.is_empty())) {
+                self.send(Event::DragLeave);
+            }
+        } else if ty == atoms.xdnd_drop {
+            let Some(drag) = self.drag.take() else {
+                return;
+            };
+            let paths = drag.paths.unwrap_or_default();
+            if drag.accept {
+                let (x, y) = self.mouse_position.get().unwrap_or_default();
+                self.send(Event::Drop {
+                    paths: &paths,
+                    x,
+                    y,
+                });
+            }
+
+            let action = if drag.accept {
+                atoms.xdnd_action_copy
+            } else {
+                0
+            };
+            self.surface.send_xdnd(
+                drag.source,
+                atoms.xdnd_finished,
+                [drag.accept as c_long, action as c_long, 0, 0],
+            );
+        }
+    }
+
+    fn on_xdnd_position(&self, message: &xlib::XClientMessageEvent) {
+        let atoms = &self.surface.connection.atoms;
+        let data = message.data;
+
+        let mut drag = self.drag.borrow_mut();
+        let Some(drag) = drag.as_mut() else {
+            return;
+        };
+
+        let position = data.get_long(2);
+        let (x, y) = self
+            .surface
+            .translate_root((position >> 16) as i32, (position & 0xFFFF) as i32);
+        self.mouse_position.set(Some((x, y)));
+
+        let entered = drag.paths.is_some();
+        let paths = drag
+            .paths
+            .get_or_insert_with(|| self.surface.drag_paths(data.get_long(3) as xlib::Time));
+
+        let mut accept = false;
+        if !paths.is_empty() {
+            let event = if entered {
+                Event::DragOver {
+                    paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            } else {
+                Event::DragEnter {
+                    paths,
+                    x,
+                    y,
+                    accept: &mut accept,
+                }
+            };
+            self.send(event);
+        }
+        drag.accept = accept;
+
+        // Always ask for further positions, acceptance depends on the position.
+        let action = if accept { atoms.xdnd_action_copy } else { 0 };
+        self.surface.send_xdnd(
+            drag.source,
+            atoms.xdnd_status,
+            [accept as c_long | 0b10, 0, 0, action as c_long],
+        );
+    }
+
+    fn on_button_press(&self, event: &xlib::XButtonEvent) {
+        if let Some(delta) = wheel_delta(event.button) {
+            self.send(Event::Scroll {
+                delta,
+                modifiers: Modifiers::from_state(event.state),
+            });
+            return;
+        }
+
+        let Some(button) = mouse_button(event.button) else {
+            return;
+        };
+
+        let area = self.hittest(event.x, event.y);
+        if button == MouseButtons::LEFT {
+            self.keydown_area.set(area);
+        }
+
+        if area != SurfaceArea::Client {
+            // Client side decorations, dragging is handed over to the window manager.
+            if let Some(direction) = moveresize_direction(area) {
+                if button == MouseButtons::LEFT {
+                    self.surface
+                        .moveresize(event.x_root, event.y_root, direction, event.button);
+                }
+            }
+            return;
+        }
+
+        let mut counter = self.clicks.get();
+        let clicks = counter.press(
+            button,
+            event.time as u32,
+            (event.x, event.y),
+            self.surface.connection.click_interval,
+            CLICK_DISTANCE,
+        );
+        self.clicks.set(counter);
+
+        self.mouse_buttons.set(self.mouse_buttons.get() | button);
+        self.send(Event::MouseButton {
+            button,
+            state: KeyState::Down,
+            clicks,
+            modifiers: Modifiers::from_state(event.state),
+        });
+    }
+
+    fn on_button_release(&self, event: &xlib::XButtonEvent) {
+        let Some(button) = mouse_button(event.button) else {
+            return;
+        };
+
+        if button == MouseButtons::LEFT {
+            let area = self.keydown_area.replace(SurfaceArea::Client);
+            if area != SurfaceArea::Client {
+                // Caption buttons trigger when released over the pressed button.
+                if self.hittest(event.x, event.y) == area {
+                    match area {
+                        SurfaceArea::Minimize => self.surface.minimize(),
+                        SurfaceArea::Maximize => self.surface.toggle_maximize(),
+                        SurfaceArea::Close => self.request_close(),
+                        _ => (),
+                    }
+                }
+                return;
+            }
+        }
+
+        let buttons = self.mouse_buttons.get();
+        if !buttons.contains(button) {
+            return;
+        }
+        self.mouse_buttons.set(buttons - button);
+        self.send(Event::MouseButton {
+            button,
+            state: KeyState::Up,
+            clicks: self.clicks.get().release(button),
+            modifiers: Modifiers::from_state(event.state),
+        });
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Surface {
+    connection: &'static Connection,
+    window: xlib::Window,
+}
+
+impl Surface {
+    pub fn show(&self) {
+        let connection = self.connection;
+        unsafe {
+            (connection.xlib.XMapWindow)(connection.display, self.window);
+            (connection.xlib.XFlush)(connection.display);
+        }
+    }
+
+    pub fn window(self) -> xlib::Window {
+        self.window
+    }
+
+    pub fn id(&self) -> SurfaceId {
+        SurfaceId(self.window as _)
+    }
+
+    pub fn extent(&self) -> Extent {
+        let connection = self.connection;
+        let mut attributes = MaybeUninit::uninit();
+        unsafe {
+            (connection.xlib.XGetWindowAttributes)(
+                connection.display,
+                self.window,
+                attributes.as_mut_ptr(),
+            );
+        }
+        let attributes = unsafe { attributes.assume_init() };
+        Extent {
+            width: attributes.width as f64,
+            height: attributes.height as f64,
+        }
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        let atoms = &self.connection.atoms;
+        let state = self.atom_property(atoms.net_wm_state);
+        state.contains(&atoms.net_wm_state_maximized_vert)
+            && state.contains(&atoms.net_wm_state_maximized_horz)
+    }
+
+    pub fn dpi(&self) -> f64 {
+        let dpi = self.connection.resources().and_then(|resources| {
+            let dpi: f64 = resource(&resources, "Xft.dpi")?.parse().ok()?;
+            (dpi > 0.0).then_some(dpi)
+        });
+        dpi.map_or(1.0, |dpi| dpi / 96.0)
+    }
+
+    pub fn redraw(&self) {
+        let connection = self.connection;
+        unsafe {
+            (connection.xlib.XClearArea)(connection.display, self.window, 0, 0, 0, 0, xlib::True);
+            (connection.xlib.XFlush)(connection.display);
+        }
+    }
+
+    pub fn set_ime_cursor_area(&self, area: Rect) {
+        let connection = self.connection;
+        let Some(ic) = connection.input_context(self.window) else {
+            return;
+        };
+
+        // Candidate windows are placed below the spot, at the bottom of the caret.
+        let mut spot = xlib::XPoint {
+            x: area.x as _,
+            y: (area.y + area.height) as _,
+        };
+        unsafe {
+            let xlib = &connection.xlib;
+            let attributes = (xlib.XVaCreateNestedList)(
+                0,
+                xlib::XNSpotLocation_0.as_ptr(),
+                &mut spot,
+                ptr::null_mut::<c_void>(),
+            );
+            (xlib.XSetICValues)(
+                ic,
+                xlib::XNPreeditAttributes_0.as_ptr(),
+                attributes,
+                ptr::null_mut::<c_void>(),
+            );
+            (xlib.XFree)(attributes);
+            (xlib.XFlush)(connection.display);
+        }
+    }
+
+    pub fn clipboard(&self, mime: &str) -> Option<Vec<u8>> {
+        let connection = self.connection;
+        let atoms = &connection.atoms;
+        let target = connection.clipboard_target(mime);
+
+        unsafe {
+            // Converting our own selection would wait for ourselves.
+            let owner = (connection.xlib.XGetSelectionOwner)(connection.display, atoms.clipboard);
+            if owner == 0 {
+                return None;
+            }
+            if owner == connection.selection_window {
+                let contents = connection.clipboard.borrow();
+                let (_, data) = contents.iter().find(|(ty, _)| *ty == target)?;
+                return Some(data.clone());
+            }
+        }
+
+        connection.convert_selection(atoms.clipboard, target, xlib::CurrentTime)
+    }
+
+    pub fn set_clipboard(&self, contents: &[(&str, &[u8])]) -> Result<(), clipboard::Error> {
+        let connection = self.connection;
+        let xlib = &connection.xlib;
+        let window = connection.selection_window;
+
+        *connection.clipboard.borrow_mut() = contents
+            .iter()
+            .map(|(mime, data)| (connection.clipboard_target(mime), data.to_vec()))
+            .collect();
+
+        unsafe {
+            let clipboard = connection.atoms.clipboard;
+            (xlib.XSetSelectionOwner)(connection.display, clipboard, window, xlib::CurrentTime);
+            if (xlib.XGetSelectionOwner)(connection.display, clipboard) != window {
+                connection.clipboard.borrow_mut().clear();
+                return Err(clipboard::Error::Unavailable);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn atom_property(&self, property: xlib::Atom) -> Vec<xlib::Atom> {
+        let connection = self.connection;
+        let mut ty = 0;
+        let mut format = 0;
+        let mut num_items = 0;
+        let mut bytes_after = 0;
+        let mut data = ptr::null_mut();
+
+        unsafe {
+            let status = (connection.xlib.XGetWindowProperty)(
+                connection.display,
+                self.window,
+                property,
+                0,
+                64,
+                xlib::False,
+                xlib::XA_ATOM,
+                &mut ty,
+                &mut format,
+                &mut num_items,
+                &mut bytes_after,
+                &mut data,
+            );
+            if data.is_null() {
+                return Vec::new();
+            }
+
+            // Format 32 properties are returned as arrays of `long`.
+            let atoms = if status == xlib::Success as c_int && format == 32 {
+                slice::from_raw_parts(data as *const xlib::Atom, num_items as usize).to_vec()
+            } else {
+                Vec::new()
+            };
+            (connection.xlib.XFree)(data as *mut c_void);
+            atoms
+        }
+    }
+
+    /// Send a client message to the window manager.
+    fn send_message(&self, message_type: xlib::Atom, data: [c_long; 5]) {
+        self.send_client_message(
+            self.connection.root,
+            self.window,
+            xlib::SubstructureRedirectMask | xlib::SubstructureNotifyMask,
+            message_type,
+            data,
+        );
+    }
+
+    fn send_client_message(
+        &self,
+        destination: xlib::Window,
+        window: xlib::Window,
+        mask: c_long,
+        message_type: xlib::Atom,
+        data: [c_long; 5],
+    ) {
+        let connection = self.connection;
+        unsafe {
+            let mut event: xlib::XEvent = mem::zeroed();
+            let message = &mut event.client_message;
+            message.type_ = xlib::ClientMessage;
+            message.window = window;
+            message.message_type = message_type;
+            message.format = 32;
+            message.data.as_longs_mut().copy_from_slice(&data);
+
+            (connection.xlib.XSendEvent)(
+                connection.display,
+                destination,
+                xlib::False,
+                mask,
+                &mut event,
+            );
+            (connection.xlib.XFlush)(connection.display);
+        }
+    }
+
+    /// Send a message of the XDND protocol to the drag source.
+    fn send_xdnd(&self, source: xlib::Window, message_type: xlib::Atom, data: [c_long; 4]) {
+        let [a, b, c, d] = data;
+        self.send_client_message(
+            source,
+            source,
+            xlib::NoEventMask,
+            message_type,
+            [self.window as c_long, a, b, c, d],
+        );
+    }
+
+    /// Window coordinates of a position on the root window.
+    fn translate_root(&self, x_root: i32, y_root: i32) -> (i32, i32) {
+        let connection = self.connection;
+        let (mut x, mut y, mut child) = (x_root, y_root, 0);
+        unsafe {
+            (connection.xlib.XTranslateCoordinates)(
+                connection.display,
+                connection.root,
+                self.window,
+                x_root,
+                y_root,
+                &mut x,
+                &mut y,
+                &mut child,
+            );
+        }
+        (x, y)
+    }
+
+    /// Paths of the files dragged by the XDND source.
+    fn drag_paths(&self, time: xlib::Time) -> Vec<PathBuf> {
+        let connection = self.connection;
+        let atoms = &connection.atoms;
+        connection
+            .convert_selection(atoms.xdnd_selection, atoms.text_uri_list, time)
+            .map(|list| uri_list_paths(&list))
+            .unwrap_or_default()
+    }
+
+    fn moveresize(&self, x_root: i32, y_root: i32, direction: c_long, button: c_uint) {
+        let connection = self.connection;
+        unsafe {
+            // The window manager can't grab the pointer during our implicit grab.
+            (connection.xlib.XUngrabPointer)(connection.display, xlib::CurrentTime);
+        }
+        self.send_message(
+            connection.atoms.net_wm_moveresize,
+            [x_root as _, y_root as _, direction, button as _, 1],
+        );
+    }
+
+    fn minimize(&self) {
+        let connection = self.connection;
+        unsafe {
+            (connection.xlib.XIconifyWindow)(connection.display, self.window, connection.screen);
+            (connection.xlib.XFlush)(connection.display);
+        }
+    }
+
+    fn toggle_maximize(&self) {
+        let atoms = &self.connection.atoms;
+        self.send_message(
+            atoms.net_wm_state,
+            [
+                NET_WM_STATE_TOGGLE,
+                atoms.net_wm_state_maximized_vert as _,
+                atoms.net_wm_state_maximized_horz as _,
+                1,
+                0,
+            ],
+        );
+    }
+}
+
+/// Creates and closes the windows of the platform.
+#[derive(Clone)]
+pub struct Surfaces {
+    shared: Rc<Shared>,
+}
+
+impl Surfaces {
+    pub fn create(&self) -> Surface {
+        let connection = self.shared.connection;
+        let xlib = &connection.xlib;
+        let display = connection.display;
+        let atoms = &connection.atoms;
+
+        let window = unsafe {
+            let mut attributes: xlib::XSetWindowAttributes = mem::zeroed();
+            // no background, avoids flickering on expose
+            attributes.background_pixmap = 0;
+            attributes.event_mask = xlib::ExposureMask
+                | xlib::StructureNotifyMask
+                | xlib::KeyPressMask
+                | xlib::KeyReleaseMask
+                | xlib::ButtonPressMask
+                | xlib::ButtonReleaseMask
+                | xlib::PointerMotionMask
+                | xlib::LeaveWindowMask
+                | xlib::FocusChangeMask
+                | xlib::VisibilityChangeMask;
+
+            (xlib.XCreateWindow)(
+                display,
+                connection.root,
+                0,
+                0,
+                DEFAULT_EXTENT.0,
+                DEFAULT_EXTENT.1,
+                0,
+                xlib::CopyFromParent,
+                xlib::InputOutput as c_uint,
+                ptr::null_mut(),
+                xlib::CWBackPixmap | xlib::CWEventMask,
+                &mut attributes,
+            )
+        };
+
+        unsafe {
+            let mut protocols = [atoms.wm_delete_window];
+            (xlib.XSetWMProtocols)(display, window, protocols.as_mut_ptr(), 1);
+
+            let title = c"nari";
+            (xlib.XStoreName)(display, window, title.as_ptr());
+            (xlib.XChangeProperty)(
+                display,
+                window,
+                atoms.net_wm_name,
+                atoms.utf8_string,
+                8,
+                xlib::PropModeReplace,
+                title.as_ptr() as *const _,
+                title.to_bytes().len() as c_int,
+            );
+
+            let version = [XDND_VERSION];
+            (xlib.XChangeProperty)(
+                display,
+                window,
+                atoms.xdnd_aware,
+                xlib::XA_ATOM,
+                32,
+                xlib::PropModeReplace,
+                version.as_ptr() as *const _,
+                1,
+            );
+
+            // Remove server side decorations: flags = MWM_HINTS_DECORATIONS, decorations = 0
+            let hints: [c_long; 5] = [2, 0, 0, 0, 0];
+            (xlib.XChangeProperty)(
+                display,
+                window,
+                atoms.motif_wm_hints,
+                atoms.motif_wm_hints,
+                32,
+                xlib::PropModeReplace,
+                hints.as_ptr() as *const _,
+                hints.len() as c_int,
+            );
+        }
+
+        let surface = Surface { connection, window };
+
+        let user_data = Rc::new(UserData {
+            shared: self.shared.clone(),
+            surface,
+            cursor: Cell::new(Cursor::Default),
+            mouse_position: Cell::new(None),
+            mouse_buttons: Cell::new(MouseButtons::empty()),
+            clicks: Cell::new(ClickCounter::default()),
+            keydown_area: Cell::new(SurfaceArea::Client),
+            size: Cell::new((0, 0)),
+            dpi: Cell::new(surface.dpi()),
+            focused: Cell::new(false),
+            occluded: Cell::new(false),
+            pressed_keys: RefCell::new(HashSet::new()),
+            preedit: RefCell::new(Preedit::default()),
+            compositions: RefCell::new(Vec::new()),
+            drag: RefCell::new(None),
+        });
+
+        unsafe {
+            connection.create_ic(window, Rc::as_ptr(&user_data));
+        }
+        self.shared.windows.borrow_mut().insert(window, user_data);
+
+        surface
+    }
+
+    pub fn close(&self, surface: SurfaceId) {
+        self.shared.closing.borrow_mut().push(surface.0 as _);
+    }
+
+    pub fn request_animation_frame(&self, surface: SurfaceId) {
+        let mut frames = self.shared.frames.borrow_mut();
+        let window = surface.0 as xlib::Window;
+        if !frames.contains(&window) {
+            frames.push(window);
+        }
+    }
+
+    pub fn set_timer(&self, surface: SurfaceId, interval: Duration, repeat: bool) -> TimerId {
+        let id = TimerId(self.shared.next_timer.get());
+        self.shared.next_timer.set(id.0 + 1);
+        self.shared.timers.borrow_mut().push(Timer {
+            id,
+            window: surface.0 as _,
+            deadline: Instant::now() + interval,
+            interval: repeat.then_some(interval),
+        });
+        id
+    }
+
+    pub fn cancel_timer(&self, timer: TimerId) {
+        self.shared.timers.borrow_mut().retain(|t| t.id != timer);
+    }
+
+    pub fn create_proxy(&self, surface: SurfaceId) -> Proxy {
+        Proxy {
+            window: surface.0 as _,
+            events: self.shared.user_events.clone(),
+            wake: self.shared.wake.1.clone(),
+        }
+    }
+}
+
+/// Sends user events to a window from other threads.
+#[derive(Clone)]
+pub struct Proxy {
+    window: xlib::Window,
+    events: UserEvents,
+    wake: Arc<UnixStream>,
+}
+
+impl Proxy {
+    pub fn send(&self, event: Box<dyn Any + Send>) {
+        self.events.lock().unwrap().push((self.window, event));
+        // A full socket already wakes up the event loop.
+        let _ = (&*self.wake).write(&[0]);
+    }
+}
+
+pub struct Platform {
+    pub surface: Surface,
+    surfaces: Surfaces,
+}
+
+impl Platform {
+    pub fn new() -> Self {
+        let (wake, waker) = UnixStream::pair().expect("failed to create wake up socket");
+        for socket in [&wake, &waker] {
+            socket
+                .set_nonblocking(true)
+                .expect("failed to create wake up socket");
+        }
+
+        let shared = Rc::new(Shared {
+            connection: Connection::open(),
+            control_flow: Cell::new(ControlFlow::Continue),
+            event_callback: RefCell::new(Box::new(|_, _| ControlFlow::Continue)),
+            windows: RefCell::new(HashMap::new()),
+            closing: RefCell::new(Vec::new()),
+            timers: RefCell::new(Vec::new()),
+            next_timer: Cell::new(1),
+            frames: RefCell::new(Vec::new()),
+            last_frame: Cell::new(Instant::now()),
+            user_events: UserEvents::default(),
+            wake: (wake, Arc::new(waker)),
+        });
+        let surfaces = Surfaces { shared };
+        let surface = surfaces.create();
+
+        Platform { surface, surfaces }
+    }
+
+    pub fn surfaces(&self) -> Surfaces {
+        self.surfaces.clone()
+    }
+
+    pub fn run<F: FnMut(EventLoop, Event) -> ControlFlow + 'static>(self, callback: F) {
+        let shared = &self.surfaces.shared;
+        let _ = shared.event_callback.replace(Box::new(callback));
+
+        self.surface.show();
+
+        // Mapping doesn't generate a `ConfigureNotify` without a window manager.
+        let windows = shared
+            .windows
+            .borrow()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for user_data in windows {
+            let extent = user_data.surface.extent();
+            user_data.resize(extent.width as i32, extent.height as i32);
+        }
+
+        let connection = shared.connection;
+        loop {
+            shared.close_windows();
+            if shared.control_flow.get() == ControlFlow::Exit || shared.windows.borrow().is_empty()
+            {
+                break;
+            }
+
+            unsafe {
+                // Flushes the output buffer and reads events without blocking.
+                if (connection.xlib.XPending)(connection.display) == 0 {
+                    shared.wait();
+                } else {
+                    let mut event = MaybeUninit::uninit();
+                    (connection.xlib.XNextEvent)(connection.display, event.as_mut_ptr());
+                    let mut event = event.assume_init();
+
+                    // Events consumed by the input method.
+                    if (connection.xlib.XFilterEvent)(&mut event, 0) == xlib::False {
+                        shared.handle(&mut event);
+                    }
+                }
+            }
+
+            let windows = shared
+                .windows
+                .borrow()
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+            for user_data in windows {
+                user_data.send_compositions();
+            }
+
+            shared.dispatch();
+        }
+
+        // Remaining windows reference the shared state.
+        let remaining = shared.windows.borrow().keys().copied().collect::<Vec<_>>();
+        shared.closing.borrow_mut().extend(remaining);
+        shared.close_windows();
+    }
+}
+
+impl HasDisplayHandle for Surface {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let handle = XlibDisplayHandle::new(
+            NonNull::new(self.connection.display as *mut c_void),
+            self.connection.screen,
+        );
+        Ok(unsafe { DisplayHandle::borrow_raw(RawDisplayHandle::Xlib(handle)) })
+    }
+}
+
+impl HasWindowHandle for Surface {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let handle = XlibWindowHandle::new(self.window);
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Xlib(handle)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decorations() {
+        assert_eq!(
+            moveresize_direction(SurfaceArea::Caption),
+            Some(MOVERESIZE_MOVE)
+        );
+        assert_eq!(
+            moveresize_direction(SurfaceArea::BottomRight),
+            Some(MOVERESIZE_SIZE_BOTTOMRIGHT)
+        );
+        assert_eq!(moveresize_direction(SurfaceArea::Client), None);
+        assert_eq!(moveresize_direction(SurfaceArea::Close), None);
+    }
+
+    #[test]
+    fn resources() {
+        let resources = "Xft.antialias:\t1\nXft.dpi:\t144\n*multiClickTime:\t250\n";
+        assert_eq!(resource(resources, "Xft.dpi"), Some("144"));
+        assert_eq!(resource(resources, "*multiClickTime"), Some("250"));
+        assert_eq!(resource(resources, "*background"), None);
+    }
+
+    #[test]
+    fn buttons() {
+        assert_eq!(mouse_button(xlib::Button2), Some(MouseButtons::MIDDLE));
+        assert_eq!(mouse_button(8), Some(MouseButtons::BACK));
+        assert_eq!(mouse_button(xlib::Button4), None);
+        assert_eq!(
+            wheel_delta(xlib::Button5),
+            Some(ScrollDelta::Lines {
+                x: 0.0,
+                y: -WHEEL_LINES
+            })
+        );
+        assert_eq!(wheel_delta(xlib::Button1), None);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(key_code(keysym::XK_KP_Left), Some(KeyCode::Left));
+        assert_eq!(key_code(keysym::XK_a), None);
+        assert_eq!(keysym_char(keysym::XK_a), Some('a'));
+        assert_eq!(keysym_char(keysym::XK_adiaeresis), Some('ä'));
+        assert_eq!(keysym_char(keysym::XK_KP_7), Some('7'));
+        assert_eq!(keysym_char(keysym::XK_Return), None);
+        assert_eq!(key_code(keysym::XK_Return), Some(KeyCode::Enter));
+        assert_eq!(key_code(keysym::XK_F12), Some(KeyCode::F12));
+        assert_eq!(
+            key_code(keysym::XK_ISO_Level3_Shift),
+            Some(KeyCode::AltRight)
+        );
+        assert_eq!(
+            key_code(keysym::XK_Super_L).and_then(KeyCode::modifier),
+            Some(Modifiers::SUPER)
+        );
+        assert_eq!(keysym_char(0x0100_20AC), Some('€'));
+        assert_eq!(keysym_char(keysym::XK_Shift_L), None);
+    }
+
+    #[test]
+    fn scancodes() {
+        // keycodes of the evdev driver
+        assert_eq!(scancode(38), ScanCode(0x1E)); // A
+        assert_eq!(scancode(50), ScanCode(0x2A)); // left shift
+        assert_eq!(scancode(113), ScanCode(0xE04B)); // left arrow
+        assert_eq!(scancode(105), ScanCode(0xE01D)); // right control
+    }
+
+    #[test]
+    fn uri_list() {
+        let list = b"# comment\r\nfile:///home/nari/My%20Icon.svg\r\nhttps://example.com/a.svg\r\nfile://host/tmp/%E2%82%AC%zz\r\n";
+        assert_eq!(
+            uri_list_paths(list),
+            [
+                PathBuf::from("/home/nari/My Icon.svg"),
+                PathBuf::from("/tmp/€%zz"),
+            ]
+        );
+    }
+
+    #[test]
+    fn preedit() {
+        let mut preedit = Preedit::default();
+        let chars = "にほんご".chars().collect::<Vec<_>>();
+        preedit.draw(0, 0, Some(&chars), &[]);
+        preedit.caret = 2;
+        assert_eq!(
+            preedit.composition(),
+            Composition::Update {
+                text: "にほんご".into(),
+                cursor: Some(6..6),
+            }
+        );
+
+        // converted to kanji, with the first clause selected
+        let chars = "日本語".chars().collect::<Vec<_>>();
+        preedit.draw(0, 4, Some(&chars), &[XIM_REVERSE, XIM_REVERSE, 0]);
+        assert_eq!(
+            preedit.composition(),
+            Composition::Update {
+                text: "日本語".into(),
+                cursor: Some(0..6),
+            }
+        );
+
+        // selection moves to the next clause
+        preedit.draw(0, 3, None, &[0, 0, XIM_REVERSE]);
+        assert_eq!(
+            preedit.composition(),
+            Composition::Update {
+                text: "日本語".into(),
+                cursor: Some(6..9),
+            }
+        );
+
+        preedit.draw(1, 10, Some(&[]), &[]);
+        preedit.caret = 5;
+        assert_eq!(
+            preedit.composition(),
+            Composition::Update {
+                text: "日".into(),
+                cursor: Some(3..3),
+            }
+        );
+    }
+
+    /// Requires an X server, e.g. `xvfb-run cargo test`.
+    #[test]
+    fn platform() {
+        if std::env::var_os("DISPLAY").is_none() {
+            return;
+        }
+
+        let platform = Platform::new();
+        let surface = platform.surface;
+        assert!(surface.dpi() > 0.0);
+        assert!(!surface.is_maximized());
+        assert!(surface.window_handle().is_ok());
+
+        surface
+            .set_clipboard(&[(clipboard::TEXT, b"nari")])
+            .unwrap();
+        assert_eq!(surface.clipboard(clipboard::TEXT), Some(b"nari".to_vec()));
+
+        let inspector = platform.surfaces().create();
+        assert_ne!(inspector.id(), surface.id());
+
+        let resized = Rc::new(Cell::new(None));
+        let closed = Rc::new(Cell::new(0));
+        platform.run({
+            let resized = resized.clone();
+            let closed = closed.clone();
+            move |event_loop, event| {
+                match event {
+                    Event::Resize(extent) if event_loop.surface.id() == surface.id() => {
+                        resized.set(Some((extent.width, extent.height)));
+                        // exits once both surfaces are closed
+                        event_loop.surfaces.close(&event_loop.surface);
+                        event_loop.surfaces.close(&inspector.into());
+                    }
+                    Event::Close => closed.set(closed.get() + 1),
+                    _ => (),
+                }
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(
+            resized.get(),
+            Some((DEFAULT_EXTENT.0 as f64, DEFAULT_EXTENT.1 as f64))
+        );
+        assert_eq!(closed.get(), 2);
+    }
+}
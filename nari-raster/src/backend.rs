@@ -0,0 +1,338 @@
+use crate::scene::{self, Draw, Scene, Style};
+use nari_ochre::{stroke::stroke, Coord, Encoder, FillRule, Rasterizer, Tile, TILE_SIZE};
+use nari_path::{
+    color::ColorSpace,
+    image::{rgbaf32, Image},
+    kurbo, msaa,
+};
+use nari_scene::DisplayList;
+use std::ops::Range;
+use zeno::{Command, Fill, Mask, PathData};
+
+/// Flattening tolerance in device pixels.
+const TOLERANCE: f32 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// nari-ochre analytic coverage.
+    Ochre,
+    /// nari-path sparse multisampling.
+    Msaa,
+    /// zeno as reference.
+    Zeno,
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ochre" => Some(Backend::Ochre),
+            "msaa" => Some(Backend::Msaa),
+            "zeno" => Some(Backend::Zeno),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Ochre => "ochre",
+            Backend::Msaa => "msaa",
+            Backend::Zeno => "zeno",
+        }
+    }
+}
+
+/// Premultiplied sRGB framebuffer.
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[1.0; 4]; width as usize * height as usize],
+        }
+    }
+
+    /// Blend `color` with `coverage` over the pixel, ignores pixels outside.
+    fn blend(&mut self, x: i32, y: i32, coverage: f32, color: [f32; 4]) {
+        if coverage <= 0.0 || x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let alpha = coverage.min(1.0) * color[3];
+        let pixel = &mut self.pixels[y as usize * self.width as usize + x as usize];
+        for c in 0..3 {
+            pixel[c] = color[c] * alpha + pixel[c] * (1.0 - alpha);
+        }
+        pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let alpha = pixel[3];
+                let unpremultiply = |c: f32| if alpha > 0.0 { c / alpha } else { 0.0 };
+                [
+                    (unpremultiply(pixel[0]).clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                    (unpremultiply(pixel[1]).clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                    (unpremultiply(pixel[2]).clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                    (alpha.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                ]
+            })
+            .collect()
+    }
+}
+
+struct CanvasEncoder<'a> {
+    canvas: &'a mut Canvas,
+    color: [f32; 4],
+}
+
+impl Encoder for CanvasEncoder<'_> {
+    fn solid(&mut self, y: Coord, x: Range<Coord>) {
+        for tx in x {
+            for py in 0..TILE_SIZE {
+                for px in 0..TILE_SIZE {
+                    let x = tx as i32 * TILE_SIZE as i32 + px as i32;
+                    let y = y as i32 * TILE_SIZE as i32 + py as i32;
+                    self.canvas.blend(x, y, 1.0, self.color);
+                }
+            }
+        }
+    }
+
+    fn mask(&mut self, y: Coord, x: Coord, mask: &Tile<u8>) {
+        for (py, row) in mask.iter().enumerate() {
+            for (px, coverage) in row.iter().enumerate() {
+                let x = x as i32 * TILE_SIZE as i32 + px as i32;
+                let y = y as i32 * TILE_SIZE as i32 + py as i32;
+                self.canvas
+                    .blend(x, y, *coverage as f32 / 255.0, self.color);
+            }
+        }
+    }
+}
+
+/// Outline of the draw in device space, strokes are expanded by the
+/// nari-ochre stroker.
+fn outline(draw: &Draw) -> Vec<Command> {
+    match draw.style {
+        Style::Fill { .. } => scene::transform(&draw.path, &draw.transform),
+        Style::Stroke(ref style) => {
+            let t = &draw.transform;
+            let scale = (t.xx * t.yy - t.xy * t.yx).abs().sqrt().max(1e-6);
+            let mut outline = Vec::new();
+            stroke(
+                &draw.path[..],
+                &style.style(),
+                TOLERANCE / scale,
+                &mut outline,
+            );
+            scene::transform(&outline, t)
+        }
+    }
+}
+
+/// Fill rule of the outline, stroke outlines are filled with nonzero.
+fn fill_rule(draw: &Draw) -> FillRule {
+    match draw.style {
+        Style::Fill { even_odd: true } => FillRule::EvenOdd,
+        Style::Fill { even_odd: false } | Style::Stroke(_) => FillRule::NonZero,
+    }
+}
+
+fn kurbo_path(path: &[Command]) -> Vec<kurbo::PathEl> {
+    let p = |p: zeno::Point| kurbo::Point::new(p.x as f64, p.y as f64);
+    path.iter()
+        .map(|command| match *command {
+            Command::MoveTo(p0) => kurbo::PathEl::MoveTo(p(p0)),
+            Command::LineTo(p0) => kurbo::PathEl::LineTo(p(p0)),
+            Command::QuadTo(p1, p2) => kurbo::PathEl::QuadTo(p(p1), p(p2)),
+            Command::CurveTo(p1, p2, p3) => kurbo::PathEl::CurveTo(p(p1), p(p2), p(p3)),
+            Command::Close => kurbo::PathEl::ClosePath,
+        })
+        .collect()
+}
+
+/// Render all draws of the scene onto a white canvas.
+pub fn render(backend: Backend, scene: &Scene) -> anyhow::Result<Canvas> {
+    let mut canvas = Canvas::new(scene.width, scene.height);
+    let mut rasterizer = Rasterizer::default();
+    let mut mask = Vec::new();
+
+    for draw in &scene.draws {
+        match backend {
+            Backend::Ochre => {
+                rasterizer.set_tolerance(TOLERANCE);
+                rasterizer.set_fill_rule(fill_rule(draw));
+                rasterizer.begin();
+                let outline = outline(draw);
+                (&outline[..]).copy_to(&mut rasterizer);
+                rasterizer.end(&mut CanvasEncoder {
+                    canvas: &mut canvas,
+                    color: draw.color,
+                })?;
+            }
+            Backend::Msaa => {
+                let mut rasterizer = msaa::Rasterizer::new(scene.width, scene.height);
+                rasterizer.set_fill_rule(fill_rule(draw));
+                rasterizer.fill(kurbo_path(&outline(draw)), TOLERANCE as f64);
+                let raster = rasterizer.finish();
+                for quads in raster.quads.chunk_by(|a, b| a.x == b.x && a.y == b.y) {
+                    let coverage = raster.resolve_quad(quads);
+                    for (i, coverage) in coverage.into_iter().enumerate() {
+                        let x = (quads[0].x as u32 * msaa::QUAD_SIZE) as i32;
+                        let y = (quads[0].y as u32 * msaa::QUAD_SIZE) as i32;
+                        let (ix, iy) = (i as u32 % msaa::QUAD_SIZE, i as u32 / msaa::QUAD_SIZE);
+                        canvas.blend(x + ix as i32, y + iy as i32, coverage, draw.color);
+                    }
+                }
+            }
+            Backend::Zeno => {
+                mask.clear();
+                mask.resize(scene.width as usize * scene.height as usize, 0u8);
+                let mut render = Mask::new(&draw.path[..]);
+                render
+                    .transform(Some(draw.transform))
+                    .size(scene.width, scene.height);
+                match draw.style {
+                    Style::Fill { even_odd } => {
+                        render.style(if even_odd {
+                            Fill::EvenOdd
+                        } else {
+                            Fill::NonZero
+                        });
+                    }
+                    Style::Stroke(ref style) => {
+                        render.style(style.style());
+                    }
+                }
+                render.render_into(&mut mask, None);
+
+                for (i, coverage) in mask.iter().enumerate() {
+                    let x = (i as u32 % scene.width) as i32;
+                    let y = (i as u32 / scene.width) as i32;
+                    canvas.blend(x, y, *coverage as f32 / 255.0, draw.color);
+                }
+            }
+        }
+    }
+
+    Ok(canvas)
+}
+
+/// Replay a display list onto a white canvas.
+///
+/// Display lists are replayed by the nari-path renderer, which rasterizes
+/// with nari-ochre. The msaa and zeno backends only produce coverage of
+/// single paths, without paints, clips or layers, so they are rejected.
+pub fn render_list(backend: Backend, list: &DisplayList) -> anyhow::Result<Canvas> {
+    if backend != Backend::Ochre {
+        anyhow::bail!(
+            "display lists are only replayed with the ochre backend, not {}",
+            backend.name()
+        );
+    }
+    let target =
+        Image::new(list.width, list.height, rgbaf32::WHITE).with_color_space(ColorSpace::Srgb);
+    let image = nari_path::render::render(list, target)?;
+    Ok(Canvas {
+        width: image.width,
+        height: image.height,
+        pixels: image.memory.iter().map(|c| [c.r, c.g, c.b, c.a]).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="48">
+        <rect x="4" y="4" width="24" height="16" fill="red"/>
+        <circle cx="40" cy="28" r="14" fill="blue" fill-opacity="0.5"/>
+        <path d="M4 40 L60 44" fill="none" stroke="black" stroke-width="3"/>
+    </svg>"#;
+
+    /// Self-overlapping star, the center is a hole with the even-odd rule.
+    const STAR: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="64">
+        <path d="M32 2 L49.6 56.3 L3.5 22.7 L60.5 22.7 L14.4 56.3 Z" fill="green"
+            fill-rule="evenodd"/>
+    </svg>"#;
+
+    #[test]
+    fn backends() {
+        let scene = Scene::from_svg(SVG, 2.0).unwrap();
+        assert_eq!((scene.width, scene.height), (128, 96));
+        assert_eq!(scene.draws.len(), 3);
+
+        let reference = render(Backend::Zeno, &scene).unwrap().to_rgba8();
+        for backend in [Backend::Ochre, Backend::Msaa] {
+            let image = render(backend, &scene).unwrap().to_rgba8();
+
+            // pixel aligned rect interior and background match exactly
+            let pixel = |image: &[u8], x: usize, y: usize| {
+                let i = (y * 128 + x) * 4;
+                [image[i], image[i + 1], image[i + 2], image[i + 3]]
+            };
+            assert_eq!(pixel(&image, 20, 20), [255, 0, 0, 255]);
+            assert_eq!(pixel(&image, 120, 4), [255, 255, 255, 255]);
+
+            let error = image
+                .iter()
+                .zip(&reference)
+                .map(|(a, b)| (*a as f32 - *b as f32).abs())
+                .sum::<f32>()
+                / image.len() as f32;
+            assert!(error < 1.0, "{}: mean error {}", backend.name(), error);
+        }
+    }
+
+    #[test]
+    fn even_odd() {
+        let scene = Scene::from_svg(STAR, 1.0).unwrap();
+        let reference = render(Backend::Zeno, &scene).unwrap().to_rgba8();
+        for backend in [Backend::Ochre, Backend::Msaa] {
+            let image = render(backend, &scene).unwrap().to_rgba8();
+            let pixel = |image: &[u8], x: usize, y: usize| {
+                let i = (y * 64 + x) * 4;
+                [image[i], image[i + 1], image[i + 2], image[i + 3]]
+            };
+            assert_eq!(pixel(&image, 32, 34), [255, 255, 255, 255]);
+            assert_eq!(pixel(&image, 32, 12), pixel(&reference, 32, 12));
+
+            let error = image
+                .iter()
+                .zip(&reference)
+                .map(|(a, b)| (*a as f32 - *b as f32).abs())
+                .sum::<f32>()
+                / image.len() as f32;
+            assert!(error < 1.0, "{}: mean error {}", backend.name(), error);
+        }
+    }
+
+    #[test]
+    fn display_list() {
+        use nari_scene::{kurbo::Affine, kurbo::Rect, Color, FillRule};
+
+        let mut list = DisplayList::new(16, 8);
+        list.fill(
+            FillRule::NonZero,
+            Affine::IDENTITY,
+            Color::rgb(1.0, 0.0, 0.0),
+            None,
+            &Rect::new(0.0, 0.0, 8.0, 8.0),
+        );
+        let list = DisplayList::decode(&list.encode()).unwrap();
+
+        let rgba = render_list(Backend::Ochre, &list).unwrap().to_rgba8();
+        assert_eq!(rgba[..4], [255, 0, 0, 255]);
+        assert_eq!(rgba[(8 * 4)..(8 * 4 + 4)], [255, 255, 255, 255]);
+        for backend in [Backend::Msaa, Backend::Zeno] {
+            assert!(render_list(backend, &list).is_err());
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Render an SVG with one of the CPU rasterizers and write a PNG plus a JSON
+//! timing report, without requiring a display.
+//!
+//! ```text
+//! nari-raster <input.svg> [--backend ochre|msaa|zeno] [--scale <factor>]
+//!     [--output <file.png>] [--report <file.json>] [--iterations <n>]
+//! ```
+//!
+//! The report is written to stdout if no report file is given. Display lists
+//! captured by the studio (`NARI_CAPTURE`) are replayed at their recorded
+//! size with the ochre backend, the other backends reject them.
+
+mod backend;
+mod scene;
+
+use anyhow::{anyhow, bail, Context};
+use backend::{Backend, Canvas};
+use nari_scene::DisplayList;
+use scene::Scene;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: nari-raster <input.svg> [--backend ochre|msaa|zeno] [--scale <factor>] [--output <file.png>] [--report <file.json>] [--iterations <n>]";
+
+struct Args {
+    input: String,
+    backend: Backend,
+    scale: f32,
+    output: Option<String>,
+    report: Option<String>,
+    iterations: usize,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let mut input = None;
+    let mut backend = Backend::Ochre;
+    let mut scale: f32 = 1.0;
+    let mut output = None;
+    let mut report = None;
+    let mut iterations = 1;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--backend" => {
+                let name = value()?;
+                backend =
+                    Backend::parse(&name).ok_or_else(|| anyhow!("unknown backend {}", name))?;
+            }
+            "--scale" => scale = value()?.parse().context("invalid scale")?,
+            "--output" => output = Some(value()?),
+            "--report" => report = Some(value()?),
+            "--iterations" => iterations = value()?.parse().context("invalid iterations")?,
+            "--help" | "-h" => bail!(USAGE),
+            _ if arg.starts_with("--") => bail!("unknown option {}\n{}", arg, USAGE),
+            _ if input.is_none() => input = Some(arg),
+            _ => bail!("unexpected argument {}\n{}", arg, USAGE),
+        }
+    }
+
+    if scale.is_nan() || scale <= 0.0 {
+        bail!("scale must be positive");
+    }
+
+    Ok(Args {
+        input: input.ok_or_else(|| anyhow!(USAGE))?,
+        backend,
+        scale,
+        output,
+        report,
+        iterations: iterations.max(1),
+    })
+}
+
+enum Input {
+    Svg(Scene),
+    List(DisplayList),
+}
+
+impl Input {
+    fn parse(data: &[u8], scale: f32) -> anyhow::Result<Self> {
+        if DisplayList::is_encoded(data) {
+            if scale != 1.0 {
+                bail!("display lists can't be scaled");
+            }
+            return Ok(Input::List(DisplayList::decode(data)?));
+        }
+        let data = std::str::from_utf8(data).context("invalid svg")?;
+        Ok(Input::Svg(Scene::from_svg(data, scale)?))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Input::Svg(scene) => (scene.width, scene.height),
+            Input::List(list) => (list.width, list.height),
+        }
+    }
+
+    fn draws(&self) -> usize {
+        match self {
+            Input::Svg(scene) => scene.draws.len(),
+            Input::List(list) => list.commands().len(),
+        }
+    }
+
+    fn render(&self, backend: Backend) -> anyhow::Result<Canvas> {
+        match self {
+            Input::Svg(scene) => backend::render(backend, scene),
+            Input::List(list) => backend::render_list(backend, list),
+        }
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Escape a string for a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("{}.{}.png", args.input, args.backend.name()));
+
+    let data =
+        std::fs::read(&args.input).with_context(|| format!("failed to read {}", args.input))?;
+
+    let start = Instant::now();
+    let input = Input::parse(&data, args.scale)?;
+    let parse = start.elapsed();
+
+    let mut render = Vec::with_capacity(args.iterations);
+    let mut canvas = None;
+    for _ in 0..args.iterations {
+        let start = Instant::now();
+        canvas = Some(input.render(args.backend)?);
+        render.push(start.elapsed());
+    }
+    let canvas = canvas.unwrap();
+
+    let start = Instant::now();
+    image::save_buffer(
+        &output,
+        &canvas.to_rgba8(),
+        canvas.width,
+        canvas.height,
+        image::ColorType::Rgba8,
+    )
+    .with_context(|| format!("failed to write {}", output))?;
+    let encode = start.elapsed();
+
+    let total: Duration = render.iter().sum();
+    let (width, height) = input.size();
+    let report = format!(
+        concat!(
+            "{{\n",
+            "  \"input\": {},\n",
+            "  \"output\": {},\n",
+            "  \"backend\": {},\n",
+            "  \"scale\": {},\n",
+            "  \"width\": {},\n",
+            "  \"height\": {},\n",
+            "  \"draws\": {},\n",
+            "  \"iterations\": {},\n",
+            "  \"parse_ms\": {:.3},\n",
+            "  \"render_ms\": {{ \"min\": {:.3}, \"mean\": {:.3}, \"max\": {:.3} }},\n",
+            "  \"encode_ms\": {:.3}\n",
+            "}}\n"
+        ),
+        json_string(&args.input),
+        json_string(&output),
+        json_string(args.backend.name()),
+        args.scale,
+        width,
+        height,
+        input.draws(),
+        args.iterations,
+        ms(parse),
+        ms(*render.iter().min().unwrap()),
+        ms(total) / args.iterations as f64,
+        ms(*render.iter().max().unwrap()),
+        ms(encode),
+    );
+
+    match args.report {
+        Some(path) => {
+            std::fs::write(&path, report).with_context(|| format!("failed to write {}", path))?
+        }
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> anyhow::Result<Args> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn arguments() {
+        let parsed = args(&["in.svg"]).unwrap();
+        assert_eq!(parsed.input, "in.svg");
+        assert_eq!(parsed.backend, Backend::Ochre);
+        assert_eq!((parsed.scale, parsed.iterations), (1.0, 1));
+
+        let parsed = args(&["--backend", "msaa", "in.svg", "--scale", "2.5"]).unwrap();
+        assert_eq!(parsed.backend, Backend::Msaa);
+        assert_eq!(parsed.scale, 2.5);
+
+        assert!(args(&[]).is_err());
+        assert!(args(&["in.svg", "--backend", "gpu"]).is_err());
+        assert!(args(&["in.svg", "--scale", "-1"]).is_err());
+        assert!(args(&["in.svg", "--scale"]).is_err());
+        assert!(args(&["in.svg", "out.svg"]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(json_string("\n"), r#""\u000a""#);
+    }
+}
@@ -0,0 +1,182 @@
+use usvg::NodeExt;
+use zeno::{Cap, Command, Join, Point, Transform};
+
+/// Path outline of a draw.
+#[derive(Clone, Debug)]
+pub enum Style {
+    Fill { even_odd: bool },
+    Stroke(Stroke),
+}
+
+#[derive(Clone, Debug)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: Join,
+    pub miter_limit: f32,
+    pub cap: Cap,
+    pub dashes: Vec<f32>,
+    pub offset: f32,
+}
+
+impl Stroke {
+    pub fn style(&self) -> zeno::Stroke<'_> {
+        zeno::Stroke {
+            width: self.width,
+            join: self.join,
+            miter_limit: self.miter_limit,
+            start_cap: self.cap,
+            end_cap: self.cap,
+            dashes: &self.dashes,
+            offset: self.offset,
+            scale: true,
+        }
+    }
+}
+
+/// Single path draw of the scene in user space.
+#[derive(Clone, Debug)]
+pub struct Draw {
+    pub path: Vec<Command>,
+    /// User to device space transform.
+    pub transform: Transform,
+    pub style: Style,
+    /// Non-premultiplied sRGB color with alpha.
+    pub color: [f32; 4],
+}
+
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub draws: Vec<Draw>,
+}
+
+impl Scene {
+    /// Load an SVG document rendered at `scale`.
+    ///
+    /// Paint servers are not supported and replaced by black.
+    pub fn from_svg(data: &str, scale: f32) -> anyhow::Result<Self> {
+        let tree = usvg::Tree::from_str(data, &usvg::Options::default().to_ref())?;
+        let svg = tree.svg_node();
+        let width = (svg.size.width() as f32 * scale).ceil() as u32;
+        let height = (svg.size.height() as f32 * scale).ceil() as u32;
+
+        let mut view = usvg::Transform::new_scale(scale as f64, scale as f64);
+        view.append(&usvg::utils::view_box_to_transform(
+            svg.view_box.rect,
+            svg.view_box.aspect,
+            svg.size,
+        ));
+
+        let mut draws = Vec::new();
+        for node in tree.root().descendants() {
+            if let usvg::NodeKind::Path(ref path) = *node.borrow() {
+                if path.visibility != usvg::Visibility::Visible {
+                    continue;
+                }
+
+                let mut transform = view;
+                transform.append(&node.abs_transform());
+                let transform = Transform::new(
+                    transform.a as f32,
+                    transform.b as f32,
+                    transform.c as f32,
+                    transform.d as f32,
+                    transform.e as f32,
+                    transform.f as f32,
+                );
+                let commands = commands(&path.data);
+
+                if let Some(ref fill) = path.fill {
+                    draws.push(Draw {
+                        path: commands.clone(),
+                        transform,
+                        style: Style::Fill {
+                            even_odd: fill.rule == usvg::FillRule::EvenOdd,
+                        },
+                        color: color(&fill.paint, fill.opacity.value()),
+                    });
+                }
+
+                if let Some(ref stroke) = path.stroke {
+                    draws.push(Draw {
+                        path: commands,
+                        transform,
+                        style: Style::Stroke(Stroke {
+                            width: stroke.width.value() as f32,
+                            join: match stroke.linejoin {
+                                usvg::LineJoin::Miter => Join::Miter,
+                                usvg::LineJoin::Round => Join::Round,
+                                usvg::LineJoin::Bevel => Join::Bevel,
+                            },
+                            miter_limit: stroke.miterlimit.value() as f32,
+                            cap: match stroke.linecap {
+                                usvg::LineCap::Butt => Cap::Butt,
+                                usvg::LineCap::Round => Cap::Round,
+                                usvg::LineCap::Square => Cap::Square,
+                            },
+                            dashes: stroke
+                                .dasharray
+                                .iter()
+                                .flatten()
+                                .map(|dash| *dash as f32)
+                                .collect(),
+                            offset: stroke.dashoffset,
+                        }),
+                        color: color(&stroke.paint, stroke.opacity.value()),
+                    });
+                }
+            }
+        }
+
+        Ok(Scene {
+            width,
+            height,
+            draws,
+        })
+    }
+}
+
+fn color(paint: &usvg::Paint, opacity: f64) -> [f32; 4] {
+    match paint {
+        usvg::Paint::Color(c) => [
+            c.red as f32 / 255.0,
+            c.green as f32 / 255.0,
+            c.blue as f32 / 255.0,
+            opacity as f32,
+        ],
+        usvg::Paint::Link(_) => [0.0, 0.0, 0.0, opacity as f32],
+    }
+}
+
+fn commands(data: &usvg::PathData) -> Vec<Command> {
+    let point = |x: f64, y: f64| Point::new(x as f32, y as f32);
+    data.iter()
+        .map(|segment| match *segment {
+            usvg::PathSegment::MoveTo { x, y } => Command::MoveTo(point(x, y)),
+            usvg::PathSegment::LineTo { x, y } => Command::LineTo(point(x, y)),
+            usvg::PathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => Command::CurveTo(point(x1, y1), point(x2, y2), point(x, y)),
+            usvg::PathSegment::ClosePath => Command::Close,
+        })
+        .collect()
+}
+
+/// Apply the transform to all points of the path.
+pub fn transform(path: &[Command], transform: &Transform) -> Vec<Command> {
+    let t = |p: Point| transform.transform_point(p);
+    path.iter()
+        .map(|command| match *command {
+            Command::MoveTo(p) => Command::MoveTo(t(p)),
+            Command::LineTo(p) => Command::LineTo(t(p)),
+            Command::QuadTo(p1, p2) => Command::QuadTo(t(p1), t(p2)),
+            Command::CurveTo(p1, p2, p3) => Command::CurveTo(t(p1), t(p2), t(p3)),
+            Command::Close => Command::Close,
+        })
+        .collect()
+}
@@ -0,0 +1,773 @@
+//! Binary on-disk form of display lists.
+//!
+//! Little endian, starting with `MAGIC` and `VERSION`, followed by the
+//! canvas size, images, glyph outlines and commands. Arrays are prefixed
+//! with their length as `u32`.
+
+use crate::{
+    BlendMode, Brush, Color, ColorStop, Command, Compose, DisplayList, Extend, FillRule, Glyph,
+    GlyphId, Gradient, GradientKind, Image, ImageId, Mix,
+};
+use kurbo::{Affine, BezPath, Cap, Join, PathEl, Point, Stroke};
+use std::collections::HashMap;
+
+const MAGIC: [u8; 4] = *b"NARI";
+const VERSION: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Data doesn't start with the display list magic.
+    InvalidMagic,
+    /// Display list was written by an incompatible version.
+    UnsupportedVersion(u32),
+    /// Data ends in the middle of the display list.
+    UnexpectedEnd,
+    /// Unknown enum tag.
+    InvalidTag(u8),
+    /// Reference to a missing image or glyph, or inconsistent image size.
+    InvalidData,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidMagic => write!(f, "not a display list"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported display list version {}", version)
+            }
+            Error::UnexpectedEnd => write!(f, "unexpected end of display list"),
+            Error::InvalidTag(tag) => write!(f, "invalid tag {} in display list", tag),
+            Error::InvalidData => write!(f, "invalid display list data"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn point(&mut self, p: Point) {
+        self.f64(p.x);
+        self.f64(p.y);
+    }
+
+    fn affine(&mut self, affine: &Affine) {
+        for v in affine.as_coeffs() {
+            self.f64(v);
+        }
+    }
+
+    fn color(&mut self, color: Color) {
+        for v in [color.r, color.g, color.b, color.a] {
+            self.f32(v);
+        }
+    }
+
+    fn path(&mut self, path: &BezPath) {
+        self.len(path.elements().len());
+        for element in path.elements() {
+            match *element {
+                PathEl::MoveTo(p) => {
+                    self.u8(0);
+                    self.point(p);
+                }
+                PathEl::LineTo(p) => {
+                    self.u8(1);
+                    self.point(p);
+                }
+                PathEl::QuadTo(p1, p2) => {
+                    self.u8(2);
+                    self.point(p1);
+                    self.point(p2);
+                }
+                PathEl::CurveTo(p1, p2, p3) => {
+                    self.u8(3);
+                    self.point(p1);
+                    self.point(p2);
+                    self.point(p3);
+                }
+                PathEl::ClosePath => self.u8(4),
+            }
+        }
+    }
+
+    fn brush(&mut self, brush: &Brush) {
+        match brush {
+            Brush::Solid(color) => {
+                self.u8(0);
+                self.color(*color);
+            }
+            Brush::Gradient(gradient) => {
+                self.u8(1);
+                match gradient.kind {
+                    GradientKind::Linear { start, end } => {
+                        self.u8(0);
+                        self.point(start);
+                        self.point(end);
+                    }
+                    GradientKind::Radial {
+                        center,
+                        radius,
+                        focal,
+                    } => {
+                        self.u8(1);
+                        self.point(center);
+                        self.f64(radius);
+                        self.point(focal);
+                    }
+                    GradientKind::Sweep {
+                        center,
+                        start_angle,
+                        end_angle,
+                    } => {
+                        self.u8(2);
+                        self.point(center);
+                        self.f64(start_angle);
+                        self.f64(end_angle);
+                    }
+                }
+                self.u8(gradient.extend as u8);
+                self.len(gradient.stops.len());
+                for stop in &gradient.stops {
+                    self.f32(stop.offset);
+                    self.color(stop.color);
+                }
+            }
+        }
+    }
+
+    fn brush_transform(&mut self, transform: &Option<Affine>) {
+        match transform {
+            Some(transform) => {
+                self.u8(1);
+                self.affine(transform);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn stroke(&mut self, style: &Stroke) {
+        self.f64(style.width);
+        self.u8(style.join as u8);
+        self.f64(style.miter_limit);
+        self.u8(style.start_cap as u8);
+        self.u8(style.end_cap as u8);
+        self.len(style.dash_pattern.len());
+        for dash in &style.dash_pattern {
+            self.f64(*dash);
+        }
+        self.f64(style.dash_offset);
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Fill {
+                rule,
+                transform,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                self.u8(0);
+                self.u8(*rule as u8);
+                self.affine(transform);
+                self.brush(brush);
+                self.brush_transform(brush_transform);
+                self.path(path);
+            }
+            Command::Stroke {
+                style,
+                transform,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                self.u8(1);
+                self.stroke(style);
+                self.affine(transform);
+                self.brush(brush);
+                self.brush_transform(brush_transform);
+                self.path(path);
+            }
+            Command::PushClip { transform, path } => {
+                self.u8(2);
+                self.affine(transform);
+                self.path(path);
+            }
+            Command::PushLayer { blend, opacity } => {
+                self.u8(3);
+                self.u8(blend.mix as u8);
+                self.u8(blend.compose as u8);
+                self.f32(*opacity);
+            }
+            Command::Pop => self.u8(4),
+            Command::Glyphs {
+                transform,
+                brush,
+                glyphs,
+            } => {
+                self.u8(5);
+                self.affine(transform);
+                self.brush(brush);
+                self.len(glyphs.len());
+                for glyph in glyphs {
+                    self.u32(glyph.id.0);
+                    self.f32(glyph.x);
+                    self.f32(glyph.y);
+                }
+            }
+            Command::Image { image, transform } => {
+                self.u8(6);
+                self.u32(image.0);
+                self.affine(transform);
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.0.len() < N {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    /// Array length, bounded by the remaining data to reject corrupt
+    /// lengths before allocating.
+    fn len(&mut self, min_size: usize) -> Result<usize, Error> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(min_size) > self.0.len() {
+            return Err(Error::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+
+    fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    fn point(&mut self) -> Result<Point, Error> {
+        Ok(Point::new(self.f64()?, self.f64()?))
+    }
+
+    fn affine(&mut self) -> Result<Affine, Error> {
+        let mut coeffs = [0.0; 6];
+        for v in &mut coeffs {
+            *v = self.f64()?;
+        }
+        Ok(Affine::new(coeffs))
+    }
+
+    fn color(&mut self) -> Result<Color, Error> {
+        Ok(Color {
+            r: self.f32()?,
+            g: self.f32()?,
+            b: self.f32()?,
+            a: self.f32()?,
+        })
+    }
+
+    fn path(&mut self) -> Result<BezPath, Error> {
+        let len = self.len(1)?;
+        let mut path = BezPath::new();
+        for _ in 0..len {
+            let element = match self.u8()? {
+                0 => PathEl::MoveTo(self.point()?),
+                1 => PathEl::LineTo(self.point()?),
+                2 => PathEl::QuadTo(self.point()?, self.point()?),
+                3 => PathEl::CurveTo(self.point()?, self.point()?, self.point()?),
+                4 => PathEl::ClosePath,
+                tag => return Err(Error::InvalidTag(tag)),
+            };
+            // Paths start with a move.
+            if path.elements().is_empty() && !matches!(element, PathEl::MoveTo(_)) {
+                return Err(Error::InvalidData);
+            }
+            path.push(element);
+        }
+        Ok(path)
+    }
+
+    fn extend(&mut self) -> Result<Extend, Error> {
+        match self.u8()? {
+            0 => Ok(Extend::Pad),
+            1 => Ok(Extend::Repeat),
+            2 => Ok(Extend::Reflect),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn brush(&mut self) -> Result<Brush, Error> {
+        match self.u8()? {
+            0 => Ok(Brush::Solid(self.color()?)),
+            1 => {
+                let kind = match self.u8()? {
+                    0 => GradientKind::Linear {
+                        start: self.point()?,
+                        end: self.point()?,
+                    },
+                    1 => GradientKind::Radial {
+                        center: self.point()?,
+                        radius: self.f64()?,
+                        focal: self.point()?,
+                    },
+                    2 => GradientKind::Sweep {
+                        center: self.point()?,
+                        start_angle: self.f64()?,
+                        end_angle: self.f64()?,
+                    },
+                    tag => return Err(Error::InvalidTag(tag)),
+                };
+                let extend = self.extend()?;
+                let len = self.len(20)?;
+                let mut stops = Vec::with_capacity(len);
+                for _ in 0..len {
+                    stops.push(ColorStop {
+                        offset: self.f32()?,
+                        color: self.color()?,
+                    });
+                }
+                Ok(Brush::Gradient(Gradient {
+                    kind,
+                    stops,
+                    extend,
+                }))
+            }
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn brush_transform(&mut self) -> Result<Option<Affine>, Error> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.affine()?)),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn fill_rule(&mut self) -> Result<FillRule, Error> {
+        match self.u8()? {
+            0 => Ok(FillRule::NonZero),
+            1 => Ok(FillRule::EvenOdd),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn join(&mut self) -> Result<Join, Error> {
+        match self.u8()? {
+            0 => Ok(Join::Bevel),
+            1 => Ok(Join::Miter),
+            2 => Ok(Join::Round),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn cap(&mut self) -> Result<Cap, Error> {
+        match self.u8()? {
+            0 => Ok(Cap::Butt),
+            1 => Ok(Cap::Square),
+            2 => Ok(Cap::Round),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn stroke(&mut self) -> Result<Stroke, Error> {
+        let width = self.f64()?;
+        let join = self.join()?;
+        let miter_limit = self.f64()?;
+        let start_cap = self.cap()?;
+        let end_cap = self.cap()?;
+        let len = self.len(8)?;
+        let mut dashes = Vec::with_capacity(len);
+        for _ in 0..len {
+            dashes.push(self.f64()?);
+        }
+        let dash_offset = self.f64()?;
+
+        Ok(Stroke {
+            width,
+            join,
+            miter_limit,
+            start_cap,
+            end_cap,
+            dash_pattern: dashes.into_iter().collect(),
+            dash_offset,
+        })
+    }
+
+    fn blend(&mut self) -> Result<BlendMode, Error> {
+        const MIX: [Mix; 16] = [
+            Mix::Normal,
+            Mix::Multiply,
+            Mix::Screen,
+            Mix::Overlay,
+            Mix::Darken,
+            Mix::Lighten,
+            Mix::ColorDodge,
+            Mix::ColorBurn,
+            Mix::HardLight,
+            Mix::SoftLight,
+            Mix::Difference,
+            Mix::Exclusion,
+            Mix::Hue,
+            Mix::Saturation,
+            Mix::Color,
+            Mix::Luminosity,
+        ];
+        const COMPOSE: [Compose; 13] = [
+            Compose::Clear,
+            Compose::Copy,
+            Compose::Dest,
+            Compose::SrcOver,
+            Compose::DestOver,
+            Compose::SrcIn,
+            Compose::DestIn,
+            Compose::SrcOut,
+            Compose::DestOut,
+            Compose::SrcAtop,
+            Compose::DestAtop,
+            Compose::Xor,
+            Compose::Plus,
+        ];
+
+        let mix = self.u8()?;
+        let mix = *MIX.get(mix as usize).ok_or(Error::InvalidTag(mix))?;
+        let compose = self.u8()?;
+        let compose = *COMPOSE
+            .get(compose as usize)
+            .ok_or(Error::InvalidTag(compose))?;
+        Ok(BlendMode { mix, compose })
+    }
+
+    fn command(&mut self, list: &DisplayList) -> Result<Command, Error> {
+        let command = match self.u8()? {
+            0 => Command::Fill {
+                rule: self.fill_rule()?,
+                transform: self.affine()?,
+                brush: self.brush()?,
+                brush_transform: self.brush_transform()?,
+                path: self.path()?,
+            },
+            1 => Command::Stroke {
+                style: self.stroke()?,
+                transform: self.affine()?,
+                brush: self.brush()?,
+                brush_transform: self.brush_transform()?,
+                path: self.path()?,
+            },
+            2 => Command::PushClip {
+                transform: self.affine()?,
+                path: self.path()?,
+            },
+            3 => Command::PushLayer {
+                blend: self.blend()?,
+                opacity: self.f32()?,
+            },
+            4 => Command::Pop,
+            5 => {
+                let transform = self.affine()?;
+                let brush = self.brush()?;
+                let len = self.len(12)?;
+                let mut glyphs = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let id = self.u32()?;
+                    if id as usize >= list.glyphs.len() {
+                        return Err(Error::InvalidData);
+                    }
+                    glyphs.push(Glyph {
+                        id: GlyphId(id),
+                        x: self.f32()?,
+                        y: self.f32()?,
+                    });
+                }
+                Command::Glyphs {
+                    transform,
+                    brush,
+                    glyphs,
+                }
+            }
+            6 => {
+                let image = self.u32()?;
+                if image as usize >= list.images.len() {
+                    return Err(Error::InvalidData);
+                }
+                Command::Image {
+                    image: ImageId(image),
+                    transform: self.affine()?,
+                }
+            }
+            tag => return Err(Error::InvalidTag(tag)),
+        };
+        Ok(command)
+    }
+}
+
+impl DisplayList {
+    /// Serialize into the binary on-disk form.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(&MAGIC);
+        writer.u32(VERSION);
+        writer.u32(self.width);
+        writer.u32(self.height);
+
+        writer.len(self.images.len());
+        for image in &self.images {
+            writer.u32(image.width);
+            writer.u32(image.height);
+            writer.0.extend_from_slice(&image.data);
+        }
+
+        writer.len(self.glyphs.len());
+        for glyph in &self.glyphs {
+            writer.path(glyph);
+        }
+
+        writer.len(self.commands.len());
+        for command in &self.commands {
+            writer.command(command);
+        }
+
+        writer.0
+    }
+
+    /// Deserialize from the binary on-disk form.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(data);
+        if reader.bytes::<4>().map_err(|_| Error::InvalidMagic)? != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut list = DisplayList::new(reader.u32()?, reader.u32()?);
+
+        let len = reader.len(8)?;
+        for _ in 0..len {
+            let width = reader.u32()?;
+            let height = reader.u32()?;
+            let size = (width as usize)
+                .checked_mul(height as usize)
+                .and_then(|size| size.checked_mul(4))
+                .ok_or(Error::InvalidData)?;
+            if size > reader.0.len() {
+                return Err(Error::UnexpectedEnd);
+            }
+            let (data, rest) = reader.0.split_at(size);
+            reader.0 = rest;
+            list.images.push(Image::new(width, height, data));
+        }
+
+        let len = reader.len(4)?;
+        for _ in 0..len {
+            let glyph = reader.path()?;
+            list.glyphs.push(glyph);
+        }
+
+        let len = reader.len(1)?;
+        for _ in 0..len {
+            let command = reader.command(&list)?;
+            list.commands.push(command);
+        }
+
+        // glyph keys are only valid while recording
+        list.glyph_keys = HashMap::new();
+        Ok(list)
+    }
+
+    /// Check if the data starts like an encoded display list.
+    pub fn is_encoded(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kurbo::{Circle, Rect, RoundedRect, Shape};
+
+    fn list() -> DisplayList {
+        let mut list = DisplayList::new(320, 200);
+        list.fill(
+            FillRule::EvenOdd,
+            Affine::translate((10.0, 20.0)),
+            Color::rgba(0.2, 0.4, 0.6, 0.8),
+            None,
+            &RoundedRect::new(0.0, 0.0, 100.0, 50.0, 6.0),
+        );
+        list.stroke(
+            &Stroke::new(2.5)
+                .with_join(Join::Miter)
+                .with_caps(Cap::Square)
+                .with_dashes(1.5, [4.0, 2.0]),
+            Affine::scale(2.0),
+            Gradient {
+                kind: GradientKind::Radial {
+                    center: Point::new(5.0, 6.0),
+                    radius: 7.0,
+                    focal: Point::new(4.0, 6.5),
+                },
+                stops: vec![
+                    ColorStop {
+                        offset: 0.0,
+                        color: Color::WHITE,
+                    },
+                    ColorStop {
+                        offset: 1.0,
+                        color: Color::BLACK,
+                    },
+                ],
+                extend: Extend::Reflect,
+            },
+            Some(Affine::rotate(0.5)),
+            &Circle::new((50.0, 50.0), 20.0),
+        );
+        list.push_clip(Affine::IDENTITY, &Rect::new(0.0, 0.0, 160.0, 100.0));
+        list.push_layer(BlendMode::new(Mix::Luminosity, Compose::Xor), 0.5);
+
+        let image = list.add_image(Image::new(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 128]));
+        list.draw_image(image, Affine::scale(16.0));
+
+        let glyph = list.add_glyph(42, || Rect::new(0.0, 0.0, 5.0, 8.0).to_path(0.1));
+        list.draw_glyphs(
+            Affine::translate((0.0, 100.0)),
+            Color::BLACK,
+            [
+                Glyph {
+                    id: glyph,
+                    x: 0.0,
+                    y: 0.0,
+                },
+                Glyph {
+                    id: glyph,
+                    x: 6.0,
+                    y: 0.0,
+                },
+            ],
+        );
+        list.pop();
+        list.pop();
+        list
+    }
+
+    #[test]
+    fn roundtrip() {
+        let list = list();
+        let data = list.encode();
+        assert!(DisplayList::is_encoded(&data));
+
+        let decoded = DisplayList::decode(&data).unwrap();
+        assert_eq!((decoded.width, decoded.height), (320, 200));
+        assert_eq!(decoded.image(ImageId(0)), list.image(ImageId(0)));
+        assert_eq!(decoded.glyph(GlyphId(0)), list.glyph(GlyphId(0)));
+        assert_eq!(decoded.encode(), data);
+        assert_eq!(decoded.commands().len(), 8);
+    }
+
+    #[test]
+    fn invalid() {
+        let data = list().encode();
+
+        assert_eq!(DisplayList::decode(b"NA").unwrap_err(), Error::InvalidMagic);
+        assert_eq!(
+            DisplayList::decode(b"PNG\0\0\0\0\0").unwrap_err(),
+            Error::InvalidMagic
+        );
+
+        let mut version = data.clone();
+        version[4] = 2;
+        assert_eq!(
+            DisplayList::decode(&version).unwrap_err(),
+            Error::UnsupportedVersion(2)
+        );
+
+        for len in [12, 20, data.len() / 2, data.len() - 1] {
+            assert_eq!(
+                DisplayList::decode(&data[..len]).unwrap_err(),
+                Error::UnexpectedEnd,
+                "{}",
+                len
+            );
+        }
+
+        // huge array lengths are rejected without allocating
+        let mut len = data.clone();
+        len[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(DisplayList::decode(&len).unwrap_err(), Error::UnexpectedEnd);
+
+        // image reference out of range
+        let mut list = DisplayList::new(1, 1);
+        list.draw_image(ImageId(3), Affine::IDENTITY);
+        assert_eq!(
+            DisplayList::decode(&list.encode()).unwrap_err(),
+            Error::InvalidData
+        );
+
+        // paths that don't start with a move
+        let mut list = DisplayList::new(1, 1);
+        list.fill(
+            FillRule::NonZero,
+            Affine::IDENTITY,
+            Color::WHITE,
+            None,
+            &Rect::new(1234.5, 0.0, 2000.0, 1.0),
+        );
+        let data = list.encode();
+        let tag = data
+            .windows(8)
+            .position(|w| w == 1234.5f64.to_le_bytes())
+            .unwrap()
+            - 1;
+        assert_eq!(data[tag], 0);
+        for element in [1, 4] {
+            let mut path = data.clone();
+            path[tag] = element;
+            assert_eq!(
+                DisplayList::decode(&path).unwrap_err(),
+                Error::InvalidData,
+                "{}",
+                element
+            );
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Renderer independent display list.
+//!
+//! Frames are recorded into a `DisplayList` which can be consumed by the GPU
+//! canvas and the CPU rasterizers, or written to disk and replayed later.
+
+mod codec;
+
+pub use codec::Error;
+pub use kurbo;
+
+use kurbo::{Affine, BezPath, Point, Shape, Stroke};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Flattening tolerance when converting shapes into paths.
+const TOLERANCE: f64 = 0.1;
+
+/// sRGB color with straight alpha.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::rgba(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Self = Self::rgba(1.0, 1.0, 1.0, 1.0);
+    pub const TRANSPARENT: Self = Self::rgba(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn rgb(r: f64, g: f64, b: f64) -> Self {
+        Self::rgba(r, g, b, 1.0)
+    }
+
+    pub const fn rgba(r: f64, g: f64, b: f64, a: f64) -> Self {
+        Self {
+            r: r as f32,
+            g: g as f32,
+            b: b as f32,
+            a: a as f32,
+        }
+    }
+
+    pub fn with_alpha_factor(self, alpha: f32) -> Self {
+        Self {
+            a: self.a * alpha,
+            ..self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+/// Behavior outside of the `[0, 1]` gradient range or image bounds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Extend {
+    /// Clamp to the edge.
+    #[default]
+    Pad,
+    /// Repeat the content.
+    Repeat,
+    /// Repeat the content, mirrored on every other repetition.
+    Reflect,
+}
+
+/// Gradient stop, renderers may use their own color type.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColorStop<C = Color> {
+    pub offset: f32,
+    pub color: C,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GradientKind {
+    /// Gradient along the line from `start` to `end`.
+    Linear { start: Point, end: Point },
+    /// Circles interpolated from `focal` with zero radius towards `center`
+    /// with `radius`.
+    Radial {
+        center: Point,
+        radius: f64,
+        focal: Point,
+    },
+    /// Gradient around `center`, angles in radians from the positive x axis
+    /// towards the positive y axis.
+    Sweep {
+        center: Point,
+        start_angle: f64,
+        end_angle: f64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub stops: Vec<ColorStop>,
+    pub extend: Extend,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Brush {
+    Solid(Color),
+    Gradient(Gradient),
+}
+
+impl From<Color> for Brush {
+    fn from(color: Color) -> Self {
+        Brush::Solid(color)
+    }
+}
+
+impl From<Gradient> for Brush {
+    fn from(gradient: Gradient) -> Self {
+        Brush::Gradient(gradient)
+    }
+}
+
+/// Porter-Duff compositing operator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Compose {
+    Clear,
+    Copy,
+    Dest,
+    #[default]
+    SrcOver,
+    DestOver,
+    SrcIn,
+    DestIn,
+    SrcOut,
+    DestOut,
+    SrcAtop,
+    DestAtop,
+    Xor,
+    Plus,
+}
+
+/// Color mixing function of the source and backdrop.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mix {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+/// Mix function followed by a Porter-Duff operator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlendMode {
+    pub mix: Mix,
+    pub compose: Compose,
+}
+
+impl BlendMode {
+    pub const SRC_OVER: Self = Self::new(Mix::Normal, Compose::SrcOver);
+
+    pub const fn new(mix: Mix, compose: Compose) -> Self {
+        Self { mix, compose }
+    }
+}
+
+impl From<Mix> for BlendMode {
+    fn from(mix: Mix) -> Self {
+        Self::new(mix, Compose::SrcOver)
+    }
+}
+
+impl From<Compose> for BlendMode {
+    fn from(compose: Compose) -> Self {
+        Self::new(Mix::Normal, compose)
+    }
+}
+
+/// RGBA8 image with straight alpha in sRGB.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Arc<[u8]>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, data: impl Into<Arc<[u8]>>) -> Self {
+        let data = data.into();
+        assert_eq!(data.len(), width as usize * height as usize * 4);
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(pub u32);
+
+/// Glyph outline of the display list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GlyphId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub id: GlyphId,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Fill {
+        rule: FillRule,
+        transform: Affine,
+        brush: Brush,
+        /// Brush to path space transform.
+        brush_transform: Option<Affine>,
+        path: BezPath,
+    },
+    Stroke {
+        style: Stroke,
+        transform: Affine,
+        brush: Brush,
+        brush_transform: Option<Affine>,
+        path: BezPath,
+    },
+    /// Clip all following commands to the path until the matching `Pop`.
+    PushClip {
+        transform: Affine,
+        path: BezPath,
+    },
+    /// Draw all following commands into an isolated group until the
+    /// matching `Pop`.
+    PushLayer {
+        blend: BlendMode,
+        opacity: f32,
+    },
+    Pop,
+    /// Glyph outlines filled with the nonzero rule, each placed with
+    /// `transform * translate(x, y)`.
+    Glyphs {
+        transform: Affine,
+        brush: Brush,
+        glyphs: Vec<Glyph>,
+    },
+    /// Image covering the unit square per pixel in image space.
+    Image {
+        image: ImageId,
+        transform: Affine,
+    },
+}
+
+/// Recorded frame.
+#[derive(Clone, Debug, Default)]
+pub struct DisplayList {
+    pub width: u32,
+    pub height: u32,
+    commands: Vec<Command>,
+    images: Vec<Image>,
+    glyphs: Vec<BezPath>,
+    glyph_keys: HashMap<u64, GlyphId>,
+}
+
+impl DisplayList {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// Clear all recorded commands and resources.
+    pub fn reset(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.commands.clear();
+        self.images.clear();
+        self.glyphs.clear();
+        self.glyph_keys.clear();
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn image(&self, id: ImageId) -> &Image {
+        &self.images[id.0 as usize]
+    }
+
+    pub fn glyph(&self, id: GlyphId) -> &BezPath {
+        &self.glyphs[id.0 as usize]
+    }
+
+    pub fn push(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+
+    pub fn fill(
+        &mut self,
+        rule: FillRule,
+        transform: Affine,
+        brush: impl Into<Brush>,
+        brush_transform: Option<Affine>,
+        shape: &impl Shape,
+    ) {
+        self.push(Command::Fill {
+            rule,
+            transform,
+            brush: brush.into(),
+            brush_transform,
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn stroke(
+        &mut self,
+        style: &Stroke,
+        transform: Affine,
+        brush: impl Into<Brush>,
+        brush_transform: Option<Affine>,
+        shape: &impl Shape,
+    ) {
+        self.push(Command::Stroke {
+            style: style.clone(),
+            transform,
+            brush: brush.into(),
+            brush_transform,
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn push_clip(&mut self, transform: Affine, shape: &impl Shape) {
+        self.push(Command::PushClip {
+            transform,
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn push_layer(&mut self, blend: impl Into<BlendMode>, opacity: f32) {
+        self.push(Command::PushLayer {
+            blend: blend.into(),
+            opacity,
+        });
+    }
+
+    /// Pop the most recent clip or layer.
+    pub fn pop(&mut self) {
+        self.push(Command::Pop);
+    }
+
+    pub fn add_image(&mut self, image: Image) -> ImageId {
+        self.images.push(image);
+        ImageId(self.images.len() as u32 - 1)
+    }
+
+    pub fn draw_image(&mut self, image: ImageId, transform: Affine) {
+        self.push(Command::Image { image, transform });
+    }
+
+    /// Glyph outline for the user defined `key`, the outline is only built
+    /// on first use.
+    pub fn add_glyph(&mut self, key: u64, outline: impl FnOnce() -> BezPath) -> GlyphId {
+        let glyphs = &mut self.glyphs;
+        *self.glyph_keys.entry(key).or_insert_with(|| {
+            glyphs.push(outline());
+            GlyphId(glyphs.len() as u32 - 1)
+        })
+    }
+
+    pub fn draw_glyphs(
+        &mut self,
+        transform: Affine,
+        brush: impl Into<Brush>,
+        glyphs: impl IntoIterator<Item = Glyph>,
+    ) {
+        self.push(Command::Glyphs {
+            transform,
+            brush: brush.into(),
+            glyphs: glyphs.into_iter().collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kurbo::{Circle, Rect};
+
+    #[test]
+    fn record() {
+        let mut list = DisplayList::new(64, 32);
+        list.fill(
+            FillRule::NonZero,
+            Affine::IDENTITY,
+            Color::rgb(1.0, 0.0, 0.0),
+            None,
+            &Rect::new(0.0, 0.0, 10.0, 10.0),
+        );
+        list.push_clip(Affine::IDENTITY, &Circle::new((5.0, 5.0), 4.0));
+        list.pop();
+
+        let mut builds = 0;
+        let mut glyph = |list: &mut DisplayList, key| {
+            list.add_glyph(key, || {
+                builds += 1;
+                Rect::new(0.0, 0.0, 1.0, 1.0).to_path(0.1)
+            })
+        };
+        let a = glyph(&mut list, 7);
+        let b = glyph(&mut list, 9);
+        assert_eq!(glyph(&mut list, 7), a);
+        assert_ne!(a, b);
+        assert_eq!(builds, 2);
+
+        assert_eq!(list.commands().len(), 3);
+        assert!(matches!(
+            list.commands()[1],
+            Command::PushClip { ref path, .. } if path.elements().len() > 4
+        ));
+
+        list.reset(8, 8);
+        assert!(list.commands().is_empty());
+        assert_eq!(list.add_glyph(9, BezPath::new), GlyphId(0));
+    }
+}
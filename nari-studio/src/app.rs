@@ -1,7 +1,11 @@
 use nari_platform::{EventLoop, MouseButtons, Platform};
-use nari_vello::{icon::Icon, peniko::Color, typo::FontScaled, Canvas};
+use nari_scene::Color;
+use nari_vello::{icon::Icon, typo::FontScaled, Canvas};
 use parley::fontique::Attributes;
 
+/// Body font size in points.
+const FONT_SIZE_BODY: f64 = 16.0;
+
 pub struct Style {
     pub font_regular: FontScaled,
 
@@ -42,7 +46,7 @@ impl App {
         let font_body = canvas.create_font(std::fs::read("assets/Lato/Lato-Regular.ttf")?);
         let font_body_regular = canvas.create_font_scaled(
             font_body,
-            canvas.scale(16.0).round() as u32,
+            canvas.scale(FONT_SIZE_BODY).round() as u32,
             Attributes::default(),
         );
 
@@ -50,6 +54,8 @@ impl App {
             canvas,
             event_loop: EventLoop {
                 surface: platform.surface,
+                surfaces: platform.surfaces(),
+                clipboard: platform.clipboard(),
                 mouse_position: None,
                 mouse_buttons: MouseButtons::empty(),
             },
@@ -71,4 +77,16 @@ impl App {
             },
         })
     }
+
+    /// Rescale the canvas and fonts for the new DPI of the surface.
+    pub fn set_dpi(&mut self, dpi: f64) {
+        self.canvas.set_scale(dpi);
+
+        let font = self.style.font_regular;
+        self.style.font_regular = self.canvas.create_font_scaled(
+            font.font,
+            self.canvas.scale(FONT_SIZE_BODY).round() as u32,
+            font.attributes,
+        );
+    }
 }
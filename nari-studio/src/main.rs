@@ -1,14 +1,16 @@
 use nari_platform::{
-    ControlFlow, Cursor, Event, Extent, Key, KeyCode, KeyState, Modifiers, MouseButtons, Platform,
-    SurfaceArea,
+    Composition, ControlFlow, Cursor, Event, Extent, Key, KeyCode, KeyState, Modifiers,
+    MouseButtons, Platform, SurfaceArea,
 };
+use nari_scene::{Color, DisplayList, FillRule};
 use nari_vello::{
+    icon::Icon,
     kurbo::{Affine, Point, Rect, RoundedRect, Stroke},
-    peniko::{Brush, Color, Fill},
     typo::{Cursor as SelectionCursor, TextRun},
-    Align, Canvas, Scene,
+    Align, Canvas,
 };
 use parley::layout::cursor::Movement;
+use std::path::Path;
 
 mod app;
 mod notebook;
@@ -19,6 +21,8 @@ use app::App;
 // with grapheme clusters.
 struct TextCursor {
     text: String,
+    /// Unscaled position of the pen.
+    origin: Point,
     pen: Point,
     text_run: TextRun,
     cursor: Option<SelectionCursor>,
@@ -30,12 +34,24 @@ impl TextCursor {
 
         Self {
             text: text.to_string(),
+            origin: pen,
             pen: app.canvas.scale_pt(pen),
             text_run,
             cursor: None,
         }
     }
 
+    /// Layout the text again after the DPI changed.
+    fn rescale(&mut self, app: &mut App) {
+        self.pen = app.canvas.scale_pt(self.origin);
+        self.text_run = app
+            .canvas
+            .build_text_run(app.style.font_regular, &self.text);
+        self.cursor = self.cursor.map(|cursor| {
+            SelectionCursor::from_position(&self.text_run.layout, cursor.insert_point, true)
+        });
+    }
+
     fn on_char(&mut self, app: &mut App, c: char) {
         if c.is_control() {
             return;
@@ -120,7 +136,7 @@ impl TextCursor {
         }
     }
 
-    fn paint(&self, app: &mut App, mut sb: &mut Scene) {
+    fn paint(&self, app: &mut App, list: &mut DisplayList) {
         let pen = Affine::translate(self.pen.to_vec2());
 
         let text_run = app
@@ -130,32 +146,27 @@ impl TextCursor {
 
         // selection background
         if self.cursor.is_some() {
-            sb.fill(
-                Fill::NonZero,
+            list.fill(
+                FillRule::NonZero,
                 pen,
-                &Brush::Solid(app.style.color_text_select),
+                app.style.color_text_select,
                 None,
                 &bounds,
             );
         }
 
-        app.canvas.text_run(
-            &mut sb,
-            &text_run,
-            pen,
-            Align::Positive,
-            Brush::Solid(app.style.color_text),
-        );
+        app.canvas
+            .record_text_run(list, &text_run, pen, Align::Positive, app.style.color_text);
 
         // draw caret
         if let Some(cursor) = self.cursor {
             let advance = cursor.offset as f64;
             let line = cursor.path.line(&self.text_run.layout).unwrap();
             let metrics = line.metrics();
-            sb.fill(
-                Fill::NonZero,
+            list.fill(
+                FillRule::NonZero,
                 pen,
-                &Brush::Solid(app.style.color_cursor),
+                app.style.color_cursor,
                 None,
                 &Rect {
                     x0: advance,
@@ -271,7 +282,7 @@ impl Caption {
         None
     }
 
-    fn paint(app: &mut App, mut sb: &mut Scene) {
+    fn paint(app: &mut App, list: &mut DisplayList) {
         let extent = app.event_loop.surface.extent();
         let canvas = &app.canvas;
 
@@ -288,26 +299,26 @@ impl Caption {
             .map(|(x, y)| Point::new(x as f64, y as f64));
         if let Some(p) = mouse_pos {
             if chrome_minimize.contains(p) {
-                sb.fill(
-                    Fill::NonZero,
+                list.fill(
+                    FillRule::NonZero,
                     Affine::IDENTITY,
-                    &Brush::Solid(Color::rgb(0.85, 0.85, 0.85)),
+                    Color::rgb(0.85, 0.85, 0.85),
                     None,
                     &chrome_minimize,
                 );
             } else if chrome_maximize.contains(p) {
-                sb.fill(
-                    Fill::NonZero,
+                list.fill(
+                    FillRule::NonZero,
                     Affine::IDENTITY,
-                    &Brush::Solid(Color::rgb(0.85, 0.85, 0.85)),
+                    Color::rgb(0.85, 0.85, 0.85),
                     None,
                     &chrome_maximize,
                 );
             } else if chrome_close.contains(p) {
-                sb.fill(
-                    Fill::NonZero,
+                list.fill(
+                    FillRule::NonZero,
                     Affine::IDENTITY,
-                    &Brush::Solid(Color::rgb(0.80, 0.20, 0.15)),
+                    Color::rgb(0.80, 0.20, 0.15),
                     None,
                     &chrome_close,
                 );
@@ -319,10 +330,10 @@ impl Caption {
                 - canvas.scale_pt(app.style.icon_chrome_minimize.bbox.center()))
             .floor(),
         );
-        app.style.icon_chrome_minimize.paint(
-            &mut sb,
+        app.style.icon_chrome_minimize.record(
+            list,
             affine_minimize * affine_dpi,
-            &Brush::Solid(app.style.color_text),
+            app.style.color_text,
         );
 
         let icon_maximize = if app.event_loop.surface.is_maximized() {
@@ -333,11 +344,7 @@ impl Caption {
         let affine_maximize = Affine::translate(
             (chrome_maximize.center() - canvas.scale_pt(icon_maximize.bbox.center())).floor(),
         );
-        icon_maximize.paint(
-            &mut sb,
-            affine_maximize * affine_dpi,
-            &Brush::Solid(app.style.color_text),
-        );
+        icon_maximize.record(list, affine_maximize * affine_dpi, app.style.color_text);
 
         let close_color = if let Some(p) = mouse_pos {
             if chrome_close.contains(p) {
@@ -352,11 +359,9 @@ impl Caption {
             (chrome_close.center() - canvas.scale_pt(app.style.icon_chrome_close.bbox.center()))
                 .floor(),
         );
-        app.style.icon_chrome_close.paint(
-            &mut sb,
-            affine_close * affine_dpi,
-            &Brush::Solid(close_color),
-        );
+        app.style
+            .icon_chrome_close
+            .record(list, affine_close * affine_dpi, close_color);
     }
 }
 
@@ -373,7 +378,12 @@ async fn run() -> anyhow::Result<()> {
         Point::new(10.0, 70.0),
     );
 
-    let mut scene = Scene::default();
+    // write the latest frame to `NARI_CAPTURE` for headless replay
+    let capture = std::env::var_os("NARI_CAPTURE");
+    let mut list = DisplayList::default();
+
+    // SVG files dropped into the window, drawn at the drop position
+    let mut dropped_icons: Vec<(Icon, Point)> = Vec::new();
 
     platform.run(move |event_loop, event| {
         app.event_loop = event_loop;
@@ -384,6 +394,13 @@ async fn run() -> anyhow::Result<()> {
                 app.event_loop.surface.redraw();
             }
 
+            Event::DpiChanged { dpi, .. } => {
+                app.set_dpi(dpi);
+                text_cursor.rescale(&mut app);
+                text_cursor2.rescale(&mut app);
+                app.event_loop.surface.redraw();
+            }
+
             Event::Hittest { x, y, area } => {
                 let p = Point::new(x as f64, y as f64);
 
@@ -395,13 +412,19 @@ async fn run() -> anyhow::Result<()> {
             }
 
             Event::Paint => {
-                let mut sb = &mut scene;
-                sb.reset();
+                let extent = app.event_loop.surface.extent();
+                list.reset(extent.width as u32, extent.height as u32);
 
-                Caption::paint(&mut app, &mut sb);
+                Caption::paint(&mut app, &mut list);
 
-                text_cursor.paint(&mut app, &mut sb);
-                text_cursor2.paint(&mut app, &mut sb);
+                text_cursor.paint(&mut app, &mut list);
+                text_cursor2.paint(&mut app, &mut list);
+
+                for (icon, pos) in &dropped_icons {
+                    let affine =
+                        Affine::translate(pos.to_vec2()) * Affine::scale(app.canvas.scale(1.0));
+                    icon.record(&mut list, affine, app.style.color_text);
+                }
 
                 let pen = Affine::translate((app.canvas.scale(30.0), app.canvas.scale(400.0)));
 
@@ -413,40 +436,57 @@ async fn run() -> anyhow::Result<()> {
                     .inflate(app.canvas.scale(10.0), app.canvas.scale(5.0));
                 let bounds_round = RoundedRect::from_rect(bounds, app.canvas.scale(6.0));
 
-                sb.fill(
-                    Fill::NonZero,
+                list.fill(
+                    FillRule::NonZero,
                     pen,
-                    &Brush::Solid(app.style.color_text_select),
+                    app.style.color_text_select,
                     None,
                     &bounds_round,
                 );
-                sb.stroke(
+                list.stroke(
                     &Stroke::new(1.0),
                     pen,
-                    &Brush::Solid(Color::rgb(0.42, 0.45, 0.47)),
+                    Color::rgb(0.42, 0.45, 0.47),
                     None,
                     &bounds_round,
                 );
 
-                app.canvas.text_run(
-                    &mut sb,
+                app.canvas.record_text_run(
+                    &mut list,
                     &text_run,
                     pen,
                     Align::Positive,
-                    Brush::Solid(app.style.color_text),
+                    app.style.color_text,
                 );
 
-                app.canvas.present(&scene, app.style.color_background);
+                app.canvas.present_list(&list, app.style.color_background);
+                if let Some(path) = &capture {
+                    if let Err(err) = std::fs::write(path, list.encode()) {
+                        eprintln!("failed to capture frame: {}", err);
+                    }
+                }
             }
 
             Event::Key {
                 key,
                 state,
                 modifiers,
+                ..
             } => {
                 text_cursor.on_key(key, state, modifiers);
                 text_cursor2.on_key(key, state, modifiers);
 
+                if key == Key::Char('V')
+                    && state == KeyState::Down
+                    && modifiers == Modifiers::CONTROL
+                {
+                    let text = app.event_loop.clipboard.text().unwrap_or_default();
+                    for c in text.chars() {
+                        text_cursor.on_char(&mut app, c);
+                        text_cursor2.on_char(&mut app, c);
+                    }
+                }
+
                 app.event_loop.surface.redraw();
             }
 
@@ -454,6 +494,7 @@ async fn run() -> anyhow::Result<()> {
                 button,
                 state,
                 modifiers,
+                ..
             } => {
                 text_cursor.on_mouse(&mut app, button, state, modifiers);
                 text_cursor2.on_mouse(&mut app, button, state, modifiers);
@@ -491,6 +532,42 @@ async fn run() -> anyhow::Result<()> {
 
                 app.event_loop.surface.redraw();
             }
+
+            Event::Composition(Composition::Commit(text)) => {
+                for c in text.chars() {
+                    text_cursor.on_char(&mut app, c);
+                    text_cursor2.on_char(&mut app, c);
+                }
+
+                app.event_loop.surface.redraw();
+            }
+
+            Event::DragEnter { paths, accept, .. } | Event::DragOver { paths, accept, .. } => {
+                *accept = paths.iter().any(|path| is_svg(path));
+            }
+
+            Event::Drop { paths, x, y } => {
+                let pos = Point::new(x as f64, y as f64);
+                for path in paths.iter().filter(|path| is_svg(path)) {
+                    match load_icon(path) {
+                        Ok(icon) => dropped_icons.push((icon, pos)),
+                        Err(err) => eprintln!("failed to load {}: {}", path.display(), err),
+                    }
+                }
+
+                app.event_loop.surface.redraw();
+            }
+
+            Event::Scroll { .. }
+            | Event::Focus(_)
+            | Event::Occluded(_)
+            | Event::CloseRequest { .. }
+            | Event::Composition(_)
+            | Event::DragLeave
+            | Event::Close
+            | Event::AnimationFrame
+            | Event::Timer(_)
+            | Event::User(_) => (),
         }
         ControlFlow::Continue
     });
@@ -498,6 +575,14 @@ async fn run() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn is_svg(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "svg")
+}
+
+fn load_icon(path: &Path) -> anyhow::Result<Icon> {
+    Icon::build(&std::fs::read(path)?)
+}
+
 fn main() -> anyhow::Result<()> {
     pollster::block_on(run())
 }
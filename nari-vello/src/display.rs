@@ -0,0 +1,180 @@
+//! Encoding of `nari_scene` display lists into vello scenes.
+
+use crate::{
+    kurbo::{Affine, Rect},
+    peniko::{self, BlendMode, Blob, Compose, Fill, Format, Mix},
+    Scene,
+};
+use nari_scene::{Brush, Command, DisplayList, FillRule, ImageId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn color(color: nari_scene::Color) -> peniko::Color {
+    peniko::Color::rgba(
+        color.r as f64,
+        color.g as f64,
+        color.b as f64,
+        color.a as f64,
+    )
+}
+
+fn extend(extend: nari_scene::Extend) -> peniko::Extend {
+    match extend {
+        nari_scene::Extend::Pad => peniko::Extend::Pad,
+        nari_scene::Extend::Repeat => peniko::Extend::Repeat,
+        nari_scene::Extend::Reflect => peniko::Extend::Reflect,
+    }
+}
+
+pub fn brush(brush: &Brush) -> peniko::Brush {
+    let gradient = match brush {
+        Brush::Solid(c) => return peniko::Brush::Solid(color(*c)),
+        Brush::Gradient(gradient) => gradient,
+    };
+
+    let stops = gradient
+        .stops
+        .iter()
+        .map(|stop| peniko::ColorStop {
+            offset: stop.offset,
+            color: color(stop.color),
+        })
+        .collect::<Vec<_>>();
+    let base = match gradient.kind {
+        nari_scene::GradientKind::Linear { start, end } => peniko::Gradient::new_linear(start, end),
+        nari_scene::GradientKind::Radial {
+            center,
+            radius,
+            focal,
+        } => peniko::Gradient::new_two_point_radial(focal, 0.0, center, radius as f32),
+        nari_scene::GradientKind::Sweep {
+            center,
+            start_angle,
+            end_angle,
+        } => peniko::Gradient::new_sweep(center, start_angle as f32, end_angle as f32),
+    };
+
+    peniko::Brush::Gradient(
+        base.with_extend(extend(gradient.extend))
+            .with_stops(&stops[..]),
+    )
+}
+
+fn blend_mode(mode: nari_scene::BlendMode) -> BlendMode {
+    let mix = match mode.mix {
+        nari_scene::Mix::Normal => Mix::Normal,
+        nari_scene::Mix::Multiply => Mix::Multiply,
+        nari_scene::Mix::Screen => Mix::Screen,
+        nari_scene::Mix::Overlay => Mix::Overlay,
+        nari_scene::Mix::Darken => Mix::Darken,
+        nari_scene::Mix::Lighten => Mix::Lighten,
+        nari_scene::Mix::ColorDodge => Mix::ColorDodge,
+        nari_scene::Mix::ColorBurn => Mix::ColorBurn,
+        nari_scene::Mix::HardLight => Mix::HardLight,
+        nari_scene::Mix::SoftLight => Mix::SoftLight,
+        nari_scene::Mix::Difference => Mix::Difference,
+        nari_scene::Mix::Exclusion => Mix::Exclusion,
+        nari_scene::Mix::Hue => Mix::Hue,
+        nari_scene::Mix::Saturation => Mix::Saturation,
+        nari_scene::Mix::Color => Mix::Color,
+        nari_scene::Mix::Luminosity => Mix::Luminosity,
+    };
+    let compose = match mode.compose {
+        nari_scene::Compose::Clear => Compose::Clear,
+        nari_scene::Compose::Copy => Compose::Copy,
+        nari_scene::Compose::Dest => Compose::Dest,
+        nari_scene::Compose::SrcOver => Compose::SrcOver,
+        nari_scene::Compose::DestOver => Compose::DestOver,
+        nari_scene::Compose::SrcIn => Compose::SrcIn,
+        nari_scene::Compose::DestIn => Compose::DestIn,
+        nari_scene::Compose::SrcOut => Compose::SrcOut,
+        nari_scene::Compose::DestOut => Compose::DestOut,
+        nari_scene::Compose::SrcAtop => Compose::SrcAtop,
+        nari_scene::Compose::DestAtop => Compose::DestAtop,
+        nari_scene::Compose::Xor => Compose::Xor,
+        nari_scene::Compose::Plus => Compose::Plus,
+    };
+    BlendMode::new(mix, compose)
+}
+
+/// Append the commands of the display list to the scene.
+pub fn encode(list: &DisplayList, scene: &mut Scene) {
+    let bounds = Rect::new(0.0, 0.0, list.width as f64, list.height as f64);
+    let mut images = HashMap::<ImageId, peniko::Image>::new();
+
+    for command in list.commands() {
+        match command {
+            Command::Fill {
+                rule,
+                transform,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                let fill = match rule {
+                    FillRule::NonZero => Fill::NonZero,
+                    FillRule::EvenOdd => Fill::EvenOdd,
+                };
+                scene.fill(
+                    fill,
+                    *transform,
+                    &self::brush(brush),
+                    *brush_transform,
+                    path,
+                );
+            }
+            Command::Stroke {
+                style,
+                transform,
+                brush,
+                brush_transform,
+                path,
+            } => {
+                scene.stroke(
+                    style,
+                    *transform,
+                    &self::brush(brush),
+                    *brush_transform,
+                    path,
+                );
+            }
+            Command::PushClip { transform, path } => {
+                scene.push_layer(Mix::Clip, 1.0, *transform, path);
+            }
+            Command::PushLayer { blend, opacity } => {
+                scene.push_layer(blend_mode(*blend), *opacity, Affine::IDENTITY, &bounds);
+            }
+            Command::Pop => scene.pop_layer(),
+            Command::Glyphs {
+                transform,
+                brush,
+                glyphs,
+            } => {
+                let brush = self::brush(brush);
+                for glyph in glyphs {
+                    // brushes are shared by all glyphs of the run
+                    let offset = Affine::translate((glyph.x as f64, glyph.y as f64));
+                    scene.fill(
+                        Fill::NonZero,
+                        *transform * offset,
+                        &brush,
+                        Some(offset.inverse()),
+                        list.glyph(glyph.id),
+                    );
+                }
+            }
+            Command::Image { image, transform } => {
+                let image = images.entry(*image).or_insert_with(|| {
+                    let data = list.image(*image);
+                    peniko::Image::new(
+                        Blob::new(Arc::new(data.data.clone())),
+                        Format::Rgba8,
+                        data.width,
+                        data.height,
+                    )
+                });
+                scene.draw_image(image, *transform);
+            }
+        }
+    }
+}
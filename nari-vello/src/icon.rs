@@ -71,6 +71,37 @@ impl Icon {
         Ok(icon)
     }
 
+    pub fn record(
+        &self,
+        list: &mut nari_scene::DisplayList,
+        affine: Affine,
+        brush: impl Into<nari_scene::Brush>,
+    ) {
+        let brush = brush.into();
+        for path in &self.paths {
+            let transform = affine * path.transform;
+
+            if path.fill {
+                list.fill(
+                    nari_scene::FillRule::NonZero,
+                    transform,
+                    brush.clone(),
+                    None,
+                    &path.path,
+                );
+            }
+            if let Some(stroke) = path.stroke {
+                list.stroke(
+                    &Stroke::new(stroke.into()),
+                    transform,
+                    brush.clone(),
+                    None,
+                    &path.path,
+                );
+            }
+        }
+    }
+
     pub fn paint(&self, sb: &mut Scene, affine: Affine, brush: &Brush) {
         for path in &self.paths {
             let transform = affine * path.transform;
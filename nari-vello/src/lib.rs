@@ -1,6 +1,7 @@
 mod engine;
 mod fxp;
 
+pub mod display;
 pub mod icon;
 pub mod typo;
 use parley::fontique::Attributes;
@@ -117,6 +118,13 @@ impl Canvas {
         self.device.poll(wgpu::Maintain::Poll);
     }
 
+    /// Present a recorded frame.
+    pub fn present_list(&mut self, list: &nari_scene::DisplayList, background: nari_scene::Color) {
+        let mut scene = Scene::new();
+        display::encode(list, &mut scene);
+        self.present(&scene, display::color(background));
+    }
+
     pub fn resize(&mut self, size: Extent) {
         self.swapchain_config.width = size.width as _;
         self.swapchain_config.height = size.height as _;
@@ -132,6 +140,11 @@ impl Canvas {
         Point::new(self.scale(pt.x), self.scale(pt.y))
     }
 
+    /// Update the scale factor after the surface DPI changed.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
     pub fn create_font(&mut self, data: Vec<u8>) -> Font {
         self.engine.create_font(data)
     }
@@ -188,4 +201,53 @@ impl Canvas {
             }
         }
     }
+
+    /// Record the text run into the display list, see `text_run`.
+    pub fn record_text_run(
+        &self,
+        list: &mut nari_scene::DisplayList,
+        text_run: &TextRun,
+        affine: Affine,
+        align_x: Align,
+        brush: impl Into<nari_scene::Brush>,
+    ) {
+        let flip = Affine::scale_non_uniform(1.0, -1.0);
+        // glyph positions are applied after `affine`, move them in front
+        let [a, b, c, d, _, _] = affine.as_coeffs();
+        let linear_inv = Affine::new([a, b, c, d, 0.0, 0.0]).inverse();
+        let px: f64 = text_run.offset_x(align_x);
+
+        let mut glyphs = Vec::new();
+        for line in text_run.layout.lines() {
+            for glyph_run in line.glyph_runs() {
+                let mut run_x = px as f32 + glyph_run.offset();
+                let run_y = glyph_run.baseline();
+
+                for glyph in glyph_run.glyphs() {
+                    let glyph_x = run_x + glyph.x;
+                    let glyph_y = run_y - glyph.y;
+                    run_x += glyph.advance;
+
+                    let size = text_run.font.size;
+                    let key = GlyphKey { id: glyph.id as _ };
+                    let id = list.add_glyph(((size as u64) << 32) | key.id as u64, || {
+                        let path = self
+                            .glyph_cache
+                            .get(&(size, key))
+                            .expect("missing glyph entry");
+                        flip * path
+                    });
+
+                    let p = linear_inv * Point::new(glyph_x as _, glyph_y as _);
+                    glyphs.push(nari_scene::Glyph {
+                        id,
+                        x: p.x as _,
+                        y: p.y as _,
+                    });
+                }
+            }
+        }
+
+        list.draw_glyphs(affine, brush, glyphs);
+    }
 }